@@ -49,7 +49,7 @@ impl BloomFilter {
         serialized_data[0] = self.hash_fun_count;
 
         // Push 8 bytes for the data length
-        let data_len_bytes = (self.data.len() as u64).to_ne_bytes();
+        let data_len_bytes = (self.data.len() as u64).to_le_bytes();
         serialized_data[1..9].copy_from_slice(&data_len_bytes);
 
         // Copy the data bytes directly
@@ -71,7 +71,7 @@ impl BloomFilter {
         let mut data_len_bytes = [0u8; 8];
         data_len_bytes.copy_from_slice(&input[1..9]);
 
-        let data_len = usize::from_ne_bytes(data_len_bytes);
+        let data_len = u64::from_le_bytes(data_len_bytes) as usize;
 
         if input.len() != 9 + data_len {
             return Err(std::io::Error::new(
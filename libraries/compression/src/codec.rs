@@ -0,0 +1,71 @@
+use std::io::{Error, ErrorKind};
+
+/// Compresses blocks of bytes, such as the data blocks of SSTables.
+pub trait Codec: Send + Sync {
+    /// Identifier of the codec, stored with each compressed block so it's decompressed with the same codec.
+    fn id(&self) -> u8;
+
+    /// Returns the compressed `data`.
+    fn compress(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Returns the decompressed `data`, which has to be `decompressed_len` bytes long.
+    /// Returns an error if the data can't be decompressed.
+    fn decompress(&self, data: &[u8], decompressed_len: usize) -> std::io::Result<Vec<u8>>;
+}
+
+/// Stores the data as it is.
+pub struct NoneCodec;
+
+impl Codec for NoneCodec {
+    fn id(&self) -> u8 {
+        0
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        data.to_vec()
+    }
+
+    fn decompress(&self, data: &[u8], decompressed_len: usize) -> std::io::Result<Vec<u8>> {
+        if data.len() != decompressed_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Uncompressed block has the wrong length!",
+            ));
+        }
+
+        Ok(data.to_vec())
+    }
+}
+
+/// Fast general-purpose compression with the LZ4 block format.
+pub struct Lz4Codec;
+
+impl Codec for Lz4Codec {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        lz4_flex::block::compress(data)
+    }
+
+    fn decompress(&self, data: &[u8], decompressed_len: usize) -> std::io::Result<Vec<u8>> {
+        match lz4_flex::block::decompress(data, decompressed_len) {
+            Ok(decompressed) if decompressed.len() == decompressed_len => Ok(decompressed),
+            Ok(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Decompressed block has the wrong length!",
+            )),
+            Err(e) => Err(Error::new(ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
+/// Returns the built-in codec with the given identifier, None if there is no such codec.
+pub fn codec_by_id(id: u8) -> Option<&'static dyn Codec> {
+    match id {
+        0 => Some(&NoneCodec),
+        1 => Some(&Lz4Codec),
+        _ => None,
+    }
+}
@@ -1,12 +1,14 @@
+mod codec;
 mod compression;
 
+pub use codec::{codec_by_id, Codec, Lz4Codec, NoneCodec};
 pub use compression::CompressionDictionary;
 pub use compression::{variable_decode, variable_encode};
 
 #[cfg(test)]
 mod tests {
     use crate::compression::{variable_decode, variable_encode};
-    use crate::CompressionDictionary;
+    use crate::{codec_by_id, Codec, CompressionDictionary, Lz4Codec, NoneCodec};
     use std::fs;
     use std::fs::remove_dir_all;
 
@@ -224,4 +226,20 @@ mod tests {
         assert_eq!(value.unwrap(), solution, "Test 4 failed!");
         assert_eq!(length, 1, "Test 4 failed!");
     }
+
+    #[test]
+    fn codec_test() {
+        let data: Vec<u8> = (0..10_000)
+            .flat_map(|i| format!("{{\"id\": {}, \"name\": \"user\"}}", i % 100).into_bytes())
+            .collect();
+
+        for codec in [&NoneCodec as &dyn Codec, &Lz4Codec] {
+            let compressed = codec.compress(&data);
+            assert_eq!(codec.decompress(&compressed, data.len()).unwrap(), data);
+            assert_eq!(codec_by_id(codec.id()).unwrap().id(), codec.id());
+            assert!(codec.decompress(&compressed, data.len() + 1).is_err());
+        }
+        assert!(Lz4Codec.compress(&data).len() < data.len() / 4);
+        assert!(codec_by_id(u8::MAX).is_none());
+    }
 }
@@ -30,10 +30,15 @@ impl CMSketch {
 
     /// Increases the count for some given key.
     pub fn increase_count(&mut self, key: &[u8]) {
+        self.increase_count_by(key, 1);
+    }
+
+    /// Increases the count for some given key by the given amount.
+    pub fn increase_count_by(&mut self, key: &[u8], count: u64) {
         for (seed, row) in self.table.iter_mut().enumerate() {
             let hash = hash64_with_seed(key, seed as u64) as usize % row.len();
 
-            row[hash] += 1;
+            row[hash] = row[hash].saturating_add(count);
         }
     }
 
@@ -43,4 +43,15 @@ mod tests {
         assert_eq!(cms2.get_count(b"video2"), 1);
         assert_eq!(cms2.get_count(b"video3"), 2);
     }
+    #[test]
+    fn count_by() {
+        let mut cms1 = CMSketch::new(0.001, 0.99999);
+
+        cms1.increase_count_by(b"video1", 5);
+        cms1.increase_count(b"video1");
+        cms1.increase_count_by(b"video2", 0);
+
+        assert_eq!(cms1.get_count(b"video1"), 6);
+        assert_eq!(cms1.get_count(b"video2"), 0);
+    }
 }
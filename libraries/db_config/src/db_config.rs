@@ -1,7 +1,9 @@
+use db_error::Error;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, Error, ErrorKind, Write};
+use std::io::{BufReader, Write};
+use std::path::Path;
 
 /// Options for the implementation of memory table
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -27,6 +29,8 @@ impl fmt::Display for MemoryTableType {
 pub enum CompactionAlgorithmType {
     SizeTiered,
     Leveled,
+    TimeWindow,
+    Fifo,
 }
 
 /// Helper function to display MemoryTableType
@@ -35,6 +39,70 @@ impl fmt::Display for CompactionAlgorithmType {
         match self {
             CompactionAlgorithmType::SizeTiered => write!(f, "SizeTiered"),
             CompactionAlgorithmType::Leveled => write!(f, "Leveled"),
+            CompactionAlgorithmType::TimeWindow => write!(f, "TimeWindow"),
+            CompactionAlgorithmType::Fifo => write!(f, "Fifo"),
+        }
+    }
+}
+
+/// Options for the codec that compresses the data blocks of SSTables
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum BlockCodecType {
+    None,
+    Lz4,
+}
+
+/// Helper function to display BlockCodecType
+impl fmt::Display for BlockCodecType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockCodecType::None => write!(f, "None"),
+            BlockCodecType::Lz4 => write!(f, "Lz4"),
+        }
+    }
+}
+
+/// Options for extracting the key prefixes that go into the prefix Bloom filters of SSTables
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum PrefixExtractorType {
+    // SSTables don't have prefix filters
+    #[default]
+    None,
+    // The given number of bytes at the start of each key
+    FixedLength(usize),
+    // The start of each key up to and including the first occurrence of the given byte
+    Delimiter(u8),
+}
+
+/// Helper function to display PrefixExtractorType
+impl fmt::Display for PrefixExtractorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefixExtractorType::None => write!(f, "None"),
+            PrefixExtractorType::FixedLength(length) => write!(f, "FixedLength({})", length),
+            PrefixExtractorType::Delimiter(delimiter) => {
+                write!(f, "Delimiter({:?})", char::from(*delimiter))
+            }
+        }
+    }
+}
+
+/// Configuration of a named column family. Options that aren't set are taken from the database configuration.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ColumnFamilyConfig {
+    pub name: String,
+    pub memory_table_type: Option<MemoryTableType>,
+    pub compaction_algorithm_type: Option<CompactionAlgorithmType>,
+    pub use_compression: Option<bool>,
+}
+
+impl ColumnFamilyConfig {
+    /// Creates a column family configuration without any overrides.
+    pub fn new(name: &str) -> Self {
+        ColumnFamilyConfig {
+            name: name.to_string(),
+            ..Default::default()
         }
     }
 }
@@ -60,17 +128,35 @@ pub struct DBConfig {
     pub index_density: usize,
     pub sstable_single_file: bool,
     pub sstable_dir: String,
+    pub sstable_block_size: usize,
+    pub sstable_block_codec: BlockCodecType,
+    pub sstable_cache_capacity: usize,
+    pub sstable_use_mmap: bool,
+    pub sstable_prefix_extractor: PrefixExtractorType,
     pub lsm_max_level: usize,
+    /// Number of tables a level holds before it's compacted. Under leveled compaction it only applies to the first
+    /// level, whose flushed tables overlap each other, so every read checks all of them and their number has to stay
+    /// bounded no matter how small they are. The deeper levels don't overlap and are bounded by their size instead.
     pub lsm_max_per_level: usize,
+    /// How many times the target size of each level under leveled compaction is bigger than the one above it.
     pub lsm_leveled_amplification: usize,
+    /// Target size in bytes of the second level under leveled compaction.
+    pub lsm_leveled_base_size: usize,
+    pub lsm_tombstone_compaction_ratio: f64,
+    pub lsm_time_window_ms: usize,
+    pub lsm_fifo_max_size: usize,
+    pub lsm_compaction_io_rate_limit: usize,
+    pub lsm_compaction_io_auto_tune: bool,
     pub compaction_enabled: bool,
     pub compaction_algorithm_type: CompactionAlgorithmType,
     pub cache_max_size: usize,
     pub token_bucket_capacity: usize,
     pub token_bucket_refill_rate: usize,
+    pub token_bucket_checkpoint_interval_ms: usize,
     pub use_compression: bool,
     pub use_variable_encoding: bool,
     pub compression_dictionary_path: String,
+    pub column_families: Vec<ColumnFamilyConfig>,
 }
 
 /// Default values for configuration parameters, used if properties are missing in JSON file
@@ -94,17 +180,30 @@ impl Default for DBConfig {
             index_density: 2,
             sstable_single_file: false,
             sstable_dir: "./sstables/".to_string(),
+            sstable_block_size: 4096,
+            sstable_block_codec: BlockCodecType::Lz4,
+            sstable_cache_capacity: 64,
+            sstable_use_mmap: false,
+            sstable_prefix_extractor: PrefixExtractorType::None,
             lsm_max_level: 5,
             lsm_max_per_level: 5,
             lsm_leveled_amplification: 10,
+            lsm_leveled_base_size: 10_485_760,
+            lsm_tombstone_compaction_ratio: 0.5,
+            lsm_time_window_ms: 3_600_000,
+            lsm_fifo_max_size: 1_073_741_824,
+            lsm_compaction_io_rate_limit: 0,
+            lsm_compaction_io_auto_tune: false,
             compaction_enabled: true,
             compaction_algorithm_type: CompactionAlgorithmType::SizeTiered,
             cache_max_size: 5000,
             token_bucket_capacity: 1000,
             token_bucket_refill_rate: 100,
+            token_bucket_checkpoint_interval_ms: 1000,
             use_compression: true,
             use_variable_encoding: true,
             compression_dictionary_path: "./dictionary.bin".to_string(),
+            column_families: vec![],
         }
     }
 }
@@ -117,14 +216,41 @@ impl DBConfig {
         }
     }
 
+    /// Returns the configuration of the given column family. The overrides of the column family are applied and
+    /// its SSTables and compression dictionary are kept in its own directory inside of the SSTable directory.
+    pub fn for_column_family(&self, column_family: &ColumnFamilyConfig) -> DBConfig {
+        let sstable_dir =
+            Path::new(&self.sstable_dir).join(format!("column_family_{}", column_family.name));
+
+        DBConfig {
+            memory_table_type: column_family
+                .memory_table_type
+                .clone()
+                .unwrap_or(self.memory_table_type.clone()),
+            compaction_algorithm_type: column_family
+                .compaction_algorithm_type
+                .unwrap_or(self.compaction_algorithm_type),
+            use_compression: column_family
+                .use_compression
+                .unwrap_or(self.use_compression),
+            compression_dictionary_path: sstable_dir
+                .join("dictionary.bin")
+                .to_string_lossy()
+                .to_string(),
+            sstable_dir: sstable_dir.to_string_lossy().to_string() + "/",
+            column_families: vec![],
+            ..self.clone()
+        }
+    }
+
     /// Loads and returns configuration from JSON file from `file_path`
-    pub fn load(file_path: &str) -> std::io::Result<DBConfig> {
+    pub fn load(file_path: &str) -> db_error::Result<DBConfig> {
         let file = File::open(file_path)?;
         let reader = BufReader::new(file);
 
         match serde_json::from_reader(reader) {
             Ok(data) => Ok(data),
-            Err(e) => Err(Error::new(ErrorKind::InvalidData, e)),
+            Err(e) => Err(Error::Config(e.to_string())),
         }
     }
 
@@ -1,13 +1,16 @@
 mod db_config;
 
+pub use db_config::BlockCodecType;
+pub use db_config::ColumnFamilyConfig;
 pub use db_config::CompactionAlgorithmType;
 pub use db_config::DBConfig;
 pub use db_config::MemoryTableType;
+pub use db_config::PrefixExtractorType;
 
 #[cfg(test)]
 mod tests {
     use super::DBConfig;
-    use crate::db_config::MemoryTableType;
+    use crate::db_config::{ColumnFamilyConfig, CompactionAlgorithmType, MemoryTableType};
 
     #[test]
     fn save_load() {
@@ -37,4 +40,42 @@ mod tests {
         assert_ne!(config1, config3);
         assert_eq!(config2, config3);
     }
+
+    #[test]
+    fn load_invalid_config() {
+        let file_path = "config3.json";
+        std::fs::write(file_path, "{ \"memory_table_capacity\": \"many\" }").unwrap();
+
+        let result = DBConfig::load(file_path);
+        std::fs::remove_file(file_path).unwrap();
+
+        assert!(matches!(result, Err(db_error::Error::Config(_))));
+    }
+
+    #[test]
+    fn column_family_overrides() {
+        let mut config = DBConfig::new();
+        config.sstable_dir = "./sstables/".to_string();
+        config.memory_table_type = MemoryTableType::BTree;
+
+        let mut column_family = ColumnFamilyConfig::new("users");
+        column_family.memory_table_type = Some(MemoryTableType::SkipList);
+        column_family.compaction_algorithm_type = Some(CompactionAlgorithmType::Leveled);
+        config.column_families.push(column_family.clone());
+
+        let family_config = config.for_column_family(&column_family);
+
+        assert_eq!(family_config.memory_table_type, MemoryTableType::SkipList);
+        assert_eq!(
+            family_config.compaction_algorithm_type,
+            CompactionAlgorithmType::Leveled
+        );
+        assert_eq!(family_config.use_compression, config.use_compression);
+        assert_eq!(family_config.sstable_dir, "./sstables/column_family_users/");
+        assert_eq!(
+            family_config.compression_dictionary_path,
+            "./sstables/column_family_users/dictionary.bin"
+        );
+        assert!(family_config.column_families.is_empty());
+    }
 }
@@ -0,0 +1,66 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors returned by the public API of the database, its LSM tree and its write ahead log.
+#[derive(Debug)]
+pub enum Error {
+    /// The token bucket ran out of tokens. The request can be retried once it refills.
+    RateLimited,
+    /// The name is reserved for the internal structures of the database.
+    ReservedKey(String),
+    /// The checksum of a record doesn't match its contents.
+    Corruption {
+        // File that holds the record
+        path: PathBuf,
+        // Offset of the record in the file
+        offset: u64,
+    },
+    /// There is no probabilistic structure of the requested type under the given key.
+    MissingProbabilisticStructure(String),
+    /// There is no column family with the given name.
+    UnknownColumnFamily(String),
+    /// The configuration is invalid or can't be parsed.
+    Config(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RateLimited => write!(f, "Input rate limit exceeded. Please try again later."),
+            Error::ReservedKey(name) => {
+                write!(f, "{} is reserved for the internal structures.", name)
+            }
+            Error::Corruption { path, offset } => write!(
+                f,
+                "Data in {} at offset {} is corrupted.",
+                path.display(),
+                offset
+            ),
+            Error::MissingProbabilisticStructure(key) => {
+                write!(f, "Failed to get probabilistic structure with key {}.", key)
+            }
+            Error::UnknownColumnFamily(name) => write!(f, "Column family {} doesn't exist.", name),
+            Error::Config(message) => write!(f, "Invalid configuration: {}", message),
+            Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,34 @@
+mod db_error;
+
+pub use db_error::Error;
+pub use db_error::Result;
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use std::error::Error as StdError;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[test]
+    fn io_error_conversion() {
+        let error: Error = io::Error::new(io::ErrorKind::NotFound, "missing file").into();
+
+        assert!(matches!(error, Error::Io(_)));
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn corruption_message() {
+        let error = Error::Corruption {
+            path: PathBuf::from("./sstables/sstable_1/SSTable-Data.db"),
+            offset: 42,
+        };
+
+        assert_eq!(
+            "Data in ./sstables/sstable_1/SSTable-Data.db at offset 42 is corrupted.",
+            error.to_string()
+        );
+        assert!(error.source().is_none());
+    }
+}
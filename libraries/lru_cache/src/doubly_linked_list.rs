@@ -1,9 +1,12 @@
 use crate::dll_node::{Entry, Node};
-use std::cell::RefCell;
-use std::rc::Rc;
 
+/// Doubly linked list whose nodes live in a vector and point to each other by index.
+/// Next links point towards the head (newest node) and prev links towards the tail (oldest node).
 #[derive(Debug)]
 pub(crate) struct DoublyLinkedList {
+    nodes: Vec<Option<Node>>,
+    // Indexes of removed nodes that can be reused
+    free: Vec<usize>,
     pub(crate) head: Link,
     pub(crate) tail: Link,
 }
@@ -11,6 +14,8 @@ pub(crate) struct DoublyLinkedList {
 impl DoublyLinkedList {
     pub(crate) fn new() -> Self {
         Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
             head: None,
             tail: None,
         }
@@ -20,73 +25,108 @@ impl DoublyLinkedList {
         self.tail.is_none()
     }
 
-    pub(crate) fn push_head(&mut self, el: Entry) {
-        let node = Rc::new(RefCell::new(Node::new(el)));
-        if let Some(prev_head) = self.head.take() {
-            prev_head.borrow_mut().next = Some(Rc::clone(&node));
-            node.borrow_mut().prev = Some(prev_head);
-            self.head = Some(node);
-        } else {
-            self.head = Some(Rc::clone(&node));
-            self.tail = Some(node);
-        }
+    fn node(&self, index: usize) -> &Node {
+        self.nodes[index]
+            .as_ref()
+            .expect("Index doesn't point to a node in the list")
     }
 
-    pub(crate) fn pop_tail(&mut self) -> Link {
-        if self.is_empty() {
-            return None;
-        }
+    fn node_mut(&mut self, index: usize) -> &mut Node {
+        self.nodes[index]
+            .as_mut()
+            .expect("Index doesn't point to a node in the list")
+    }
 
-        if let Some(prev_tail) = self.tail.take() {
-            if prev_tail.borrow().next.is_none() {
-                self.tail = None;
-                self.head = None;
-                return Some(prev_tail);
-            } else {
-                let new_last = prev_tail.as_ref().borrow().next.clone().unwrap();
-                Rc::clone(&new_last).borrow_mut().prev = None;
-                self.tail = Some(Rc::clone(&new_last));
-                return Some(prev_tail);
+    /// Pushes the element to the head of the list and returns the index of its node.
+    pub(crate) fn push_head(&mut self, el: Entry) -> usize {
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = Some(Node::new(el));
+                index
             }
-        }
+            None => {
+                self.nodes.push(Some(Node::new(el)));
+                self.nodes.len() - 1
+            }
+        };
+        self.link_head(index);
 
-        None
+        index
     }
 
-    fn pop_head(&mut self) -> Link {
-        if self.is_empty() {
-            return None;
-        }
+    /// Removes the node from the tail of the list and returns its element.
+    pub(crate) fn pop_tail(&mut self) -> Option<Entry> {
+        let index = self.tail?;
+        self.unlink(index);
+        self.free.push(index);
+
+        self.nodes[index].take().map(|node| node.el)
+    }
 
-        if let Some(prev_head) = self.head.take() {
-            return if prev_head.borrow().prev.is_none() {
-                self.tail = None;
-                self.head = None;
-                Some(prev_head)
-            } else {
-                let new_first = prev_head.as_ref().borrow().prev.clone().unwrap();
-                Rc::clone(&new_first).borrow_mut().next = None;
-                self.head = Some(Rc::clone(&new_first));
-                Some(prev_head)
-            };
+    /// Moves the node with the given index to the head of the list.
+    pub(crate) fn move_to_head(&mut self, index: usize) {
+        if self.head == Some(index) {
+            return;
         }
 
-        None
+        self.unlink(index);
+        self.link_head(index);
+    }
+
+    pub(crate) fn get(&self, index: usize) -> &Entry {
+        &self.node(index).el
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> &mut Entry {
+        &mut self.node_mut(index).el
+    }
+
+    #[cfg(test)]
+    pub(crate) fn peak_head(&self) -> Option<&Entry> {
+        self.head.map(|index| self.get(index))
     }
 
-    pub fn peak_head(&self) -> Link {
-        if !self.is_empty() {
-            return self.head.clone();
+    #[cfg(test)]
+    pub(crate) fn peak_tail(&self) -> Option<&Entry> {
+        self.tail.map(|index| self.get(index))
+    }
+
+    /// Links a detached node as the new head of the list.
+    fn link_head(&mut self, index: usize) {
+        let prev_head = self.head;
+        {
+            let node = self.node_mut(index);
+            node.prev = prev_head;
+            node.next = None;
         }
 
-        None
+        match prev_head {
+            Some(prev_head) => self.node_mut(prev_head).next = Some(index),
+            None => self.tail = Some(index),
+        }
+        self.head = Some(index);
     }
-}
 
-impl Drop for DoublyLinkedList {
-    fn drop(&mut self) {
-        while self.pop_head().is_some() {}
+    /// Detaches the node from its neighbours, leaving it in the vector.
+    fn unlink(&mut self, index: usize) {
+        if self.is_empty() {
+            return;
+        }
+
+        let (prev, next) = {
+            let node = self.node_mut(index);
+            (node.prev.take(), node.next.take())
+        };
+
+        match prev {
+            Some(prev) => self.node_mut(prev).next = next,
+            None => self.tail = next,
+        }
+        match next {
+            Some(next) => self.node_mut(next).prev = prev,
+            None => self.head = prev,
+        }
     }
 }
 
-pub(crate) type Link = Option<Rc<RefCell<Node>>>;
+pub(crate) type Link = Option<usize>;
@@ -24,7 +24,7 @@ mod tests {
             let newest = lru.get(&i.to_ne_bytes());
             if let Some(element) = newest {
                 if let Some(node) = lru.list.peak_head() {
-                    let actual = node.borrow().el.mem_entry.clone();
+                    let actual = node.mem_entry.clone();
                     assert_eq!(actual, element);
                 }
             }
@@ -35,7 +35,7 @@ mod tests {
             let newest = lru.get(&i.to_ne_bytes());
             if let Some(element) = newest {
                 if let Some(node) = lru.list.peak_head() {
-                    let actual = node.borrow().el.mem_entry.clone();
+                    let actual = node.mem_entry.clone();
                     assert_eq!(actual, element);
                 }
             }
@@ -65,7 +65,7 @@ mod tests {
             let newest = lru.get(key.as_bytes());
             if let Some(element) = newest {
                 if let Some(node) = lru.list.peak_head() {
-                    let actual = node.borrow().el.mem_entry.clone();
+                    let actual = node.mem_entry.clone();
                     assert_eq!(actual, element);
                 }
             }
@@ -78,7 +78,7 @@ mod tests {
             let newest = lru.get(key.as_bytes());
             if let Some(element) = newest {
                 if let Some(node) = lru.list.peak_head() {
-                    let actual = node.borrow().el.mem_entry.clone();
+                    let actual = node.mem_entry.clone();
                     assert_eq!(actual, element);
                 }
             }
@@ -101,14 +101,10 @@ mod tests {
                 );
             }
 
-            let oldest = if !lru.list.tail.is_none() {
-                lru.list.tail.clone()
-            } else {
-                None
-            };
+            let oldest = lru.list.peak_tail().cloned();
 
-            if let Some(node_ptr) = oldest {
-                let memory_entry = node_ptr.borrow().el.mem_entry.clone();
+            if let Some(node) = oldest {
+                let memory_entry = node.mem_entry.clone();
                 assert_eq!(memory_entry, lru.get(&1_u32.to_ne_bytes()).unwrap())
             }
             assert_eq!(capacity, lru.get_size());
@@ -148,4 +144,62 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_get_refreshes_recency() {
+        let mut lru = LRUCache::new(3);
+        for i in 0..3u32 {
+            lru.insert(
+                &i.to_ne_bytes(),
+                Some(MemoryEntry::from(
+                    &(2 * i).to_ne_bytes(),
+                    false,
+                    TimeStamp::Now.get_time(),
+                )),
+            );
+        }
+
+        // 0 becomes the most recently used, so inserting 3 evicts 1
+        assert!(lru.get(&0u32.to_ne_bytes()).is_some());
+        lru.insert(
+            &3u32.to_ne_bytes(),
+            Some(MemoryEntry::from(
+                &6u32.to_ne_bytes(),
+                false,
+                TimeStamp::Now.get_time(),
+            )),
+        );
+
+        assert_eq!(3, lru.get_size());
+        assert!(lru.get(&1u32.to_ne_bytes()).is_none());
+        for i in [0u32, 2, 3] {
+            assert_eq!(
+                <[u8; 4] as Into<Box<[u8]>>>::into((2 * i).to_ne_bytes()),
+                lru.get(&i.to_ne_bytes()).unwrap().get_value()
+            );
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut lru = LRUCache::new(10);
+        for i in 0..5u32 {
+            lru.insert(
+                &i.to_ne_bytes(),
+                Some(MemoryEntry::from(
+                    &i.to_ne_bytes(),
+                    false,
+                    TimeStamp::Now.get_time(),
+                )),
+            );
+        }
+
+        lru.clear();
+        assert_eq!(0, lru.get_size());
+        assert!(lru.get(&0u32.to_ne_bytes()).is_none());
+
+        lru.insert(&7u32.to_ne_bytes(), None);
+        assert_eq!(1, lru.get_size());
+        assert!(lru.get(&7u32.to_ne_bytes()).unwrap().get_tombstone());
+    }
 }
@@ -1,13 +1,12 @@
-use crate::dll_node::{Entry, Node};
+use crate::dll_node::Entry;
 use crate::doubly_linked_list::DoublyLinkedList;
 use segment_elements::{MemoryEntry, TimeStamp};
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
 
 pub struct LRUCache {
     pub(crate) list: DoublyLinkedList,
-    pub(crate) map: HashMap<Box<[u8]>, Rc<RefCell<Node>>>,
+    // Maps keys to indexes of their nodes in the list
+    pub(crate) map: HashMap<Box<[u8]>, usize>,
     pub(crate) size: usize,
     pub(crate) capacity: usize,
 }
@@ -31,53 +30,39 @@ impl LRUCache {
     }
 
     pub fn get(&mut self, key: &[u8]) -> Option<MemoryEntry> {
-        if self.map.contains_key(key) {
-            let node = self.map.get(key);
-            let value = node.unwrap().borrow().el.mem_entry.clone();
-            let prev_node = node.unwrap().as_ref().borrow_mut().prev.take();
-            let next_node = node.unwrap().as_ref().borrow_mut().next.take();
-
-            if prev_node.is_some() {
-                prev_node.as_ref().unwrap().borrow_mut().next = next_node.clone();
-            } else {
-                self.list.tail = next_node.clone();
-            }
-
-            if next_node.is_some() {
-                next_node.as_ref().unwrap().borrow_mut().prev = prev_node;
-            } else {
-                self.list.head = prev_node;
-            }
+        let index = *self.map.get(key)?;
+        self.list.move_to_head(index);
 
-            self.list.push_head(node.unwrap().borrow().el.clone());
-            return Some(value);
-        }
-        None
+        Some(self.list.get(index).mem_entry.clone())
     }
 
     pub fn update(&mut self, key: &[u8], memory_entry: Option<MemoryEntry>) {
-        let entry = get_entry(key, &memory_entry);
-        let node = self.map.get(key);
-        if let Some(node) = node {
-            node.borrow_mut().el = entry;
+        if let Some(&index) = self.map.get(key) {
+            *self.list.get_mut(index) = get_entry(key, &memory_entry);
         }
     }
 
+    /// Removes all cached entries.
+    pub fn clear(&mut self) {
+        self.list = DoublyLinkedList::new();
+        self.map.clear();
+        self.size = 0;
+    }
+
     pub fn insert(&mut self, key: &[u8], memory_entry: Option<MemoryEntry>) {
-        let entry = get_entry(key, &memory_entry);
         if self.map.contains_key(key) {
             self.update(key, memory_entry);
             return;
         }
-        self.list.push_head(entry);
-        let node = self.list.peak_head();
-        self.map.insert(Box::from(key), node.unwrap());
+        let index = self.list.push_head(get_entry(key, &memory_entry));
+        self.map.insert(Box::from(key), index);
         self.size += 1;
 
         if self.size > self.capacity {
-            let popped = self.list.pop_tail();
-            self.map.remove(popped.unwrap().borrow().el.key.as_ref());
-            self.size -= 1;
+            if let Some(popped) = self.list.pop_tail() {
+                self.map.remove(popped.key.as_ref());
+                self.size -= 1;
+            }
         }
     }
 }
@@ -85,12 +70,14 @@ impl LRUCache {
 fn get_entry(key: &[u8], memory_entry: &Option<MemoryEntry>) -> Entry {
     if memory_entry.is_some() {
         let memory_entry = memory_entry.as_ref().unwrap();
-        Entry::from(
+        let mut entry = Entry::from(
             key,
             memory_entry.get_value().as_ref(),
             memory_entry.get_tombstone(),
             TimeStamp::Custom(memory_entry.get_timestamp()),
-        )
+        );
+        entry.mem_entry.set_expiry(memory_entry.get_expiry());
+        entry
     } else {
         Entry::from(key, &[], true, TimeStamp::Now)
     }
@@ -0,0 +1,102 @@
+//! Compares reading SSTables through file handles with reading them from memory-mapped files,
+//! for random point gets and for range scans. Run with `cargo bench -p lsm --bench read_modes`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use db_config::DBConfig;
+use lsm::{Paginator, LSM};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use segment_elements::TimeStamp;
+use std::hint::black_box;
+use tempfile::TempDir;
+
+const KEY_COUNT: u32 = 50_000;
+const SCAN_LEN: u32 = 100;
+
+/// An LSM whose keys are all flushed to SSTables, with the directories it's stored in.
+struct BenchLSM {
+    lsm: LSM,
+    _sstable_dir: TempDir,
+    _write_ahead_log_dir: TempDir,
+}
+
+fn key(i: u32) -> String {
+    format!("key{:08}", i)
+}
+
+/// Fills an LSM with `KEY_COUNT` keys. The cache of read entries is disabled and all tables are kept open,
+/// so every read is served by the SSTables in the given read mode.
+fn setup(use_mmap: bool) -> BenchLSM {
+    let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let mut db_config = DBConfig::default();
+    db_config.memory_table_capacity = 5_000;
+    db_config.compaction_enabled = false;
+    db_config.cache_max_size = 0;
+    db_config.use_compression = false;
+    db_config.sstable_cache_capacity = 1_000;
+    db_config.sstable_use_mmap = use_mmap;
+    db_config.sstable_dir = sstable_dir.path().to_str().unwrap().to_string() + "/";
+    db_config.write_ahead_log_dir = write_ahead_log_dir.path().to_str().unwrap().to_string() + "/";
+
+    let lsm = LSM::new(&db_config).unwrap();
+    for i in 0..KEY_COUNT {
+        let key = key(i);
+        lsm.insert(key.as_bytes(), key.as_bytes(), TimeStamp::Now)
+            .unwrap();
+    }
+    lsm.wait_for_background_work();
+
+    BenchLSM {
+        lsm,
+        _sstable_dir: sstable_dir,
+        _write_ahead_log_dir: write_ahead_log_dir,
+    }
+}
+
+fn read_mode_name(use_mmap: bool) -> &'static str {
+    if use_mmap {
+        "mmap"
+    } else {
+        "file"
+    }
+}
+
+fn point_gets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("point_get");
+    for use_mmap in [false, true] {
+        let bench_lsm = setup(use_mmap);
+        let mut rng = StdRng::seed_from_u64(42);
+        group.bench_function(BenchmarkId::from_parameter(read_mode_name(use_mmap)), |b| {
+            b.iter(|| {
+                let key = key(rng.gen_range(0..KEY_COUNT));
+                black_box(bench_lsm.lsm.get(key.as_bytes()).unwrap());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn range_scans(c: &mut Criterion) {
+    let mut group = c.benchmark_group("range_scan");
+    group.sample_size(10);
+    for use_mmap in [false, true] {
+        let bench_lsm = setup(use_mmap);
+        let mut rng = StdRng::seed_from_u64(42);
+        group.bench_function(BenchmarkId::from_parameter(read_mode_name(use_mmap)), |b| {
+            b.iter(|| {
+                let start = rng.gen_range(0..KEY_COUNT - SCAN_LEN);
+                let (min_key, max_key) = (key(start), key(start + SCAN_LEN - 1));
+                let mut paginator = Paginator::new(&bench_lsm.lsm);
+                black_box(
+                    paginator
+                        .range_scan(min_key.as_bytes(), max_key.as_bytes(), 0, SCAN_LEN as usize)
+                        .unwrap(),
+                );
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, point_gets, range_scans);
+criterion_main!(benches);
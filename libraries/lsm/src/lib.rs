@@ -2,13 +2,18 @@ mod lsm;
 mod mem_pool;
 mod memtable;
 mod sstable;
+#[cfg(test)]
+mod test_utils;
 
 pub use lsm::paginator::Paginator;
+pub use lsm::snapshot::LSMSnapshot;
 pub use lsm::LSM;
 
 #[cfg(test)]
 mod mem_pool_tests {
+    use crate::mem_pool::record_iterator::{LogRecord, RecordIterator};
     use crate::mem_pool::MemoryPool;
+    use crate::test_utils::legacy_fixture;
     use db_config::DBConfig;
     use segment_elements::TimeStamp;
 
@@ -29,7 +34,7 @@ mod mem_pool_tests {
             let key = format!("{}{}", base_key, i.to_string());
             let value = format!("{}{}", base_value, i.to_string());
 
-            mem_pool.insert(key.as_bytes(), value.as_bytes(), timestamp_custom);
+            mem_pool.insert(key.as_bytes(), value.as_bytes(), timestamp_custom, i);
         }
 
         for i in 0..100_000 {
@@ -46,13 +51,48 @@ mod mem_pool_tests {
             assert_eq!(value.as_bytes(), &*get_op);
         }
     }
+
+    #[test]
+    fn test_legacy_log_records() {
+        // The log was written before the files had headers, its records are replayed from the byte index
+        let fixture = legacy_fixture("separate_files");
+        let records: Vec<_> = RecordIterator::new(&fixture.path().join("wal"), None)
+            .unwrap()
+            .map(|record| match record.unwrap() {
+                LogRecord::Single(record) => record,
+                LogRecord::Batch { .. } => panic!("The legacy log has no batches"),
+            })
+            .collect();
+
+        assert_eq!(records.len(), 14);
+        for (record, i) in records[..12].iter().zip(18..30usize) {
+            assert_eq!(record.key.as_ref(), format!("key{:03}", i).as_bytes());
+            assert_eq!(
+                record.value.as_deref(),
+                Some(format!("value{:03}", i).as_bytes())
+            );
+            assert_eq!(record.timestamp, 1_000 + i as u128);
+            assert!(!record.tombstone);
+            assert_eq!(record.expiry, None);
+        }
+        for (record, (key, timestamp)) in records[12..]
+            .iter()
+            .zip([(b"key005", 2_000), (b"key025", 2_001)])
+        {
+            assert_eq!(record.key.as_ref(), key);
+            assert_eq!(record.timestamp, timestamp);
+            assert!(record.tombstone);
+        }
+    }
 }
 
 #[cfg(test)]
 mod paginator_tests {
     use crate::lsm::paginator::Paginator;
+    use crate::sstable::SSTable;
+    use crate::test_utils::{get_config, get_key, TestOptions};
     use crate::LSM;
-    use db_config::{CompactionAlgorithmType, DBConfig};
+    use db_config::{CompactionAlgorithmType, DBConfig, PrefixExtractorType};
     use segment_elements::TimeStamp;
     use std::fs::{create_dir_all, remove_dir_all, remove_file};
     use tempfile::TempDir;
@@ -110,6 +150,8 @@ mod paginator_tests {
             }
         }
 
+        drop(paginator);
+        lsm.wait_for_background_work();
         remove_dir_all(db_config.sstable_dir).expect("Failed to remove sstable dirs");
         remove_dir_all(db_config.write_ahead_log_dir).expect("Failed to remove wal dirs");
         remove_file(db_config.compression_dictionary_path).expect("Failed to remove dictionary");
@@ -165,11 +207,57 @@ mod paginator_tests {
         }
 
         // Clean up
+        drop(paginator);
+        lsm.wait_for_background_work();
         remove_dir_all(db_config.sstable_dir).expect("Failed to remove sstable dirs");
         remove_dir_all(db_config.write_ahead_log_dir).expect("Failed to remove wal dirs");
         remove_file(db_config.compression_dictionary_path).expect("Failed to remove dictionary");
     }
 
+    #[test]
+    fn test_prefix_scan_empty_prefix() {
+        let mut db_config = DBConfig::default();
+        db_config.memory_table_pool_num = 2;
+        db_config.memory_table_capacity = 10;
+        db_config.sstable_dir = TempDir::new()
+            .expect("Failed to create temp directory")
+            .path()
+            .to_str()
+            .unwrap()
+            .to_string();
+        db_config.write_ahead_log_dir = TempDir::new()
+            .expect("Failed to create temp directory")
+            .path()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let mut lsm = LSM::new(&db_config).unwrap();
+
+        // Enough keys to be spread across sstables and the memory tables
+        for i in 0..100u32 {
+            let key = format!("key{:03}", i);
+            lsm.insert(key.as_bytes(), key.as_bytes(), TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+
+        let mut paginator = Paginator::new(&mut lsm);
+
+        // An empty prefix matches every key
+        let result_page = paginator
+            .prefix_scan(&[], 0, 200)
+            .expect("Failed to get pagination result");
+        let keys: Vec<_> = result_page.into_iter().map(|(key, _)| key).collect();
+        let expected: Vec<_> = (0..100u32)
+            .map(|i| format!("key{:03}", i).into_bytes().into_boxed_slice())
+            .collect();
+        assert_eq!(keys, expected);
+
+        drop(paginator);
+        lsm.wait_for_background_work();
+        remove_dir_all(db_config.sstable_dir).expect("Failed to remove sstable dirs");
+        remove_dir_all(db_config.write_ahead_log_dir).expect("Failed to remove wal dirs");
+    }
+
     #[test]
     fn test_prefix_scan_iter() {
         let mut db_config = DBConfig::default();
@@ -251,6 +339,8 @@ mod paginator_tests {
             assert_eq!(&*result, key_str.as_bytes());
         }
 
+        drop(paginator);
+        lsm.wait_for_background_work();
         remove_dir_all(db_config.sstable_dir).expect("Failed to remove sstable dirs");
         remove_dir_all(db_config.write_ahead_log_dir).expect("Failed to remove wal dirs");
         remove_file(db_config.compression_dictionary_path).expect("Failed to remove dictionary");
@@ -359,6 +449,8 @@ mod paginator_tests {
             );
         }
 
+        drop(paginator);
+        lsm.wait_for_background_work();
         remove_dir_all(db_config.sstable_dir).expect("Failed to remove sstable dirs");
         remove_dir_all(db_config.write_ahead_log_dir).expect("Failed to remove wal dirs");
         remove_file(db_config.compression_dictionary_path).expect("Failed to remove dictionary");
@@ -415,6 +507,8 @@ mod paginator_tests {
             );
         }
 
+        drop(paginator);
+        lsm.wait_for_background_work();
         remove_dir_all(db_config.sstable_dir).expect("Failed to remove sstable dirs");
         remove_dir_all(db_config.write_ahead_log_dir).expect("Failed to remove wal dirs");
         remove_file(db_config.compression_dictionary_path).expect("Failed to remove dictionary");
@@ -495,20 +589,331 @@ mod paginator_tests {
             assert_eq!(&*result, key_str.as_bytes());
         }
 
+        drop(paginator);
+        lsm.wait_for_background_work();
         remove_dir_all(db_config.sstable_dir).expect("Failed to remove sstable dirs");
         remove_dir_all(db_config.write_ahead_log_dir).expect("Failed to remove wal dirs");
         remove_file(db_config.compression_dictionary_path).expect("Failed to remove dictionary");
     }
+
+    #[test]
+    fn test_snapshot_range_scan() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        let mut lsm = LSM::new(&db_config).unwrap();
+
+        for i in 0..50 {
+            lsm.insert(get_key(i).as_bytes(), b"old", TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+
+        let snapshot = lsm.snapshot();
+
+        for i in 0..50 {
+            if i % 2 == 0 {
+                lsm.insert(get_key(i).as_bytes(), b"new", TimeStamp::Now)
+                    .expect("Failed to insert into lsm");
+            } else {
+                lsm.delete(get_key(i).as_bytes(), TimeStamp::Now)
+                    .expect("Failed to delete from lsm");
+            }
+        }
+        for i in 50..100 {
+            lsm.insert(get_key(i).as_bytes(), b"new", TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+
+        let mut paginator = Paginator::with_snapshot(&mut lsm, &snapshot);
+        for i in 0..50 {
+            let (key, entry) = paginator
+                .range_iterate_next(get_key(0).as_bytes(), get_key(99).as_bytes())
+                .expect("Failed to iterate to next entry")
+                .expect("Failed to get memory entry");
+            assert_eq!(&*key, get_key(i).as_bytes());
+            assert_eq!(&*entry.get_value(), b"old");
+        }
+        assert!(paginator
+            .range_iterate_next(get_key(0).as_bytes(), get_key(99).as_bytes())
+            .expect("Failed to iterate to next entry")
+            .is_none());
+
+        drop(paginator);
+        drop(snapshot);
+
+        lsm.wait_for_background_work();
+    }
+
+    /// Returns the configuration of the tenant tests, whose sstables hold 49 keys each and are only merged by hand.
+    fn get_tenants_config(
+        sstable_dir: &TempDir,
+        write_ahead_log_dir: &TempDir,
+        in_single_file: bool,
+        prefix_extractor: PrefixExtractorType,
+    ) -> DBConfig {
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                prefix_extractor,
+                ..TestOptions::default()
+            },
+        );
+        db_config.memory_table_capacity = 50;
+        db_config.compaction_enabled = false;
+        db_config.cache_max_size = 0;
+        db_config.use_compression = true;
+        db_config.sstable_single_file = in_single_file;
+        db_config
+    }
+
+    /// Inserts 50 keys for each of 10 tenants one tenant after another, so each sstable holds few tenants.
+    fn fill_tenants(lsm: &LSM) {
+        for tenant in 0..10u32 {
+            for item in 0..50u32 {
+                let key = format!("tenant{}:item{:02}", tenant, item);
+                lsm.insert(
+                    key.as_bytes(),
+                    format!("value{}", item).as_bytes(),
+                    TimeStamp::Now,
+                )
+                .unwrap();
+            }
+        }
+        lsm.wait_for_background_work();
+    }
+
+    /// Returns the keys of a prefix scan and of iterating over the same prefix.
+    fn scan_prefix(lsm: &LSM, prefix: &[u8]) -> (Vec<Box<[u8]>>, Vec<Box<[u8]>>) {
+        let mut paginator = Paginator::new(lsm);
+        let scanned = paginator
+            .prefix_scan(prefix, 0, 500)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+
+        let mut paginator = Paginator::new(lsm);
+        let mut iterated = Vec::new();
+        while let Some((key, _)) = paginator.prefix_iterate_next(prefix).unwrap() {
+            iterated.push(key);
+        }
+
+        (scanned, iterated)
+    }
+
+    /// Returns the number of sstables whose prefix filter doesn't rule out the prefix.
+    fn tables_with_prefix(
+        sstable_dir: &TempDir,
+        lsm: &LSM,
+        in_single_file: bool,
+        prefix: &[u8],
+        prefix_extractor: PrefixExtractorType,
+    ) -> usize {
+        lsm.table_directories()
+            .into_iter()
+            .filter(|directory| {
+                SSTable::open(sstable_dir.path().join(directory), in_single_file)
+                    .unwrap()
+                    .may_contain_prefix(prefix, prefix_extractor)
+                    .unwrap()
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_prefix_scans_skip_tables() {
+        for in_single_file in [false, true] {
+            let scans: Vec<_> = [
+                PrefixExtractorType::None,
+                PrefixExtractorType::Delimiter(b':'),
+            ]
+            .into_iter()
+            .map(|prefix_extractor| {
+                let (sstable_dir, write_ahead_log_dir) =
+                    (TempDir::new().unwrap(), TempDir::new().unwrap());
+                let db_config = get_tenants_config(
+                    &sstable_dir,
+                    &write_ahead_log_dir,
+                    in_single_file,
+                    prefix_extractor,
+                );
+                let lsm = LSM::new(&db_config).unwrap();
+                fill_tenants(&lsm);
+
+                let table_count = lsm.table_directories().len();
+                assert!(table_count > 5);
+                let tables = tables_with_prefix(
+                    &sstable_dir,
+                    &lsm,
+                    in_single_file,
+                    b"tenant3:",
+                    prefix_extractor,
+                );
+                match prefix_extractor {
+                    PrefixExtractorType::None => assert_eq!(tables, table_count),
+                    _ => assert!(tables < 3),
+                }
+
+                [&b"tenant3:"[..], b"tenant7:item1", b"tenant", b"tenant42:"]
+                    .into_iter()
+                    .map(|prefix| scan_prefix(&lsm, prefix))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+            for (scanned, iterated) in &scans[1] {
+                assert_eq!(scanned, iterated);
+            }
+            assert_eq!(scans[1][0].0.len(), 50);
+            assert!(scans[1][0].0.iter().all(|key| key.starts_with(b"tenant3:")));
+            assert_eq!(scans[1][1].0.len(), 10);
+            assert_eq!(scans[1][2].0.len(), 500);
+            assert!(scans[1][3].0.is_empty());
+            assert_eq!(scans[0], scans[1]);
+        }
+    }
+
+    #[test]
+    fn test_scan_after_last_key() {
+        for (use_compression, sstable_single_file, use_mmap) in [
+            (false, false, false),
+            (true, true, false),
+            (false, true, true),
+        ] {
+            let (sstable_dir, write_ahead_log_dir) =
+                (TempDir::new().unwrap(), TempDir::new().unwrap());
+            let mut db_config = get_config(
+                sstable_dir.path(),
+                write_ahead_log_dir.path(),
+                TestOptions {
+                    use_mmap,
+                    ..TestOptions::default()
+                },
+            );
+            db_config.use_compression = use_compression;
+            db_config.sstable_single_file = sstable_single_file;
+            let lsm = LSM::new(&db_config).unwrap();
+
+            for i in 0..100 {
+                lsm.insert(get_key(i).as_bytes(), b"value", TimeStamp::Now)
+                    .unwrap();
+            }
+            for i in (0..100).step_by(7) {
+                lsm.delete(get_key(i).as_bytes(), TimeStamp::Now).unwrap();
+            }
+            lsm.wait_for_background_work();
+            lsm.insert(b"other", b"value", TimeStamp::Now).unwrap();
+
+            // Each page starts after the last key of the previous one, also when that key gets deleted in between
+            let mut paginator = Paginator::new(&lsm);
+            let mut keys = Vec::new();
+            let mut last_key: Option<Box<[u8]>> = None;
+            loop {
+                let page = paginator
+                    .prefix_scan_after(b"key_", last_key.as_deref(), 6)
+                    .unwrap();
+                keys.extend(page.iter().map(|(key, _)| key.clone()));
+                match page.last() {
+                    Some((key, _)) if page.len() == 6 => last_key = Some(key.clone()),
+                    _ => break,
+                }
+                if keys.len() == 12 {
+                    lsm.delete(&keys[11], TimeStamp::Now).unwrap();
+                }
+            }
+            let expected: Vec<Box<[u8]>> = (0..100)
+                .filter(|i| i % 7 != 0)
+                .map(|i| Box::from(get_key(i).as_bytes()))
+                .collect();
+            assert_eq!(keys, expected);
+
+            let mut keys = Vec::new();
+            let mut last_key: Option<Box<[u8]>> = None;
+            loop {
+                let page = paginator
+                    .range_scan_after(
+                        get_key(30).as_bytes(),
+                        get_key(70).as_bytes(),
+                        last_key.as_deref(),
+                        4,
+                    )
+                    .unwrap();
+                keys.extend(page.iter().map(|(key, _)| key.clone()));
+                match page.last() {
+                    Some((key, _)) if page.len() == 4 => last_key = Some(key.clone()),
+                    _ => break,
+                }
+            }
+            let expected: Vec<Box<[u8]>> = (30..=70)
+                .filter(|i| i % 7 != 0)
+                .map(|i| Box::from(get_key(i).as_bytes()))
+                .collect();
+            assert_eq!(keys, expected);
+
+            // A key before the prefix or the range doesn't move the start of the scan
+            let page = paginator.prefix_scan_after(b"key_", Some(b"a"), 2).unwrap();
+            assert_eq!(&*page[0].0, get_key(1).as_bytes());
+            let page = paginator
+                .range_scan_after(
+                    get_key(30).as_bytes(),
+                    get_key(70).as_bytes(),
+                    Some(b"a"),
+                    2,
+                )
+                .unwrap();
+            assert_eq!(&*page[0].0, get_key(30).as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_prefix_filter_after_compaction() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let prefix_extractor = PrefixExtractorType::FixedLength(7);
+        let db_config =
+            get_tenants_config(&sstable_dir, &write_ahead_log_dir, false, prefix_extractor);
+        let lsm = LSM::new(&db_config).unwrap();
+        fill_tenants(&lsm);
+        let scanned = scan_prefix(&lsm, b"tenant5");
+
+        // The merged table has a prefix filter over the prefixes of all merged tables
+        lsm.compact_range(None, None).unwrap();
+        assert_eq!(lsm.table_directories().len(), 1);
+        for tenant in 0..10 {
+            let prefix = format!("tenant{}", tenant);
+            assert_eq!(
+                tables_with_prefix(
+                    &sstable_dir,
+                    &lsm,
+                    false,
+                    prefix.as_bytes(),
+                    prefix_extractor
+                ),
+                1
+            );
+        }
+        assert_eq!(
+            tables_with_prefix(&sstable_dir, &lsm, false, b"tenanx5", prefix_extractor),
+            0
+        );
+        assert_eq!(scan_prefix(&lsm, b"tenant5"), scanned);
+        assert_eq!(scanned.0.len(), 50);
+    }
 }
 
 #[cfg(test)]
 mod lsm_wal_tests {
     use crate::LSM;
-    use db_config::DBConfig;
-    use segment_elements::TimeStamp;
+    use db_config::{ColumnFamilyConfig, DBConfig};
+    use segment_elements::{TimeStamp, WriteBatch, FILE_HEADER_LEN};
     use std::fs;
     use std::fs::{read_dir, remove_dir_all, remove_file};
     use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use write_ahead_log::WriteAheadLog;
 
     fn prepare_dirs(dbconfig: &DBConfig) {
         match read_dir(&dbconfig.write_ahead_log_dir) {
@@ -526,7 +931,7 @@ mod lsm_wal_tests {
             Ok(dir) => dir
                 .map(|dir_entry| dir_entry.unwrap().path())
                 .filter(|dir| dir.file_name().unwrap() != ".keep")
-                .for_each(|dir| remove_dir_all(dir).unwrap_or(())),
+                .for_each(|path| remove_dir_all(&path).or_else(|_| remove_file(&path)).unwrap_or(())),
             Err(_) => (),
         }
 
@@ -547,14 +952,15 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..20_000u32 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
                 .expect("IO error");
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..20_000u32 {
             println!("{i}");
@@ -580,7 +986,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         let key = "test_key";
 
@@ -594,7 +1000,8 @@ mod lsm_wal_tests {
         lsm.insert(&10u32.to_ne_bytes(), &20u32.to_ne_bytes(), TimeStamp::Now)
             .expect("IO error");
 
-        let mut new_lsm = LSM::load_from_dir(&config).unwrap();
+        drop(lsm);
+        let new_lsm = LSM::load_from_dir(&config).unwrap();
 
         assert_eq!(
             new_lsm.get(key.as_bytes()).unwrap().unwrap(),
@@ -624,7 +1031,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..5u128 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
@@ -637,10 +1044,56 @@ mod lsm_wal_tests {
             .filter(|file| file.file_name().unwrap() != ".keep")
             .filter(|file| file.extension().unwrap() == "log")
         {
-            assert!(fs::metadata(Path::new(&file)).unwrap().len() <= 50);
+            assert!(fs::metadata(Path::new(&file)).unwrap().len() <= 50 + FILE_HEADER_LEN as u64);
+        }
+
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
+
+        for i in 0..5u128 {
+            assert_eq!(
+                load_lsm.get(&i.to_ne_bytes()).unwrap(),
+                Some(Box::from((i * 2).to_ne_bytes()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_wal_legacy_files_reconstruction() {
+        let mut config = DBConfig::default();
+        config.sstable_dir = "sstable_wal_test/".to_string();
+        config.sstable_dir += "test_wal_legacy_files_reconstruction/";
+        config.write_ahead_log_dir = "wal_wal_test/".to_string();
+        config.write_ahead_log_dir += "test_wal_legacy_files_reconstruction/";
+        config.compression_dictionary_path = "wal_wal_test/dict/test_wal_legacy_files_reconstruction/dictionary.bin".to_string();
+        config.memory_table_capacity = 10;
+        config.memory_table_pool_num = 3;
+        config.write_ahead_log_size = 50;
+
+        prepare_dirs(&config);
+
+        let lsm = LSM::new(&config).unwrap();
+
+        for i in 0..5u128 {
+            lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
+                .expect("IO error");
+        }
+
+        drop(lsm);
+
+        // Files written before the headers were added start right with their content
+        for file in read_dir(&config.write_ahead_log_dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .filter(|file| file.file_name().unwrap() != ".keep")
+        {
+            let bytes = fs::read(&file).unwrap();
+            if bytes.len() >= FILE_HEADER_LEN {
+                fs::write(&file, &bytes[FILE_HEADER_LEN..]).unwrap();
+            }
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..5u128 {
             assert_eq!(
@@ -664,7 +1117,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..100u128 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
@@ -677,10 +1130,13 @@ mod lsm_wal_tests {
             .filter(|file| file.file_name().unwrap() != ".keep")
             .filter(|file| file.extension().unwrap() == "log")
         {
-            assert!(fs::metadata(Path::new(&file)).unwrap().len() <= 69 * 3);
+            assert!(
+                fs::metadata(Path::new(&file)).unwrap().len() <= 69 * 3 + FILE_HEADER_LEN as u64
+            );
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..100u128 {
             assert_eq!(
@@ -704,7 +1160,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..10u128 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
@@ -717,10 +1173,11 @@ mod lsm_wal_tests {
             .filter(|file| file.file_name().unwrap() != ".keep")
             .filter(|file| file.extension().unwrap() == "log")
         {
-            assert!(fs::metadata(Path::new(&file)).unwrap().len() <= 10);
+            assert!(fs::metadata(Path::new(&file)).unwrap().len() <= 10 + FILE_HEADER_LEN as u64);
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..10u128 {
             assert_eq!(
@@ -745,7 +1202,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..10u128 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
@@ -758,10 +1215,11 @@ mod lsm_wal_tests {
             .filter(|file| file.file_name().unwrap() != ".keep")
             .filter(|file| file.extension().unwrap() == "log")
         {
-            assert!(fs::metadata(Path::new(&file)).unwrap().len() <= 200);
+            assert!(fs::metadata(Path::new(&file)).unwrap().len() <= 200 + FILE_HEADER_LEN as u64);
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..10u128 {
             assert_eq!(
@@ -786,14 +1244,16 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..30000u128 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
                 .expect("IO error");
         }
+        lsm.wait_for_background_work();
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 30000u128 - 84..30000u128 {
             assert_eq!(
@@ -817,12 +1277,13 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..30000u128 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
                 .expect("IO error");
         }
+        lsm.wait_for_background_work();
 
         assert!(
             read_dir(&config.write_ahead_log_dir)
@@ -849,7 +1310,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         let big_data = "老";
 
@@ -859,7 +1320,8 @@ mod lsm_wal_tests {
                 .expect("IO error");
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..100 {
             let big_input = big_data.repeat(i);
@@ -884,14 +1346,15 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..53u8 {
             lsm.insert(&i.to_ne_bytes(), &1_u128.to_ne_bytes(), TimeStamp::Now)
                 .expect("IO error");
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..53u8 {
             assert_eq!(
@@ -917,7 +1380,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..3000u128 {
             lsm.insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
@@ -927,7 +1390,8 @@ mod lsm_wal_tests {
         let mut config_changed = config.clone();
         config_changed.memory_table_capacity = 10;
 
-        let mut lsm_changed = LSM::load_from_dir(&config_changed).expect("IO error");
+        drop(lsm);
+        let lsm_changed = LSM::load_from_dir(&config_changed).expect("IO error");
 
         for i in 0..3000u128 {
             println!("{i}");
@@ -936,6 +1400,7 @@ mod lsm_wal_tests {
                 Some(Box::from((i * 2).to_ne_bytes()))
             );
         }
+        lsm_changed.wait_for_background_work();
 
         assert!(
             read_dir(&config.write_ahead_log_dir)
@@ -962,14 +1427,15 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..20_000u32 {
             lsm.delete(&i.to_ne_bytes(), TimeStamp::Now)
                 .expect("IO error");
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..20_000u32 {
             println!("{i}");
@@ -991,7 +1457,7 @@ mod lsm_wal_tests {
 
         prepare_dirs(&config);
 
-        let mut lsm = LSM::new(&config).unwrap();
+        let lsm = LSM::new(&config).unwrap();
 
         for i in 0..10000u32 {
             if i % 2 == 0 {
@@ -1012,7 +1478,8 @@ mod lsm_wal_tests {
             }
         }
 
-        let mut load_lsm = LSM::load_from_dir(&config).expect("IO error");
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
 
         for i in 0..10000u32 {
             println!("{i}");
@@ -1026,17 +1493,177 @@ mod lsm_wal_tests {
             }
         }
     }
+
+    #[test]
+    fn test_wal_batch_reconstruction() {
+        let mut config = DBConfig::default();
+        config.sstable_dir = "sstable_wal_test/".to_string();
+        config.sstable_dir += "test_wal_batch_reconstruction/";
+        config.write_ahead_log_dir = "wal_wal_test/".to_string();
+        config.write_ahead_log_dir += "test_wal_batch_reconstruction/";
+        config.compression_dictionary_path = "wal_wal_test/dict/test_wal_batch_reconstruction/dictionary.bin".to_string();
+        config.memory_table_capacity = 1000;
+        config.write_ahead_log_num_of_logs = 1000;
+        config.memory_table_pool_num = 20;
+
+        prepare_dirs(&config);
+
+        let lsm = LSM::new(&config).unwrap();
+
+        for i in 0..1000u32 {
+            let mut batch = WriteBatch::new();
+            for j in 0..10u32 {
+                let key = i * 10 + j;
+                batch.insert(&key.to_ne_bytes(), &(key * 2).to_ne_bytes());
+            }
+            // Deletes every odd key from the previous batch
+            if i > 0 {
+                for j in (1..10u32).step_by(2) {
+                    batch.delete(&((i - 1) * 10 + j).to_ne_bytes());
+                }
+            }
+            lsm.write_batch(&batch, TimeStamp::Now).expect("IO error");
+        }
+
+        drop(lsm);
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
+
+        for key in 0..10_000u32 {
+            if key % 2 == 1 && key < 9990 {
+                assert_eq!(load_lsm.get(&key.to_ne_bytes()).unwrap(), None);
+            } else {
+                assert_eq!(
+                    load_lsm.get(&key.to_ne_bytes()).unwrap(),
+                    Some(Box::from((key * 2).to_ne_bytes()))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_wal_torn_batch_not_applied() {
+        let mut config = DBConfig::default();
+        config.sstable_dir = "sstable_wal_test/".to_string();
+        config.sstable_dir += "test_wal_torn_batch_not_applied/";
+        config.write_ahead_log_dir = "wal_wal_test/".to_string();
+        config.write_ahead_log_dir += "test_wal_torn_batch_not_applied/";
+        config.compression_dictionary_path = "wal_wal_test/dict/test_wal_torn_batch_not_applied/dictionary.bin".to_string();
+        config.memory_table_capacity = 1000;
+        config.write_ahead_log_num_of_logs = 1000;
+        config.memory_table_pool_num = 20;
+
+        prepare_dirs(&config);
+
+        let lsm = LSM::new(&config).unwrap();
+
+        lsm.insert(&0u32.to_ne_bytes(), &0u32.to_ne_bytes(), TimeStamp::Now)
+            .expect("IO error");
+
+        let mut batch = WriteBatch::new();
+        batch.delete(&0u32.to_ne_bytes());
+        for key in 1..100u32 {
+            batch.insert(&key.to_ne_bytes(), &(key * 2).to_ne_bytes());
+        }
+        lsm.write_batch(&batch, TimeStamp::Now).expect("IO error");
+        lsm.finalize();
+
+        // Simulate a crash in the middle of writing the batch by cutting off the end of the last log
+        let mut logs: Vec<_> = read_dir(&config.write_ahead_log_dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .filter(|file| file.extension().unwrap() == "log")
+            .collect();
+        logs.sort();
+        let last_log = fs::OpenOptions::new()
+            .write(true)
+            .open(logs.last().unwrap())
+            .unwrap();
+        let len = last_log.metadata().unwrap().len();
+        last_log.set_len(len - 10).unwrap();
+
+        let load_lsm = LSM::load_from_dir(&config).expect("IO error");
+
+        assert_eq!(
+            load_lsm.get(&0u32.to_ne_bytes()).unwrap(),
+            Some(Box::from(0u32.to_ne_bytes()))
+        );
+        for key in 1..100u32 {
+            assert_eq!(load_lsm.get(&key.to_ne_bytes()).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_wal_shared_by_column_families() {
+        let mut config = DBConfig::default();
+        config.sstable_dir = "sstable_wal_test/".to_string();
+        config.sstable_dir += "test_wal_shared_by_column_families/";
+        config.write_ahead_log_dir = "wal_wal_test/".to_string();
+        config.write_ahead_log_dir += "test_wal_shared_by_column_families/";
+        config.compression_dictionary_path = "wal_wal_test/dict/test_wal_shared_by_column_families/dictionary.bin".to_string();
+        config.memory_table_capacity = 10;
+        config.memory_table_pool_num = 2;
+        config.write_ahead_log_size = 500;
+        let users_config = config.for_column_family(&ColumnFamilyConfig::new("users"));
+
+        prepare_dirs(&config);
+        remove_dir_all(&users_config.sstable_dir).ok();
+
+        let wal = Arc::new(Mutex::new(WriteAheadLog::from_dir(&config).unwrap()));
+        let lsm = LSM::new_column_family(&config, None, Arc::clone(&wal)).unwrap();
+        let users_lsm =
+            LSM::new_column_family(&users_config, Some("users"), Arc::clone(&wal)).unwrap();
+
+        // The record of the default column family is the oldest one and isn't flushed,
+        // so the log can't be removed when the other column family flushes its tables
+        lsm.insert(b"default_key", b"default_value", TimeStamp::Now)
+            .expect("IO error");
+        for i in 0..1000u32 {
+            users_lsm
+                .insert(&i.to_ne_bytes(), &(i * 2).to_ne_bytes(), TimeStamp::Now)
+                .expect("IO error");
+        }
+
+        drop(lsm);
+        drop(users_lsm);
+        drop(wal);
+
+        let wal = Arc::new(Mutex::new(WriteAheadLog::from_dir(&config).unwrap()));
+        let lsm = LSM::load_column_family(&config, None, Arc::clone(&wal)).expect("IO error");
+        let users_lsm = LSM::load_column_family(&users_config, Some("users"), Arc::clone(&wal))
+            .expect("IO error");
+
+        assert_eq!(
+            lsm.get(b"default_key").unwrap().as_deref(),
+            Some(&b"default_value"[..])
+        );
+        assert_eq!(users_lsm.get(b"default_key").unwrap(), None);
+        for i in 0..1000u32 {
+            assert_eq!(
+                users_lsm.get(&i.to_ne_bytes()).unwrap(),
+                Some(Box::from((i * 2).to_ne_bytes()))
+            );
+            assert_eq!(lsm.get(&i.to_ne_bytes()).unwrap(), None);
+        }
+
+        users_lsm.wait_for_background_work();
+        remove_dir_all(&users_config.sstable_dir).ok();
+    }
 }
 
 #[cfg(test)]
 mod sstable_tests {
     use crate::memtable::MemoryTable;
-    use crate::sstable::SSTable;
-    use compression::CompressionDictionary;
-    use db_config::{DBConfig, MemoryTableType};
-    use segment_elements::TimeStamp;
-    use std::fs::{create_dir_all, remove_dir_all};
+    use crate::sstable::table_properties::{FORMAT_VERSION, PROPERTIES_FOOTER_LEN};
+    use crate::sstable::{KeyRange, MergeContext, SSTable, TableOptions};
+    use crate::test_utils::{get_config, get_key, legacy_fixture, TestOptions};
+    use compression::{Codec, CompressionDictionary, Lz4Codec, NoneCodec};
+    use db_config::{DBConfig, MemoryTableType, PrefixExtractorType};
+    use db_error::Error;
+    use merkle_tree::merkle_tree::MerkleTree;
+    use segment_elements::{MemoryEntry, TimeStamp, FILE_HEADER_LEN};
+    use std::fs::{create_dir_all, read, read_dir, remove_dir_all};
     use std::path::PathBuf;
+    use std::sync::Mutex;
     use tempfile::TempDir;
 
     // Helper function to get default config and inner mem of memory type
@@ -1056,6 +1683,20 @@ mod sstable_tests {
         )
     }
 
+    // Helper function to get the options of the flushed and merged tables
+    fn table_options(
+        summary_density: usize,
+        index_density: usize,
+        use_variable_encoding: bool,
+    ) -> TableOptions {
+        TableOptions {
+            summary_density,
+            index_density,
+            use_variable_encoding,
+            ..TableOptions::from(&DBConfig::default())
+        }
+    }
+
     // Helper function to set up the test environment
     fn setup_test_environment(
         mem_table_type: &MemoryTableType,
@@ -1099,15 +1740,113 @@ mod sstable_tests {
     }
 
     #[test]
-    fn test_flushing_uncompressed_no_variable_encoding() {
-        for range in (1..=1_000).step_by(99) {
-            for mem_table_type in &[
-                MemoryTableType::SkipList,
-                MemoryTableType::HashMap,
-                MemoryTableType::BTree,
-            ] {
-                check_flushed_table(true, &mem_table_type.clone(), range, false, false);
-                check_flushed_table(false, &mem_table_type.clone(), range, false, false);
+    fn test_corrupted_entry() {
+        let (temp_dir, mut mem_table, summary_density, index_density) =
+            setup_test_environment(&MemoryTableType::SkipList, false);
+        insert_test_data(&mut mem_table, 10);
+
+        let mut sstable = SSTable::open(temp_dir.path().to_path_buf(), false)
+            .expect("Failed to open SSTable");
+        sstable
+            .flush(
+                &mem_table,
+                // Each entry is stored uncompressed in its own block
+                TableOptions {
+                    block_size: 1,
+                    codec: &NoneCodec,
+                    ..table_options(summary_density, index_density, false)
+                },
+                &Mutex::new(None),
+                None,
+            )
+            .expect("Failed to flush sstable");
+
+        // Flip the last byte of the value of the last key, which is followed by the block index of 10 blocks,
+        // the length of the data and the number of blocks, all of them 8 bytes long
+        let data_path = temp_dir.path().join("SSTable-Data.db");
+        let mut data = std::fs::read(&data_path).unwrap();
+        let value_end = data.len() - 11 * 2 * 8;
+        data[value_end - 1] ^= 0xFF;
+        std::fs::write(&data_path, data).unwrap();
+
+        let mut sstable = SSTable::open(temp_dir.path().to_path_buf(), false)
+            .expect("Failed to open SSTable");
+        assert!(sstable
+            .get("test_key_0".as_bytes(), index_density, &mut None, false)
+            .unwrap()
+            .is_some());
+        match sstable.get("test_key_9".as_bytes(), index_density, &mut None, false) {
+            Err(Error::Corruption { path, offset }) => {
+                assert_eq!(path, data_path);
+                assert!(offset > 0);
+            }
+            _ => panic!("Expected a corruption error"),
+        }
+    }
+
+    #[test]
+    fn test_corrupted_compressed_block() {
+        let (temp_dir, mut mem_table, summary_density, index_density) =
+            setup_test_environment(&MemoryTableType::SkipList, false);
+        insert_test_data(&mut mem_table, 10);
+
+        let mut sstable = SSTable::open(temp_dir.path().to_path_buf(), false)
+            .expect("Failed to open SSTable");
+        sstable
+            .flush(
+                &mem_table,
+                // Each entry is compressed in its own block
+                TableOptions {
+                    block_size: 1,
+                    codec: &Lz4Codec,
+                    ..table_options(summary_density, index_density, false)
+                },
+                &Mutex::new(None),
+                None,
+            )
+            .expect("Failed to flush sstable");
+
+        // Overwrite the compressed bytes of the last block, which follow its codec id. The last block ends where
+        // the block index of 10 blocks starts, and its offset in the data part is the last one in the block index
+        let data_path = temp_dir.path().join("SSTable-Data.db");
+        let mut data = std::fs::read(&data_path).unwrap();
+        let block_index_offset = data.len() - 11 * 2 * 8;
+        let last_index_entry = block_index_offset + 9 * 2 * 8;
+        let physical_offset = u64::from_le_bytes(
+            data[last_index_entry + 8..last_index_entry + 16]
+                .try_into()
+                .unwrap(),
+        );
+        let block_offset = FILE_HEADER_LEN + physical_offset as usize;
+        data[block_offset + 1..block_index_offset].fill(0xFF);
+        std::fs::write(&data_path, data).unwrap();
+
+        // The block fails to decompress, so the key is reported as corrupted instead of missing
+        let mut sstable =
+            SSTable::open(temp_dir.path().to_path_buf(), false).expect("Failed to open SSTable");
+        assert!(sstable
+            .get("test_key_0".as_bytes(), index_density, &mut None, false)
+            .unwrap()
+            .is_some());
+        match sstable.get("test_key_9".as_bytes(), index_density, &mut None, false) {
+            Err(Error::Corruption { path, offset }) => {
+                assert_eq!(path, data_path);
+                assert_eq!(offset, block_offset as u64);
+            }
+            result => panic!("Expected a corruption error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_flushing_uncompressed_no_variable_encoding() {
+        for range in (1..=1_000).step_by(99) {
+            for mem_table_type in &[
+                MemoryTableType::SkipList,
+                MemoryTableType::HashMap,
+                MemoryTableType::BTree,
+            ] {
+                check_flushed_table(true, &mem_table_type.clone(), range, false, false);
+                check_flushed_table(false, &mem_table_type.clone(), range, false, false);
             }
         }
     }
@@ -1172,17 +1911,15 @@ mod sstable_tests {
         // Create compression dict if required
         let compression_dict_dir =
             tempfile::tempdir().expect("Failed to create temporary directory");
-        let mut compression_dictionary =
-            get_compression_dict(&compression_dict_dir, use_compression);
+        let compression_dictionary =
+            Mutex::new(get_compression_dict(&compression_dict_dir, use_compression));
 
         sstable
             .flush(
-                mem_table,
-                summary_density,
-                index_density,
+                &mem_table,
+                table_options(summary_density, index_density, use_variable_encoding),
+                &compression_dictionary,
                 None,
-                &mut compression_dictionary,
-                use_variable_encoding,
             )
             .expect("Failed to flush sstable");
 
@@ -1195,9 +1932,9 @@ mod sstable_tests {
             if let Some(entry) = sstable.get(
                 key.as_bytes(),
                 index_density,
-                &mut compression_dictionary,
+                &mut compression_dictionary.lock().unwrap(),
                 use_variable_encoding,
-            ) {
+            ).unwrap() {
                 // Get the value using the get_value method
                 let actual_value_bytes: Box<[u8]> = entry.get_value();
 
@@ -1291,17 +2028,15 @@ mod sstable_tests {
         // Create compression dict if required
         let compression_dict_dir =
             tempfile::tempdir().expect("Failed to create temporary directory");
-        let mut compression_dictionary =
-            get_compression_dict(&compression_dict_dir, use_compression);
+        let compression_dictionary =
+            Mutex::new(get_compression_dict(&compression_dict_dir, use_compression));
 
         sstable
             .flush(
-                mem_table,
-                summary_density,
-                index_density,
+                &mem_table,
+                table_options(summary_density, index_density, use_variable_encoding),
+                &compression_dictionary,
                 None,
-                &mut compression_dictionary,
-                use_variable_encoding,
             )
             .expect("Failed to flush sstable");
 
@@ -1649,8 +2384,8 @@ mod sstable_tests {
         // Create compression dict if required
         let compression_dict_dir =
             tempfile::tempdir().expect("Failed to create temporary directory");
-        let mut compression_dictionary =
-            get_compression_dict(&compression_dict_dir, use_compression);
+        let compression_dictionary =
+            Mutex::new(get_compression_dict(&compression_dict_dir, use_compression));
 
         // generate data for all sstables nad insert paths to sstable_paths
         for i in 0..in_single_file.len() {
@@ -1665,12 +2400,10 @@ mod sstable_tests {
 
             sstable
                 .flush(
-                    mem_table,
-                    summary_density,
-                    index_density,
+                    &mem_table,
+                    table_options(summary_density, index_density, use_variable_encoding),
+                    &compression_dictionary,
                     None,
-                    &mut compression_dictionary,
-                    use_variable_encoding,
                 )
                 .expect("Failed to flush sstable");
             sstable_paths.push(sstable_path.to_owned());
@@ -1691,10 +2424,9 @@ mod sstable_tests {
             in_single_file,
             &merged_sstable_path.to_owned(),
             merged_in_single_file,
-            summary_density,
-            index_density,
-            use_variable_encoding,
-            &mut compression_dictionary,
+            table_options(summary_density, index_density, use_variable_encoding),
+            &compression_dictionary,
+            MergeContext::default(),
         )
         .expect("Failed to merge SSTables");
 
@@ -1704,12 +2436,382 @@ mod sstable_tests {
             range,
             merged_in_single_file,
             use_variable_encoding,
-            &mut compression_dictionary,
+            &mut compression_dictionary.lock().unwrap(),
         );
 
         remove_dir_all(&compression_dict_dir).expect("Failed to remove compression dict dirs");
     }
 
+    #[test]
+    fn test_merge_many_tables() {
+        let (temp_dir, _, summary_density, index_density) =
+            setup_test_environment(&MemoryTableType::SkipList, false);
+
+        // Every table holds every fifth key, the last table also overwrites the first keys
+        let mut sstable_paths = Vec::new();
+        for table in 0..5 {
+            let (_, _, mut mem_table) =
+                get_density_and_mem_table(&MemoryTableType::SkipList, false);
+            for i in (table..500).step_by(5) {
+                let key = format!("test_key_{:03}", i);
+                mem_table.insert(key.as_bytes(), b"old_value", TimeStamp::Custom(1));
+            }
+            if table == 4 {
+                for i in 0..100 {
+                    let key = format!("test_key_{:03}", i);
+                    mem_table.insert(key.as_bytes(), b"new_value", TimeStamp::Custom(2));
+                }
+            }
+
+            let sstable_path = temp_dir.path().join(format!("sstable{}", table));
+            SSTable::open(sstable_path.clone(), table % 2 == 0)
+                .expect("Failed to open SSTable")
+                .flush(
+                    &mem_table,
+                    table_options(summary_density, index_density, false),
+                    &Mutex::new(None),
+                    None,
+                )
+                .expect("Failed to flush sstable");
+            sstable_paths.push(sstable_path);
+        }
+
+        for merged_in_single_file in [true, false] {
+            let merged_sstable_path = temp_dir
+                .path()
+                .join(format!("merged_sstable_{}", merged_in_single_file));
+            SSTable::merge(
+                sstable_paths.clone(),
+                vec![true, false, true, false, true],
+                &merged_sstable_path,
+                merged_in_single_file,
+                table_options(summary_density, index_density, false),
+                &Mutex::new(None),
+                MergeContext::default(),
+            )
+            .expect("Failed to merge SSTables");
+
+            // The merged table holds each key once, in order, with its newest value
+            let merged_entries: Vec<_> = SSTable::merge_entries(
+                vec![merged_sstable_path.clone()],
+                vec![merged_in_single_file],
+                false,
+                &Mutex::new(None),
+                &[],
+                &[],
+            )
+            .expect("Failed to read merged SSTable")
+            .collect::<db_error::Result<_>>()
+            .expect("Failed to read merged SSTable");
+            assert_eq!(merged_entries.len(), 500);
+            for (i, (key, entry)) in merged_entries.iter().enumerate() {
+                assert_eq!(key.as_ref(), format!("test_key_{:03}", i).as_bytes());
+                let expected_value: &[u8] = if i < 100 { b"new_value" } else { b"old_value" };
+                assert_eq!(entry.get_value().as_ref(), expected_value);
+            }
+
+            let mut merged_sstable =
+                SSTable::open(merged_sstable_path.clone(), merged_in_single_file)
+                    .expect("Failed to open merged SSTable");
+            for i in 0..500 {
+                let key = format!("test_key_{:03}", i);
+                assert!(merged_sstable
+                    .get(key.as_bytes(), index_density, &mut None, false)
+                    .expect("Failed to get entry")
+                    .is_some());
+            }
+            assert!(merged_sstable
+                .get(b"test_key_500", index_density, &mut None, false)
+                .expect("Failed to get entry")
+                .is_none());
+
+            // The Merkle tree written chunk by chunk matches the tree of the whole data
+            let serialized_data: Vec<u8> = merged_entries
+                .iter()
+                .flat_map(|(key, entry)| entry.serialize(key, false).into_vec())
+                .collect();
+            let serialized_merkle_tree = if merged_in_single_file {
+                let file =
+                    read(merged_sstable_path.join("SSTable.db")).expect("Failed to read SSTable");
+                // The offset of the Merkle tree is the last of the offsets that follow the file header
+                let merkle_offset = u64::from_le_bytes(
+                    file[FILE_HEADER_LEN + 4 * 8..FILE_HEADER_LEN + 5 * 8]
+                        .try_into()
+                        .unwrap(),
+                ) as usize;
+                let properties_offset = u64::from_le_bytes(
+                    file[file.len() - PROPERTIES_FOOTER_LEN..][..8]
+                        .try_into()
+                        .unwrap(),
+                ) as usize;
+                file[merkle_offset..properties_offset].to_vec()
+            } else {
+                read(merged_sstable_path.join("SSTable-MerkleTree.db"))
+                    .expect("Failed to read Merkle tree")[FILE_HEADER_LEN..]
+                    .to_vec()
+            };
+            assert_eq!(
+                serialized_merkle_tree.as_slice(),
+                MerkleTree::new(&serialized_data).serialize().as_ref()
+            );
+        }
+    }
+
+    #[test]
+    fn test_table_properties() {
+        for in_single_file in [true, false] {
+            let (temp_dir, mut mem_table, summary_density, index_density) =
+                setup_test_environment(&MemoryTableType::SkipList, false);
+            for i in 0..20 {
+                let key = format!("test_key_{:02}", i);
+                mem_table.insert(key.as_bytes(), b"value", TimeStamp::Custom(10 + i));
+            }
+            mem_table.delete(b"test_key_05", TimeStamp::Custom(100));
+            mem_table.delete(b"test_key_15", TimeStamp::Custom(50));
+
+            SSTable::open(temp_dir.path().to_path_buf(), in_single_file)
+                .expect("Failed to open SSTable")
+                .flush(
+                    &mem_table,
+                    table_options(summary_density, index_density, false),
+                    &Mutex::new(None),
+                    None,
+                )
+                .expect("Failed to flush sstable");
+
+            // The data size is the length of the entries before they are compressed
+            let data: Vec<u8> = mem_table
+                .iterator()
+                .flat_map(|(key, entry)| entry.serialize(&key, false))
+                .collect();
+
+            let properties = SSTable::open(temp_dir.path().to_path_buf(), in_single_file)
+                .expect("Failed to open SSTable")
+                .read_properties(false)
+                .expect("Failed to read properties");
+            assert_eq!(properties.format_version, FORMAT_VERSION);
+            assert_eq!(properties.entry_count, 20);
+            assert_eq!(properties.tombstone_count, 2);
+            assert_eq!(properties.min_time_stamp, 10);
+            assert_eq!(properties.max_time_stamp, 100);
+            assert_eq!(properties.data_size, data.len() as u64);
+            assert_eq!(properties.min_key.as_ref(), b"test_key_00");
+            assert_eq!(properties.max_key.as_ref(), b"test_key_19");
+        }
+    }
+
+    #[test]
+    fn test_legacy_tables() {
+        // Tables written before the files had headers have no properties and store their entries back to back,
+        // the properties are gathered from their data instead
+        for (layout, in_single_file) in [("separate_files", false), ("single_file", true)] {
+            let fixture = legacy_fixture(layout);
+            let mut table_paths: Vec<PathBuf> = read_dir(fixture.path().join("sstables"))
+                .expect("Failed to read the fixture")
+                .map(|dir_entry| dir_entry.expect("Failed to read the fixture").path())
+                .collect();
+            table_paths.sort();
+            assert_eq!(table_paths.len(), 2);
+
+            for (table, table_path) in table_paths.into_iter().enumerate() {
+                let mut sstable =
+                    SSTable::open(table_path, in_single_file).expect("Failed to open SSTable");
+                let properties = sstable
+                    .read_properties(true)
+                    .expect("Failed to read properties");
+                let first_key = table * 9;
+                assert_eq!(properties.format_version, 0);
+                assert_eq!(properties.entry_count, 9);
+                assert_eq!(properties.tombstone_count, 0);
+                assert_eq!(properties.min_time_stamp, 1_000 + first_key as u128);
+                assert_eq!(properties.max_time_stamp, 1_008 + first_key as u128);
+                assert_eq!(
+                    properties.min_key.as_ref(),
+                    format!("key{:03}", first_key).as_bytes()
+                );
+                assert_eq!(
+                    properties.max_key.as_ref(),
+                    format!("key{:03}", first_key + 8).as_bytes()
+                );
+                assert_eq!(properties.prefix_extractor, PrefixExtractorType::None);
+
+                for i in 0..30 {
+                    let key = format!("key{:03}", i);
+                    let value = sstable
+                        .get(key.as_bytes(), 2, &mut None, true)
+                        .expect("Failed to read SSTable")
+                        .map(|entry| entry.get_value());
+                    let expected = (first_key..first_key + 9)
+                        .contains(&i)
+                        .then(|| format!("value{:03}", i));
+                    assert_eq!(value.as_deref(), expected.as_ref().map(|value| value.as_bytes()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_prefix_filter() {
+        for in_single_file in [true, false] {
+            // Each table holds the even tenants of its half
+            let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+            let (summary_density, index_density) = (2, 2);
+            let options = TableOptions {
+                prefix_extractor: PrefixExtractorType::Delimiter(b':'),
+                ..table_options(summary_density, index_density, false)
+            };
+            let mut sstable_paths = Vec::new();
+            for half in 0..2 {
+                let (_, _, mut mem_table) =
+                    get_density_and_mem_table(&MemoryTableType::SkipList, false);
+                for tenant in (half * 10..half * 10 + 10).step_by(2) {
+                    for item in 0..10 {
+                        let key = format!("tenant{:02}:item{:02}", tenant, item);
+                        mem_table.insert(key.as_bytes(), b"value", TimeStamp::Now);
+                    }
+                }
+
+                let sstable_path = temp_dir.path().join(format!("sstable_{}", half));
+                create_dir_all(&sstable_path).expect("Failed to create SSTable directory");
+                SSTable::open(sstable_path.clone(), in_single_file)
+                    .expect("Failed to open SSTable")
+                    .flush(&mem_table, options, &Mutex::new(None), None)
+                    .expect("Failed to flush sstable");
+                sstable_paths.push(sstable_path);
+            }
+
+            let merged_sstable_path = temp_dir.path().join("merged_sstable");
+            SSTable::merge(
+                sstable_paths.clone(),
+                vec![in_single_file; 2],
+                &merged_sstable_path,
+                in_single_file,
+                options,
+                &Mutex::new(None),
+                MergeContext::default(),
+            )
+            .expect("Failed to merge SSTables");
+
+            let contained_tenants = |sstable_path: &PathBuf, prefix_extractor| -> Vec<usize> {
+                let mut sstable = SSTable::open(sstable_path.clone(), in_single_file)
+                    .expect("Failed to open SSTable");
+                (0..20)
+                    .filter(|tenant| {
+                        let prefix = format!("tenant{:02}:", tenant);
+                        sstable
+                            .may_contain_prefix(prefix.as_bytes(), prefix_extractor)
+                            .expect("Failed to read prefix filter")
+                    })
+                    .collect()
+            };
+
+            let delimiter = PrefixExtractorType::Delimiter(b':');
+            assert_eq!(
+                contained_tenants(&sstable_paths[0], delimiter),
+                vec![0, 2, 4, 6, 8]
+            );
+            assert_eq!(
+                contained_tenants(&sstable_paths[1], delimiter),
+                vec![10, 12, 14, 16, 18]
+            );
+            assert_eq!(
+                contained_tenants(&merged_sstable_path, delimiter),
+                (0..20).step_by(2).collect::<Vec<_>>()
+            );
+
+            // Another extractor can't use the filter
+            assert_eq!(
+                contained_tenants(&sstable_paths[0], PrefixExtractorType::FixedLength(9)),
+                (0..20).collect::<Vec<_>>()
+            );
+
+            let mut sstable = SSTable::open(sstable_paths[0].clone(), in_single_file)
+                .expect("Failed to open SSTable");
+            let properties = sstable
+                .read_properties(false)
+                .expect("Failed to read properties");
+            assert_eq!(properties.prefix_extractor, delimiter);
+            assert!(properties.prefix_filter_len > 0);
+
+            // Longer prefixes are checked by their extracted prefix, shorter ones can't be ruled out
+            for (prefix, may_contain_prefix) in [
+                (&b"tenant04:item03"[..], true),
+                (b"tenant05:item03", false),
+                (b"tenant05", true),
+                (b"", true),
+            ] {
+                assert_eq!(
+                    sstable
+                        .may_contain_prefix(prefix, delimiter)
+                        .expect("Failed to read prefix filter"),
+                    may_contain_prefix
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compressed_blocks() {
+        let mut data_lens = Vec::new();
+        for codec in [&NoneCodec as &'static dyn Codec, &Lz4Codec] {
+            for in_single_file in [true, false] {
+                let (temp_dir, mut mem_table, summary_density, index_density) =
+                    setup_test_environment(&MemoryTableType::SkipList, false);
+                for i in 0..500 {
+                    let key = format!("test_key_{:03}", i);
+                    let value = format!("test_value_{:03}", i).repeat(10);
+                    mem_table.insert(key.as_bytes(), value.as_bytes(), TimeStamp::Now);
+                }
+
+                SSTable::open(temp_dir.path().to_path_buf(), in_single_file)
+                    .expect("Failed to open SSTable")
+                    .flush(
+                        &mem_table,
+                        TableOptions {
+                            block_size: 512,
+                            codec,
+                            ..table_options(summary_density, index_density, false)
+                        },
+                        &Mutex::new(None),
+                        None,
+                    )
+                    .expect("Failed to flush sstable");
+                if !in_single_file {
+                    let data =
+                        read(temp_dir.path().join("SSTable-Data.db")).expect("Failed to read data");
+                    data_lens.push(data.len());
+                }
+
+                // Keys are read in a shuffled order, so blocks are read both from the cache and the file
+                let mut sstable = SSTable::open(temp_dir.path().to_path_buf(), in_single_file)
+                    .expect("Failed to open SSTable");
+                for i in (0..500).map(|i| i * 7 % 500) {
+                    let key = format!("test_key_{:03}", i);
+                    let entry = sstable
+                        .get(key.as_bytes(), index_density, &mut None, false)
+                        .expect("Failed to read SSTable")
+                        .expect("Key not found");
+                    assert_eq!(
+                        entry.get_value(),
+                        format!("test_value_{:03}", i).repeat(10).as_bytes().into()
+                    );
+                }
+                assert!(sstable
+                    .get(b"test_key_500", index_density, &mut None, false)
+                    .expect("Failed to read SSTable")
+                    .is_none());
+
+                let properties = sstable
+                    .read_properties(false)
+                    .expect("Failed to read properties");
+                assert_eq!(properties.entry_count, 500);
+            }
+        }
+
+        // Repeated values compress well
+        assert!(data_lens[1] < data_lens[0] / 2);
+    }
+
     // Helper function to verify that the merged SSTable contains the correct data
     fn verify_merged_sstable(
         merged_sstable_path: &PathBuf,
@@ -1735,7 +2837,7 @@ mod sstable_tests {
                 index_density,
                 compression_dictionary,
                 use_variable_encoding,
-            ) {
+            ).unwrap() {
                 // Get the value using the get_value method
                 let actual_value_bytes: Box<[u8]> = entry.get_value();
 
@@ -1750,4 +2852,1726 @@ mod sstable_tests {
         }
         remove_dir_all(merged_sstable_path).expect("Failed to remove all dirs");
     }
+
+    #[test]
+    fn test_merge_keeps_snapshot_versions() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let db_config = get_config(temp_dir.path(), temp_dir.path(), TestOptions::default());
+        let compression_dictionary = Mutex::new(None);
+
+        // Three versions of every key, written at time stamps 10, 20 and 30
+        let mut sstable_paths = Vec::new();
+        for (i, value) in [b"first", b"secnd", b"third"].iter().enumerate() {
+            let mut mem_table =
+                MemoryTable::new(&db_config).expect("Failed to create memory table");
+            for j in 0..20 {
+                mem_table.insert(
+                    get_key(j).as_bytes(),
+                    *value,
+                    TimeStamp::Custom(10 * (i as u128 + 1)),
+                );
+            }
+
+            let sstable_path = temp_dir.path().join(format!("sstable{}", i + 1));
+            SSTable::open(sstable_path.clone(), true)
+                .expect("Failed to open SSTable")
+                .flush(
+                    &mem_table,
+                    TableOptions::from(&db_config),
+                    &compression_dictionary,
+                    None,
+                )
+                .expect("Failed to flush sstable");
+            sstable_paths.push(sstable_path);
+        }
+
+        // Only the snapshot at 15 needs an older version, the second version isn't visible to any snapshot
+        let merged_sstable_path = temp_dir.path().join("merged_sstable");
+        SSTable::merge(
+            sstable_paths,
+            vec![true, true, true],
+            &merged_sstable_path,
+            true,
+            TableOptions::from(&db_config),
+            &compression_dictionary,
+            MergeContext {
+                snapshots: &[15, 35],
+                ..MergeContext::default()
+            },
+        )
+        .expect("Failed to merge SSTables");
+
+        let mut merged_sstable =
+            SSTable::open(merged_sstable_path, true).expect("Failed to open merged SSTable");
+        for j in 0..20 {
+            let key = get_key(j);
+            let get_value = |sstable: &mut SSTable, snapshot: u128| {
+                sstable
+                    .get_at(
+                        key.as_bytes(),
+                        snapshot,
+                        db_config.index_density,
+                        &mut None,
+                        db_config.use_variable_encoding,
+                    )
+                    .unwrap()
+                    .map(|entry| entry.get_value())
+            };
+            assert_eq!(get_value(&mut merged_sstable, 5), None);
+            assert_eq!(
+                get_value(&mut merged_sstable, 15).as_deref(),
+                Some(&b"first"[..])
+            );
+            assert_eq!(
+                get_value(&mut merged_sstable, 25).as_deref(),
+                Some(&b"first"[..])
+            );
+            assert_eq!(
+                get_value(&mut merged_sstable, 35).as_deref(),
+                Some(&b"third"[..])
+            );
+        }
+    }
+
+    #[test]
+    fn test_retain_snapshot_versions() {
+        let versions = vec![
+            MemoryEntry::from(b"a", false, 10),
+            MemoryEntry::from(b"b", false, 20),
+            MemoryEntry::from(b"c", true, 30),
+            MemoryEntry::from(b"d", false, 40),
+        ];
+
+        let retained = SSTable::retain_snapshot_versions(versions.clone(), &[]);
+        assert_eq!(retained.len(), 1);
+        assert_eq!(retained[0].get_timestamp(), 40);
+
+        let retained = SSTable::retain_snapshot_versions(versions, &[10, 35]);
+        let timestamps: Vec<u128> = retained.iter().map(|entry| entry.get_timestamp()).collect();
+        assert_eq!(timestamps, vec![40, 30, 10]);
+    }
+
+    #[test]
+    fn test_merge_drops_expired() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let db_config = get_config(temp_dir.path(), temp_dir.path(), TestOptions::default());
+
+        let mut mem_table = MemoryTable::new(&db_config).expect("Failed to create memory table");
+        for i in 0..10 {
+            let expiry = if i % 2 == 0 { 2 } else { u128::MAX };
+            mem_table.insert_with_expiry(
+                get_key(i).as_bytes(),
+                b"value",
+                expiry,
+                TimeStamp::Custom(1),
+            );
+        }
+
+        let sstable_path = temp_dir.path().join("sstable");
+        SSTable::open(sstable_path.clone(), true)
+            .expect("Failed to open SSTable")
+            .flush(
+                &mem_table,
+                TableOptions {
+                    use_variable_encoding: false,
+                    ..TableOptions::from(&db_config)
+                },
+                &Mutex::new(None),
+                None,
+            )
+            .expect("Failed to flush sstable");
+
+        // Expired entries are dropped when no older table can hold their keys
+        let merged_entries: Vec<_> = SSTable::merge_entries(
+            vec![sstable_path.clone()],
+            vec![true],
+            false,
+            &Mutex::new(None),
+            &[],
+            &[],
+        )
+        .expect("Failed to merge SSTables")
+        .collect::<db_error::Result<_>>()
+        .expect("Failed to merge SSTables");
+        let keys: Vec<Box<[u8]>> = merged_entries.into_iter().map(|(key, _)| key).collect();
+        let expected_keys: Vec<Box<[u8]>> = (1..10)
+            .step_by(2)
+            .map(|i| Box::from(get_key(i).as_bytes()))
+            .collect();
+        assert_eq!(keys, expected_keys);
+
+        // Otherwise they are replaced with tombstones, so that older versions don't come back
+        let older_key_ranges = [(
+            Box::from(get_key(0).as_bytes()),
+            Box::from(get_key(9).as_bytes()),
+        )];
+        let merged_entries: Vec<_> = SSTable::merge_entries(
+            vec![sstable_path],
+            vec![true],
+            false,
+            &Mutex::new(None),
+            &[],
+            &older_key_ranges,
+        )
+        .expect("Failed to merge SSTables")
+        .collect::<db_error::Result<_>>()
+        .expect("Failed to merge SSTables");
+        assert_eq!(merged_entries.len(), 10);
+        for (i, (_, entry)) in merged_entries.iter().enumerate() {
+            assert_eq!(entry.get_tombstone(), i % 2 == 0);
+            assert_eq!(entry.get_value().is_empty(), i % 2 == 0);
+        }
+    }
+
+    fn flush_table(db_config: &DBConfig, path: PathBuf, mem_table: &MemoryTable) {
+        SSTable::open(path, true)
+            .expect("Failed to open SSTable")
+            .flush(
+                mem_table,
+                TableOptions::from(db_config),
+                &Mutex::new(None),
+                None,
+            )
+            .expect("Failed to flush sstable");
+    }
+
+    #[test]
+    fn test_merge_drops_tombstones() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let db_config = get_config(temp_dir.path(), temp_dir.path(), TestOptions::default());
+
+        let mut mem_table = MemoryTable::new(&db_config).expect("Failed to create memory table");
+        for i in 0..10 {
+            mem_table.insert(get_key(i).as_bytes(), b"value", TimeStamp::Custom(1));
+        }
+        let old_path = temp_dir.path().join("old");
+        flush_table(&db_config, old_path.clone(), &mem_table);
+
+        let mut mem_table = MemoryTable::new(&db_config).expect("Failed to create memory table");
+        for i in 0..10 {
+            mem_table.delete(get_key(i).as_bytes(), TimeStamp::Custom(3));
+        }
+        let new_path = temp_dir.path().join("new");
+        flush_table(&db_config, new_path.clone(), &mem_table);
+
+        let merge = |paths: Vec<PathBuf>, snapshots: &[u128], older_key_ranges: &[KeyRange]| {
+            let single_file = vec![true; paths.len()];
+            SSTable::merge_entries(
+                paths,
+                single_file,
+                db_config.use_variable_encoding,
+                &Mutex::new(None),
+                snapshots,
+                older_key_ranges,
+            )
+            .expect("Failed to merge SSTables")
+            .collect::<db_error::Result<Vec<_>>>()
+            .expect("Failed to merge SSTables")
+        };
+
+        // Nothing older than the merged tables can hold the keys
+        assert!(merge(vec![old_path.clone(), new_path.clone()], &[], &[]).is_empty());
+
+        // The tombstones still hide the older table
+        let older_key_ranges = [(
+            Box::from(get_key(5).as_bytes()),
+            Box::from(get_key(20).as_bytes()),
+        )];
+        let merged_entries = merge(vec![new_path.clone()], &[], &older_key_ranges);
+        let keys: Vec<Box<[u8]>> = merged_entries.iter().map(|(key, _)| key.clone()).collect();
+        let expected_keys: Vec<Box<[u8]>> =
+            (5..10).map(|i| Box::from(get_key(i).as_bytes())).collect();
+        assert_eq!(keys, expected_keys);
+        assert!(merged_entries
+            .iter()
+            .all(|(_, entry)| entry.get_tombstone()));
+
+        // A snapshot taken before the deletes still sees the values under the tombstones
+        let merged_entries = merge(vec![old_path, new_path], &[2], &[]);
+        assert_eq!(merged_entries.len(), 20);
+        for versions in merged_entries.chunks(2) {
+            assert!(versions[0].1.get_tombstone());
+            assert_eq!(&*versions[1].1.get_value(), b"value");
+        }
+    }
+}
+
+#[cfg(test)]
+mod lsm_tests {
+    use crate::lsm::paginator::Paginator;
+    use crate::test_utils::{get_config, get_key, TestOptions};
+    use crate::LSM;
+    use db_config::{CompactionAlgorithmType, DBConfig};
+    use segment_elements::{MemoryEntry, TimeStamp};
+    use std::fs::read_dir;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    // Overwrites and deletes keys after the snapshot, with enough writes to trigger flushes and compactions
+    fn snapshot_get_after_compaction(compaction_algorithm_type: CompactionAlgorithmType) {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type,
+                ..TestOptions::default()
+            },
+        );
+        let lsm = LSM::new(&db_config).unwrap();
+
+        for i in 0..100 {
+            lsm.insert(get_key(i).as_bytes(), b"old", TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+
+        let snapshot = lsm.snapshot();
+
+        for i in 0..100 {
+            if i % 2 == 0 {
+                lsm.insert(get_key(i).as_bytes(), b"new", TimeStamp::Now)
+                    .expect("Failed to insert into lsm");
+            } else {
+                lsm.delete(get_key(i).as_bytes(), TimeStamp::Now)
+                    .expect("Failed to delete from lsm");
+            }
+        }
+        for i in 100..200 {
+            lsm.insert(get_key(i).as_bytes(), b"new", TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+
+        for i in 0..100 {
+            let key = get_key(i);
+            assert_eq!(
+                lsm.get_at(key.as_bytes(), &snapshot).unwrap().as_deref(),
+                Some(&b"old"[..])
+            );
+            let expected: Option<&[u8]> = if i % 2 == 0 { Some(b"new") } else { None };
+            assert_eq!(lsm.get(key.as_bytes()).unwrap().as_deref(), expected);
+        }
+        for i in 100..200 {
+            assert_eq!(lsm.get_at(get_key(i).as_bytes(), &snapshot).unwrap(), None);
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_snapshot_get_size_tiered() {
+        snapshot_get_after_compaction(CompactionAlgorithmType::SizeTiered);
+    }
+
+    #[test]
+    fn test_snapshot_get_leveled() {
+        snapshot_get_after_compaction(CompactionAlgorithmType::Leveled);
+    }
+
+    // Even keys are inserted with a time stamp so old that they have already expired, odd keys live for an hour
+    fn insert_with_ttl(lsm: &mut LSM, range: std::ops::Range<usize>) {
+        for i in range {
+            if i % 2 == 0 {
+                lsm.insert_with_ttl(
+                    get_key(i).as_bytes(),
+                    b"value",
+                    Duration::from_micros(1),
+                    TimeStamp::Custom(1),
+                )
+                .expect("Failed to insert into lsm");
+            } else {
+                lsm.insert_with_ttl(
+                    get_key(i).as_bytes(),
+                    b"value",
+                    Duration::from_secs(3600),
+                    TimeStamp::Now,
+                )
+                .expect("Failed to insert into lsm");
+            }
+        }
+    }
+
+    fn expired_keys_hidden(compaction_algorithm_type: CompactionAlgorithmType) {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type,
+                ..TestOptions::default()
+            },
+        );
+        let mut lsm = LSM::new(&db_config).unwrap();
+
+        insert_with_ttl(&mut lsm, 0..100);
+
+        for i in 0..100 {
+            let expected: Option<&[u8]> = if i % 2 == 0 { None } else { Some(b"value") };
+            assert_eq!(lsm.get(get_key(i).as_bytes()).unwrap().as_deref(), expected);
+        }
+
+        let mut paginator = Paginator::new(&mut lsm);
+        for i in (1..100).step_by(2) {
+            let (key, _) = paginator
+                .range_iterate_next(get_key(0).as_bytes(), get_key(99).as_bytes())
+                .expect("Failed to iterate to next entry")
+                .expect("Failed to get memory entry");
+            assert_eq!(&*key, get_key(i).as_bytes());
+        }
+        assert!(paginator
+            .range_iterate_next(get_key(0).as_bytes(), get_key(99).as_bytes())
+            .expect("Failed to iterate to next entry")
+            .is_none());
+
+        drop(paginator);
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_ttl_size_tiered() {
+        expired_keys_hidden(CompactionAlgorithmType::SizeTiered);
+    }
+
+    #[test]
+    fn test_ttl_leveled() {
+        expired_keys_hidden(CompactionAlgorithmType::Leveled);
+    }
+
+    #[test]
+    fn test_ttl_wal_reconstruction() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        db_config.memory_table_capacity = 1000;
+        let mut lsm = LSM::new(&db_config).unwrap();
+
+        insert_with_ttl(&mut lsm, 0..100);
+        drop(lsm);
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        for i in 0..100 {
+            let expected: Option<&[u8]> = if i % 2 == 0 { None } else { Some(b"value") };
+            assert_eq!(lsm.get(get_key(i).as_bytes()).unwrap().as_deref(), expected);
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_newer_expired_version_hides_flushed_one() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        let mut lsm = LSM::new(&db_config).unwrap();
+
+        for i in 0..20 {
+            lsm.insert(get_key(i).as_bytes(), b"value", TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        lsm.wait_for_background_work();
+        assert!(!lsm.table_directories().is_empty());
+
+        // The flushed versions of both keys are older than the expired and the deleted one in memory
+        lsm.insert_with_ttl(
+            get_key(2).as_bytes(),
+            b"value",
+            Duration::from_millis(1),
+            TimeStamp::Now,
+        )
+        .expect("Failed to insert into lsm");
+        lsm.delete(get_key(3).as_bytes(), TimeStamp::Now)
+            .expect("Failed to delete from lsm");
+        thread::sleep(Duration::from_millis(10));
+
+        assert_eq!(lsm.get(get_key(2).as_bytes()).unwrap(), None);
+        let mut paginator = Paginator::new(&mut lsm);
+        for i in (0..20).filter(|i| *i != 2 && *i != 3) {
+            let (key, _) = paginator
+                .range_iterate_next(get_key(0).as_bytes(), get_key(19).as_bytes())
+                .expect("Failed to iterate to next entry")
+                .expect("Failed to get memory entry");
+            assert_eq!(&*key, get_key(i).as_bytes());
+        }
+        assert!(paginator
+            .range_iterate_next(get_key(0).as_bytes(), get_key(19).as_bytes())
+            .expect("Failed to iterate to next entry")
+            .is_none());
+
+        drop(paginator);
+        lsm.wait_for_background_work();
+    }
+
+    fn get_thread_key(thread: usize, i: usize) -> String {
+        format!("key_{}_{:03}", thread, i)
+    }
+
+    #[test]
+    fn test_lsm_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<LSM>();
+    }
+
+    // Writers trigger flushes and compactions while readers get keys that were already written and scan them
+    fn concurrent_reads_and_writes(compaction_algorithm_type: CompactionAlgorithmType) {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type,
+                ..TestOptions::default()
+            },
+        );
+        let lsm = Arc::new(LSM::new(&db_config).unwrap());
+
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let lsm = Arc::clone(&lsm);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let key = get_thread_key(thread, i);
+                        lsm.insert(key.as_bytes(), key.as_bytes(), TimeStamp::Now)
+                            .expect("Failed to insert into lsm");
+
+                        let written_key = get_thread_key(thread, i / 2);
+                        assert_eq!(
+                            lsm.get(written_key.as_bytes()).unwrap().as_deref(),
+                            Some(written_key.as_bytes())
+                        );
+                    }
+
+                    // Every key written by this thread is seen by the scan
+                    let mut paginator = Paginator::new(&lsm);
+                    let prefix = format!("key_{}_", thread);
+                    let entries = paginator.prefix_scan(prefix.as_bytes(), 0, 200).unwrap();
+                    assert_eq!(entries.len(), 100);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().expect("Thread panicked");
+        }
+
+        for thread in 0..4 {
+            for i in 0..100 {
+                let key = get_thread_key(thread, i);
+                assert_eq!(
+                    lsm.get(key.as_bytes()).unwrap().as_deref(),
+                    Some(key.as_bytes())
+                );
+            }
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_concurrent_reads_and_writes_size_tiered() {
+        concurrent_reads_and_writes(CompactionAlgorithmType::SizeTiered);
+    }
+
+    #[test]
+    fn test_concurrent_reads_and_writes_leveled() {
+        concurrent_reads_and_writes(CompactionAlgorithmType::Leveled);
+    }
+
+    #[test]
+    fn test_finalize_waits_for_background_work() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        let lsm = LSM::new(&db_config).unwrap();
+        for i in 0..200 {
+            let key = get_thread_key(0, i);
+            lsm.insert(key.as_bytes(), key.as_bytes(), TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+
+        // All scheduled flushes and compactions are done, so the first level is within its limit
+        lsm.wait_for_background_work();
+        let sstable_dirs: Vec<_> = read_dir(&db_config.sstable_dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert!(!sstable_dirs.is_empty());
+        assert!(
+            sstable_dirs
+                .iter()
+                .filter(|dir| dir.starts_with("sstable_1_"))
+                .count()
+                <= db_config.lsm_max_per_level
+        );
+
+        lsm.finalize();
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        for i in 0..200 {
+            let key = get_thread_key(0, i);
+            assert_eq!(
+                lsm.get(key.as_bytes()).unwrap().as_deref(),
+                Some(key.as_bytes())
+            );
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_concurrent_compare_and_swap() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        let lsm = Arc::new(LSM::new(&db_config).unwrap());
+        lsm.insert(b"counter", &0u32.to_ne_bytes(), TimeStamp::Now)
+            .expect("Failed to insert into lsm");
+
+        // Every thread retries until its increments succeed, so no increment may be lost
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let lsm = Arc::clone(&lsm);
+                thread::spawn(move || {
+                    let mut increments = 0;
+                    while increments < 25 {
+                        let current = lsm.get(b"counter").unwrap().unwrap();
+                        let value = u32::from_ne_bytes(current.as_ref().try_into().unwrap());
+                        if lsm
+                            .compare_and_swap(
+                                b"counter",
+                                Some(&current),
+                                Some(&(value + 1).to_ne_bytes()),
+                                TimeStamp::Now,
+                            )
+                            .unwrap()
+                        {
+                            increments += 1;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().expect("Thread panicked");
+        }
+
+        assert_eq!(
+            lsm.get(b"counter").unwrap().as_deref(),
+            Some(&100u32.to_ne_bytes()[..])
+        );
+
+        lsm.wait_for_background_work();
+    }
+
+    /// Returns the configuration of the mmap tests, whose sstables hold many small blocks and are only merged by hand.
+    fn get_mmap_config(
+        sstable_dir: &TempDir,
+        write_ahead_log_dir: &TempDir,
+        in_single_file: bool,
+        use_mmap: bool,
+    ) -> DBConfig {
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                use_mmap,
+                ..TestOptions::default()
+            },
+        );
+        db_config.memory_table_capacity = 50;
+        db_config.compaction_enabled = false;
+        db_config.cache_max_size = 0;
+        db_config.sstable_block_size = 256;
+        db_config.sstable_single_file = in_single_file;
+        db_config
+    }
+
+    /// Inserts 500 keys and updates every fifth one, so most of them are flushed to sstables.
+    fn fill_with_updates(lsm: &LSM) {
+        for i in 0..500 {
+            let key = get_key(i);
+            lsm.insert(
+                key.as_bytes(),
+                format!("value{}", i).as_bytes(),
+                TimeStamp::Now,
+            )
+            .unwrap();
+        }
+        for i in (0..500).step_by(5) {
+            let key = get_key(i);
+            lsm.insert(
+                key.as_bytes(),
+                format!("updated{}", i).as_bytes(),
+                TimeStamp::Now,
+            )
+            .unwrap();
+        }
+        lsm.wait_for_background_work();
+    }
+
+    fn assert_updated_keys(lsm: &LSM) {
+        for i in 0..500 {
+            let key = get_key(i);
+            let expected = match i % 5 {
+                0 => format!("updated{}", i),
+                _ => format!("value{}", i),
+            };
+            assert_eq!(
+                lsm.get(key.as_bytes()).unwrap().as_deref(),
+                Some(expected.as_bytes())
+            );
+        }
+    }
+
+    /// Returns the keys and values of a range scan and of a prefix scan.
+    fn scan_updated_keys(lsm: &LSM) -> (Vec<(Box<[u8]>, Box<[u8]>)>, Vec<(Box<[u8]>, Box<[u8]>)>) {
+        let key_values = |entries: Vec<(Box<[u8]>, MemoryEntry)>| -> Vec<(Box<[u8]>, Box<[u8]>)> {
+            entries
+                .into_iter()
+                .map(|(key, entry)| (key, entry.get_value()))
+                .collect()
+        };
+        let mut paginator = Paginator::new(lsm);
+        let range = key_values(
+            paginator
+                .range_scan(get_key(120).as_bytes(), get_key(380).as_bytes(), 0, 500)
+                .unwrap(),
+        );
+        let prefix = key_values(paginator.prefix_scan(b"key_2", 0, 500).unwrap());
+
+        (range, prefix)
+    }
+
+    #[test]
+    fn test_mmap_reads_match_file_reads() {
+        for in_single_file in [false, true] {
+            let scans: Vec<_> = [false, true]
+                .into_iter()
+                .map(|use_mmap| {
+                    let (sstable_dir, write_ahead_log_dir) =
+                        (TempDir::new().unwrap(), TempDir::new().unwrap());
+                    let db_config = get_mmap_config(
+                        &sstable_dir,
+                        &write_ahead_log_dir,
+                        in_single_file,
+                        use_mmap,
+                    );
+                    let lsm = LSM::new(&db_config).unwrap();
+                    fill_with_updates(&lsm);
+                    assert!(lsm.table_directories().len() > 1);
+
+                    assert_updated_keys(&lsm);
+                    scan_updated_keys(&lsm)
+                })
+                .collect();
+
+            let (range, prefix) = &scans[1];
+            assert_eq!(range.len(), 261);
+            assert_eq!(prefix.len(), 100);
+            assert_eq!(scans[0], scans[1]);
+        }
+    }
+
+    #[test]
+    fn test_mmap_reads_after_compaction() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_mmap_config(&sstable_dir, &write_ahead_log_dir, false, true);
+        let lsm = LSM::new(&db_config).unwrap();
+        fill_with_updates(&lsm);
+        assert_updated_keys(&lsm);
+
+        let scanned = scan_updated_keys(&lsm);
+
+        // The mapped tables are removed while they're cached, the merged table is mapped once it's read
+        lsm.compact_range(None, None).unwrap();
+        assert_eq!(lsm.table_directories().len(), 1);
+        assert_updated_keys(&lsm);
+        assert_eq!(scan_updated_keys(&lsm), scanned);
+    }
+}
+
+#[cfg(test)]
+mod compaction_tests {
+    use crate::test_utils::{get_config, get_key, TestOptions};
+    use crate::LSM;
+    use db_config::{CompactionAlgorithmType, DBConfig};
+    use segment_elements::TimeStamp;
+    use std::fs::read_dir;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    // Length of a time window in microseconds, the unit of the time stamps
+    const WINDOW: u128 = 1_000_000;
+
+    const MAX_SIZE: usize = 64 * 1024;
+
+    const BASE_SIZE: usize = 64 * 1024;
+
+    fn deleted_keys_dropped(compaction_algorithm_type: CompactionAlgorithmType) {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type,
+                ..TestOptions::default()
+            },
+        );
+        // Only tombstones can trigger compactions
+        db_config.lsm_max_per_level = 100;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        for i in 0..100 {
+            lsm.insert(get_key(i).as_bytes(), b"value", TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        for i in 0..100 {
+            lsm.delete(get_key(i).as_bytes(), TimeStamp::Now)
+                .expect("Failed to delete from lsm");
+        }
+        lsm.wait_for_background_work();
+
+        // Tables made of tombstones are compacted on their own, and nothing older is left to hide
+        assert_eq!(lsm.tombstone_count(), 0);
+        for i in 0..100 {
+            assert_eq!(lsm.get(get_key(i).as_bytes()).unwrap(), None);
+        }
+
+        drop(lsm);
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        assert_eq!(lsm.tombstone_count(), 0);
+        for i in 0..100 {
+            assert_eq!(lsm.get(get_key(i).as_bytes()).unwrap(), None);
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_tombstone_compaction_size_tiered() {
+        deleted_keys_dropped(CompactionAlgorithmType::SizeTiered);
+    }
+
+    #[test]
+    fn test_tombstone_compaction_leveled() {
+        deleted_keys_dropped(CompactionAlgorithmType::Leveled);
+    }
+
+    // Every window gets 30 keys, which fill a few memory tables
+    fn insert_windows(lsm: &LSM, windows: std::ops::Range<usize>) {
+        for window in windows {
+            for i in window * 30..(window + 1) * 30 {
+                lsm.insert(
+                    get_key(i).as_bytes(),
+                    get_key(i).as_bytes(),
+                    TimeStamp::Custom(window as u128 * WINDOW + i as u128),
+                )
+                .expect("Failed to insert into lsm");
+            }
+        }
+    }
+
+    fn windows_of_tables(lsm: &LSM) -> Vec<u128> {
+        // Tables stay in the first level and belong to the window of their newest entry
+        let time_ranges = lsm.table_time_ranges();
+        assert!(time_ranges[1..].iter().all(Vec::is_empty));
+        time_ranges[0]
+            .iter()
+            .map(|(_, max_time_stamp)| max_time_stamp / WINDOW)
+            .collect()
+    }
+
+    #[test]
+    fn test_time_window_compaction() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type: CompactionAlgorithmType::TimeWindow,
+                ..TestOptions::default()
+            },
+        );
+        db_config.lsm_time_window_ms = 1000;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        insert_windows(&lsm, 0..4);
+        lsm.wait_for_background_work();
+
+        // Past windows are merged into a single table, the current one is merged only once it's too big
+        assert_eq!(&windows_of_tables(&lsm)[..3], &[0, 1, 2]);
+        let past_tables = lsm.table_directories()[..3].to_vec();
+
+        insert_windows(&lsm, 4..8);
+        lsm.wait_for_background_work();
+
+        // Tables of past windows are never rewritten
+        let windows = windows_of_tables(&lsm);
+        assert_eq!(&windows[..7], &[0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(&lsm.table_directories()[..3], &past_tables);
+
+        for i in 0..240 {
+            assert_eq!(
+                lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                Some(get_key(i).as_bytes())
+            );
+        }
+
+        drop(lsm);
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        assert_eq!(windows_of_tables(&lsm), windows);
+        for i in 0..240 {
+            assert_eq!(
+                lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                Some(get_key(i).as_bytes())
+            );
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_time_window_late_data() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type: CompactionAlgorithmType::TimeWindow,
+                ..TestOptions::default()
+            },
+        );
+        db_config.lsm_time_window_ms = 1000;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        insert_windows(&lsm, 0..3);
+        lsm.wait_for_background_work();
+
+        // Late writes into the first window, newer versions of its keys must win over the merged window
+        for i in 0..30 {
+            lsm.insert(
+                get_key(i).as_bytes(),
+                b"late",
+                TimeStamp::Custom(WINDOW - 1),
+            )
+            .expect("Failed to insert into lsm");
+        }
+        insert_windows(&lsm, 3..5);
+        lsm.wait_for_background_work();
+
+        for i in 0..150 {
+            let expected = if i < 30 {
+                b"late".to_vec()
+            } else {
+                get_key(i).into_bytes()
+            };
+            assert_eq!(
+                lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                Some(&expected[..])
+            );
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    fn tables_size(db_config: &DBConfig, lsm: &LSM) -> u64 {
+        lsm.table_directories()
+            .iter()
+            .flat_map(|directory| {
+                read_dir(Path::new(&db_config.sstable_dir).join(directory)).unwrap()
+            })
+            .map(|file| file.unwrap().metadata().unwrap().len())
+            .sum()
+    }
+
+    /// Returns the index of the oldest key that can still be found, after checking that all newer ones are found too.
+    fn oldest_found_key(lsm: &LSM) -> usize {
+        let oldest = (0..300)
+            .position(|i| lsm.get(get_key(i).as_bytes()).unwrap().is_some())
+            .expect("All keys were deleted");
+        for i in oldest..300 {
+            assert_eq!(
+                lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                Some(&[i as u8; 100][..])
+            );
+        }
+        oldest
+    }
+
+    #[test]
+    fn test_fifo_compaction() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type: CompactionAlgorithmType::Fifo,
+                ..TestOptions::default()
+            },
+        );
+        db_config.lsm_fifo_max_size = MAX_SIZE;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        for i in 0..30 {
+            lsm.insert(get_key(i).as_bytes(), &[i as u8; 100], TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        lsm.wait_for_background_work();
+        // The first key gets cached
+        assert!(lsm.get(get_key(0).as_bytes()).unwrap().is_some());
+
+        for i in 30..300 {
+            lsm.insert(get_key(i).as_bytes(), &[i as u8; 100], TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        lsm.wait_for_background_work();
+
+        // Tables are never merged and only the newest ones that fit are kept
+        assert!(lsm.table_time_ranges()[1..].iter().all(Vec::is_empty));
+        assert!(tables_size(&db_config, &lsm) <= MAX_SIZE as u64);
+        assert!(lsm.get(get_key(0).as_bytes()).unwrap().is_none());
+        let oldest = oldest_found_key(&lsm);
+        assert!(oldest > 0);
+
+        drop(lsm);
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        assert_eq!(oldest_found_key(&lsm), oldest);
+
+        lsm.wait_for_background_work();
+    }
+
+    fn tables_per_level(lsm: &LSM) -> Vec<usize> {
+        lsm.table_time_ranges().iter().map(Vec::len).collect()
+    }
+
+    fn compact_all(compaction_algorithm_type: CompactionAlgorithmType) {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type,
+                ..TestOptions::default()
+            },
+        );
+        db_config.lsm_max_level = 3;
+        // Only the manual compaction merges tables
+        db_config.compaction_enabled = false;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        for i in 0..100 {
+            lsm.insert(get_key(i).as_bytes(), b"value", TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        for i in 0..50 {
+            lsm.delete(get_key(i).as_bytes(), TimeStamp::Now)
+                .expect("Failed to delete from lsm");
+        }
+        lsm.compact_range(None, None)
+            .expect("Failed to compact range");
+
+        // Everything ends up in a single table of the last level, without the deleted entries
+        assert_eq!(tables_per_level(&lsm), vec![0, 0, 1]);
+        assert_eq!(lsm.tombstone_count(), 0);
+        for i in 0..100 {
+            let expected: Option<&[u8]> = if i < 50 { None } else { Some(b"value") };
+            assert_eq!(lsm.get(get_key(i).as_bytes()).unwrap().as_deref(), expected);
+        }
+
+        drop(lsm);
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        for i in 0..100 {
+            let expected: Option<&[u8]> = if i < 50 { None } else { Some(b"value") };
+            assert_eq!(lsm.get(get_key(i).as_bytes()).unwrap().as_deref(), expected);
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_compact_range_size_tiered() {
+        compact_all(CompactionAlgorithmType::SizeTiered);
+    }
+
+    #[test]
+    fn test_compact_range_leveled() {
+        compact_all(CompactionAlgorithmType::Leveled);
+    }
+
+    #[test]
+    fn test_compact_range_time_window() {
+        compact_all(CompactionAlgorithmType::TimeWindow);
+    }
+
+    #[test]
+    fn test_compact_range_partial() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        db_config.lsm_max_level = 3;
+        // Only the manual compaction merges tables
+        db_config.compaction_enabled = false;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        // A memory table is flushed once it holds 9 keys, so each of these becomes a table, from the oldest one
+        let tables: [(&[usize], &str); 5] = [
+            (&[0, 1, 2, 3, 4, 5, 6, 7, 8], "first"),
+            (&[40, 41, 42, 43, 44, 45, 46, 47, 48], "between"),
+            (&[0, 45, 70, 71, 72, 73, 74, 75, 76], "newest"),
+            (&[80, 81, 82, 83, 84, 85, 86, 87, 88], "outside"),
+            (&[90, 91, 92, 93, 94, 95, 96, 97, 98], "memory"),
+        ];
+        let mut expected = vec![None; 100];
+        for (keys, value) in tables {
+            for &i in keys {
+                lsm.insert(get_key(i).as_bytes(), value.as_bytes(), TimeStamp::Now)
+                    .expect("Failed to insert into lsm");
+                expected[i] = Some(value);
+            }
+        }
+        lsm.wait_for_background_work();
+        assert_eq!(tables_per_level(&lsm), vec![4, 0, 0]);
+
+        // The table in between doesn't overlap the range, but it's older than a merged table that shares its key
+        lsm.compact_range(Some(get_key(0).as_bytes()), Some(get_key(0).as_bytes()))
+            .expect("Failed to compact range");
+
+        assert_eq!(tables_per_level(&lsm), vec![1, 0, 1]);
+        for (i, value) in expected.iter().enumerate() {
+            assert_eq!(
+                lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                value.map(str::as_bytes)
+            );
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_leveled_size_targets() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type: CompactionAlgorithmType::Leveled,
+                ..TestOptions::default()
+            },
+        );
+        db_config.lsm_max_level = 4;
+        db_config.lsm_max_per_level = 4;
+        db_config.lsm_leveled_amplification = 2;
+        db_config.lsm_leveled_base_size = BASE_SIZE;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        for i in 0..600 {
+            lsm.insert(get_key(i).as_bytes(), &[i as u8; 100], TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        lsm.wait_for_background_work();
+
+        // The first level is limited by its number of tables, the deeper ones by their size except for the last one
+        assert!(lsm.table_time_ranges()[0].len() <= db_config.lsm_max_per_level);
+        let level_sizes = lsm.level_sizes();
+        assert!(level_sizes[1] <= BASE_SIZE as u64);
+        assert!(level_sizes[2] <= 2 * BASE_SIZE as u64);
+        assert!(level_sizes[3] > 0);
+
+        drop(lsm);
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        for i in 0..600 {
+            assert_eq!(
+                lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                Some(&[i as u8; 100][..])
+            );
+        }
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_get_from_key_ordered_levels() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type: CompactionAlgorithmType::Leveled,
+                ..TestOptions::default()
+            },
+        );
+        db_config.lsm_max_level = 4;
+        db_config.lsm_max_per_level = 4;
+        db_config.lsm_leveled_amplification = 2;
+        db_config.lsm_leveled_base_size = BASE_SIZE;
+        let lsm = LSM::new(&db_config).unwrap();
+
+        // Every other key is inserted, so missing keys fall between the key ranges of the tables too
+        for i in (0..600).step_by(2) {
+            lsm.insert(get_key(i).as_bytes(), &[i as u8; 100], TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        lsm.wait_for_background_work();
+
+        // Key ranges of the deeper levels don't overlap, so point lookups binary search them
+        let check_gets = |lsm: &LSM| {
+            assert!(lsm.key_ordered_levels()[1..].iter().all(|ordered| *ordered));
+            for i in 0..600 {
+                let expected = (i % 2 == 0).then(|| vec![i as u8; 100]);
+                assert_eq!(
+                    lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                    expected.as_deref()
+                );
+            }
+            assert!(lsm.get(b"key").unwrap().is_none());
+            assert!(lsm.get(get_key(600).as_bytes()).unwrap().is_none());
+        };
+        check_gets(&lsm);
+
+        drop(lsm);
+        let lsm = LSM::load_from_dir(&db_config).expect("Failed to load lsm from dir");
+        check_gets(&lsm);
+
+        lsm.wait_for_background_work();
+    }
+
+    #[test]
+    fn test_compaction_io_rate_limit() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_io_rate_limit: 20_000,
+                ..TestOptions::default()
+            },
+        );
+        let lsm = LSM::new(&db_config).unwrap();
+
+        // Flushes alone write more than a second worth of bytes
+        let start = Instant::now();
+        for i in 0..300 {
+            lsm.insert(get_key(i).as_bytes(), &[i as u8; 100], TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        lsm.wait_for_background_work();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        // The limit can be lifted while the LSM is running
+        lsm.set_compaction_io_rate_limit(0);
+        for i in 300..600 {
+            lsm.insert(get_key(i).as_bytes(), &[i as u8; 100], TimeStamp::Now)
+                .expect("Failed to insert into lsm");
+        }
+        lsm.wait_for_background_work();
+
+        for i in 0..600 {
+            assert_eq!(
+                lsm.get(get_key(i).as_bytes()).unwrap().as_deref(),
+                Some(&[i as u8; 100][..])
+            );
+        }
+
+        drop(lsm);
+    }
+}
+
+#[cfg(test)]
+mod rate_limiter_tests {
+    use crate::lsm::rate_limiter::RateLimiter;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_rate_limiter_throttles() {
+        let rate_limiter = RateLimiter::new(1_000_000, false);
+        let start = Instant::now();
+        for _ in 0..10 {
+            rate_limiter.request(100_000);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(800));
+
+        // Without a limit nothing waits
+        rate_limiter.set_bytes_per_second(0);
+        let start = Instant::now();
+        rate_limiter.request(1_000_000_000);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_rate_limiter_auto_tune() {
+        let rate_limiter = RateLimiter::new(1_600_000, true);
+        assert_eq!(rate_limiter.rate(), 1_600_000);
+
+        // The rate backs off while foreground traffic is high, but not below a sixteenth of the limit
+        for expected_rate in [800_000, 400_000, 200_000, 100_000, 100_000] {
+            for _ in 0..1000 {
+                rate_limiter.record_foreground_request();
+            }
+            thread::sleep(Duration::from_millis(150));
+            assert_eq!(rate_limiter.rate(), expected_rate);
+        }
+
+        // Then it goes back up once the traffic calms down
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(rate_limiter.rate(), 200_000);
+
+        rate_limiter.set_auto_tune(false);
+        assert_eq!(rate_limiter.rate(), 1_600_000);
+    }
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use crate::lsm::paginator::Paginator;
+    use crate::sstable::table_properties::FORMAT_VERSION;
+    use crate::sstable::SSTable;
+    use crate::test_utils::{
+        get_config, get_key, legacy_config, legacy_fixture, legacy_value, TestOptions,
+    };
+    use crate::LSM;
+    use db_config::{CompactionAlgorithmType, DBConfig};
+    use db_error::Error;
+    use segment_elements::{read_file_header, FileKind, TimeStamp, FILE_HEADER_LEN};
+    use std::collections::HashSet;
+    use std::env;
+    use std::fs::{
+        create_dir_all, read, read_dir, remove_dir_all, remove_file, write, File, OpenOptions,
+    };
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    /// Inserts keys until the first 90 of them are flushed to sstables.
+    fn fill(db_config: &DBConfig) {
+        let lsm = LSM::new(db_config).unwrap();
+        for i in 0..100 {
+            let key = get_key(i);
+            lsm.insert(key.as_bytes(), key.as_bytes(), TimeStamp::Now)
+                .unwrap();
+        }
+        lsm.wait_for_background_work();
+    }
+
+    /// Opens the LSM without the WAL, so only the keys from the sstables are found.
+    fn assert_flushed_keys(db_config: &DBConfig) {
+        let lsm = LSM::new(db_config).unwrap();
+        for i in 0..90 {
+            let key = get_key(i);
+            assert_eq!(
+                lsm.get(key.as_bytes()).unwrap().as_deref(),
+                Some(key.as_bytes())
+            );
+        }
+    }
+
+    fn table_directories(db_config: &DBConfig) -> Vec<String> {
+        read_dir(&db_config.sstable_dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("sstable_"))
+            .collect()
+    }
+
+    #[test]
+    fn test_manifest_ignores_stray_directories() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        fill(&db_config);
+
+        // An unfinished flush, an unfinished compaction output and a directory with an unknown name
+        let parent_dir = Path::new(&db_config.sstable_dir);
+        create_dir_all(parent_dir.join("sstable_1_1_s")).unwrap();
+        create_dir_all(parent_dir.join("sstable_2_99999999999999999999_m")).unwrap();
+        File::create(parent_dir.join("sstable_2_99999999999999999999_m/SSTable-Data.db")).unwrap();
+        create_dir_all(parent_dir.join("sstable_unknown")).unwrap();
+
+        assert_flushed_keys(&db_config);
+    }
+
+    #[test]
+    fn test_manifest_torn_record() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        fill(&db_config);
+
+        // A record that was only partly written before a crash
+        let mut manifest = OpenOptions::new()
+            .append(true)
+            .open(Path::new(&db_config.sstable_dir).join("MANIFEST"))
+            .unwrap();
+        manifest.write_all(&[7, 0, 0, 0, 200, 0, 0]).unwrap();
+        drop(manifest);
+
+        assert_flushed_keys(&db_config);
+        // The torn record is dropped when the manifest is rewritten, so later records aren't lost behind it
+        fill(&db_config);
+        assert_flushed_keys(&db_config);
+    }
+
+    #[test]
+    fn test_manifest_created_from_legacy_directories() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        fill(&db_config);
+
+        let manifest_path = Path::new(&db_config.sstable_dir).join("MANIFEST");
+        remove_file(&manifest_path).unwrap();
+        create_dir_all(Path::new(&db_config.sstable_dir).join("sstable_x_y")).unwrap();
+
+        assert_flushed_keys(&db_config);
+        assert!(manifest_path.exists());
+    }
+
+    #[test]
+    fn test_manifest_missing_table() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        fill(&db_config);
+
+        let table_directory = table_directories(&db_config).remove(0);
+        remove_dir_all(Path::new(&db_config.sstable_dir).join(&table_directory)).unwrap();
+
+        assert!(matches!(
+            LSM::new(&db_config),
+            Err(Error::Corruption { path, .. }) if path.ends_with(&table_directory)
+        ));
+    }
+
+    #[test]
+    fn test_legacy_format_upgraded() {
+        // The fixtures were written by the version of the format from before the files had headers
+        for (layout, in_single_file) in [("separate_files", false), ("single_file", true)] {
+            let fixture = legacy_fixture(layout);
+            let db_config = legacy_config(&fixture, in_single_file);
+            assert_legacy_keys(&db_config);
+
+            let lsm = LSM::load_from_dir(&db_config).unwrap();
+            assert_eq!(lsm.upgrade_tables().unwrap(), 2);
+            assert_eq!(lsm.upgrade_tables().unwrap(), 0);
+            lsm.finalize();
+
+            let tables = table_directories(&db_config);
+            assert_eq!(tables.len(), 2);
+            for table_directory in tables {
+                let base_path = Path::new(&db_config.sstable_dir).join(table_directory);
+                let (kind, file_name) = if in_single_file {
+                    (FileKind::SSTable, "SSTable.db")
+                } else {
+                    (FileKind::SSTableData, "SSTable-Data.db")
+                };
+                assert_eq!(
+                    read_file_header(
+                        kind,
+                        &read(base_path.join(file_name)).unwrap(),
+                        FORMAT_VERSION
+                    )
+                    .unwrap(),
+                    Some(FORMAT_VERSION)
+                );
+                let properties = SSTable::open(base_path, in_single_file)
+                    .unwrap()
+                    .read_properties(db_config.use_variable_encoding)
+                    .unwrap();
+                assert_eq!(properties.format_version, FORMAT_VERSION);
+            }
+            assert_legacy_keys(&db_config);
+        }
+    }
+
+    /// Checks the keys of a legacy fixture, the newest of them are found in the WAL.
+    fn assert_legacy_keys(db_config: &DBConfig) {
+        let mut lsm = LSM::load_from_dir(db_config).unwrap();
+        for i in 0..30 {
+            let key = format!("key{:03}", i);
+            assert_eq!(
+                lsm.get(key.as_bytes()).unwrap().as_deref(),
+                legacy_value(i).as_ref().map(|value| value.as_bytes())
+            );
+        }
+
+        let mut paginator = Paginator::new(&mut lsm);
+        // The scans merge the keys of both tables with the records replayed from the WAL, whose tombstones hide
+        // a key of the first table and a key of the log
+        let expected: Vec<_> = (0..30)
+            .filter_map(|i| legacy_value(i).map(|value| (format!("key{:03}", i), value)))
+            .collect();
+        for (entries, expected) in [
+            (
+                paginator.range_scan(b"key000", b"key029", 0, 30).unwrap(),
+                &expected[..],
+            ),
+            (
+                paginator.prefix_scan(b"key00", 0, 30).unwrap(),
+                &expected[..9],
+            ),
+        ] {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(key, entry)| {
+                    (
+                        String::from_utf8(key.to_vec()).unwrap(),
+                        String::from_utf8(entry.get_value().to_vec()).unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(entries, expected);
+        }
+    }
+
+    /// Run by the child process, which is aborted at the crash point given in its environment.
+    #[test]
+    #[ignore]
+    fn crash_child() {
+        let (Ok(sstable_dir), Ok(write_ahead_log_dir), Ok(compaction_algorithm_type)) = (
+            env::var("LSM_CRASH_SSTABLE_DIR"),
+            env::var("LSM_CRASH_WAL_DIR"),
+            env::var("LSM_CRASH_COMPACTION"),
+        ) else {
+            return;
+        };
+        let compaction_algorithm_type = match compaction_algorithm_type.as_str() {
+            "leveled" => CompactionAlgorithmType::Leveled,
+            _ => CompactionAlgorithmType::SizeTiered,
+        };
+
+        let lsm = LSM::new(&get_config(
+            Path::new(&sstable_dir),
+            Path::new(&write_ahead_log_dir),
+            TestOptions {
+                compaction_algorithm_type,
+                ..TestOptions::default()
+            },
+        ))
+        .unwrap();
+        for i in 0..100 {
+            let key = get_key(i);
+            lsm.insert(key.as_bytes(), key.as_bytes(), TimeStamp::Now)
+                .unwrap();
+        }
+        lsm.wait_for_background_work();
+    }
+
+    /// Kills a process that fills the LSM at the given step, then checks that reopening it
+    /// removes the unfinished tables and that no written key is lost.
+    fn crash_at(crash_point: &str, compaction_algorithm_type: CompactionAlgorithmType) {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+
+        let status = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "manifest_tests::crash_child", "--ignored"])
+            .env("LSM_CRASH_POINT", crash_point)
+            .env("LSM_CRASH_SSTABLE_DIR", sstable_dir.path())
+            .env("LSM_CRASH_WAL_DIR", write_ahead_log_dir.path())
+            .env(
+                "LSM_CRASH_COMPACTION",
+                match compaction_algorithm_type {
+                    CompactionAlgorithmType::Leveled => "leveled",
+                    _ => "size_tiered",
+                },
+            )
+            .output()
+            .unwrap()
+            .status;
+        assert!(!status.success(), "The child didn't reach {}", crash_point);
+
+        let db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions {
+                compaction_algorithm_type,
+                ..TestOptions::default()
+            },
+        );
+        let lsm = LSM::load_from_dir(&db_config).unwrap();
+
+        // Keys are written one by one, so the ones that made it into the wal come first.
+        // At least the keys of the first flushed table were written before the crash
+        let written_keys = (0..100)
+            .take_while(|i| lsm.get(get_key(*i).as_bytes()).unwrap().is_some())
+            .count();
+        assert!(
+            written_keys >= 10,
+            "Only {} keys were recovered",
+            written_keys
+        );
+        for i in 0..100 {
+            let key = get_key(i);
+            let expected = if i < written_keys {
+                Some(key.as_bytes())
+            } else {
+                None
+            };
+            assert_eq!(lsm.get(key.as_bytes()).unwrap().as_deref(), expected);
+        }
+
+        // Only the tables in the levels are left in the directory
+        lsm.wait_for_background_work();
+        let directories: HashSet<PathBuf> = read_dir(sstable_dir.path())
+            .unwrap()
+            .map(|dir_entry| PathBuf::from(dir_entry.unwrap().file_name()))
+            .filter(|name| name.to_string_lossy().starts_with("sstable_"))
+            .collect();
+        assert_eq!(
+            directories,
+            lsm.table_directories().into_iter().collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_crash_after_flush() {
+        crash_at("flush_published", CompactionAlgorithmType::SizeTiered);
+    }
+
+    #[test]
+    fn test_crash_during_size_tiered_compaction() {
+        for crash_point in [
+            "compaction_merged",
+            "compaction_published",
+            "compaction_logged",
+            "compaction_removed_table",
+        ] {
+            crash_at(crash_point, CompactionAlgorithmType::SizeTiered);
+        }
+    }
+
+    #[test]
+    fn test_crash_during_leveled_compaction() {
+        for crash_point in [
+            "compaction_merged",
+            "compaction_published",
+            "compaction_logged",
+            "compaction_removed_table",
+        ] {
+            crash_at(crash_point, CompactionAlgorithmType::Leveled);
+        }
+    }
+}
+
+#[cfg(test)]
+mod table_cache_tests {
+    use crate::test_utils::{get_config, get_key, TestOptions};
+    use crate::LSM;
+    use db_config::DBConfig;
+    use segment_elements::TimeStamp;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// Returns the configuration of an LSM that keeps the given number of sstables open.
+    fn get_table_cache_config(
+        sstable_dir: &TempDir,
+        write_ahead_log_dir: &TempDir,
+        sstable_cache_capacity: usize,
+    ) -> DBConfig {
+        let mut db_config = get_config(
+            sstable_dir.path(),
+            write_ahead_log_dir.path(),
+            TestOptions::default(),
+        );
+        db_config.compaction_enabled = false;
+        // Keys are always looked up in the tables instead of the cache of read entries
+        db_config.cache_max_size = 0;
+        db_config.sstable_cache_capacity = sstable_cache_capacity;
+        db_config
+    }
+
+    /// Inserts keys until the first 90 of them are flushed to sstables, each table holding 10 of them.
+    fn fill(lsm: &LSM) {
+        for i in 0..100 {
+            let key = get_key(i);
+            lsm.insert(key.as_bytes(), key.as_bytes(), TimeStamp::Now)
+                .unwrap();
+        }
+        lsm.wait_for_background_work();
+    }
+
+    fn assert_keys(lsm: &LSM) {
+        for i in 0..100 {
+            let key = get_key(i);
+            assert_eq!(
+                lsm.get(key.as_bytes()).unwrap().as_deref(),
+                Some(key.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn test_table_cache_capacity() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_table_cache_config(&sstable_dir, &write_ahead_log_dir, 3);
+        let lsm = LSM::new(&db_config).unwrap();
+        fill(&lsm);
+        assert!(lsm.table_directories().len() > db_config.sstable_cache_capacity);
+
+        // Tables are reopened once they're evicted
+        assert_keys(&lsm);
+        assert_eq!(lsm.cached_tables().len(), db_config.sstable_cache_capacity);
+        assert_keys(&lsm);
+        assert_eq!(lsm.cached_tables().len(), db_config.sstable_cache_capacity);
+    }
+
+    #[test]
+    fn test_table_cache_disabled() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_table_cache_config(&sstable_dir, &write_ahead_log_dir, 0);
+        let lsm = LSM::new(&db_config).unwrap();
+        fill(&lsm);
+
+        assert_keys(&lsm);
+        assert!(lsm.cached_tables().is_empty());
+    }
+
+    #[test]
+    fn test_table_cache_evicts_compacted_tables() {
+        let (sstable_dir, write_ahead_log_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let db_config = get_table_cache_config(&sstable_dir, &write_ahead_log_dir, 100);
+        let lsm = LSM::new(&db_config).unwrap();
+        fill(&lsm);
+
+        assert_keys(&lsm);
+        assert_eq!(lsm.cached_tables().len(), lsm.table_directories().len());
+
+        lsm.compact_range(None, None).unwrap();
+        assert!(lsm.cached_tables().is_empty());
+
+        // Only the merged table is opened again
+        assert_keys(&lsm);
+        let live_tables: Vec<_> = lsm
+            .table_directories()
+            .iter()
+            .map(|directory| Path::new(&db_config.sstable_dir).join(directory))
+            .collect();
+        assert_eq!(lsm.cached_tables(), live_tables);
+    }
 }
@@ -1,21 +1,32 @@
-use crate::lsm::iterator::LSMIterator;
+use crate::lsm::background::BackgroundWorkers;
+use crate::lsm::iterator::{LSMIterator, ScanParameters};
+use crate::lsm::manifest::{Manifest, TableMetadata, VersionEdit, TEMP_TABLE_SUFFIX};
+use crate::lsm::rate_limiter::RateLimiter;
+use crate::lsm::snapshot::{LSMSnapshot, SnapshotRegistry};
+use crate::lsm::table_cache::TableCache;
 use crate::mem_pool::MemoryPool;
 use crate::memtable::MemoryTable;
-use crate::sstable::SSTable;
+use crate::sstable::table_properties::FORMAT_VERSION;
+use crate::sstable::{KeyRange, MergeContext, SSTable, TableOptions};
 use compression::CompressionDictionary;
 use db_config::{CompactionAlgorithmType, DBConfig};
 use lru_cache::LRUCache;
-use num_traits::pow;
-use segment_elements::{MemoryEntry, TimeStamp};
+use segment_elements::{MemoryEntry, TimeStamp, WriteBatch};
 use std::cmp::Ordering;
-use std::error::Error;
-use std::fs::{create_dir_all, read_dir, remove_dir_all};
-use std::io;
-use std::path::PathBuf;
+use std::fs::{create_dir_all, read_dir, remove_dir_all, rename, File};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use write_ahead_log::WriteAheadLog;
 
+mod background;
 mod iterator;
+mod manifest;
 pub mod paginator;
+pub(crate) mod rate_limiter;
+pub mod snapshot;
+mod table_cache;
 
 #[derive(Clone, Copy)]
 pub enum ScanType {
@@ -31,13 +42,29 @@ struct LSMConfig {
     // Maximum number of SSTables per level
     max_per_level: usize,
     leveled_amplification: usize,
+    // Size in bytes of the second level under leveled compaction, each deeper level can be leveled_amplification times bigger
+    leveled_base_size: u64,
+    // Share of tombstones at which an SSTable is compacted on its own
+    tombstone_compaction_ratio: f64,
     // The compaction algorithm in use
     compaction_algorithm: CompactionAlgorithmType,
+    // Length of the time windows of the time window compaction, in microseconds
+    time_window: u128,
+    // Size in bytes that all tables can take before the oldest ones are deleted by the FIFO compaction
+    fifo_max_size: u64,
     in_single_file: bool,
-    summary_density: usize,
+    // Flag indicating whether the SSTables are mapped into memory when they're read
+    use_mmap: bool,
     index_density: usize,
+    // Options of the SSTables that are written
+    table_options: TableOptions,
     compaction_enabled: bool,
     use_variable_encoding: bool,
+    // Number of full memory tables that can wait for a flush before writes are blocked
+    memory_table_pool_num: usize,
+    // Bytes per second that flushes and compactions can read and write, 0 if they aren't limited
+    compaction_io_rate_limit: u64,
+    compaction_io_auto_tune: bool,
 }
 
 impl LSMConfig {
@@ -47,25 +74,57 @@ impl LSMConfig {
             max_level: dbconfig.lsm_max_level,
             max_per_level: dbconfig.lsm_max_per_level,
             leveled_amplification: dbconfig.lsm_leveled_amplification,
+            leveled_base_size: dbconfig.lsm_leveled_base_size as u64,
+            tombstone_compaction_ratio: dbconfig.lsm_tombstone_compaction_ratio,
             compaction_algorithm: dbconfig.compaction_algorithm_type,
+            time_window: (dbconfig.lsm_time_window_ms as u128 * 1000).max(1),
+            fifo_max_size: dbconfig.lsm_fifo_max_size as u64,
             compaction_enabled: dbconfig.compaction_enabled,
             use_variable_encoding: dbconfig.use_variable_encoding,
             in_single_file: dbconfig.sstable_single_file,
-            summary_density: dbconfig.summary_density,
+            use_mmap: dbconfig.sstable_use_mmap,
             index_density: dbconfig.index_density,
+            table_options: TableOptions::from(dbconfig),
+            memory_table_pool_num: dbconfig.memory_table_pool_num,
+            compaction_io_rate_limit: dbconfig.lsm_compaction_io_rate_limit as u64,
+            compaction_io_auto_tune: dbconfig.lsm_compaction_io_auto_tune,
         }
     }
 }
 
-/// LSM(Log-Structured Merge Trees) struct for optimizing write-intensive workloads
+/// LSM(Log-Structured Merge Trees) struct for optimizing write-intensive workloads.
+/// It can be shared between threads: reads run in parallel while writes are applied one at a time.
+/// Full memory tables are flushed and SSTables are compacted by background workers.
 pub struct LSM {
-    // Each vector represents one level containing directory names for SSTables
-    sstable_directory_names: Vec<Vec<PathBuf>>,
-    wal: WriteAheadLog,
-    mem_pool: MemoryPool,
-    lru_cache: LRUCache,
-    compression_dictionary: Option<CompressionDictionary>,
+    state: Arc<LSMState>,
+    workers: BackgroundWorkers,
+}
+
+/// The part of the LSM that is shared with the background workers.
+struct LSMState {
+    // Each vector represents one level containing the metadata of its SSTables, from the oldest to the newest
+    levels: RwLock<Vec<Vec<TableMetadata>>>,
+    // Indexes of the tables of each level sorted by their key ranges, None if the key ranges of the level overlap.
+    // Changed while the levels are locked for writing, so point lookups can binary search the levels
+    key_orders: RwLock<Vec<Option<Vec<usize>>>>,
+    // Log of the changes of the levels. Locked before the levels, so the changes are logged in the order they're applied
+    manifest: Mutex<Manifest>,
+    // Held for the whole write, so writes are serialized. Shared by all column families of the database
+    wal: Arc<Mutex<WriteAheadLog>>,
+    // The column family of the LSM, None for the default one
+    column_family: Option<String>,
+    mem_pool: RwLock<MemoryPool>,
+    lru_cache: Mutex<LRUCache>,
+    // Open tables that point lookups read from, evicted once compaction removes them
+    table_cache: TableCache,
+    compression_dictionary: Mutex<Option<CompressionDictionary>>,
+    // Limits the disk bandwidth taken by flushes and compactions
+    rate_limiter: RateLimiter,
     config: LSMConfig,
+    // Time stamps of all live snapshots
+    snapshots: SnapshotRegistry,
+    // The last time stamp given to a write, used to keep the write time stamps strictly increasing
+    last_timestamp: Mutex<u128>,
 }
 
 impl LSM {
@@ -79,46 +138,80 @@ impl LSM {
     /// # Returns
     ///
     /// LSM instance
-    pub fn new(dbconfig: &DBConfig) -> Result<Self, Box<dyn Error>> {
-        let lru_cache = LRUCache::new(dbconfig.cache_max_size);
-        let mem_pool = MemoryPool::new(dbconfig)?;
-        let wal = WriteAheadLog::from_dir(&dbconfig)?;
+    pub fn new(dbconfig: &DBConfig) -> db_error::Result<Self> {
+        let wal = WriteAheadLog::from_dir(dbconfig)?;
+        LSM::new_column_family(dbconfig, None, Arc::new(Mutex::new(wal)))
+    }
+
+    /// Creates a new LSM instance for a column family, which writes into the WAL shared by all column families.
+    /// Each column family keeps its SSTables in the SSTable directory of its configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_config` - Configuration of the column family.
+    /// * `column_family` - Name of the column family, None for the default one.
+    /// * `wal` - The shared WAL.
+    ///
+    /// # Returns
+    ///
+    /// LSM instance
+    pub fn new_column_family(
+        dbconfig: &DBConfig,
+        column_family: Option<&str>,
+        wal: Arc<Mutex<WriteAheadLog>>,
+    ) -> db_error::Result<Self> {
+        LSM::build(dbconfig, MemoryPool::new(dbconfig)?, column_family, wal)
+    }
 
-        let mut sstable_directory_names = vec![vec![]; dbconfig.lsm_max_level];
+    /// Creates a new LSM instance that uses the given memory pool.
+    fn build(
+        dbconfig: &DBConfig,
+        mem_pool: MemoryPool,
+        column_family: Option<&str>,
+        wal: Arc<Mutex<WriteAheadLog>>,
+    ) -> db_error::Result<Self> {
+        let lru_cache = LRUCache::new(dbconfig.cache_max_size);
 
         create_dir_all(&dbconfig.sstable_dir)?;
-        let dirs = read_dir(&dbconfig.sstable_dir)?
-            .map(|dir_entry| dir_entry.unwrap().path())
-            .filter(|entry| entry.is_dir())
-            .collect::<Vec<PathBuf>>();
-
-        for dir in dirs {
-            let level = dir
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .split("_")
-                .collect::<Vec<&str>>()[1]
-                .parse::<usize>()
-                .unwrap();
-            let path = PathBuf::from(dir.to_str().unwrap().split("/").last().unwrap());
-            sstable_directory_names[level - 1].push(path);
-        }
+        let mut compression_dictionary = match dbconfig.use_compression {
+            true => Some(CompressionDictionary::load(
+                dbconfig.compression_dictionary_path.as_str(),
+            )?),
+            false => None,
+        };
 
-        Ok(LSM {
-            config: LSMConfig::from(dbconfig),
+        let (manifest, levels) = Manifest::open(
+            Path::new(&dbconfig.sstable_dir),
+            dbconfig.lsm_max_level,
+            || LSM::find_legacy_tables(dbconfig, &mut compression_dictionary),
+        )?;
+
+        let config = LSMConfig::from(dbconfig);
+        let state = LSMState {
+            rate_limiter: RateLimiter::new(
+                config.compaction_io_rate_limit,
+                config.compaction_io_auto_tune,
+            ),
+            config,
             wal,
-            mem_pool,
-            lru_cache,
-            compression_dictionary: match dbconfig.use_compression {
-                true => Some(
-                    CompressionDictionary::load(dbconfig.compression_dictionary_path.as_str())
-                        .unwrap(),
-                ),
-                false => None,
-            },
-            sstable_directory_names,
+            column_family: column_family.map(String::from),
+            mem_pool: RwLock::new(mem_pool),
+            lru_cache: Mutex::new(lru_cache),
+            table_cache: TableCache::new(
+                dbconfig.sstable_cache_capacity,
+                dbconfig.sstable_use_mmap,
+            ),
+            compression_dictionary: Mutex::new(compression_dictionary),
+            key_orders: RwLock::new(key_orders(&levels)),
+            levels: RwLock::new(levels),
+            manifest: Mutex::new(manifest),
+            snapshots: SnapshotRegistry::default(),
+            last_timestamp: Mutex::new(0),
+        };
+
+        Ok(LSM {
+            state: Arc::new(state),
+            workers: BackgroundWorkers::new(),
         })
     }
 
@@ -143,94 +236,154 @@ impl LSM {
         ))
     }
 
-    /// Determines whether sstable is in a single file by reading its path.
+    /// Finds the tables of each level in the SSTable directories named `sstable_{level}_{time stamp}_{s|m}`,
+    /// which is how the levels were stored before the manifest. Directories that don't match the name
+    /// or whose table can't be read are skipped.
     ///
     /// # Arguments
     ///
-    /// * `path` - Path to the SSTable
+    /// * `db_config` - Configuration file.
+    /// * `compression_dictionary` - Used to decode the key ranges of the tables.
     ///
     /// # Returns
     ///
-    /// Boolean indicating the construction of SSTable
-    fn is_in_single_file(path: &PathBuf) -> bool {
-        path.to_str().unwrap().chars().last().unwrap() == 's'
+    /// A `Result` containing the metadata of the tables of each level, from the oldest to the newest.
+    fn find_legacy_tables(
+        dbconfig: &DBConfig,
+        compression_dictionary: &mut Option<CompressionDictionary>,
+    ) -> db_error::Result<Vec<Vec<TableMetadata>>> {
+        let parent_dir = PathBuf::from(&dbconfig.sstable_dir);
+        let mut levels = vec![vec![]; dbconfig.lsm_max_level];
+
+        for dir_entry in read_dir(&parent_dir)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name().to_string_lossy().to_string();
+            // Other column families can keep their directories inside of this one
+            if !dir_entry.path().is_dir() || !name.starts_with("sstable_") {
+                continue;
+            }
+
+            let parsed_name = match name.split('_').collect::<Vec<&str>>()[..] {
+                [_, level, time_stamp, layout] => {
+                    match (level.parse::<usize>(), time_stamp.parse::<u128>(), layout) {
+                        (Ok(level), Ok(time_stamp), "s" | "m")
+                            if level >= 1 && level <= dbconfig.lsm_max_level =>
+                        {
+                            Some((level - 1, time_stamp, layout == "s"))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            let Some((level, time_stamp, in_single_file)) = parsed_name else {
+                eprintln!("Skipping the unknown SSTable directory {}.", name);
+                continue;
+            };
+
+            match LSM::read_table_metadata(
+                &parent_dir,
+                PathBuf::from(&name),
+                in_single_file,
+                dbconfig.use_variable_encoding,
+                compression_dictionary,
+            ) {
+                Ok(table) => levels[level].push((time_stamp, table)),
+                Err(e) => eprintln!("Skipping the unreadable SSTable {}: {}", name, e),
+            }
+        }
+
+        // Newer tables are searched first, so they have to come after the older ones
+        Ok(levels
+            .into_iter()
+            .map(|mut tables| {
+                tables.sort_by_key(|(time_stamp, _)| *time_stamp);
+                tables.into_iter().map(|(_, table)| table).collect()
+            })
+            .collect())
     }
 
-    /// Function that returns full path to a sstable and wether or not is it in single file
+    /// Reads the metadata of an SSTable.
     ///
     /// # Arguments
     ///
-    /// * `sstable_directory` - The directory of sstable
+    /// * `parent_dir` - The SSTable directory.
+    /// * `directory` - Directory of the table, relative to the SSTable directory.
+    /// * `in_single_file` - Indicates whether the table is stored in a single or multiple files.
+    /// * `use_variable_encoding` - Indicates whether the entries are serialized with variable encoding.
+    /// * `compression_dictionary` - Used to decode the key range of the table.
     ///
     /// # Returns
     ///
-    /// Full path and boolean indicating its structure
-    fn get_sstable_path(&self, sstable_directory: &PathBuf) -> (PathBuf, bool) {
-        let in_single_file = LSM::is_in_single_file(sstable_directory);
-        let full_path = self.config.parent_dir.join(sstable_directory);
-        (full_path, in_single_file)
+    /// A `Result` containing the metadata of the table.
+    fn read_table_metadata(
+        parent_dir: &Path,
+        directory: PathBuf,
+        in_single_file: bool,
+        use_variable_encoding: bool,
+        compression_dictionary: &mut Option<CompressionDictionary>,
+    ) -> db_error::Result<TableMetadata> {
+        let base_path = parent_dir.join(&directory);
+
+        let mut size = 0;
+        for file in read_dir(&base_path)? {
+            size += file?.metadata()?.len();
+        }
+
+        let properties =
+            SSTable::open(base_path, in_single_file)?.read_properties(use_variable_encoding)?;
+        let (min_key, max_key) = match compression_dictionary {
+            Some(compression_dictionary) if properties.entry_count > 0 => (
+                compression_dictionary.decode(&properties.min_key)?,
+                compression_dictionary.decode(&properties.max_key)?,
+            ),
+            _ => (properties.min_key, properties.max_key),
+        };
+
+        Ok(TableMetadata {
+            directory,
+            in_single_file,
+            min_key,
+            max_key,
+            size,
+            entry_count: properties.entry_count,
+            tombstone_count: properties.tombstone_count,
+            min_time_stamp: properties.min_time_stamp,
+            max_time_stamp: properties.max_time_stamp,
+            data_size: properties.data_size,
+            format_version: properties.format_version,
+        })
     }
 
     /// Finds SSTables with similar key ranges as the SSTable that started compaction process.
     ///
     /// # Arguments
     ///
-    /// * `sstable_directory_names` - Directory names of the sstables
-    /// * `parent_dir` - Parent directory
+    /// * `levels` - Metadata of the sstables of each level
     /// * `main_min_key` - Min key from main SSTable
     /// * `main_max_key` - Max key from main SSTable
     /// * `level` - One level below our main SSTable that started compaction process
     ///
     /// # Returns
     ///
-    /// A `Result` containing vector with tuple as elements.
-    /// Each tuple contains index of a path in sstable_directory_names[level] as well as an actual path to that SSTable.
+    /// A vector with tuple as elements.
+    /// Each tuple contains index of a table in levels[level] as well as the metadata of that SSTable.
     /// The purpose of an index is to be able to quickly delete all the tables involved in compaction process later.
     fn find_similar_key_ranges<'a>(
-        sstable_directory_names: &'a Vec<Vec<PathBuf>>,
-        parent_dir: &'a PathBuf,
+        levels: &'a [Vec<TableMetadata>],
         main_min_key: &[u8],
         main_max_key: &[u8],
         level: usize,
-        compression_dictionary: &mut Option<CompressionDictionary>,
-    ) -> io::Result<Vec<(usize, &'a PathBuf)>> {
-        let base_paths = sstable_directory_names[level]
+    ) -> Vec<(usize, &'a TableMetadata)> {
+        levels[level]
             .iter()
             .enumerate()
-            .filter(|(_, path)| {
-                let sstable_base_path = parent_dir.join(path);
-                let in_single_file = LSM::is_in_single_file(path);
-                match SSTable::get_key_range(sstable_base_path, in_single_file) {
-                    Ok((min_key, max_key)) => {
-                        let decoded_min_key = match compression_dictionary {
-                            Some(compression_dictionary) => compression_dictionary
-                                .decode(&min_key.to_vec().into_boxed_slice())
-                                .unwrap()
-                                .clone(),
-                            None => min_key.to_vec().into_boxed_slice(),
-                        };
-                        let decoded_max_key = match compression_dictionary {
-                            Some(compression_dictionary) => compression_dictionary
-                                .decode(&max_key.to_vec().into_boxed_slice())
-                                .unwrap()
-                                .clone(),
-                            None => max_key.to_vec().into_boxed_slice(),
-                        };
-                        let left = decoded_max_key >= Box::from(main_min_key);
-                        let right = decoded_min_key <= Box::from(main_max_key);
-                        let condition = left && right;
-                        condition
-                    }
-                    Err(_) => false,
-                }
-            })
-            .collect();
-
-        Ok(base_paths)
+            .filter(|(_, table)| *table.max_key >= *main_min_key && *table.min_key <= *main_max_key)
+            .collect()
     }
 
     /// Function that returns bytes representing entry that is associated with a given key if it exists
-    /// Also it inserts the record in lru cache if it exists.
+    /// Also it inserts the record in lru cache if it was read from a sstable.
     /// If record doesn't exist, it still gets inserted into cache as unsuccessful get request
     ///
     /// # Arguments
@@ -239,42 +392,51 @@ impl LSM {
     ///
     /// # Returns
     ///
-    /// An io::Result containing bytes representing data associated with a given key.
+    /// A db_error::Result containing bytes representing data associated with a given key.
     /// Bytes are wrapped in option because key may not be present in our database
-    pub fn get(&mut self, key: &[u8]) -> io::Result<Option<Box<[u8]>>> {
-        if let Some(memory_entry) = self.mem_pool.get(key) {
-            self.lru_cache.insert(&key, Some(memory_entry.clone()));
+    pub fn get(&self, key: &[u8]) -> db_error::Result<Option<Box<[u8]>>> {
+        self.state.rate_limiter.record_foreground_request();
+        let now = TimeStamp::Now.get_time();
 
-            return if !memory_entry.get_tombstone() {
+        let memory_entry = self.state.mem_pool.read().unwrap().get(key);
+        if let Some(memory_entry) = memory_entry {
+            return if !memory_entry.get_tombstone() && !memory_entry.is_expired(now) {
                 Ok(Some(memory_entry.get_value()))
             } else {
                 Ok(None)
             };
         }
 
-        if let Some(memory_entry) = self.lru_cache.get(key) {
-            self.lru_cache.insert(&key, Some(memory_entry.clone()));
-
-            return if !memory_entry.get_tombstone() {
+        let memory_entry = self.state.lru_cache.lock().unwrap().get(key);
+        if let Some(memory_entry) = memory_entry {
+            return if !memory_entry.get_tombstone() && !memory_entry.is_expired(now) {
                 Ok(Some(memory_entry.get_value()))
             } else {
                 Ok(None)
             };
         }
 
-        for level in &self.sstable_directory_names {
-            for sstable_dir in level.iter().rev() {
-                let (path, in_single_file) = self.get_sstable_path(sstable_dir);
-                let mut sstable = SSTable::open(path, in_single_file)?;
-                if let Some(memory_entry) = sstable.get(
+        // The cache is filled while holding the levels, so a flush can't refresh the key before an older version is put in
+        let levels = self.state.levels.read().unwrap();
+        let key_orders = self.state.key_orders.read().unwrap();
+        for (level, key_order) in levels.iter().zip(key_orders.iter()) {
+            for table in tables_with_key(level, key_order.as_deref(), key) {
+                let (path, in_single_file) = self.state.get_sstable_path(table);
+                let sstable = self.state.table_cache.get(&path, in_single_file)?;
+                let memory_entry = sstable.lock().unwrap().get(
                     key,
-                    self.config.index_density,
-                    &mut self.compression_dictionary,
-                    self.config.use_variable_encoding,
-                ) {
-                    self.lru_cache.insert(&key, Some(memory_entry.clone()));
-
-                    return if !memory_entry.get_tombstone() {
+                    self.state.config.index_density,
+                    &mut self.state.compression_dictionary.lock().unwrap(),
+                    self.state.config.use_variable_encoding,
+                )?;
+                if let Some(memory_entry) = memory_entry {
+                    self.state
+                        .lru_cache
+                        .lock()
+                        .unwrap()
+                        .insert(&key, Some(memory_entry.clone()));
+
+                    return if !memory_entry.get_tombstone() && !memory_entry.is_expired(now) {
                         Ok(Some(memory_entry.get_value()))
                     } else {
                         Ok(None)
@@ -283,7 +445,7 @@ impl LSM {
             }
         }
 
-        self.lru_cache.insert(key, None);
+        self.state.lru_cache.lock().unwrap().insert(key, None);
         Ok(None)
     }
 
@@ -298,236 +460,412 @@ impl LSM {
     ///
     /// # Returns
     ///
-    /// An io::Result representing the success of operation
-    pub fn insert(&mut self, key: &[u8], value: &[u8], time_stamp: TimeStamp) -> io::Result<()> {
-        self.wal.insert(key, value, time_stamp)?;
-        if let Some(memory_table) = self.mem_pool.insert(key, value, time_stamp) {
-            self.flush(memory_table)?;
+    /// A db_error::Result representing the success of operation
+    pub fn insert(&self, key: &[u8], value: &[u8], time_stamp: TimeStamp) -> db_error::Result<()> {
+        self.workers
+            .throttle(self.state.config.memory_table_pool_num);
+        self.state.rate_limiter.record_foreground_request();
+        let mut wal = self.state.wal.lock().unwrap();
+        let time_stamp = self.next_timestamp(time_stamp);
+
+        let wal_position = wal.end_position();
+
+        wal.insert(self.state.column_family.as_deref(), key, value, time_stamp)?;
+        let memory_table =
+            self.state
+                .mem_pool
+                .write()
+                .unwrap()
+                .insert(key, value, time_stamp, wal_position);
+        if let Some(memory_table) = memory_table {
+            self.schedule_flush(memory_table);
         }
 
         Ok(())
     }
 
-    /// Function that delets entry into database, First we put this record in wal and the in read/write memory table
-    /// Also gives signal for flushing process if needed
+    /// Function that inserts entry which expires after the given time to live. The expiry is stored in the wal
+    /// and in the memory entry, so the entry stays hidden after the flush and gets dropped during compaction.
     ///
     /// # Arguments
     ///
     /// * `key` - The key that user passed to our program
+    /// * `value` - The value that user passed to our program
+    /// * `ttl` - How long the entry lives, counting from the time stamp of the insert
     /// * `time_stamp` - the time when event took place
     ///
     /// # Returns
     ///
-    /// An io::Result representing the success of operation
-    pub fn delete(&mut self, key: &[u8], time_stamp: TimeStamp) -> io::Result<()> {
-        self.wal.delete(key, time_stamp)?;
-        if let Some(memory_table) = self.mem_pool.delete(key, time_stamp) {
-            self.flush(memory_table)?;
+    /// A db_error::Result representing the success of operation
+    pub fn insert_with_ttl(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+        time_stamp: TimeStamp,
+    ) -> db_error::Result<()> {
+        self.workers
+            .throttle(self.state.config.memory_table_pool_num);
+        self.state.rate_limiter.record_foreground_request();
+        let mut wal = self.state.wal.lock().unwrap();
+        let time_stamp = self.next_timestamp(time_stamp);
+        let expiry = time_stamp.get_time() + ttl.as_micros();
+
+        let wal_position = wal.end_position();
+
+        wal.insert_with_expiry(
+            self.state.column_family.as_deref(),
+            key,
+            value,
+            expiry,
+            time_stamp,
+        )?;
+        let memory_table = self.state.mem_pool.write().unwrap().insert_with_expiry(
+            key,
+            value,
+            expiry,
+            time_stamp,
+            wal_position,
+        );
+        if let Some(memory_table) = memory_table {
+            self.schedule_flush(memory_table);
         }
+
         Ok(())
     }
 
-    /// FLushes MemTable onto disk and starts the compaction process if necessary.
+    /// Function that inserts entry which expires after the given time to live, but only if the key has no live value.
+    /// The wal lock is held from the read until the write, so of all concurrent inserts of the same key exactly one is written.
     ///
     /// # Arguments
     ///
-    /// * `inner_mem` - The MemTable that needs to be flushed.
-    /// * `db_config` - Configuration file.
+    /// * `key` - The key that user passed to our program
+    /// * `value` - The value that user passed to our program
+    /// * `ttl` - How long the entry lives, counting from the time stamp of the insert
+    /// * `time_stamp` - the time when event took place
     ///
     /// # Returns
     ///
-    /// io::Result indicating success of flushing process
-    pub(crate) fn flush<'a>(&mut self, mem_table: MemoryTable) -> io::Result<()> {
-        let in_single_file = self.config.in_single_file;
-        let summary_density = self.config.summary_density;
-        let index_density = self.config.index_density;
-        let directory_name = LSM::get_directory_name(0, in_single_file);
-        let sstable_base_path = self.config.parent_dir.join(directory_name.as_path());
-        let use_variable_encoding = self.config.use_variable_encoding;
-        let memtable_wal_bytes_len = mem_table.wal_size();
-
-        let mut sstable = SSTable::open(sstable_base_path.to_owned(), in_single_file)?;
-        sstable.flush(
-            mem_table,
-            summary_density,
-            index_density,
-            Some(&mut self.lru_cache),
-            &mut self.compression_dictionary,
-            use_variable_encoding,
-        )?;
+    /// A db_error::Result containing true if the key had no value and the entry was inserted
+    pub fn insert_with_ttl_if_absent(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+        time_stamp: TimeStamp,
+    ) -> db_error::Result<bool> {
+        self.workers
+            .throttle(self.state.config.memory_table_pool_num);
+        self.state.rate_limiter.record_foreground_request();
+        let mut wal = self.state.wal.lock().unwrap();
+
+        if self.get(key)?.is_some() {
+            return Ok(false);
+        }
 
-        self.wal.remove_logs_until(memtable_wal_bytes_len)?;
+        let time_stamp = self.next_timestamp(time_stamp);
+        let expiry = time_stamp.get_time() + ttl.as_micros();
+        let wal_position = wal.end_position();
 
-        self.sstable_directory_names[0].push(PathBuf::from(directory_name));
-        if self.config.compaction_enabled
-            && self.sstable_directory_names[0].len() > self.config.max_per_level
-        {
-            if self.config.compaction_algorithm == CompactionAlgorithmType::SizeTiered {
-                self.size_tiered_compaction(0)?;
-            } else {
-                self.leveled_compaction(0)?;
-            }
+        wal.insert_with_expiry(
+            self.state.column_family.as_deref(),
+            key,
+            value,
+            expiry,
+            time_stamp,
+        )?;
+        let memory_table = self.state.mem_pool.write().unwrap().insert_with_expiry(
+            key,
+            value,
+            expiry,
+            time_stamp,
+            wal_position,
+        );
+        if let Some(memory_table) = memory_table {
+            self.schedule_flush(memory_table);
         }
 
-        Ok(())
+        Ok(true)
     }
 
-    fn remove_all_compacted(sstable_base_paths: Vec<PathBuf>) -> io::Result<()> {
-        for dir in sstable_base_paths {
-            remove_dir_all(dir)?;
+    /// Function that delets entry into database, First we put this record in wal and the in read/write memory table
+    /// Also gives signal for flushing process if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that user passed to our program
+    /// * `time_stamp` - the time when event took place
+    ///
+    /// # Returns
+    ///
+    /// A db_error::Result representing the success of operation
+    pub fn delete(&self, key: &[u8], time_stamp: TimeStamp) -> db_error::Result<()> {
+        self.workers
+            .throttle(self.state.config.memory_table_pool_num);
+        self.state.rate_limiter.record_foreground_request();
+        let mut wal = self.state.wal.lock().unwrap();
+        let time_stamp = self.next_timestamp(time_stamp);
+
+        let wal_position = wal.end_position();
+
+        wal.delete(self.state.column_family.as_deref(), key, time_stamp)?;
+        let memory_table =
+            self.state
+                .mem_pool
+                .write()
+                .unwrap()
+                .delete(key, time_stamp, wal_position);
+        if let Some(memory_table) = memory_table {
+            self.schedule_flush(memory_table);
         }
         Ok(())
     }
 
-    /// Size-tiered compaction algorithm. Deletes all SSTables on current level and makes one bigger table located one level below
-    /// This process can be propagated through levels
+    /// Function that deletes the key only if it has a live value. The wal lock is held from the read until the
+    /// write, so of all concurrent deletes of the same key exactly one finds the value.
     ///
     /// # Arguments
     ///
-    /// * `level` - The level where compactions started
+    /// * `key` - The key that user passed to our program
+    /// * `time_stamp` - the time when event took place
     ///
     /// # Returns
     ///
-    /// io::Result indicating success of SSTable merging process
-    fn size_tiered_compaction(&mut self, mut level: usize) -> io::Result<()> {
-        let use_variable_encoding = self.config.use_variable_encoding;
-        let merged_in_single_file = self.config.in_single_file;
+    /// A db_error::Result containing true if the key had a value and was deleted
+    pub fn delete_if_present(&self, key: &[u8], time_stamp: TimeStamp) -> db_error::Result<bool> {
+        self.workers
+            .throttle(self.state.config.memory_table_pool_num);
+        self.state.rate_limiter.record_foreground_request();
+        let mut wal = self.state.wal.lock().unwrap();
+
+        if self.get(key)?.is_none() {
+            return Ok(false);
+        }
 
-        while self.sstable_directory_names[level].len() > self.config.max_per_level {
-            let mut sstable_base_paths = Vec::new();
-            let mut sstable_single_file = Vec::new();
+        let time_stamp = self.next_timestamp(time_stamp);
+        let wal_position = wal.end_position();
 
-            // Find all SSTables that need to be merged and create vector of booleans indicating whether each SSTable is in a single file
-            for path in &self.sstable_directory_names[level] {
-                let base_path = self.config.parent_dir.join(path);
-                sstable_base_paths.push(base_path);
-                sstable_single_file.push(LSM::is_in_single_file(path));
-            }
+        wal.delete(self.state.column_family.as_deref(), key, time_stamp)?;
+        let memory_table =
+            self.state
+                .mem_pool
+                .write()
+                .unwrap()
+                .delete(key, time_stamp, wal_position);
+        if let Some(memory_table) = memory_table {
+            self.schedule_flush(memory_table);
+        }
 
-            // Make a name for new SSTable and convert PathBuf into Path
-            let sstable_base_paths: Vec<_> = sstable_base_paths
-                .iter()
-                .map(|path_buf| path_buf.to_owned())
-                .collect();
-            let merged_directory =
-                PathBuf::from(LSM::get_directory_name(level + 1, merged_in_single_file));
-            let merged_base_path = self.config.parent_dir.join(merged_directory.clone());
-
-            // Merge them all together, push merged SSTable into sstable_directory_names and delete all SSTables involved in merging process
-            SSTable::merge(
-                sstable_base_paths.clone(),
-                sstable_single_file,
-                &merged_base_path,
-                merged_in_single_file,
-                self.config.summary_density,
-                self.config.index_density,
-                use_variable_encoding,
-                &mut self.compression_dictionary,
-            )?;
-            self.sstable_directory_names[level].clear();
-            Self::remove_all_compacted(sstable_base_paths)?;
-            self.sstable_directory_names[level + 1].push(merged_directory);
+        Ok(true)
+    }
 
-            // Check for possibility of another compaction occurring
-            level += 1;
-            if level >= self.config.max_level - 1 {
-                break;
-            }
+    /// Function that applies all operations of a batch atomically. The whole batch is written to the wal
+    /// as a single record, so after a crash it is either replayed completely or not at all.
+    /// All operations in the batch share the same time stamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The operations that need to be applied
+    /// * `time_stamp` - the time when event took place
+    ///
+    /// # Returns
+    ///
+    /// A db_error::Result representing the success of operation
+    pub fn write_batch(&self, batch: &WriteBatch, time_stamp: TimeStamp) -> db_error::Result<()> {
+        self.workers
+            .throttle(self.state.config.memory_table_pool_num);
+        self.state.rate_limiter.record_foreground_request();
+        let mut wal = self.state.wal.lock().unwrap();
+        let time_stamp = self.next_timestamp(time_stamp);
+
+        let wal_position = wal.end_position();
+
+        wal.write_batch(self.state.column_family.as_deref(), batch, time_stamp)?;
+        let memory_table =
+            self.state
+                .mem_pool
+                .write()
+                .unwrap()
+                .write_batch(batch, time_stamp, wal_position);
+        if let Some(memory_table) = memory_table {
+            self.schedule_flush(memory_table);
         }
         Ok(())
     }
 
-    /// Leveled compaction algorithm.
-    /// Chooses oldest table on current level and merges it with sstables that have similar key ranges from one level below
-    /// This process creates one bigger sstable that gets placed one level below current
-    /// This process can be propagated through levels
+    /// Function that replaces the value of the key only if its current value matches the expected one.
+    /// The wal lock is held from the read until the write, so no other write can change the key in between.
     ///
     /// # Arguments
     ///
-    /// * `level` - The level where compactions started
+    /// * `key` - The key that user passed to our program
+    /// * `expected` - The expected current value, None if the key is expected to be absent
+    /// * `new` - The new value, None if the key should be deleted
+    /// * `time_stamp` - the time when event took place
     ///
     /// # Returns
     ///
-    /// io::Result indicating success of SSTable merging process
-    fn leveled_compaction(&mut self, mut level: usize) -> io::Result<()> {
-        let use_variable_encoding = self.config.use_variable_encoding;
-        let merged_in_single_file = self.config.in_single_file;
+    /// A db_error::Result containing true if the current value matched and the new one was written
+    pub fn compare_and_swap(
+        &self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+        time_stamp: TimeStamp,
+    ) -> db_error::Result<bool> {
+        self.workers
+            .throttle(self.state.config.memory_table_pool_num);
+        self.state.rate_limiter.record_foreground_request();
+        let mut wal = self.state.wal.lock().unwrap();
+
+        let current = self.get(key)?;
+        if current.as_deref() != expected {
+            return Ok(false);
+        }
 
-        while self.sstable_directory_names[level].len()
-            > self.config.max_per_level * pow(self.config.leveled_amplification, level)
-        {
-            // Choose first SStable from given level
-            let main_sstable_base_path = self
-                .config
-                .parent_dir
-                .join(self.sstable_directory_names[level].remove(0));
-            let in_single_file = LSM::is_in_single_file(&main_sstable_base_path);
-            let (main_min_key, main_max_key) =
-                SSTable::get_key_range(main_sstable_base_path.to_owned(), in_single_file)?;
+        let time_stamp = self.next_timestamp(time_stamp);
+        let memory_table = match new {
+            Some(value) => {
+                let wal_position = wal.end_position();
+                wal.insert(self.state.column_family.as_deref(), key, value, time_stamp)?;
+                self.state
+                    .mem_pool
+                    .write()
+                    .unwrap()
+                    .insert(key, value, time_stamp, wal_position)
+            }
+            None if current.is_some() => {
+                let wal_position = wal.end_position();
+                wal.delete(self.state.column_family.as_deref(), key, time_stamp)?;
+                self.state
+                    .mem_pool
+                    .write()
+                    .unwrap()
+                    .delete(key, time_stamp, wal_position)
+            }
+            None => None,
+        };
+        if let Some(memory_table) = memory_table {
+            self.schedule_flush(memory_table);
+        }
 
-            // Find SStables with keys in similar range one level below
-            let in_range_paths = LSM::find_similar_key_ranges(
-                &self.sstable_directory_names,
-                &self.config.parent_dir,
-                &main_min_key,
-                &main_max_key,
-                level + 1,
-                &mut self.compression_dictionary,
-            )?;
-            let mut sstable_base_paths: Vec<_> = in_range_paths
-                .clone()
-                .into_iter()
-                .map(|(_, path)| self.config.parent_dir.join(path.to_owned()))
-                .collect();
+        Ok(true)
+    }
 
-            // Put main SStable in vector and create vector of booleans indicating whether each SSTable is in a single file
-            sstable_base_paths.push(main_sstable_base_path.to_owned());
-            let mut sstable_single_file = Vec::new();
-            for path in &sstable_base_paths {
-                sstable_single_file.push(LSM::is_in_single_file(&path.to_owned().to_path_buf()));
-            }
+    /// Schedules a flush of the read only memory table on the flush worker. The flush starts a compaction
+    /// on the compaction worker if the first level gets too big or the new table holds many tombstones. Errors are reported on stderr,
+    /// because there is no caller left to return them to.
+    fn schedule_flush(&self, memory_table: Arc<MemoryTable>) {
+        let state = Arc::clone(&self.state);
+        self.workers
+            .flush(move |compaction| match state.flush(&memory_table) {
+                Ok(true) => BackgroundWorkers::compact(compaction, move || {
+                    if let Err(e) = state.compact() {
+                        eprintln!("Compaction failed: {}", e);
+                    }
+                }),
+                Ok(false) => (),
+                Err(e) => eprintln!("Flush failed: {}", e),
+            });
+    }
 
-            // Make a name for new SSTable
-            let merged_directory =
-                PathBuf::from(LSM::get_directory_name(level + 1, merged_in_single_file));
-            let merged_base_path = self.config.parent_dir.join(merged_directory.clone());
+    /// Resolves the time stamp of a write. Current time stamps are made strictly greater than all previous ones,
+    /// so that no write can share a time stamp with a snapshot that was taken before it.
+    fn next_timestamp(&self, time_stamp: TimeStamp) -> TimeStamp {
+        let mut last_timestamp = self.state.last_timestamp.lock().unwrap();
+        let time = match time_stamp {
+            TimeStamp::Now => time_stamp.get_time().max(*last_timestamp + 1),
+            TimeStamp::Custom(time) => time,
+        };
+        *last_timestamp = last_timestamp.max(time);
 
-            // Merge them all together
-            SSTable::merge(
-                sstable_base_paths.clone(),
-                sstable_single_file,
-                &merged_base_path.to_path_buf(),
-                merged_in_single_file,
-                self.config.summary_density,
-                self.config.index_density,
-                use_variable_encoding,
-                &mut self.compression_dictionary,
-            )?;
+        TimeStamp::Custom(time)
+    }
 
-            // Extract indexes of SSTable that need to be removed
-            let indexes_to_delete: Vec<_> = in_range_paths.iter().map(|(index, _)| index).collect();
+    /// Creates a point-in-time snapshot that sees all writes made before this call and none made after it.
+    ///
+    /// # Returns
+    ///
+    /// A snapshot handle. Older versions of keys are kept during compaction until it's dropped.
+    pub fn snapshot(&self) -> LSMSnapshot {
+        // No write can be applied between taking the time stamp and copying the memory tables
+        let _wal = self.state.wal.lock().unwrap();
+        let mut last_timestamp = self.state.last_timestamp.lock().unwrap();
+        let timestamp = TimeStamp::Now.get_time().max(*last_timestamp);
+        *last_timestamp = timestamp;
+
+        LSMSnapshot::new(
+            timestamp,
+            self.state.mem_pool.read().unwrap().get_newest_entries(),
+            self.state.snapshots.clone(),
+        )
+    }
 
-            // Make new vector for sstable_directory_names one level below main that contains only SStables that weren't involved in compactions
-            let mut kept_sstable_directories: Vec<PathBuf> = self.sstable_directory_names
-                [level + 1]
-                .iter()
-                .enumerate()
-                .filter(|&(index, _)| !indexes_to_delete.contains(&&index))
-                .map(|(_, &ref elem)| elem.clone())
-                .collect();
+    /// Function that returns bytes representing the entry associated with the given key as seen by the snapshot.
+    /// Doesn't use the lru cache, because the cache only holds the newest versions.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that user passed to our program
+    /// * `snapshot` - The snapshot through which the key is read
+    ///
+    /// # Returns
+    ///
+    /// A db_error::Result containing bytes representing data associated with a given key at the time of the snapshot.
+    pub fn get_at(
+        &self,
+        key: &[u8],
+        snapshot: &LSMSnapshot,
+    ) -> db_error::Result<Option<Box<[u8]>>> {
+        self.state.rate_limiter.record_foreground_request();
+        if let Some(memory_entry) = snapshot.get_memory_entry(key) {
+            return if !memory_entry.get_tombstone()
+                && !memory_entry.is_expired(snapshot.get_timestamp())
+            {
+                Ok(Some(memory_entry.get_value()))
+            } else {
+                Ok(None)
+            };
+        }
 
-            // Replace this vector with existing in sstable_directory_names and append merged directory to it
-            kept_sstable_directories.push(merged_directory);
-            Self::remove_all_compacted(sstable_base_paths)?;
-            self.sstable_directory_names[level + 1] = kept_sstable_directories;
+        // Compaction can move versions between tables, so the newest visible version is searched in all of them
+        let mut newest_entry: Option<MemoryEntry> = None;
+        let levels = self.state.levels.read().unwrap();
+        let key_orders = self.state.key_orders.read().unwrap();
+        for (level, key_order) in levels.iter().zip(key_orders.iter()) {
+            for table in tables_with_key(level, key_order.as_deref(), key) {
+                let (path, in_single_file) = self.state.get_sstable_path(table);
+                let sstable = self.state.table_cache.get(&path, in_single_file)?;
+                let memory_entry = sstable.lock().unwrap().get_at(
+                    key,
+                    snapshot.get_timestamp(),
+                    self.state.config.index_density,
+                    &mut self.state.compression_dictionary.lock().unwrap(),
+                    self.state.config.use_variable_encoding,
+                )?;
+                if let Some(memory_entry) = memory_entry {
+                    let is_newer = match &newest_entry {
+                        Some(newest_entry) => {
+                            memory_entry.get_timestamp() > newest_entry.get_timestamp()
+                        }
+                        None => true,
+                    };
+                    if is_newer {
+                        newest_entry = Some(memory_entry);
+                    }
+                }
+            }
+        }
 
-            // Check for possibility of another compaction occurring
-            level += 1;
-            if level >= self.config.max_level - 1 {
-                break;
+        match newest_entry {
+            Some(memory_entry)
+                if !memory_entry.get_tombstone()
+                    && !memory_entry.is_expired(snapshot.get_timestamp()) =>
+            {
+                Ok(Some(memory_entry.get_value()))
             }
+            _ => Ok(None),
         }
-        Ok(())
     }
 
     /// Function that returns index in the vector of an entry with the biggest timestamp
@@ -581,11 +919,13 @@ impl LSM {
         min_indexes
     }
 
-    /// Function that merges entries from all sstables in ascending order taking in account only valid entries (tombstone=true)
+    /// Function that merges entries from all memory tables in ascending order, keeping only the newest version of each key.
+    /// Tombstones and expired entries are kept, since they hide the older versions of their keys in the sstables,
+    /// and the iterator skips them once it has compared the versions from all sources.
     ///
     /// # Arguments
     ///
-    /// * `all_entries` - Vector of vectors, each containing entries from one sstable
+    /// * `all_entries` - Vector of vectors, each containing entries from one memory table
     /// # Returns
     ///
     /// A vector of sorted entries
@@ -612,9 +952,6 @@ impl LSM {
                 break;
             }
 
-            // for updating offsets if we encounter an entry with tombstone=true
-            let mut update_positions = vec![0; all_entries.len()];
-
             // extracting entries only from vector that aren't empty and keeping original indexes for updating offsets
             let entries: Vec<_> = all_entries
                 .iter()
@@ -622,36 +959,13 @@ impl LSM {
                 .enumerate()
                 .filter_map(|(index, (vector, position))| {
                     if has_elements[index] {
-                        let mut return_entry = None;
-                        let mut curr_position = *position;
-                        while curr_position < vector.len() {
-                            let entry = &vector[curr_position];
-                            if entry.1.get_tombstone() {
-                                update_positions[index] += 1;
-                                curr_position += 1;
-                                continue;
-                            } else {
-                                return_entry = Some((index, entry));
-                                break;
-                            }
-                        }
-                        return_entry
+                        Some((index, &vector[*position]))
                     } else {
                         None
                     }
                 })
                 .collect();
 
-            // if we don't find an entry with tombstone=false break out of the loop
-            if entries.is_empty() {
-                break;
-            }
-
-            // update offsets
-            for i in 0..positions.len() {
-                positions[i] += update_positions[i];
-            }
-
             // looking for indexes of entries with minimum keys
             let min_key_indexes = LSM::find_min_keys(&entries);
 
@@ -693,6 +1007,11 @@ impl LSM {
                             continue;
                         }
                     }
+                    if let Some(min_key) = min_key {
+                        if pushed_entry.1 .0.as_ref() < min_key {
+                            continue;
+                        }
+                    }
                 }
             }
 
@@ -701,14 +1020,42 @@ impl LSM {
         scanned_entries
     }
 
-    pub fn load_from_dir(dbconfig: &DBConfig) -> Result<Self, Box<dyn Error>> {
-        let (mem_pool, tables_to_be_flushed) = MemoryPool::load_from_dir(dbconfig)?;
+    pub fn load_from_dir(dbconfig: &DBConfig) -> db_error::Result<Self> {
+        let wal = WriteAheadLog::from_dir(dbconfig)?;
+        LSM::load_column_family(dbconfig, None, Arc::new(Mutex::new(wal)))
+    }
 
-        let mut new_lsm = LSM::new(&dbconfig)?;
-        new_lsm.mem_pool = mem_pool;
+    /// Creates an LSM instance for a column family and loads the records of the column family from the shared WAL.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_config` - Configuration of the column family.
+    /// * `column_family` - Name of the column family, None for the default one.
+    /// * `wal` - The shared WAL.
+    ///
+    /// # Returns
+    ///
+    /// LSM instance
+    pub fn load_column_family(
+        dbconfig: &DBConfig,
+        column_family: Option<&str>,
+        wal: Arc<Mutex<WriteAheadLog>>,
+    ) -> db_error::Result<Self> {
+        let (mem_pool, tables_to_be_flushed) = MemoryPool::load_from_dir(dbconfig, column_family)?;
+
+        // The loaded records keep the log from being removed until they are flushed
+        let oldest_wal_position = tables_to_be_flushed
+            .iter()
+            .filter_map(|table| table.wal_start())
+            .chain(mem_pool.oldest_wal_position())
+            .min();
+        if let Some(position) = oldest_wal_position {
+            wal.lock().unwrap().mark_unflushed(column_family, position);
+        }
 
+        let new_lsm = LSM::build(dbconfig, mem_pool, column_family, wal)?;
         for table in tables_to_be_flushed {
-            new_lsm.flush(table)?;
+            new_lsm.schedule_flush(table);
         }
 
         Ok(new_lsm)
@@ -719,20 +1066,22 @@ impl LSM {
     ///
     /// # Arguments
     ///
-    /// * `min_key` - Option containing minimum key used for range scan
-    /// * `max_key` - Option containing maximum key used for prefix scan
+    /// * `min_key` - Option containing minimum key used for range scan, or the key a prefix scan starts from
+    /// * `max_key` - Option containing maximum key used for range scan
     /// * `prefix` - Option containing key prefix for prefix scan
     /// * `scan_type` - indicates the scan type, range or prefix scan
+    /// * `snapshot` - Option containing the snapshot, only entries visible to it are returned
     /// # Returns
     ///
     /// A LSMIterator
     pub(crate) fn iter(
-        &mut self,
+        &self,
         min_key: Option<&[u8]>,
         max_key: Option<&[u8]>,
         prefix: Option<&[u8]>,
         scan_type: ScanType,
-    ) -> io::Result<LSMIterator> {
+        snapshot: Option<&LSMSnapshot>,
+    ) -> db_error::Result<LSMIterator<'_>> {
         // if prefix ends with zeros trim it
         let prefix = if let Some(prefix) = prefix {
             Some(extract_prefix(prefix))
@@ -740,84 +1089,85 @@ impl LSM {
             None
         };
 
-        // merge all entries from memory tables
-        let entries = self.mem_pool.get_all_tables();
+        // merge all entries from memory tables, or from the state of memory tables at the time of the snapshot
+        let entries = match snapshot {
+            Some(snapshot) => vec![snapshot.get_memory_entries()],
+            None => self.state.mem_pool.read().unwrap().get_all_tables(),
+        };
         let merged_memory_entries =
             LSM::merge_scanned_entries(entries, min_key, max_key, prefix, scan_type);
 
+        // the levels stay locked while the iterator is alive, so compaction can't remove the opened sstables
+        let levels = self.state.levels.read().unwrap();
+
         // get all sstables with keys in given range or all sstables if scan type is prefix scan
-        let sstable_base_paths = if let (Some(min_key), Some(max_key)) = (min_key, max_key) {
-            let mut sstable_paths = Vec::new();
-            for level in 0..self.config.max_level {
-                sstable_paths.extend(LSM::find_similar_key_ranges(
-                    &self.sstable_directory_names,
-                    &self.config.parent_dir,
-                    min_key,
-                    max_key,
-                    level,
-                    &mut self.compression_dictionary,
-                )?);
-            }
-            let sstable_base_paths: Vec<_> = sstable_paths
-                .into_iter()
-                .map(|(_, path)| self.config.parent_dir.join(path))
-                .collect();
-            sstable_base_paths
+        let tables: Vec<&TableMetadata> = if let (Some(min_key), Some(max_key)) = (min_key, max_key)
+        {
+            (0..self.state.config.max_level)
+                .flat_map(|level| LSM::find_similar_key_ranges(&levels, min_key, max_key, level))
+                .map(|(_, table)| table)
+                .collect()
         } else {
-            let sstable_paths: Vec<_> = self
-                .sstable_directory_names
-                .iter()
-                .flat_map(|vec_path| vec_path.iter())
-                .collect();
-            let sstable_base_paths: Vec<_> = sstable_paths
-                .into_iter()
-                .map(|path| self.config.parent_dir.join(path))
-                .collect();
-            sstable_base_paths
+            levels.iter().flatten().collect()
         };
+        let mut sstable_base_paths = Vec::new();
+        let mut in_single_files = Vec::new();
+        for table in tables {
+            let (path, in_single_file) = self.state.get_sstable_path(table);
+
+            // skip the sstables whose prefix bloom filter rules out the scanned prefix
+            if let (ScanType::PrefixScan, Some(prefix)) = (scan_type, prefix) {
+                let may_contain_prefix = self
+                    .state
+                    .table_cache
+                    .get(&path, in_single_file)?
+                    .lock()
+                    .unwrap()
+                    .may_contain_prefix(prefix, self.state.config.table_options.prefix_extractor)?;
+                if !may_contain_prefix {
+                    continue;
+                }
+            }
 
-        // vector containing in_single_file argument of each sstable needed for their opening
-        let in_single_files: Vec<bool> = sstable_base_paths
-            .iter()
-            .map(|path| LSM::is_in_single_file(&path.to_path_buf()))
-            .collect();
+            sstable_base_paths.push(path);
+            in_single_files.push(in_single_file);
+        }
 
         // open all sstables that meet the criteria
         let mut sstables: Vec<_> = sstable_base_paths
             .iter()
             .zip(in_single_files.iter())
-            .map(
-                |(path, bool)| match SSTable::open(path.to_path_buf(), *bool) {
+            .map(|(path, bool)| {
+                match SSTable::open_with_mmap(path.to_path_buf(), *bool, self.state.config.use_mmap)
+                {
                     Ok(table) => table,
                     Err(err) => panic!("{}", err),
-                },
-            )
+                }
+            })
             .collect();
 
-        let data_offsets = vec![0; sstables.len()];
+        // the dictionary is locked once the cached sstables are released, point reads lock them the other way around
+        let mut compression_dictionary = self.state.compression_dictionary.lock().unwrap();
 
-        // update offsets because the index is jagged
+        // update offsets because the index is jagged, a prefix scan with a min key starts at the first key after it
         let updates_offsets = if let Some(min_key) = min_key {
             SSTable::update_sstable_offsets(
                 &mut sstables,
-                data_offsets,
                 min_key,
-                scan_type,
-                self.config.use_variable_encoding,
-                &mut self.compression_dictionary,
+                ScanType::RangeScan,
+                self.state.config.use_variable_encoding,
+                &mut compression_dictionary,
             )?
         } else {
             SSTable::update_sstable_offsets(
                 &mut sstables,
-                data_offsets,
                 prefix.unwrap(),
                 scan_type,
-                self.config.use_variable_encoding,
-                &mut self.compression_dictionary,
+                self.state.config.use_variable_encoding,
+                &mut compression_dictionary,
             )?
         };
-
-        let memory_offset = 0;
+        drop(compression_dictionary);
 
         // to know when to stop with the iteration
         let upper_bound = if let Some(max_key) = max_key {
@@ -828,24 +1178,905 @@ impl LSM {
 
         Ok(LSMIterator::new(
             merged_memory_entries,
-            memory_offset,
             sstables,
             updates_offsets,
-            scan_type,
-            self.config.use_variable_encoding,
-            upper_bound,
-            &mut self.compression_dictionary,
+            ScanParameters {
+                scan_type,
+                upper_bound,
+                snapshot: snapshot.map(|snapshot| snapshot.get_timestamp()),
+            },
+            self.state.config.use_variable_encoding,
+            &self.state.compression_dictionary,
+            levels,
         ))
     }
 
-    pub fn finalize(self) {
-        self.wal.close();
-        // when adding concurrent sstable flushes, join all threads here
+    /// Returns the directories of the tables in the levels, relative to the SSTable directory.
+    #[cfg(test)]
+    pub(crate) fn table_directories(&self) -> Vec<PathBuf> {
+        self.state
+            .levels
+            .read()
+            .unwrap()
+            .iter()
+            .flatten()
+            .map(|table| table.directory.clone())
+            .collect()
     }
-}
 
-/// Function for trimming prefix if it ends with zeros
-fn extract_prefix(slice: &[u8]) -> &[u8] {
+    /// Returns the base paths of the tables that are open in the table cache.
+    #[cfg(test)]
+    pub(crate) fn cached_tables(&self) -> Vec<PathBuf> {
+        self.state.table_cache.base_paths()
+    }
+
+    /// Returns the smallest and the largest time stamp of each table in the levels.
+    #[cfg(test)]
+    pub(crate) fn table_time_ranges(&self) -> Vec<Vec<(u128, u128)>> {
+        self.state
+            .levels
+            .read()
+            .unwrap()
+            .iter()
+            .map(|tables| {
+                tables
+                    .iter()
+                    .map(|table| (table.min_time_stamp, table.max_time_stamp))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns the size in bytes of the tables of each level.
+    #[cfg(test)]
+    pub(crate) fn level_sizes(&self) -> Vec<u64> {
+        self.state
+            .levels
+            .read()
+            .unwrap()
+            .iter()
+            .map(|tables| tables.iter().map(|table| table.size).sum())
+            .collect()
+    }
+
+    /// Returns whether the tables of each level are sorted by their key ranges, which don't overlap.
+    #[cfg(test)]
+    pub(crate) fn key_ordered_levels(&self) -> Vec<bool> {
+        self.state
+            .key_orders
+            .read()
+            .unwrap()
+            .iter()
+            .map(|key_order| key_order.is_some())
+            .collect()
+    }
+
+    /// Returns the number of tombstones in the tables of the levels.
+    #[cfg(test)]
+    pub(crate) fn tombstone_count(&self) -> u64 {
+        self.state
+            .levels
+            .read()
+            .unwrap()
+            .iter()
+            .flatten()
+            .map(|table| table.tombstone_count)
+            .sum()
+    }
+
+    /// Merges every SSTable that overlaps the key range down to the last level, so deleted and expired entries
+    /// in it are dropped right away. Waits for the scheduled flushes, then runs on the compaction worker and blocks
+    /// until it's done. Entries that are still in the memory tables aren't compacted, and FIFO compaction never
+    /// merges tables, so the levels are left as they are under it.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The smallest key of the range, or None if the range is unbounded from below
+    /// * `end` - The largest key of the range, or None if the range is unbounded from above
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result indicating success of SSTable merging process
+    pub fn compact_range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> db_error::Result<()> {
+        let state = Arc::clone(&self.state);
+        let (start, end) = (start.map(Box::from), end.map(Box::from));
+        self.workers.join_flushes();
+        self.workers
+            .run_compaction(move || state.compact_range(start.as_deref(), end.as_deref()))
+    }
+
+    /// Rewrites the SSTables written with an older version of the format, so they use the current one.
+    /// Tables are rewritten one at a time, each in place of the old one on its level. Waits for the scheduled
+    /// flushes, then runs on the compaction worker and blocks until it's done. The write-ahead log and the manifest
+    /// don't need it, since new log files and the rewritten manifest always use the current format.
+    /// Tables without headers can only be read, and so upgraded, on 64-bit little-endian machines.
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result containing the number of rewritten tables
+    pub fn upgrade_tables(&self) -> db_error::Result<usize> {
+        let state = Arc::clone(&self.state);
+        self.workers.join_flushes();
+        self.workers.run_compaction(move || state.upgrade_tables())
+    }
+
+    /// Changes the number of bytes per second that flushes and compactions can read and write, 0 removes the limit.
+    /// The flushes and compactions that are already running are slowed down or sped up as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes_per_second` - The new limit
+    pub fn set_compaction_io_rate_limit(&self, bytes_per_second: usize) {
+        self.state
+            .rate_limiter
+            .set_bytes_per_second(bytes_per_second as u64);
+    }
+
+    /// Turns the auto-tuning of the compaction I/O rate limit on or off. While it's on, the rate backs off
+    /// from the configured limit while foreground reads and writes are frequent and goes back up once they calm down.
+    ///
+    /// # Arguments
+    ///
+    /// * `auto_tune` - Whether the rate limit is auto-tuned
+    pub fn set_compaction_io_auto_tune(&self, auto_tune: bool) {
+        self.state.rate_limiter.set_auto_tune(auto_tune);
+    }
+
+    /// Blocks until all scheduled flushes and compactions are done.
+    pub fn wait_for_background_work(&self) {
+        self.workers.join();
+    }
+
+    /// Waits for the background workers and closes the wal if no other column family uses it.
+    pub fn finalize(self) {
+        self.workers.join();
+        // The workers hold the state only while they run a job, so it isn't shared anymore
+        if let Ok(state) = Arc::try_unwrap(self.state) {
+            if let Ok(wal) = Arc::try_unwrap(state.wal) {
+                wal.into_inner().unwrap().close();
+            }
+        }
+    }
+}
+
+impl LSMState {
+    /// Function that returns full path to a sstable and wether or not is it in single file
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The metadata of sstable
+    ///
+    /// # Returns
+    ///
+    /// Full path and boolean indicating its structure
+    fn get_sstable_path(&self, table: &TableMetadata) -> (PathBuf, bool) {
+        let full_path = self.config.parent_dir.join(&table.directory);
+        (full_path, table.in_single_file)
+    }
+
+    /// Returns the path of the temporary directory a new table is written into before it's published.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Final directory of the table, relative to the SSTable directory.
+    fn get_temp_path(&self, directory: &Path) -> PathBuf {
+        let mut temp_directory = directory.as_os_str().to_owned();
+        temp_directory.push(TEMP_TABLE_SUFFIX);
+        self.config.parent_dir.join(temp_directory)
+    }
+
+    /// Syncs the table written into its temporary directory and renames the directory to its final name, so the
+    /// table is either complete or not there at all. The table belongs to the levels only once it's logged into
+    /// the manifest, until then it's removed on open if the process crashes.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Final directory of the table, relative to the SSTable directory.
+    fn publish_table(&self, directory: &Path) -> db_error::Result<()> {
+        let temp_path = self.get_temp_path(directory);
+        for file in read_dir(&temp_path)? {
+            File::open(file?.path())?.sync_all()?;
+        }
+
+        rename(&temp_path, self.config.parent_dir.join(directory))?;
+        File::open(&self.config.parent_dir)?.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Closes the compacted tables and removes their directories. Called once their removal is logged into
+    /// the manifest and they're out of the levels, so no reader can put them back into the table cache,
+    /// and directories that are left behind by a crash are removed on the next open.
+    fn remove_all_compacted(&self, sstable_base_paths: Vec<PathBuf>) -> db_error::Result<()> {
+        for dir in sstable_base_paths {
+            self.table_cache.evict(&dir);
+            remove_dir_all(dir)?;
+            crash_point("compaction_removed_table");
+        }
+        Ok(())
+    }
+
+    /// Returns time stamps of all live snapshots.
+    fn live_snapshots(&self) -> Vec<u128> {
+        self.snapshots.lock().unwrap().keys().copied().collect()
+    }
+
+    /// FLushes MemTable onto disk. Only runs on the flush worker, so tables are added to the first level in order.
+    /// The MemTable stays readable in the memory pool until its SSTable is added to the levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem_table` - The MemTable that needs to be flushed.
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result containing true if the first level got too big or the new table holds many tombstones,
+    /// or some time window has tables to merge under time window compaction, or the tables got too big under FIFO
+    /// compaction, so the levels need to be compacted
+    fn flush(&self, mem_table: &Arc<MemoryTable>) -> db_error::Result<bool> {
+        let in_single_file = self.config.in_single_file;
+        let directory_name = LSM::get_directory_name(0, in_single_file);
+        let sstable_base_path = self.get_temp_path(&directory_name);
+        let use_variable_encoding = self.config.use_variable_encoding;
+
+        let mut sstable = SSTable::open(sstable_base_path, in_single_file)?;
+        sstable.flush(
+            mem_table,
+            self.config.table_options,
+            &self.compression_dictionary,
+            Some(&self.rate_limiter),
+        )?;
+        self.publish_table(&directory_name)?;
+        crash_point("flush_published");
+
+        let table = LSM::read_table_metadata(
+            &self.config.parent_dir,
+            directory_name,
+            in_single_file,
+            use_variable_encoding,
+            &mut self.compression_dictionary.lock().unwrap(),
+        )?;
+
+        // Cached keys are refreshed while no reader is in the sstables, so a concurrent get can't cache an older version after it
+        let needs_compaction = {
+            let mut manifest = self.manifest.lock().unwrap();
+            manifest.append(&[VersionEdit::AddTable {
+                level: 0,
+                index: self.levels.read().unwrap()[0].len(),
+                table: table.clone(),
+            }])?;
+
+            let mut levels = self.levels.write().unwrap();
+            let is_tombstone_heavy = self.is_tombstone_heavy(&table);
+            levels[0].push(table);
+            *self.key_orders.write().unwrap() = key_orders(&levels);
+
+            let mut lru_cache = self.lru_cache.lock().unwrap();
+            for (key, entry) in mem_table.iterator() {
+                lru_cache.update(&key, Some(entry));
+            }
+
+            match self.config.compaction_algorithm {
+                CompactionAlgorithmType::TimeWindow => {
+                    !self.time_window_runs(&levels[0]).is_empty()
+                }
+                CompactionAlgorithmType::Fifo => {
+                    levels.iter().flatten().map(|table| table.size).sum::<u64>()
+                        > self.config.fifo_max_size
+                }
+                _ => levels[0].len() > self.config.max_per_level || is_tombstone_heavy,
+            }
+        };
+        {
+            // Writes are locked out, so no record can be added to the memory tables before the log learns their oldest one
+            let mut wal = self.wal.lock().unwrap();
+            let mut mem_pool = self.mem_pool.write().unwrap();
+            mem_pool.remove_flushed(mem_table);
+            wal.mark_flushed(
+                self.column_family.as_deref(),
+                mem_pool.oldest_wal_position(),
+            )?;
+        }
+
+        Ok(self.config.compaction_enabled && needs_compaction)
+    }
+
+    /// Compacts the SSTables with many tombstones, then every level that is over its limit
+    /// with the configured compaction algorithm. Time window compaction only merges tables within their windows,
+    /// while FIFO compaction never merges them.
+    fn compact(&self) -> db_error::Result<()> {
+        match self.config.compaction_algorithm {
+            CompactionAlgorithmType::TimeWindow => return self.time_window_compaction(),
+            CompactionAlgorithmType::Fifo => return self.fifo_compaction(),
+            _ => (),
+        }
+
+        self.tombstone_compaction()?;
+
+        if self.config.compaction_algorithm == CompactionAlgorithmType::Leveled {
+            return self.leveled_compaction();
+        }
+
+        // Tables with many tombstones can be pushed into any level, so every level is checked
+        for level in 0..self.config.max_level - 1 {
+            self.size_tiered_compaction(level)?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether enough of the entries of the table are tombstones for it to be compacted on its own.
+    fn is_tombstone_heavy(&self, table: &TableMetadata) -> bool {
+        table.tombstone_count > 0 && table.tombstone_ratio() >= self.config.tombstone_compaction_ratio
+    }
+
+    /// Size-tiered compaction algorithm. Deletes all SSTables on current level and makes one bigger table located one level below
+    /// This process can be propagated through levels
+    /// Only runs on the compaction worker, flushes can add tables to the first level in the meantime
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level where compactions started
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result indicating success of SSTable merging process
+    fn size_tiered_compaction(&self, mut level: usize) -> db_error::Result<()> {
+        let snapshots = self.live_snapshots();
+
+        while self.levels.read().unwrap()[level].len() > self.config.max_per_level {
+            // Readers keep using the shared levels while tables are merged, the merged table is published afterwards
+            let levels = self.levels.read().unwrap().clone();
+
+            // Merge all SSTables of the level into one table one level below
+            self.merge_tables(&levels, level, &levels[level], &[], level + 1, &snapshots)?;
+
+            // Check for possibility of another compaction occurring
+            level += 1;
+            if level >= self.config.max_level - 1 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Leveled compaction algorithm.
+    /// Chooses the level with the highest score and merges its oldest table with sstables that have similar key ranges from one level below
+    /// This process creates one bigger sstable that gets placed one level below and is repeated until every level is within its target
+    /// Only runs on the compaction worker, flushes can add tables to the first level in the meantime
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result indicating success of SSTable merging process
+    fn leveled_compaction(&self) -> db_error::Result<()> {
+        loop {
+            // Readers keep using the shared levels while tables are merged, the merged table is published afterwards
+            let levels = self.levels.read().unwrap().clone();
+            // Snapshots taken after the levels are cloned see the newest versions in them,
+            // so the ones taken while earlier tables were merged are accounted for
+            let snapshots = self.live_snapshots();
+
+            // The last level has no target, its tables can't be pushed any further
+            let Some((level, score)) = (0..self.config.max_level - 1)
+                .map(|level| (level, self.leveled_score(&levels, level)))
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            else {
+                break;
+            };
+            if score <= 1.0 {
+                break;
+            }
+
+            // Choose first SStable from given level
+            let main_table = &levels[level][0];
+
+            // Find SStables with keys in similar range one level below
+            let in_range_tables: Vec<TableMetadata> = LSM::find_similar_key_ranges(
+                &levels,
+                &main_table.min_key,
+                &main_table.max_key,
+                level + 1,
+            )
+            .into_iter()
+            .map(|(_, table)| table.clone())
+            .collect();
+
+            // Merge them all together
+            self.merge_tables(
+                &levels,
+                level,
+                std::slice::from_ref(main_table),
+                &in_range_tables,
+                level + 1,
+                &snapshots,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Scores how far over its target the level is under leveled compaction, levels scored above 1 need a compaction.
+    /// The tables of the first level overlap each other, so every read checks all of them and the level is scored by
+    /// their number against max_per_level. Scoring it by bytes would let many small flushed tables pile up there
+    /// and slow down every read before the size target is reached, which is why RocksDB triggers compactions of its
+    /// first level by the number of files too. Deeper levels don't overlap, so a read checks one table per level
+    /// and they're scored by their size in bytes, with a target of the base size on the second level that grows
+    /// leveled_amplification times with every level below it.
+    fn leveled_score(&self, levels: &[Vec<TableMetadata>], level: usize) -> f64 {
+        if level == 0 {
+            return levels[0].len() as f64 / self.config.max_per_level.max(1) as f64;
+        }
+
+        let size: u64 = levels[level].iter().map(|table| table.size).sum();
+        let target = self.config.leveled_base_size as f64
+            * (self.config.leveled_amplification as f64).powi(level as i32 - 1);
+        size as f64 / target.max(1.0)
+    }
+
+    /// Compacts the SSTables whose share of tombstones reached the configured ratio, so their tombstones are pushed
+    /// down until no older table can hold their keys and they're dropped. The level is merged from its oldest table
+    /// up to the newest such table, so no older table is left above the merged one. Above the last level the tables
+    /// are merged into the next one, together with the overlapping tables there under leveled compaction.
+    /// On the last level they're merged in place. Each level is visited once, from the first one.
+    fn tombstone_compaction(&self) -> db_error::Result<()> {
+        let snapshots = self.live_snapshots();
+
+        for level in 0..self.config.max_level {
+            let levels = self.levels.read().unwrap().clone();
+            let Some(newest_index) = levels[level]
+                .iter()
+                .rposition(|table| self.is_tombstone_heavy(table))
+            else {
+                continue;
+            };
+            let tables = &levels[level][..=newest_index];
+
+            if level == self.config.max_level - 1 {
+                // Snapshots can still need the tombstones, then rewriting the table alone wouldn't drop them
+                if tables.len() == 1 && !snapshots.is_empty() {
+                    continue;
+                }
+                self.merge_tables(&levels, level, tables, &[], level, &snapshots)?;
+                continue;
+            }
+
+            let in_range_tables: Vec<TableMetadata> =
+                if self.config.compaction_algorithm == CompactionAlgorithmType::Leveled {
+                    let min_key = tables.iter().map(|table| &table.min_key).min().unwrap();
+                    let max_key = tables.iter().map(|table| &table.max_key).max().unwrap();
+                    LSM::find_similar_key_ranges(&levels, min_key, max_key, level + 1)
+                        .into_iter()
+                        .map(|(_, table)| table.clone())
+                        .collect()
+                } else {
+                    vec![]
+                };
+
+            self.merge_tables(
+                &levels,
+                level,
+                tables,
+                &in_range_tables,
+                level + 1,
+                &snapshots,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Time window compaction algorithm. Tables of the first level are grouped into windows by their newest entry,
+    /// and only the tables of a window are merged together: the tables of a window that's over are merged into one,
+    /// while the current window is merged once it has more than the maximum number of tables per level.
+    /// Tables of past windows aren't rewritten again, so old data isn't rewritten as new data arrives.
+    /// Only runs on the compaction worker, flushes can add tables to the first level in the meantime
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result indicating success of SSTable merging process
+    fn time_window_compaction(&self) -> db_error::Result<()> {
+        let snapshots = self.live_snapshots();
+
+        loop {
+            // Readers keep using the shared levels while tables are merged, the merged table is published afterwards
+            let levels = self.levels.read().unwrap().clone();
+            let Some(run) = self.time_window_runs(&levels[0]).into_iter().next() else {
+                return Ok(());
+            };
+
+            self.merge_tables(&levels, 0, &levels[0][run], &[], 0, &snapshots)?;
+        }
+    }
+
+    /// FIFO compaction algorithm. Tables are never merged, so every entry is written to disk only once. Instead,
+    /// the oldest tables are deleted once all tables together take more than the configured size.
+    /// Deleted entries are gone for snapshots as well.
+    /// Only runs on the compaction worker, flushes can add tables to the first level in the meantime
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result indicating success of deleting the tables
+    fn fifo_compaction(&self) -> db_error::Result<()> {
+        let levels = self.levels.read().unwrap().clone();
+        let mut total_size: u64 = levels.iter().flatten().map(|table| table.size).sum();
+
+        // Deeper levels hold older tables, which another compaction algorithm could have left behind
+        let mut deleted_tables = vec![];
+        for (level, table) in levels
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(|(level, tables)| tables.iter().map(move |table| (level, table)))
+        {
+            if total_size <= self.config.fifo_max_size {
+                break;
+            }
+            total_size -= table.size;
+            deleted_tables.push((level, table));
+        }
+        if deleted_tables.is_empty() {
+            return Ok(());
+        }
+
+        let edits: Vec<_> = deleted_tables
+            .iter()
+            .map(|(level, table)| VersionEdit::RemoveTable {
+                level: *level,
+                directory: table.directory.clone(),
+            })
+            .collect();
+        {
+            let mut manifest = self.manifest.lock().unwrap();
+            manifest.append(&edits)?;
+
+            let mut shared_levels = self.levels.write().unwrap();
+            for (level, deleted_table) in &deleted_tables {
+                shared_levels[*level].retain(|table| table != *deleted_table);
+            }
+            *self.key_orders.write().unwrap() = key_orders(&shared_levels);
+
+            // Entries of the deleted tables could still be cached
+            self.lru_cache.lock().unwrap().clear();
+        }
+        self.remove_all_compacted(
+            deleted_tables
+                .iter()
+                .map(|(_, table)| self.get_sstable_path(table).0)
+                .collect(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Finds the runs of consecutive tables in the level that belong to the same time window and need to be merged.
+    /// Only consecutive tables are merged, so the merged table can take their place without hiding newer versions
+    /// of its keys. A table that gets data of a past window after its window was merged stays on its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `tables` - Metadata of the sstables of the level, ordered from the oldest to the newest one
+    ///
+    /// # Returns
+    ///
+    /// Ranges of the indexes of the tables in each run that needs to be merged
+    fn time_window_runs(&self, tables: &[TableMetadata]) -> Vec<Range<usize>> {
+        let window = |table: &TableMetadata| table.max_time_stamp / self.config.time_window;
+        let current_window = tables.iter().map(window).max().unwrap_or_default();
+
+        let mut runs = vec![];
+        let mut start = 0;
+        while start < tables.len() {
+            let run_window = window(&tables[start]);
+            let end = tables[start..]
+                .iter()
+                .position(|table| window(table) != run_window)
+                .map_or(tables.len(), |length| start + length);
+
+            let is_full = if run_window == current_window {
+                end - start > self.config.max_per_level
+            } else {
+                end - start > 1
+            };
+            if is_full {
+                runs.push(start..end);
+            }
+            start = end;
+        }
+
+        runs
+    }
+
+    /// Merges the tables that overlap the key range level by level, until they all end up in one table
+    /// of the last level, which is merged in place. The tables of a level that are older than the newest merged one
+    /// and overlap any merged one are merged as well, so no table that's left behind can hide newer versions
+    /// of their keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The smallest key of the range, or None if the range is unbounded from below
+    /// * `end` - The largest key of the range, or None if the range is unbounded from above
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result indicating success of SSTable merging process
+    fn compact_range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> db_error::Result<()> {
+        if self.config.compaction_algorithm == CompactionAlgorithmType::Fifo {
+            return Ok(());
+        }
+
+        let snapshots = self.live_snapshots();
+        let last_level = self.config.max_level - 1;
+
+        for level in 0..=last_level {
+            // Readers keep using the shared levels while tables are merged, the merged table is published afterwards
+            let levels = self.levels.read().unwrap().clone();
+            let Some(end) = end.map(Box::from).or_else(|| {
+                levels.iter().flatten().map(|table| table.max_key.clone()).max()
+            }) else {
+                return Ok(());
+            };
+
+            let mut merged_indexes: Vec<usize> =
+                LSM::find_similar_key_ranges(&levels, start.unwrap_or_default(), &end, level)
+                    .into_iter()
+                    .map(|(index, _)| index)
+                    .collect();
+            if merged_indexes.is_empty() {
+                continue;
+            }
+
+            // Tables older than the newest merged one that overlap a merged one could hold older versions of its keys
+            let newest_index = *merged_indexes.iter().max().unwrap();
+            let mut newly_merged = merged_indexes.clone();
+            while let Some(index) = newly_merged.pop() {
+                let table = &levels[level][index];
+                for (older_index, _) in
+                    LSM::find_similar_key_ranges(&levels, &table.min_key, &table.max_key, level)
+                {
+                    if older_index < newest_index && !merged_indexes.contains(&older_index) {
+                        merged_indexes.push(older_index);
+                        newly_merged.push(older_index);
+                    }
+                }
+            }
+            merged_indexes.sort_unstable();
+            let tables: Vec<TableMetadata> = merged_indexes
+                .into_iter()
+                .map(|index| levels[level][index].clone())
+                .collect();
+
+            let target_level = (level + 1).min(last_level);
+            self.merge_tables(&levels, level, &tables, &[], target_level, &snapshots)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites every table with an older format version in place, using the current format.
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result containing the number of rewritten tables
+    fn upgrade_tables(&self) -> db_error::Result<usize> {
+        let snapshots = self.live_snapshots();
+        let mut upgraded = 0;
+
+        loop {
+            // Readers keep using the shared levels while the table is rewritten, the new table is published afterwards
+            let levels = self.levels.read().unwrap().clone();
+            let Some((level, table)) = levels.iter().enumerate().find_map(|(level, tables)| {
+                tables
+                    .iter()
+                    .find(|table| table.format_version < FORMAT_VERSION)
+                    .map(|table| (level, table.clone()))
+            }) else {
+                return Ok(upgraded);
+            };
+
+            self.merge_tables(&levels, level, &[table], &[], level, &snapshots)?;
+            upgraded += 1;
+        }
+    }
+
+    /// Merges the tables of a level together with the given tables of the target level and replaces them all
+    /// with the merged table, unless all of their entries were dropped. The target level is either the next level,
+    /// where the merged table becomes its newest table, or the same level, where the merged table takes the place
+    /// of the newest merged one. There, the tables that aren't merged between the merged ones can't hold their keys.
+    /// Tombstones are dropped if no older table that isn't merged can hold their key.
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - Metadata of the sstables of each level, read before the merge
+    /// * `level` - The level of the merged tables
+    /// * `tables` - The merged tables of the level
+    /// * `lower_tables` - The merged tables of the target level, if it's the next one
+    /// * `target_level` - The level of the merged table
+    /// * `snapshots` - Time stamps of all live snapshots
+    ///
+    /// # Returns
+    ///
+    /// db_error::Result indicating success of SSTable merging process
+    fn merge_tables(
+        &self,
+        levels: &[Vec<TableMetadata>],
+        level: usize,
+        tables: &[TableMetadata],
+        lower_tables: &[TableMetadata],
+        target_level: usize,
+        snapshots: &[u128],
+    ) -> db_error::Result<()> {
+        let use_variable_encoding = self.config.use_variable_encoding;
+        let merged_in_single_file = self.config.in_single_file;
+
+        // Create vector of booleans indicating whether each SSTable is in a single file
+        let (sstable_base_paths, sstable_single_file): (Vec<PathBuf>, Vec<bool>) = lower_tables
+            .iter()
+            .chain(tables)
+            .map(|table| self.get_sstable_path(table))
+            .unzip();
+
+        // Make a name for new SSTable
+        let merged_directory = LSM::get_directory_name(target_level, merged_in_single_file);
+        let merged_base_path = self.get_temp_path(&merged_directory);
+
+        // Older versions of the merged keys can only be in the older tables of the level and the deeper tables
+        // that aren't merged
+        let newest_index = levels[level]
+            .iter()
+            .rposition(|table| tables.contains(table))
+            .unwrap_or_default();
+        let older_key_ranges: Vec<KeyRange> = levels[level][..newest_index]
+            .iter()
+            .chain(levels[level + 1..].iter().flatten())
+            .filter(|table| !tables.contains(table) && !lower_tables.contains(table))
+            .map(|table| (table.min_key.clone(), table.max_key.clone()))
+            .collect();
+
+        let merged_entry_count = SSTable::merge(
+            sstable_base_paths.clone(),
+            sstable_single_file,
+            &merged_base_path,
+            merged_in_single_file,
+            self.config.table_options,
+            &self.compression_dictionary,
+            MergeContext {
+                snapshots,
+                older_key_ranges: &older_key_ranges,
+                rate_limiter: Some(&self.rate_limiter),
+            },
+        )?;
+        crash_point("compaction_merged");
+
+        // All entries can be dropped, then there is no table to publish
+        let merged_table = if merged_entry_count > 0 {
+            self.publish_table(&merged_directory)?;
+            crash_point("compaction_published");
+            Some(LSM::read_table_metadata(
+                &self.config.parent_dir,
+                merged_directory,
+                merged_in_single_file,
+                use_variable_encoding,
+                &mut self.compression_dictionary.lock().unwrap(),
+            )?)
+        } else {
+            remove_dir_all(&merged_base_path)?;
+            None
+        };
+
+        let mut edits: Vec<_> = tables
+            .iter()
+            .map(|table| VersionEdit::RemoveTable {
+                level,
+                directory: table.directory.clone(),
+            })
+            .chain(lower_tables.iter().map(|table| VersionEdit::RemoveTable {
+                level: target_level,
+                directory: table.directory.clone(),
+            }))
+            .collect();
+        {
+            let mut manifest = self.manifest.lock().unwrap();
+
+            // The levels only change while the manifest is locked, so the merged table can be placed before they're
+            // locked for writing. It takes the place of the newest merged table on the same level and is the newest
+            // table of the next level
+            let index = {
+                let shared_levels = self.levels.read().unwrap();
+                if target_level == level {
+                    let newest_index = shared_levels[level]
+                        .iter()
+                        .rposition(|table| tables.contains(table))
+                        .unwrap_or_default();
+                    shared_levels[level][..newest_index]
+                        .iter()
+                        .filter(|table| !tables.contains(table))
+                        .count()
+                } else {
+                    shared_levels[target_level]
+                        .iter()
+                        .filter(|table| !lower_tables.contains(table))
+                        .count()
+                }
+            };
+            if let Some(merged_table) = &merged_table {
+                edits.push(VersionEdit::AddTable {
+                    level: target_level,
+                    index,
+                    table: merged_table.clone(),
+                });
+            }
+            manifest.append(&edits)?;
+
+            // Only the merged tables are removed, because flushes could have added new ones to the level
+            let mut shared_levels = self.levels.write().unwrap();
+            shared_levels[level].retain(|table| !tables.contains(table));
+            shared_levels[target_level].retain(|table| !lower_tables.contains(table));
+            if let Some(merged_table) = merged_table {
+                shared_levels[target_level].insert(index, merged_table);
+            }
+            *self.key_orders.write().unwrap() = key_orders(&shared_levels);
+        }
+        crash_point("compaction_logged");
+        self.remove_all_compacted(sstable_base_paths)?;
+
+        Ok(())
+    }
+}
+
+/// Aborts the process at the given step of a flush or a compaction if the `LSM_CRASH_POINT` environment
+/// variable names it, so tests can check that the LSM recovers from a crash at that step.
+#[cfg(test)]
+fn crash_point(step: &str) {
+    if std::env::var("LSM_CRASH_POINT").is_ok_and(|crash_point| crash_point == step) {
+        std::process::abort();
+    }
+}
+
+#[cfg(not(test))]
+fn crash_point(_step: &str) {}
+
+/// Sorts the tables of each level by their key ranges, a level whose key ranges overlap has no key order.
+fn key_orders(levels: &[Vec<TableMetadata>]) -> Vec<Option<Vec<usize>>> {
+    levels
+        .iter()
+        .map(|tables| {
+            let mut key_order: Vec<usize> = (0..tables.len()).collect();
+            key_order.sort_by(|&first, &second| tables[first].min_key.cmp(&tables[second].min_key));
+            key_order
+                .windows(2)
+                .all(|pair| tables[pair[0]].max_key < tables[pair[1]].min_key)
+                .then_some(key_order)
+        })
+        .collect()
+}
+
+/// Returns the tables of a level whose key ranges hold the key, from the newest to the oldest one.
+/// Only one table of a level with a key order can hold the key, so it's found by binary search.
+fn tables_with_key<'a>(
+    tables: &'a [TableMetadata],
+    key_order: Option<&[usize]>,
+    key: &[u8],
+) -> Vec<&'a TableMetadata> {
+    match key_order {
+        Some(key_order) => {
+            let position = key_order.partition_point(|&index| *tables[index].min_key <= *key);
+            position
+                .checked_sub(1)
+                .map(|position| &tables[key_order[position]])
+                .filter(|table| *table.max_key >= *key)
+                .into_iter()
+                .collect()
+        }
+        None => tables
+            .iter()
+            .rev()
+            .filter(|table| *table.min_key <= *key && *table.max_key >= *key)
+            .collect(),
+    }
+}
+
+/// Function for trimming prefix if it ends with zeros. An empty prefix matches every key.
+fn extract_prefix(slice: &[u8]) -> &[u8] {
+    if slice.is_empty() {
+        return slice;
+    }
     for (i, &value) in slice.iter().enumerate().rev() {
         if value != 0 {
             return &slice[..=i];
@@ -0,0 +1,80 @@
+use std::sync::mpsc;
+use threadpool::ThreadPool;
+
+/// Threads that flush read only memory tables and compact SSTables in the background.
+/// Each kind of job has its own single worker, so flushes are added to the first level in the
+/// order the memory tables were filled and only one compaction changes the levels at a time.
+/// Dropping the workers waits for all scheduled jobs to finish.
+pub(crate) struct BackgroundWorkers {
+    flush: ThreadPool,
+    compaction: ThreadPool,
+}
+
+impl BackgroundWorkers {
+    pub(crate) fn new() -> Self {
+        BackgroundWorkers {
+            flush: ThreadPool::with_name(String::from("lsm-flush"), 1),
+            compaction: ThreadPool::with_name(String::from("lsm-compaction"), 1),
+        }
+    }
+
+    /// Schedules a flush job. Jobs are run one by one in the order they were scheduled.
+    pub(crate) fn flush<F>(&self, job: F)
+    where
+        F: FnOnce(&ThreadPool) + Send + 'static,
+    {
+        let compaction = self.compaction.clone();
+        self.flush.execute(move || job(&compaction));
+    }
+
+    /// Schedules a compaction job on the given compaction worker, unless there is already one waiting.
+    /// Compaction jobs compact every level that is over its limit, so a waiting job covers the new tables as well.
+    pub(crate) fn compact<F>(compaction: &ThreadPool, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if compaction.queued_count() == 0 {
+            compaction.execute(job);
+        }
+    }
+
+    /// Runs the job on the compaction worker after the compactions that are already scheduled and waits for its result,
+    /// so it never changes the levels at the same time as another compaction.
+    pub(crate) fn run_compaction<F, T>(&self, job: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.compaction.execute(move || {
+            // The caller is blocked on the receiver, so it can't be gone
+            let _ = sender.send(job());
+        });
+        receiver.recv().expect("The compaction job panicked")
+    }
+
+    /// Blocks until all scheduled flushes are done if at least `max_pending_flushes` are waiting,
+    /// so the memory used by read only memory tables can't grow without a limit.
+    pub(crate) fn throttle(&self, max_pending_flushes: usize) {
+        if self.flush.queued_count() >= max_pending_flushes {
+            self.flush.join();
+        }
+    }
+
+    /// Blocks until all scheduled flushes are done.
+    pub(crate) fn join_flushes(&self) {
+        self.flush.join();
+    }
+
+    /// Blocks until all scheduled flushes and the compactions they started are done.
+    pub(crate) fn join(&self) {
+        self.flush.join();
+        self.compaction.join();
+    }
+}
+
+impl Drop for BackgroundWorkers {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
@@ -1,7 +1,18 @@
+use crate::lsm::manifest::TableMetadata;
 use crate::lsm::ScanType;
 use crate::sstable::SSTable;
 use compression::CompressionDictionary;
-use segment_elements::MemoryEntry;
+use segment_elements::{MemoryEntry, TimeStamp};
+use std::sync::{Mutex, RwLockReadGuard};
+
+/// Parameters of a scan that decide which entries the iterator returns and where it stops.
+pub(crate) struct ScanParameters {
+    pub(crate) scan_type: ScanType,
+    // The max key of a range scan or the prefix of a prefix scan
+    pub(crate) upper_bound: Box<[u8]>,
+    // Time stamp of the snapshot, entries written after it are skipped
+    pub(crate) snapshot: Option<u128>,
+}
 
 /// Struct for iterating over entries in memory tables and sstables
 pub struct LSMIterator<'a> {
@@ -12,29 +23,47 @@ pub struct LSMIterator<'a> {
     scan_type: ScanType,
     use_variable_encoding: bool,
     upper_bound: Box<[u8]>,
-    compression_dictionary: &'a mut Option<CompressionDictionary>,
+    compression_dictionary: &'a Mutex<Option<CompressionDictionary>>,
+    // Keeps the levels from changing, so the sstables aren't removed by compaction during the iteration
+    _levels: RwLockReadGuard<'a, Vec<Vec<TableMetadata>>>,
+    // Time stamp of the snapshot, entries written after it are skipped
+    snapshot: Option<u128>,
+    // The last returned key, its older versions in sstables are skipped
+    previous_key: Option<Box<[u8]>>,
+    // Time stamp at which expired entries are skipped, the snapshot time stamp or the creation time
+    read_time: u128,
 }
 
 impl<'a> LSMIterator<'a> {
+    /// Creates an iterator that starts at the first memory table entry and at the given sstable offsets.
     pub(crate) fn new(
         memory_table_entries: Vec<(Box<[u8]>, MemoryEntry)>,
-        memory_offset: usize,
         sstables: Vec<SSTable>,
         offsets: Vec<u64>,
-        scan_type: ScanType,
+        scan_parameters: ScanParameters,
         use_variable_encoding: bool,
-        upper_bound: Box<[u8]>,
-        compression_dictionary: &'a mut Option<CompressionDictionary>,
+        compression_dictionary: &'a Mutex<Option<CompressionDictionary>>,
+        levels: RwLockReadGuard<'a, Vec<Vec<TableMetadata>>>,
     ) -> Self {
+        let ScanParameters {
+            scan_type,
+            upper_bound,
+            snapshot,
+        } = scan_parameters;
+
         LSMIterator {
             memory_table_entries,
-            memory_offset,
+            memory_offset: 0,
             sstables,
             offsets,
             scan_type,
             use_variable_encoding,
             upper_bound,
             compression_dictionary,
+            _levels: levels,
+            snapshot,
+            previous_key: None,
+            read_time: snapshot.unwrap_or(TimeStamp::Now.get_time()),
         }
     }
 }
@@ -43,120 +72,136 @@ impl<'a> Iterator for LSMIterator<'a> {
     type Item = (Box<[u8]>, MemoryEntry);
 
     fn next(&mut self) -> Option<Self::Item> {
-        //pushed indicates whether we have more entries from memory tables
-        let mut pushed = false;
-        let mut copy_offsets = self.offsets.clone();
-        let memory_table_entry = if self.memory_offset < self.memory_table_entries.len() {
-            pushed = true;
-            copy_offsets.push(self.memory_offset as u64);
-            let (key, entry) = self.memory_table_entries[self.memory_offset].clone();
-            let encoded_key = match self.compression_dictionary {
-                Some(compression_dictionary) => compression_dictionary
-                    .encode(&key.to_vec().into_boxed_slice())
-                    .unwrap()
-                    .clone(),
-                None => key.to_vec().into_boxed_slice(),
+        let compression_dictionary = self.compression_dictionary;
+        let mut compression_dictionary = compression_dictionary.lock().unwrap();
+
+        // the newest version of a key is found among all sources first, and only then the key is skipped
+        // if that version is a tombstone or has expired, so it still hides the older versions in other sources
+        loop {
+            //pushed indicates whether we have more entries from memory tables
+            let mut pushed = false;
+            let mut copy_offsets = self.offsets.clone();
+            let memory_table_entry = if self.memory_offset < self.memory_table_entries.len() {
+                pushed = true;
+                copy_offsets.push(self.memory_offset as u64);
+                let (key, entry) = self.memory_table_entries[self.memory_offset].clone();
+                let encoded_key = match &mut *compression_dictionary {
+                    Some(compression_dictionary) => compression_dictionary
+                        .encode(&key.to_vec().into_boxed_slice())
+                        .unwrap()
+                        .clone(),
+                    None => key.to_vec().into_boxed_slice(),
+                };
+                Option::from(((encoded_key, entry), 1u64))
+            } else {
+                None
             };
-            Option::from(((encoded_key, entry), 1u64))
-        } else {
-            None
-        };
-
-        // option entry contains one entry from each sstable and we later combine it with entries from memory tables
-        // also if we stumble upon entry with tombstone=true we just skip it and move on to the next one immediately
-        let mut option_entries: Vec<Option<_>> = self
-            .sstables
-            .iter_mut()
-            .zip(self.offsets.iter())
-            .map(|(sstable, offset)| {
-                let return_value;
-                let mut new_offset = *offset; // new offset from which we continue reading in sstable
-                let mut added_offset = 0; // how many bytes we have read from stable
-                loop {
-                    if let Some((option_entry, length)) = sstable.get_entry_from_data_file(
-                        new_offset,
-                        None,
-                        None,
-                        self.use_variable_encoding,
-                    ) {
-                        added_offset += length;
-                        new_offset += length;
-                        let _ = &*option_entry.0;
-                        if option_entry.1.get_tombstone() {
-                            continue;
-                        } else {
+
+            // option entry contains one entry from each sstable and we later combine it with entries from memory tables
+            // older versions of the last key are stored right after the newest one, so they are skipped
+            let mut option_entries: Vec<Option<_>> = self
+                .sstables
+                .iter_mut()
+                .zip(self.offsets.iter())
+                .map(|(sstable, offset)| {
+                    let return_value;
+                    let mut new_offset = *offset; // new offset from which we continue reading in sstable
+                    let mut added_offset = 0; // how many bytes we have read from stable
+                    loop {
+                        // The iterator can't return errors, so a corrupted entry ends the sstable like its last entry does
+                        if let Ok(Some((option_entry, length))) = sstable.get_entry_from_data_file(
+                            new_offset,
+                            None,
+                            None,
+                            self.use_variable_encoding,
+                        ) {
+                            added_offset += length;
+                            new_offset += length;
+                            if let Some(snapshot) = self.snapshot {
+                                if option_entry.1.get_timestamp() > snapshot {
+                                    continue;
+                                }
+                            }
+                            if self.previous_key.as_ref() == Some(&option_entry.0) {
+                                continue;
+                            }
                             return_value = Option::from((option_entry, added_offset));
                             break;
+                        } else {
+                            return_value = None;
+                            break;
                         }
-                    } else {
-                        return_value = None;
-                        break;
                     }
-                }
-                return_value
-            })
-            .collect();
+                    return_value
+                })
+                .collect();
 
-        option_entries.push(memory_table_entry);
+            option_entries.push(memory_table_entry);
 
-        // if all entries are none return None and drop the iterator
-        if option_entries.iter().all(Option::is_none) {
-            return None;
-        }
+            // if all entries are none return None and drop the iterator
+            if option_entries.iter().all(Option::is_none) {
+                return None;
+            }
 
-        // need all indexes from entries in original vector
-        let enumerated_entries: Vec<_> = option_entries.iter().enumerate().collect();
-
-        // find indexes of entries with minimum keys
-        let min_indexes =
-            SSTable::find_min_keys(&enumerated_entries, false, self.compression_dictionary);
-
-        let min_entries: Vec<_> = min_indexes
-            .iter()
-            .map(|index| enumerated_entries[*index].clone())
-            .collect();
-
-        // update offsets
-        let _ = min_entries.iter().for_each(|(index, element)| {
-            copy_offsets[*index] += element.as_ref().unwrap().1.clone();
-        });
-
-        // find entry with the biggest timestamp
-        let max_index = SSTable::find_max_timestamp(&min_entries);
-
-        let (key, entry) = enumerated_entries[max_index].1.as_ref().unwrap().0.clone();
-        let decoded_key = match self.compression_dictionary {
-            Some(compression_dictionary) => compression_dictionary
-                .decode(&key.to_vec().into_boxed_slice())
-                .unwrap()
-                .clone(),
-            None => key.to_vec().into_boxed_slice(),
-        };
-        let return_entry = (decoded_key, entry);
-        let _ = &*return_entry.0;
-
-        // update offsets in LSMIterator
-        if pushed {
-            self.memory_offset = copy_offsets.pop().unwrap() as usize;
-            self.offsets = copy_offsets;
-        } else {
-            self.offsets = copy_offsets;
-        }
+            // need all indexes from entries in original vector
+            let enumerated_entries: Vec<_> = option_entries.iter().enumerate().collect();
 
-        // check if we surpassed the upper bound, if so return None and drop iterator
-        match self.scan_type {
-            ScanType::RangeScan => {
-                if return_entry.0 > self.upper_bound {
-                    return None;
-                }
+            // find indexes of entries with minimum keys
+            let min_indexes =
+                SSTable::find_min_keys(&enumerated_entries, &mut compression_dictionary);
+
+            let min_entries: Vec<_> = min_indexes
+                .iter()
+                .map(|index| enumerated_entries[*index].clone())
+                .collect();
+
+            // update offsets
+            let _ = min_entries.iter().for_each(|(index, element)| {
+                copy_offsets[*index] += element.as_ref().unwrap().1.clone();
+            });
+
+            // find entry with the biggest timestamp
+            let max_index = SSTable::find_max_timestamp(&min_entries);
+
+            let (key, entry) = enumerated_entries[max_index].1.as_ref().unwrap().0.clone();
+            self.previous_key = Some(key.clone());
+            let decoded_key = match &mut *compression_dictionary {
+                Some(compression_dictionary) => compression_dictionary
+                    .decode(&key.to_vec().into_boxed_slice())
+                    .unwrap()
+                    .clone(),
+                None => key.to_vec().into_boxed_slice(),
+            };
+            let return_entry = (decoded_key, entry);
+
+            // update offsets in LSMIterator
+            if pushed {
+                self.memory_offset = copy_offsets.pop().unwrap() as usize;
+                self.offsets = copy_offsets;
+            } else {
+                self.offsets = copy_offsets;
             }
-            ScanType::PrefixScan => {
-                if !return_entry.0.starts_with(&self.upper_bound) {
-                    return None;
+
+            // check if we surpassed the upper bound, if so return None and drop iterator
+            match self.scan_type {
+                ScanType::RangeScan => {
+                    if return_entry.0 > self.upper_bound {
+                        return None;
+                    }
+                }
+                ScanType::PrefixScan => {
+                    if !return_entry.0.starts_with(&self.upper_bound) {
+                        return None;
+                    }
                 }
             }
-        }
 
-        Some(return_entry)
+            // the key is deleted or expired, so none of its versions are returned
+            if return_entry.1.get_tombstone() || return_entry.1.is_expired(self.read_time) {
+                continue;
+            }
+
+            return Some(return_entry);
+        }
     }
 }
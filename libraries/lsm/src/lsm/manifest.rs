@@ -0,0 +1,390 @@
+use crc::{Crc, CRC_32_ISCSI};
+use db_error::Error;
+use segment_elements::{file_header, read_file_header, FileKind, FILE_HEADER_LEN};
+use std::fs::{read_dir, remove_dir_all, rename, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the manifest file in the SSTable directory.
+const MANIFEST_FILE_NAME: &str = "MANIFEST";
+/// The manifest is rewritten into this file and then renamed, so a crash can't leave a half written manifest.
+const TEMP_MANIFEST_FILE_NAME: &str = "MANIFEST.tmp";
+/// Version of the format of the manifest, written into its header.
+const FORMAT_VERSION: u32 = 1;
+/// Suffix of the directory a new SSTable is written into, before it's renamed to its final name and published.
+pub(crate) const TEMP_TABLE_SUFFIX: &str = ".tmp";
+
+const ADD_TABLE: u8 = 0;
+const REMOVE_TABLE: u8 = 1;
+
+/// Metadata of an SSTable that belongs to one of the levels.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TableMetadata {
+    // Directory of the table, relative to the SSTable directory
+    pub(crate) directory: PathBuf,
+    pub(crate) in_single_file: bool,
+    // Decoded min and max key of the table
+    pub(crate) min_key: Box<[u8]>,
+    pub(crate) max_key: Box<[u8]>,
+    // Size of all files of the table in bytes
+    pub(crate) size: u64,
+    // Number of entries in the table and how many of them are tombstones
+    pub(crate) entry_count: u64,
+    pub(crate) tombstone_count: u64,
+    // Smallest and largest time stamp of the entries in the table
+    pub(crate) min_time_stamp: u128,
+    pub(crate) max_time_stamp: u128,
+    // Length of the data part of the table in bytes and the version of its format
+    pub(crate) data_size: u64,
+    pub(crate) format_version: u32,
+}
+
+impl TableMetadata {
+    /// Returns the share of the entries of the table that are tombstones.
+    pub(crate) fn tombstone_ratio(&self) -> f64 {
+        if self.entry_count == 0 {
+            0.0
+        } else {
+            self.tombstone_count as f64 / self.entry_count as f64
+        }
+    }
+}
+
+/// A change of the tables that belong to the levels. A table is added at the given index of its level,
+/// since merged tables can take the place of older tables.
+pub(crate) enum VersionEdit {
+    AddTable {
+        level: usize,
+        index: usize,
+        table: TableMetadata,
+    },
+    RemoveTable { level: usize, directory: PathBuf },
+}
+
+/// Append-only log of the changes of the levels, which is the source of truth for the tables that belong to the LSM.
+/// Each record holds a batch of edits that are applied together, along with its checksum, so a record torn by a crash
+/// is ignored as a whole. Directories that aren't in the manifest are never loaded and get removed on open.
+///
+/// The manifest starts with a file header, followed by the records. All numbers are little-endian.
+/// Record layout: `[checksum (4B)][payload length (8B)][edit count (8B)][edits...]`, where the checksum covers everything after it.
+pub(crate) struct Manifest {
+    file: File,
+    crc_hasher: Crc<u32>,
+}
+
+impl Manifest {
+    /// Opens the manifest in the SSTable directory and returns it along with the tables of each level.
+    /// The manifest is compacted into a single record on every open, so it doesn't grow without a limit.
+    /// Tables left behind by a flush or a compaction that crashed are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The SSTable directory.
+    /// * `max_level` - The number of levels.
+    /// * `legacy_levels` - Called to find the tables of each level if there is no manifest yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Config` if a table is on a level that doesn't exist,
+    /// and `Error::Corruption` if the manifest has no header or a table in the manifest is missing.
+    pub(crate) fn open(
+        dir: &Path,
+        max_level: usize,
+        legacy_levels: impl FnOnce() -> db_error::Result<Vec<Vec<TableMetadata>>>,
+    ) -> db_error::Result<(Manifest, Vec<Vec<TableMetadata>>)> {
+        let crc_hasher = Crc::<u32>::new(&CRC_32_ISCSI);
+        let path = dir.join(MANIFEST_FILE_NAME);
+
+        let replayed = path.exists();
+        let levels = if replayed {
+            let mut bytes = Vec::new();
+            File::open(&path)?.read_to_end(&mut bytes)?;
+            if read_file_header(FileKind::Manifest, &bytes, FORMAT_VERSION)?.is_none() {
+                return Err(Error::Corruption { path, offset: 0 });
+            }
+            Manifest::replay(&bytes[FILE_HEADER_LEN..], &crc_hasher, max_level)?
+        } else {
+            legacy_levels()?
+        };
+
+        for table in levels.iter().flatten() {
+            let table_path = dir.join(&table.directory);
+            if !table_path.is_dir() {
+                return Err(Error::Corruption {
+                    path: table_path,
+                    offset: 0,
+                });
+            }
+        }
+
+        Manifest::remove_unfinished_tables(dir, &levels, replayed)?;
+
+        let edits: Vec<_> = levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, tables)| {
+                tables
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, table)| VersionEdit::AddTable {
+                        level,
+                        index,
+                        table: table.clone(),
+                    })
+            })
+            .collect();
+
+        let temp_path = dir.join(TEMP_MANIFEST_FILE_NAME);
+        let mut temp_file = File::create(&temp_path)?;
+        temp_file.write_all(&file_header(FileKind::Manifest, FORMAT_VERSION))?;
+        temp_file.write_all(&Manifest::serialize_record(&crc_hasher, &edits))?;
+        temp_file.sync_all()?;
+        rename(&temp_path, &path)?;
+        File::open(dir)?.sync_all()?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+
+        Ok((Manifest { file, crc_hasher }, levels))
+    }
+
+    /// Durably appends the edits as a single record.
+    pub(crate) fn append(&mut self, edits: &[VersionEdit]) -> db_error::Result<()> {
+        self.file
+            .write_all(&Manifest::serialize_record(&self.crc_hasher, edits))?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Removes the SSTable directories that a crash left behind. Temporary directories of tables that were never
+    /// published are always removed. If the levels were replayed from the manifest, so are the directories
+    /// that aren't in it: published tables whose addition wasn't logged and compacted tables whose removal was.
+    fn remove_unfinished_tables(
+        dir: &Path,
+        levels: &[Vec<TableMetadata>],
+        replayed: bool,
+    ) -> db_error::Result<()> {
+        for dir_entry in read_dir(dir)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name().to_string_lossy().to_string();
+            // Other column families can keep their directories inside of this one
+            if !dir_entry.path().is_dir() || !name.starts_with("sstable_") {
+                continue;
+            }
+
+            let is_unfinished = name.ends_with(TEMP_TABLE_SUFFIX)
+                || (replayed
+                    && !levels
+                        .iter()
+                        .flatten()
+                        .any(|table| table.directory.as_os_str() == name.as_str()));
+            if is_unfinished {
+                eprintln!("Removing the unfinished SSTable {}.", name);
+                remove_dir_all(dir_entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the records of the manifest in order. Reading stops at the first torn or corrupted record,
+    /// because a crash can only tear the last record.
+    fn replay(
+        bytes: &[u8],
+        crc_hasher: &Crc<u32>,
+        max_level: usize,
+    ) -> db_error::Result<Vec<Vec<TableMetadata>>> {
+        let mut levels = vec![vec![]; max_level];
+
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let edits = match Manifest::deserialize_record(&bytes[offset..], crc_hasher) {
+                Some((edits, record_len)) => {
+                    offset += record_len;
+                    edits
+                }
+                None => {
+                    eprintln!(
+                        "Ignoring the torn or corrupted end of the manifest from offset {}.",
+                        offset
+                    );
+                    break;
+                }
+            };
+
+            for edit in edits {
+                match edit {
+                    VersionEdit::AddTable {
+                        level,
+                        index,
+                        table,
+                    } => {
+                        if level >= max_level {
+                            return Err(Error::Config(format!(
+                                "SSTable {} is on level {}, but there are only {} levels.",
+                                table.directory.display(),
+                                level + 1,
+                                max_level
+                            )));
+                        }
+                        let index = index.min(levels[level].len());
+                        levels[level].insert(index, table);
+                    }
+                    VersionEdit::RemoveTable { level, directory } => {
+                        if let Some(tables) = levels.get_mut(level) {
+                            tables.retain(|table| table.directory != directory);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(levels)
+    }
+
+    fn serialize_record(crc_hasher: &Crc<u32>, edits: &[VersionEdit]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend((edits.len() as u64).to_le_bytes());
+        for edit in edits {
+            match edit {
+                VersionEdit::AddTable {
+                    level,
+                    index,
+                    table,
+                } => {
+                    payload.push(ADD_TABLE);
+                    payload.extend((*level as u64).to_le_bytes());
+                    serialize_bytes(&mut payload, table.directory.to_string_lossy().as_bytes());
+                    payload.extend((*index as u64).to_le_bytes());
+                    payload.push(table.in_single_file as u8);
+                    serialize_bytes(&mut payload, &table.min_key);
+                    serialize_bytes(&mut payload, &table.max_key);
+                    payload.extend(table.size.to_le_bytes());
+                    payload.extend(table.entry_count.to_le_bytes());
+                    payload.extend(table.tombstone_count.to_le_bytes());
+                    payload.extend(table.min_time_stamp.to_le_bytes());
+                    payload.extend(table.max_time_stamp.to_le_bytes());
+                    payload.extend(table.data_size.to_le_bytes());
+                    payload.extend(table.format_version.to_le_bytes());
+                }
+                VersionEdit::RemoveTable { level, directory } => {
+                    payload.push(REMOVE_TABLE);
+                    payload.extend((*level as u64).to_le_bytes());
+                    serialize_bytes(&mut payload, directory.to_string_lossy().as_bytes());
+                }
+            }
+        }
+
+        let mut checksummed = Vec::with_capacity(8 + payload.len());
+        checksummed.extend((payload.len() as u64).to_le_bytes());
+        checksummed.extend(payload);
+
+        let mut record = Vec::with_capacity(4 + checksummed.len());
+        record.extend(crc_hasher.checksum(&checksummed).to_le_bytes());
+        record.extend(checksummed);
+
+        record
+    }
+
+    /// Returns the edits of the record at the start of the bytes and the length of the record,
+    /// or None if the record is torn or corrupted.
+    fn deserialize_record(
+        bytes: &[u8],
+        crc_hasher: &Crc<u32>,
+    ) -> Option<(Vec<VersionEdit>, usize)> {
+        let mut reader = ByteReader::new(bytes);
+        let checksum = reader.read_u32()?;
+        let payload_len = reader.read_usize()?;
+        let payload = reader.read(payload_len)?;
+        let record_len = reader.offset;
+
+        if crc_hasher.checksum(&bytes[4..record_len]) != checksum {
+            return None;
+        }
+
+        let mut reader = ByteReader::new(payload);
+        let edit_count = reader.read_usize()?;
+        let mut edits = Vec::new();
+        for _ in 0..edit_count {
+            let tag = reader.read(1)?[0];
+            let level = reader.read_usize()?;
+            let directory = PathBuf::from(String::from_utf8(reader.read_bytes()?.to_vec()).ok()?);
+
+            edits.push(match tag {
+                ADD_TABLE => {
+                    let index = reader.read_usize()?;
+                    let table = TableMetadata {
+                        directory,
+                        in_single_file: reader.read(1)?[0] != 0,
+                        min_key: Box::from(reader.read_bytes()?),
+                        max_key: Box::from(reader.read_bytes()?),
+                        size: reader.read_u64()?,
+                        entry_count: reader.read_u64()?,
+                        tombstone_count: reader.read_u64()?,
+                        min_time_stamp: reader.read_u128()?,
+                        max_time_stamp: reader.read_u128()?,
+                        data_size: reader.read_u64()?,
+                        format_version: reader.read_u32()?,
+                    };
+
+                    VersionEdit::AddTable {
+                        level,
+                        index,
+                        table,
+                    }
+                }
+                REMOVE_TABLE => VersionEdit::RemoveTable { level, directory },
+                _ => return None,
+            });
+        }
+
+        Some((edits, record_len))
+    }
+}
+
+/// Serializes the bytes prefixed with their length.
+fn serialize_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend((bytes.len() as u64).to_le_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+/// Reads values from bytes, returning None once the bytes run out.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        ByteReader { bytes, offset: 0 }
+    }
+
+    fn read(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.offset.checked_add(len)?;
+        let bytes = self.bytes.get(self.offset..end)?;
+        self.offset = end;
+        Some(bytes)
+    }
+
+    fn read_usize(&mut self) -> Option<usize> {
+        Some(u64::from_le_bytes(self.read(8)?.try_into().unwrap()) as usize)
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.read(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.read(8)?.try_into().unwrap()))
+    }
+
+    fn read_u128(&mut self) -> Option<u128> {
+        Some(u128::from_le_bytes(self.read(16)?.try_into().unwrap()))
+    }
+
+    /// Reads bytes prefixed with their length.
+    fn read_bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.read_usize()?;
+        self.read(len)
+    }
+}
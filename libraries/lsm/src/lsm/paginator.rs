@@ -2,14 +2,16 @@ use crate::lsm::ScanType;
 use crate::LSM;
 use segment_elements::MemoryEntry;
 use crate::lsm::iterator::LSMIterator;
+use crate::lsm::snapshot::LSMSnapshot;
 
 /// A Paginator provides paginated access to entries in an LSM (Log-Structured Merge) tree.
 pub struct Paginator<'a> {
-    lsm: &'a mut LSM,
+    lsm: &'a LSM,
     cached_entry_index: usize,
     cached_entries: Vec<(Box<[u8]>, MemoryEntry)>,
     lsm_iter: Option<LSMIterator<'a>>,
-    entries_itered_count: usize
+    entries_itered_count: usize,
+    snapshot: Option<&'a LSMSnapshot>
 }
 
 impl<'a> Paginator<'a> {
@@ -22,13 +24,31 @@ impl<'a> Paginator<'a> {
     /// # Returns
     ///
     /// A new Paginator instance.
-    pub fn new(lsm: &'a mut LSM) -> Self {
+    pub fn new(lsm: &'a LSM) -> Self {
         Self {
             lsm,
             cached_entry_index: 0,
             cached_entries: vec![],
             lsm_iter: None,
-            entries_itered_count: 0
+            entries_itered_count: 0,
+            snapshot: None
+        }
+    }
+
+    /// Creates a new Paginator instance that only sees entries visible to the given snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `lsm` - A reference to the LSM instance.
+    /// * `snapshot` - The snapshot through which the entries are read.
+    ///
+    /// # Returns
+    ///
+    /// A new Paginator instance.
+    pub fn with_snapshot(lsm: &'a LSM, snapshot: &'a LSMSnapshot) -> Self {
+        Self {
+            snapshot: Some(snapshot),
+            ..Self::new(lsm)
         }
     }
 
@@ -55,7 +75,7 @@ impl<'a> Paginator<'a> {
         scan_type: ScanType,
         page_number: usize,
         page_size: usize,
-    ) -> std::io::Result<Vec<(Box<[u8]>, MemoryEntry)>>
+    ) -> db_error::Result<Vec<(Box<[u8]>, MemoryEntry)>>
     {
         let mut result: Vec<(Box<[u8]>, MemoryEntry)> = vec![];
 
@@ -63,7 +83,7 @@ impl<'a> Paginator<'a> {
 
         // Get the lsm iter (cached when calling range_iter, prefix_iter or create a new one)
         let mut lsm_iter_unwrapped = std::mem::replace(&mut self.lsm_iter, None)
-            .unwrap_or_else(|| self.lsm.iter(start_key, end_key, prefix, scan_type)
+            .unwrap_or_else(|| self.lsm.iter(start_key, end_key, prefix, scan_type, self.snapshot)
                 .expect("Failed to get LSM iterator"));
 
         // Iterate until the correct page is found
@@ -85,6 +105,7 @@ impl<'a> Paginator<'a> {
     }
 
     /// Performs a prefix scan and retrieves entries for a specific page (0-indexed).
+    /// SSTables whose prefix Bloom filter rules out the prefix aren't read.
     ///
     /// # Arguments
     ///
@@ -100,7 +121,7 @@ impl<'a> Paginator<'a> {
         prefix: &[u8],
         page_index: usize,
         page_size: usize,
-    ) -> std::io::Result<Vec<(Box<[u8]>, MemoryEntry)>>
+    ) -> db_error::Result<Vec<(Box<[u8]>, MemoryEntry)>>
     {
         self.iterate_stop();
 
@@ -132,7 +153,7 @@ impl<'a> Paginator<'a> {
         max_key: &[u8],
         page_number: usize,
         page_size: usize,
-    ) -> std::io::Result<Vec<(Box<[u8]>, MemoryEntry)>>
+    ) -> db_error::Result<Vec<(Box<[u8]>, MemoryEntry)>>
     {
         self.iterate_stop();
 
@@ -146,7 +167,98 @@ impl<'a> Paginator<'a> {
         )
     }
 
+    /// Performs a prefix scan and retrieves the entries that follow the given key, or the first ones if no key is given.
+    /// Passing the last key of each page to get the next one reads every entry once, while reaching a numbered page
+    /// traverses all the pages before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix to scan for.
+    /// * `start_after` - The key after which the page starts, usually the last key of the previous page.
+    /// * `page_size` - The size of the page.
+    ///
+    /// # Returns
+    ///
+    /// A vector of entries based on the prefix scan criteria.
+    pub fn prefix_scan_after(
+        &mut self,
+        prefix: &[u8],
+        start_after: Option<&[u8]>,
+        page_size: usize,
+    ) -> db_error::Result<Vec<(Box<[u8]>, MemoryEntry)>>
+    {
+        // Keys that come before the prefix would end the prefix scan at once
+        let start_key = start_after.filter(|key| *key > prefix);
+
+        self.scan_entries_after(
+            start_key,
+            None,
+            Some(prefix),
+            ScanType::PrefixScan,
+            start_after,
+            page_size,
+        )
+    }
+
+    /// Performs a range scan and retrieves the entries that follow the given key, or the first ones if no key is given.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_key` - The minimum key of the range.
+    /// * `max_key` - The maximum key of the range.
+    /// * `start_after` - The key after which the page starts, usually the last key of the previous page.
+    /// * `page_size` - The size of the page.
+    ///
+    /// # Returns
+    ///
+    /// A vector of entries based on the range scan criteria.
+    pub fn range_scan_after(
+        &mut self,
+        min_key: &[u8],
+        max_key: &[u8],
+        start_after: Option<&[u8]>,
+        page_size: usize,
+    ) -> db_error::Result<Vec<(Box<[u8]>, MemoryEntry)>>
+    {
+        let start_key = match start_after {
+            Some(key) if key > min_key => key,
+            _ => min_key,
+        };
+
+        self.scan_entries_after(
+            Some(start_key),
+            Some(max_key),
+            None,
+            ScanType::RangeScan,
+            start_after,
+            page_size,
+        )
+    }
+
+    /// Internal method to retrieve the entries of a prefix or range scan that starts from the given key,
+    /// skipping the key the page starts after.
+    fn scan_entries_after(
+        &mut self,
+        start_key: Option<&[u8]>,
+        end_key: Option<&[u8]>,
+        prefix: Option<&[u8]>,
+        scan_type: ScanType,
+        start_after: Option<&[u8]>,
+        page_size: usize,
+    ) -> db_error::Result<Vec<(Box<[u8]>, MemoryEntry)>>
+    {
+        self.iterate_stop();
+
+        let lsm_iter = self.lsm.iter(start_key, end_key, prefix, scan_type, self.snapshot)?;
+
+        Ok(lsm_iter
+            .filter(|(key, _)| Some(key.as_ref()) != start_after)
+            .take(page_size)
+            .collect())
+    }
+
     /// Retrieves the next entry based on prefix scan.
+    /// SSTables whose prefix Bloom filter rules out the prefix aren't read.
     ///
     /// # Arguments
     ///
@@ -158,7 +270,7 @@ impl<'a> Paginator<'a> {
     pub fn prefix_iterate_next(
         &mut self,
         prefix: &[u8],
-    ) -> std::io::Result<Option<(Box<[u8]>, MemoryEntry)>>
+    ) -> db_error::Result<Option<(Box<[u8]>, MemoryEntry)>>
     {
         let next_is_in_cache = self.check_next_in_cache();
         if next_is_in_cache.is_some() {
@@ -191,7 +303,7 @@ impl<'a> Paginator<'a> {
         &mut self,
         min_key: &[u8],
         max_key: &[u8],
-    ) -> std::io::Result<Option<(Box<[u8]>, MemoryEntry)>>
+    ) -> db_error::Result<Option<(Box<[u8]>, MemoryEntry)>>
     {
         let next_is_in_cache = self.check_next_in_cache();
         if next_is_in_cache.is_some() {
@@ -250,7 +362,7 @@ impl<'a> Paginator<'a> {
     /// # Returns
     ///
     /// An optional tuple containing the key and memory entry of the previous entry, or `None` if no more entries are available.
-    pub fn iterate_prev(&mut self) -> std::io::Result<Option<(Box<[u8]>, MemoryEntry)>> {
+    pub fn iterate_prev(&mut self) -> db_error::Result<Option<(Box<[u8]>, MemoryEntry)>> {
         // If the cache is empty or the cached index is 0, there is no previous entry
         if self.cached_entries.is_empty() || (!self.cached_entries.is_empty() && self.cached_entry_index == 0)
         {
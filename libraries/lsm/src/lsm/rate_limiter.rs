@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Share of a second of the rate that can be used at once after the limiter was idle.
+const MAX_BURST_SECONDS: f64 = 0.1;
+/// How often the auto-tuned rate is adjusted.
+const TUNE_INTERVAL: Duration = Duration::from_millis(100);
+/// Foreground requests per second above which the auto-tuned rate backs off.
+const BUSY_REQUESTS_PER_SECOND: f64 = 1000.0;
+/// Share of the configured rate below which the auto-tuned rate doesn't back off.
+const MIN_TUNED_SHARE: f64 = 1.0 / 16.0;
+
+/// Limits the bytes per second that flushes and compactions read and write, so they leave disk bandwidth
+/// to foreground reads and writes. Bytes used over the limit are paid back by waiting, so the callers are slowed
+/// down to the rate on average. With auto-tuning, the rate is halved after every interval with high foreground
+/// traffic, down to a sixteenth of the configured one, and doubled back once the traffic calms down.
+pub(crate) struct RateLimiter {
+    state: Mutex<RateLimiterState>,
+    // Foreground requests since the rate was last tuned
+    foreground_requests: AtomicU64,
+}
+
+struct RateLimiterState {
+    // Configured limit, 0 if I/O isn't limited
+    bytes_per_second: u64,
+    auto_tune: bool,
+    // Rate in use, lower than the configured one while auto-tuning backs off
+    rate: f64,
+    // Bytes that can be used right away, negative while the used bytes are paid back
+    available: f64,
+    last_refill: Instant,
+    last_tune: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(bytes_per_second: u64, auto_tune: bool) -> Self {
+        let now = Instant::now();
+        RateLimiter {
+            state: Mutex::new(RateLimiterState {
+                bytes_per_second,
+                auto_tune,
+                rate: bytes_per_second as f64,
+                available: 0.0,
+                last_refill: now,
+                last_tune: now,
+            }),
+            foreground_requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Changes the configured limit, 0 removes it. Applies to the flushes and compactions that are already running.
+    pub(crate) fn set_bytes_per_second(&self, bytes_per_second: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.refill(Instant::now());
+        state.bytes_per_second = bytes_per_second;
+        state.rate = bytes_per_second as f64;
+        state.available = state.available.min(state.rate * MAX_BURST_SECONDS);
+    }
+
+    /// Turns auto-tuning on or off, the rate starts from the configured one either way.
+    pub(crate) fn set_auto_tune(&self, auto_tune: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.refill(Instant::now());
+        state.auto_tune = auto_tune;
+        state.rate = state.bytes_per_second as f64;
+        state.last_tune = Instant::now();
+        self.foreground_requests.store(0, Ordering::Relaxed);
+    }
+
+    /// Counts a foreground read or write, which auto-tuning uses to measure the foreground traffic.
+    pub(crate) fn record_foreground_request(&self) {
+        self.foreground_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the rate in use in bytes per second, 0 if I/O isn't limited.
+    #[cfg(test)]
+    pub(crate) fn rate(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        if state.auto_tune {
+            let now = Instant::now();
+            self.tune(&mut state, now);
+        }
+        state.rate as u64
+    }
+
+    /// Takes the bytes that were just read or written from the limit. Blocks until they're paid back
+    /// if the limit was used up.
+    pub(crate) fn request(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            if state.bytes_per_second == 0 {
+                return;
+            }
+
+            let now = Instant::now();
+            state.refill(now);
+            if state.auto_tune {
+                self.tune(&mut state, now);
+            }
+
+            state.available -= bytes as f64;
+            if state.available >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-state.available / state.rate)
+        };
+
+        // Later requests wait for the bytes that are still owed, so the lock isn't held while sleeping
+        thread::sleep(wait);
+    }
+
+    /// Halves the rate if the foreground traffic was high since the last tuning, otherwise doubles it back.
+    fn tune(&self, state: &mut RateLimiterState, now: Instant) {
+        let elapsed = now.duration_since(state.last_tune);
+        if elapsed < TUNE_INTERVAL {
+            return;
+        }
+
+        let requests = self.foreground_requests.swap(0, Ordering::Relaxed);
+        let bytes_per_second = state.bytes_per_second as f64;
+        state.rate = if requests as f64 / elapsed.as_secs_f64() > BUSY_REQUESTS_PER_SECOND {
+            (state.rate / 2.0).max(bytes_per_second * MIN_TUNED_SHARE)
+        } else {
+            (state.rate * 2.0).min(bytes_per_second)
+        };
+        state.last_tune = now;
+    }
+}
+
+impl RateLimiterState {
+    /// Adds the bytes that became available since the last refill at the rate in use.
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.rate).min(self.rate * MAX_BURST_SECONDS);
+        self.last_refill = now;
+    }
+}
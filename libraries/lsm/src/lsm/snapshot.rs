@@ -0,0 +1,59 @@
+use segment_elements::MemoryEntry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Registry of time stamps of all live snapshots and the number of handles per time stamp.
+pub(crate) type SnapshotRegistry = Arc<Mutex<HashMap<u128, usize>>>;
+
+/// A point-in-time view of the LSM. Reads through the snapshot only see entries that were written
+/// at or before its time stamp. While the snapshot is alive, compaction keeps the versions it can see.
+pub struct LSMSnapshot {
+    timestamp: u128,
+    // Contents of all memory tables at the time of the snapshot, including tombstones
+    memory_entries: BTreeMap<Box<[u8]>, MemoryEntry>,
+    registry: SnapshotRegistry,
+}
+
+impl LSMSnapshot {
+    pub(crate) fn new(
+        timestamp: u128,
+        memory_entries: BTreeMap<Box<[u8]>, MemoryEntry>,
+        registry: SnapshotRegistry,
+    ) -> Self {
+        *registry.lock().unwrap().entry(timestamp).or_insert(0) += 1;
+
+        LSMSnapshot {
+            timestamp,
+            memory_entries,
+            registry,
+        }
+    }
+
+    /// Returns the time stamp of the snapshot.
+    pub fn get_timestamp(&self) -> u128 {
+        self.timestamp
+    }
+
+    pub(crate) fn get_memory_entry(&self, key: &[u8]) -> Option<&MemoryEntry> {
+        self.memory_entries.get(key)
+    }
+
+    pub(crate) fn get_memory_entries(&self) -> Vec<(Box<[u8]>, MemoryEntry)> {
+        self.memory_entries
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect()
+    }
+}
+
+impl Drop for LSMSnapshot {
+    fn drop(&mut self) {
+        let mut registry = self.registry.lock().unwrap();
+        if let Some(count) = registry.get_mut(&self.timestamp) {
+            *count -= 1;
+            if *count == 0 {
+                registry.remove(&self.timestamp);
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::sstable::SSTable;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Keeps the recently read SSTables open, so point lookups reuse their file handles, their parsed summaries
+/// and their Bloom filters instead of reading them again. Holds at most `capacity` tables and closes the least
+/// recently used one when a new table doesn't fit. Tables have to be evicted once compaction removes them.
+pub(crate) struct TableCache {
+    capacity: usize,
+    // Flag indicating whether the files of the opened tables are mapped into memory
+    use_mmap: bool,
+    state: Mutex<TableCacheState>,
+}
+
+struct TableCacheState {
+    // Open tables by their base paths, with the time they were last used
+    tables: HashMap<PathBuf, (Arc<Mutex<SSTable>>, u64)>,
+    // Incremented on every use, so the least recently used table has the smallest time
+    clock: u64,
+}
+
+impl TableCache {
+    /// Creates a cache that keeps up to `capacity` tables open, 0 opens the tables again for every lookup.
+    /// The files of the tables are mapped into memory if `use_mmap` is set.
+    pub(crate) fn new(capacity: usize, use_mmap: bool) -> Self {
+        TableCache {
+            capacity,
+            use_mmap,
+            state: Mutex::new(TableCacheState {
+                tables: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    /// Returns the open table in the given base path, opening it if it isn't in the cache.
+    /// The table is locked by the caller while it's read, since reads move its file cursors.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_path` - The base directory path of the table.
+    /// * `in_single_file` - Flag indicating whether the table is stored in a single file or multiple files.
+    pub(crate) fn get(
+        &self,
+        base_path: &Path,
+        in_single_file: bool,
+    ) -> io::Result<Arc<Mutex<SSTable>>> {
+        if self.capacity == 0 {
+            return Ok(Arc::new(Mutex::new(SSTable::open_with_mmap(
+                base_path.to_path_buf(),
+                in_single_file,
+                self.use_mmap,
+            )?)));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+        if let Some((table, last_used)) = state.tables.get_mut(base_path) {
+            *last_used = clock;
+            return Ok(Arc::clone(table));
+        }
+
+        if state.tables.len() >= self.capacity {
+            let least_recently_used = state
+                .tables
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(path, _)| path.clone())
+                .unwrap();
+            state.tables.remove(&least_recently_used);
+        }
+
+        let table = Arc::new(Mutex::new(SSTable::open_with_mmap(
+            base_path.to_path_buf(),
+            in_single_file,
+            self.use_mmap,
+        )?));
+        state
+            .tables
+            .insert(base_path.to_path_buf(), (Arc::clone(&table), clock));
+
+        Ok(table)
+    }
+
+    /// Closes the table in the given base path if it's in the cache. Readers that got it before keep it open
+    /// until they're done with it.
+    pub(crate) fn evict(&self, base_path: &Path) {
+        self.state.lock().unwrap().tables.remove(base_path);
+    }
+
+    /// Returns the base paths of the tables in the cache.
+    #[cfg(test)]
+    pub(crate) fn base_paths(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().tables.keys().cloned().collect()
+    }
+}
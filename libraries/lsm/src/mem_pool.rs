@@ -1,35 +1,45 @@
-mod record_iterator;
+pub(crate) mod record_iterator;
 
-use crate::mem_pool::record_iterator::RecordIterator;
+use crate::mem_pool::record_iterator::{LogRecord, RecordIterator};
 use crate::memtable::MemoryTable;
 use db_config::DBConfig;
-use segment_elements::{MemoryEntry, TimeStamp};
-use std::collections::VecDeque;
-use std::error::Error;
+use segment_elements::{MemoryEntry, TimeStamp, WriteBatch};
+use std::collections::{BTreeMap, VecDeque};
 use std::path::Path;
+use std::sync::Arc;
+
+/// The memory pool rebuilt from the wal and the memory tables that didn't fit in it, which need to be flushed.
+type LoadedMemoryPool = (MemoryPool, Vec<Arc<MemoryTable>>);
 
 pub(crate) struct MemoryPool {
     read_write_table: MemoryTable,
-    read_only_tables: VecDeque<MemoryTable>,
+    // Shared so that a table being flushed can still be read until its SSTable is in place
+    read_only_tables: VecDeque<Arc<MemoryTable>>,
+    // Number of the oldest read only tables that were handed out for flushing, but aren't flushed yet
+    flushing: usize,
     config: DBConfig,
 }
 
 impl MemoryPool {
-    pub(crate) fn new(dbconfig: &DBConfig) -> Result<Self, Box<dyn Error>> {
+    pub(crate) fn new(dbconfig: &DBConfig) -> db_error::Result<Self> {
         Ok(MemoryPool {
             config: dbconfig.clone(),
             read_only_tables: VecDeque::with_capacity(dbconfig.memory_table_pool_num),
+            flushing: 0,
             read_write_table: MemoryTable::new(dbconfig)?,
         })
     }
 
     /// Inserts the key with the corresponding value in the read write memory table.
+    /// The WAL position is the position of the record of this write in the WAL.
     pub(crate) fn insert(
         &mut self,
         key: &[u8],
         value: &[u8],
         time_stamp: TimeStamp,
-    ) -> Option<MemoryTable> {
+        wal_position: usize,
+    ) -> Option<Arc<MemoryTable>> {
+        self.read_write_table.set_wal_start(wal_position);
         if self.read_write_table.insert(key, value, time_stamp) {
             return self.swap();
         }
@@ -37,9 +47,35 @@ impl MemoryPool {
         None
     }
 
+    /// Inserts the key with the corresponding value that expires at the given time stamp in the read write memory table.
+    pub(crate) fn insert_with_expiry(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        expiry: u128,
+        time_stamp: TimeStamp,
+        wal_position: usize,
+    ) -> Option<Arc<MemoryTable>> {
+        self.read_write_table.set_wal_start(wal_position);
+        if self
+            .read_write_table
+            .insert_with_expiry(key, value, expiry, time_stamp)
+        {
+            return self.swap();
+        }
+
+        None
+    }
+
     /// Logically deletes an element in-place, and updates the number of elements if
     /// the deletion is "adding" a new element.
-    pub(crate) fn delete(&mut self, key: &[u8], time_stamp: TimeStamp) -> Option<MemoryTable> {
+    pub(crate) fn delete(
+        &mut self,
+        key: &[u8],
+        time_stamp: TimeStamp,
+        wal_position: usize,
+    ) -> Option<Arc<MemoryTable>> {
+        self.read_write_table.set_wal_start(wal_position);
         if self.read_write_table.delete(key, time_stamp) {
             return self.swap();
         }
@@ -47,6 +83,21 @@ impl MemoryPool {
         None
     }
 
+    /// Applies the whole batch to the read write memory table before checking if it should be swapped.
+    pub(crate) fn write_batch(
+        &mut self,
+        batch: &WriteBatch,
+        time_stamp: TimeStamp,
+        wal_position: usize,
+    ) -> Option<Arc<MemoryTable>> {
+        self.read_write_table.set_wal_start(wal_position);
+        if self.read_write_table.write_batch(batch, time_stamp) {
+            return self.swap();
+        }
+
+        None
+    }
+
     /// Tries to retrieve key's data from all memory tables currently loaded in memory.
     /// Does not go into on-disk structures.
     pub(crate) fn get(&self, key: &[u8]) -> Option<MemoryEntry> {
@@ -68,24 +119,41 @@ impl MemoryPool {
     }
 
     /// Swaps the current read write memory table with a new one. Checks if the number of read only
-    /// memory tables exceeds the capacity, and flushes the last one if necessary.
-    fn swap(&mut self) -> Option<MemoryTable> {
+    /// memory tables that aren't being flushed exceeds the capacity, and flushes the oldest one if necessary.
+    fn swap(&mut self) -> Option<Arc<MemoryTable>> {
         // unwrap allowed because any error would have been cleared in the pool creation
         // unchecked unwrap allows faster performance as it doesn't do any runtime checks
         let old_read_write = std::mem::replace(&mut self.read_write_table, unsafe {
             MemoryTable::new(&self.config).unwrap_unchecked()
         });
 
-        self.read_only_tables.push_front(old_read_write);
-        if self.read_only_tables.len() == self.config.memory_table_pool_num {
-            // unwrap allowed because if condition will never be true when unwrap can panic
-            let to_be_flushed = unsafe { self.read_only_tables.pop_back().unwrap_unchecked() };
+        self.read_only_tables.push_front(Arc::new(old_read_write));
+        let len = self.read_only_tables.len();
+        if len - self.flushing >= self.config.memory_table_pool_num {
+            let to_be_flushed = Arc::clone(&self.read_only_tables[len - 1 - self.flushing]);
+            self.flushing += 1;
             return Some(to_be_flushed);
         }
 
         None
     }
 
+    /// Removes the read only memory table once it has been flushed to disk.
+    pub(crate) fn remove_flushed(&mut self, table: &Arc<MemoryTable>) {
+        self.read_only_tables
+            .retain(|read_only_table| !Arc::ptr_eq(read_only_table, table));
+        self.flushing -= 1;
+    }
+
+    /// Returns the WAL position of the oldest record that isn't flushed yet, None if all tables are empty.
+    pub(crate) fn oldest_wal_position(&self) -> Option<usize> {
+        self.read_only_tables
+            .iter()
+            .filter_map(|table| table.wal_start())
+            .chain(self.read_write_table.wal_start())
+            .min()
+    }
+
     pub(crate) fn get_all_tables(&self) -> Vec<Vec<(Box<[u8]>, MemoryEntry)>> {
         let mut memory_tables = Vec::new();
         memory_tables.push(self.read_write_table.iterator().collect());
@@ -96,22 +164,52 @@ impl MemoryPool {
         memory_tables
     }
 
-    /// Loads from every log file in the given directory.
+    /// Returns the newest entry of every key from all memory tables, including tombstones.
+    pub(crate) fn get_newest_entries(&self) -> BTreeMap<Box<[u8]>, MemoryEntry> {
+        let mut newest_entries = BTreeMap::new();
+        for (key, entry) in self.read_write_table.iterator() {
+            newest_entries.insert(key, entry);
+        }
+
+        // Read only tables are ordered from the newest to the oldest one
+        for memory_table in &self.read_only_tables {
+            for (key, entry) in memory_table.iterator() {
+                newest_entries.entry(key).or_insert(entry);
+            }
+        }
+
+        newest_entries
+    }
+
+    /// Loads the records of the given column family from every log file in the given directory.
     pub(crate) fn load_from_dir(
         config: &DBConfig,
-    ) -> Result<(MemoryPool, Vec<MemoryTable>), Box<dyn Error>> {
+        column_family: Option<&str>,
+    ) -> db_error::Result<LoadedMemoryPool> {
         let mut pool = MemoryPool::new(config)?;
         let mut to_be_flushed = vec![];
 
-        for entry in RecordIterator::new(Path::new(&config.write_ahead_log_dir))? {
+        let mut records =
+            RecordIterator::new(Path::new(&config.write_ahead_log_dir), column_family)?;
+        while let Some(entry) = records.next() {
+            let wal_position = records.record_position();
             let entry = match entry {
-                Ok(entry) => entry,
+                Ok(LogRecord::Single(entry)) => entry,
+                Ok(LogRecord::Batch { timestamp, batch }) => {
+                    if let Some(table) =
+                        pool.write_batch(&batch, TimeStamp::Custom(timestamp), wal_position)
+                    {
+                        to_be_flushed.push(table);
+                    }
+                    continue;
+                }
                 Err(e) => {
                     eprintln!("{}", e);
                     continue;
                 }
             };
 
+            pool.read_write_table.set_wal_start(wal_position);
             if entry.tombstone {
                 if pool
                     .read_write_table
@@ -122,11 +220,20 @@ impl MemoryPool {
                     }
                 }
             } else {
-                if pool.read_write_table.insert(
-                    &entry.key,
-                    &entry.value.unwrap(),
-                    TimeStamp::Custom(entry.timestamp),
-                ) {
+                let is_full = match entry.expiry {
+                    Some(expiry) => pool.read_write_table.insert_with_expiry(
+                        &entry.key,
+                        &entry.value.unwrap(),
+                        expiry,
+                        TimeStamp::Custom(entry.timestamp),
+                    ),
+                    None => pool.read_write_table.insert(
+                        &entry.key,
+                        &entry.value.unwrap(),
+                        TimeStamp::Custom(entry.timestamp),
+                    ),
+                };
+                if is_full {
                     if let Some(table) = pool.swap() {
                         to_be_flushed.push(table);
                     }
@@ -1,26 +1,46 @@
 use crc::{Crc, CRC_32_ISCSI};
-use segment_elements::crc_error::CRCError;
-use std::fs::{read_dir, OpenOptions};
+use db_error::Error;
+use segment_elements::{read_file_header, FileKind, WriteBatch, FILE_HEADER_LEN};
+use std::fs::{read, read_dir};
 use std::io;
-use std::io::Read;
 use std::path::{Path, PathBuf};
+use write_ahead_log::{read_byte_index, FORMAT_VERSION};
 
 pub(crate) struct Record {
     pub(crate) timestamp: u128,
     pub(crate) tombstone: bool,
     pub(crate) key: Box<[u8]>,
     pub(crate) value: Option<Box<[u8]>>,
+    pub(crate) expiry: Option<u128>,
 }
 
+/// Value of the tombstone byte that marks a record holding a whole write batch.
+const BATCH_FLAG: u8 = 2;
+/// Value of the tombstone byte that marks an insert whose expiry follows the value length.
+const EXPIRY_FLAG: u8 = 3;
+/// Bit of the tombstone byte that marks a record of a named column family.
+const COLUMN_FAMILY_FLAG: u8 = 0x80;
+
+pub(crate) enum LogRecord {
+    Single(Record),
+    Batch { timestamp: u128, batch: WriteBatch },
+}
+
+/// Iterates over the records of a single column family, skipping the records of all other ones.
 pub(crate) struct RecordIterator {
     files: Vec<PathBuf>,
+    // Files that were read so far, with the positions at which their records start and the lengths of their headers
+    read_files: Vec<(PathBuf, usize, usize)>,
     read_bytes: Vec<u8>,
     crc_hasher: Crc<u32>,
     data_pointer: usize,
+    column_family: Option<String>,
+    // Position in the log at which the last returned record starts
+    record_position: usize,
 }
 
 impl RecordIterator {
-    pub fn new(dir: &Path) -> io::Result<RecordIterator> {
+    pub fn new(dir: &Path, column_family: Option<&str>) -> io::Result<RecordIterator> {
         let mut files = read_dir(dir)?
             .map(|dir_entry| dir_entry.unwrap().path())
             .filter(|file| match file.extension() {
@@ -33,20 +53,16 @@ impl RecordIterator {
 
         let all_read_bytes = Vec::new();
 
-        let starting_byte = {
-            let mut byte_file = OpenOptions::new()
-                .read(true)
-                .open(dir.join("byte_index.num"))?;
-            let mut byte_buffer = [0u8; 8];
-            byte_file.read_exact(&mut byte_buffer).ok();
-            usize::from_ne_bytes(byte_buffer)
-        };
+        let starting_byte = read_byte_index(&read(dir.join("byte_index.num"))?)?;
 
         let mut iterator = RecordIterator {
             files,
+            read_files: vec![],
             read_bytes: all_read_bytes,
             data_pointer: starting_byte,
             crc_hasher: Crc::<u32>::new(&CRC_32_ISCSI),
+            column_family: column_family.map(String::from),
+            record_position: starting_byte,
         };
 
         iterator.read_at_least(starting_byte)?;
@@ -56,12 +72,20 @@ impl RecordIterator {
 
     fn read_next_file(&mut self) -> io::Result<Option<usize>> {
         match self.files.pop() {
-            Some(file) => Ok(Some(
-                OpenOptions::new()
-                    .read(true)
-                    .open(file)?
-                    .read_to_end(&mut self.read_bytes)?,
-            )),
+            Some(file) => {
+                // Only the records are kept, so they continue where the records of the previous file end
+                let bytes = read(&file)?;
+                let header_len =
+                    match read_file_header(FileKind::WriteAheadLog, &bytes, FORMAT_VERSION)? {
+                        Some(_) => FILE_HEADER_LEN,
+                        None => 0,
+                    };
+                self.read_files
+                    .push((file, self.read_bytes.len(), header_len));
+                self.read_bytes.extend_from_slice(&bytes[header_len..]);
+
+                Ok(Some(bytes.len() - header_len))
+            }
             None => Ok(None),
         }
     }
@@ -84,96 +108,171 @@ impl RecordIterator {
 
         Ok(())
     }
-}
 
-impl Iterator for RecordIterator {
-    type Item = Result<Record, CRCError>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.read_at_least(37).ok()?;
+    /// Returns the next `len` bytes and moves the data pointer behind them.
+    /// Returns None if the log ends before that, which happens when the last record was torn.
+    fn read_slice(&mut self, len: usize) -> Option<&[u8]> {
+        self.read_at_least(len).ok()?;
 
-        if self.data_pointer >= self.read_bytes.len() {
+        let end = self.data_pointer.checked_add(len)?;
+        if end > self.read_bytes.len() {
             return None;
         }
 
-        let crc = {
-            let crc_buffer = &self.read_bytes[self.data_pointer..self.data_pointer + 4];
-            u32::from_ne_bytes(crc_buffer.try_into().ok()?)
-        };
+        let start = self.data_pointer;
+        self.data_pointer = end;
+
+        Some(&self.read_bytes[start..end])
+    }
 
-        self.data_pointer += 4;
+    fn read_usize(&mut self) -> Option<usize> {
+        Some(u64::from_le_bytes(self.read_slice(8)?.try_into().ok()?) as usize)
+    }
 
-        let timestamp = {
-            let timestamp_buffer = &self.read_bytes[self.data_pointer..self.data_pointer + 16];
-            u128::from_ne_bytes(timestamp_buffer.try_into().ok()?)
-        };
+    /// Returns the position in the log at which the last returned record starts.
+    pub(crate) fn record_position(&self) -> usize {
+        self.record_position
+    }
 
-        self.data_pointer += 16;
+    /// Returns the corruption error of the record at the record position, located by its file and its offset in it.
+    fn corruption(&self) -> Error {
+        let (path, file_start, header_len) = self
+            .read_files
+            .iter()
+            .rev()
+            .find(|(_, file_start, _)| *file_start <= self.record_position)
+            .cloned()
+            .unwrap_or_default();
+
+        Error::Corruption {
+            path,
+            offset: (header_len + self.record_position - file_start) as u64,
+        }
+    }
 
-        let tombstone = {
-            let tombstone_buffer = &self.read_bytes[self.data_pointer..self.data_pointer + 1];
-            u8::from_ne_bytes(tombstone_buffer.try_into().ok()?) != 0
-        };
+    /// Reconstructs the operations of a batch from its payload.
+    fn parse_batch(payload: &[u8], num_operations: usize) -> Option<WriteBatch> {
+        let mut batch = WriteBatch::new();
+        let mut offset = 0;
+
+        for _ in 0..num_operations {
+            let tombstone = *payload.get(offset)? != 0;
+            offset += 1;
+            let key_size =
+                u64::from_le_bytes(payload.get(offset..offset + 8)?.try_into().ok()?) as usize;
+            offset += 8;
+            let value_size =
+                u64::from_le_bytes(payload.get(offset..offset + 8)?.try_into().ok()?) as usize;
+            offset += 8;
+            let key = payload.get(offset..offset.checked_add(key_size)?)?;
+            offset += key_size;
+
+            if tombstone {
+                batch.delete(key);
+            } else {
+                let value = payload.get(offset..offset.checked_add(value_size)?)?;
+                offset += value_size;
+                batch.insert(key, value);
+            }
+        }
 
-        self.data_pointer += 1;
+        Some(batch)
+    }
 
-        let key_size = {
-            let key_size_buffer = &self.read_bytes[self.data_pointer..self.data_pointer + 8];
-            usize::from_ne_bytes(key_size_buffer.try_into().ok()?)
-        };
+    /// Reads the record at the data pointer together with the name of its column family.
+    /// Returns None if the log ends before the record does.
+    fn read_record(&mut self) -> Option<db_error::Result<(Option<String>, LogRecord)>> {
+        let crc = u32::from_le_bytes(self.read_slice(4)?.try_into().ok()?);
+        let record_start = self.data_pointer;
 
-        self.data_pointer += 8;
+        let timestamp = u128::from_le_bytes(self.read_slice(16)?.try_into().ok()?);
+        let flag = self.read_slice(1)?[0];
+        let key_size = self.read_usize()?;
+        let value_size = self.read_usize()?;
 
-        let value_size = {
-            let value_size_buffer = &self.read_bytes[self.data_pointer..self.data_pointer + 8];
-            usize::from_ne_bytes(value_size_buffer.try_into().ok()?)
+        let column_family = if flag & COLUMN_FAMILY_FLAG != 0 {
+            let name_size = self.read_usize()?;
+            Some(String::from_utf8_lossy(self.read_slice(name_size)?).to_string())
+        } else {
+            None
         };
+        let flag = flag & !COLUMN_FAMILY_FLAG;
+
+        if flag == BATCH_FLAG {
+            // The batch is only applied if the whole record was written and its checksum matches
+            let payload = self.read_slice(value_size)?.to_vec();
+
+            if self
+                .crc_hasher
+                .checksum(&self.read_bytes[record_start..self.data_pointer])
+                != crc
+            {
+                return Some(Err(self.corruption()));
+            }
 
-        self.data_pointer += 8;
+            return match RecordIterator::parse_batch(&payload, key_size) {
+                Some(batch) => Some(Ok((column_family, LogRecord::Batch { timestamp, batch }))),
+                None => Some(Err(self.corruption())),
+            };
+        }
 
-        self.read_at_least(key_size).ok()?;
+        let expiry = if flag == EXPIRY_FLAG {
+            Some(u128::from_le_bytes(self.read_slice(16)?.try_into().ok()?))
+        } else {
+            None
+        };
 
-        let key = self.read_bytes[self.data_pointer..self.data_pointer + key_size]
-            .to_vec()
-            .into_boxed_slice();
+        let tombstone = flag != 0 && flag != EXPIRY_FLAG;
 
-        self.data_pointer += key_size;
+        let key = Box::from(self.read_slice(key_size)?);
 
         let value = if !tombstone {
-            self.read_at_least(value_size).ok()?;
-
-            let value_buf = self.read_bytes[self.data_pointer..self.data_pointer + value_size]
-                .to_vec()
-                .into_boxed_slice();
-
-            self.data_pointer += value_size;
-
-            Some(value_buf)
+            Some(Box::from(self.read_slice(value_size)?))
         } else {
             None
         };
 
-        let mut bytes: Vec<u8> = Vec::new();
+        if self
+            .crc_hasher
+            .checksum(&self.read_bytes[record_start..self.data_pointer])
+            != crc
+        {
+            Some(Err(self.corruption()))
+        } else {
+            Some(Ok((
+                column_family,
+                LogRecord::Single(Record {
+                    timestamp,
+                    tombstone,
+                    key,
+                    value,
+                    expiry,
+                }),
+            )))
+        }
+    }
+}
+
+impl Iterator for RecordIterator {
+    type Item = db_error::Result<LogRecord>;
 
-        bytes.extend(timestamp.to_ne_bytes().as_ref());
-        bytes.extend((tombstone as u8).to_ne_bytes());
-        bytes.extend(key_size.to_ne_bytes());
-        bytes.extend(value_size.to_ne_bytes());
-        bytes.extend(key.as_ref());
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.read_at_least(37).ok()?;
 
-        if let Some(value) = &value {
-            bytes.extend(value.as_ref());
-        }
+            if self.data_pointer >= self.read_bytes.len() {
+                return None;
+            }
 
-        if self.crc_hasher.checksum(&bytes) != crc {
-            Some(Err(CRCError(crc)))
-        } else {
-            Some(Ok(Record {
-                timestamp,
-                tombstone,
-                key,
-                value,
-            }))
+            self.record_position = self.data_pointer;
+            let record = self.read_record()?;
+
+            // Records of other column families are only read to get to the next record
+            match record {
+                Ok((column_family, _)) if column_family != self.column_family => continue,
+                Ok((_, record)) => return Some(Ok(record)),
+                Err(e) => return Some(Err(e)),
+            }
         }
     }
 }
@@ -1,37 +1,62 @@
 use b_tree::BTree;
 use db_config::{DBConfig, MemoryTableType};
-use segment_elements::{MemEntryHashMap, MemoryEntry, TimeStamp};
+use db_error::Error;
+use segment_elements::{BatchOperation, MemEntryHashMap, MemoryEntry, TimeStamp, WriteBatch};
 use skip_list::SkipList;
-use std::error::Error;
+use std::collections::HashMap;
 
 pub(crate) struct MemoryTable {
     capacity: usize,
     len: usize,
-    wal_size: usize,
-    inner_mem: Box<dyn segment_elements::SegmentTrait + Send>,
+    // Position of the first record of the table in the WAL, None while the table is empty
+    wal_start: Option<usize>,
+    inner_mem: Box<dyn segment_elements::SegmentTrait + Send + Sync>,
+    // Expiries of keys inserted with a time to live, attached to their entries on retrieval
+    expiries: HashMap<Box<[u8]>, u128>,
 }
 
 impl MemoryTable {
-    pub(crate) fn new(dbconfig: &DBConfig) -> Result<Self, Box<dyn Error>> {
-        let inner_mem: Box<dyn segment_elements::SegmentTrait + Send> =
+    pub(crate) fn new(dbconfig: &DBConfig) -> db_error::Result<Self> {
+        let inner_mem: Box<dyn segment_elements::SegmentTrait + Send + Sync> =
             match dbconfig.memory_table_type {
                 MemoryTableType::SkipList => Box::new(SkipList::new(dbconfig.skip_list_max_level)),
                 MemoryTableType::HashMap => Box::new(MemEntryHashMap::new()),
-                MemoryTableType::BTree => Box::new(BTree::new(dbconfig.b_tree_order)?),
+                MemoryTableType::BTree => Box::new(
+                    BTree::new(dbconfig.b_tree_order)
+                        .map_err(|err| Error::Config(err.to_string()))?,
+                ),
             };
 
         Ok(MemoryTable {
             inner_mem,
             capacity: dbconfig.memory_table_capacity,
             len: 0,
-            wal_size: 0,
+            wal_start: None,
+            expiries: HashMap::new(),
         })
     }
 
     /// Inserts or updates a key value pair into the memory table. Returns true
     /// if the memory table capacity is reached.
     pub(crate) fn insert(&mut self, key: &[u8], value: &[u8], time_stamp: TimeStamp) -> bool {
-        self.wal_size += 4 + 16 + 1 + 8 + 8 + key.len() + value.len();
+        self.expiries.remove(key);
+        if self.inner_mem.insert(key, value, time_stamp) {
+            self.len += 1;
+        }
+
+        self.len as f64 > 0.8 * self.capacity as f64
+    }
+
+    /// Inserts or updates a key value pair that expires at the given time stamp. Returns true
+    /// if the memory table capacity is reached.
+    pub(crate) fn insert_with_expiry(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        expiry: u128,
+        time_stamp: TimeStamp,
+    ) -> bool {
+        self.expiries.insert(Box::from(key), expiry);
         if self.inner_mem.insert(key, value, time_stamp) {
             self.len += 1;
         }
@@ -42,7 +67,7 @@ impl MemoryTable {
     /// Logically removes a key value pair if it's present. If it isn't present, inserts a
     /// new entry with tombstone set to true.
     pub(crate) fn delete(&mut self, key: &[u8], time_stamp: TimeStamp) -> bool {
-        self.wal_size += 4 + 16 + 1 + 8 + 8 + key.len();
+        self.expiries.remove(key);
         if self.inner_mem.delete(key, time_stamp) {
             self.len += 1;
         }
@@ -50,8 +75,32 @@ impl MemoryTable {
         self.len as f64 > 0.8 * self.capacity as f64
     }
 
+    /// Applies every operation of the batch with the same time stamp. The capacity is checked
+    /// only after the whole batch is applied, so a batch never gets split between two tables.
+    /// Returns true if the memory table capacity is reached.
+    pub(crate) fn write_batch(&mut self, batch: &WriteBatch, time_stamp: TimeStamp) -> bool {
+        for operation in batch.iter() {
+            self.expiries.remove(operation.get_key());
+            let added = match operation {
+                BatchOperation::Insert { key, value } => {
+                    self.inner_mem.insert(key, value, time_stamp)
+                }
+                BatchOperation::Delete { key } => self.inner_mem.delete(key, time_stamp),
+            };
+
+            if added {
+                self.len += 1;
+            }
+        }
+
+        self.len as f64 > 0.8 * self.capacity as f64
+    }
+
     pub(crate) fn get(&self, key: &[u8]) -> Option<MemoryEntry> {
-        self.inner_mem.get(key)
+        let mut memory_entry = self.inner_mem.get(key)?;
+        memory_entry.set_expiry(self.expiries.get(key).copied());
+
+        Some(memory_entry)
     }
 
     pub(crate) fn is_empty(&self) -> bool {
@@ -59,11 +108,23 @@ impl MemoryTable {
     }
 
     pub(crate) fn iterator(&self) -> Box<dyn Iterator<Item = (Box<[u8]>, MemoryEntry)> + '_> {
-        self.inner_mem.iterator()
+        if self.expiries.is_empty() {
+            return self.inner_mem.iterator();
+        }
+
+        Box::new(self.inner_mem.iterator().map(|(key, mut memory_entry)| {
+            memory_entry.set_expiry(self.expiries.get(&key).copied());
+            (key, memory_entry)
+        }))
+    }
+
+    /// Remembers the position of the first record of the table in the WAL.
+    pub(crate) fn set_wal_start(&mut self, wal_position: usize) {
+        self.wal_start.get_or_insert(wal_position);
     }
 
-    /// Returns the WAL size of the current table.
-    pub(crate) fn wal_size(&self) -> usize {
-        self.wal_size
+    /// Returns the position of the first record of the table in the WAL.
+    pub(crate) fn wal_start(&self) -> Option<usize> {
+        self.wal_start
     }
 }
@@ -1,19 +1,192 @@
+pub(crate) mod merge_iterator;
 pub(crate) mod sstable_element_type;
+mod sstable_writer;
+mod table_file;
+pub(crate) mod table_properties;
 
+use crate::lsm::rate_limiter::RateLimiter;
 use crate::lsm::ScanType;
 use crate::memtable::MemoryTable;
+use crate::sstable::merge_iterator::MergeIterator;
 use crate::sstable::sstable_element_type::SSTableElementType;
+use crate::sstable::sstable_writer::SSTableWriter;
+use crate::sstable::table_file::TableFile;
+use crate::sstable::table_properties::{
+    TableProperties, FORMAT_VERSION, PROPERTIES_FOOTER_LEN, PROPERTIES_MAGIC,
+};
 use bloom_filter::BloomFilter;
-use compression::{variable_encode, CompressionDictionary};
-use lru_cache::LRUCache;
+use compression::{
+    codec_by_id, variable_encode, Codec, CompressionDictionary, Lz4Codec, NoneCodec,
+};
+use db_config::{BlockCodecType, DBConfig, PrefixExtractorType};
+use db_error::Error;
 use merkle_tree::merkle_tree::MerkleTree;
-use segment_elements::{deserialize_header, deserialize_usize_value, MemoryEntry};
-use std::cmp::Ordering;
+use segment_elements::{
+    deserialize_header, deserialize_usize_value, read_file_header, serialize_flags, FileKind,
+    MemoryEntry, FILE_HEADER_LEN,
+};
+use std::cmp::{Ordering, Reverse};
 use std::collections::HashMap;
-use std::fs::{create_dir_all, remove_dir_all, File, OpenOptions};
+use std::fs::{create_dir_all, read, OpenOptions};
 use std::io;
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Decoded min and max key of a table.
+pub(crate) type KeyRange = (Box<[u8]>, Box<[u8]>);
+
+/// Statistics of the entries of an SSTable, gathered from their headers.
+#[derive(Default)]
+pub(crate) struct TableStatistics {
+    pub(crate) entry_count: usize,
+    pub(crate) tombstone_count: usize,
+    // Smallest and largest time stamp of the entries
+    pub(crate) min_time_stamp: u128,
+    pub(crate) max_time_stamp: u128,
+}
+
+/// Options of the SSTables that are written.
+#[derive(Clone, Copy)]
+pub(crate) struct TableOptions {
+    // The number of entries that will be skipped in the summary and in the index
+    pub(crate) summary_density: usize,
+    pub(crate) index_density: usize,
+    pub(crate) use_variable_encoding: bool,
+    // Uncompressed size in bytes at which a data block is closed and the codec that compresses the blocks
+    pub(crate) block_size: usize,
+    pub(crate) codec: &'static dyn Codec,
+    // Extracts the key prefixes that go into the prefix Bloom filter
+    pub(crate) prefix_extractor: PrefixExtractorType,
+}
+
+impl From<&DBConfig> for TableOptions {
+    fn from(dbconfig: &DBConfig) -> Self {
+        TableOptions {
+            summary_density: dbconfig.summary_density,
+            index_density: dbconfig.index_density,
+            use_variable_encoding: dbconfig.use_variable_encoding,
+            block_size: dbconfig.sstable_block_size.max(1),
+            codec: match dbconfig.sstable_block_codec {
+                BlockCodecType::None => &NoneCodec,
+                BlockCodecType::Lz4 => &Lz4Codec,
+            },
+            prefix_extractor: dbconfig.sstable_prefix_extractor,
+        }
+    }
+}
+
+/// State of the database that decides which versions a merge keeps, and how fast the merge may read and write.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct MergeContext<'a> {
+    // Time stamps of all live snapshots, older versions that they can see are kept
+    pub(crate) snapshots: &'a [u128],
+    // Decoded key ranges of the older tables that aren't merged, tombstones of keys outside of them are dropped
+    pub(crate) older_key_ranges: &'a [KeyRange],
+    // Limits the bytes per second read from the tables and written into the merged one, None if they aren't limited
+    pub(crate) rate_limiter: Option<&'a RateLimiter>,
+}
+
+/// Extracts the prefix of a decoded key that goes into the prefix Bloom filter.
+/// Returns None if the extractor is disabled, or if the key is shorter than the fixed length or doesn't hold the delimiter.
+pub(crate) fn extract_key_prefix(
+    prefix_extractor: PrefixExtractorType,
+    key: &[u8],
+) -> Option<&[u8]> {
+    match prefix_extractor {
+        PrefixExtractorType::None => None,
+        PrefixExtractorType::FixedLength(length) => key.get(..length),
+        PrefixExtractorType::Delimiter(delimiter) => key
+            .iter()
+            .position(|byte| *byte == delimiter)
+            .map(|position| &key[..=position]),
+    }
+}
+
+/// How the entries are stored in the data part of an SSTable.
+enum DataLayout {
+    // Tables written before the data was split into blocks store the entries back to back
+    Entries,
+    Blocks(DataBlocks),
+}
+
+/// The block index of an SSTable whose entries are stored in compressed blocks.
+struct DataBlocks {
+    // Offset of the data part in its file
+    data_offset: u64,
+    // Uncompressed offset of each block and its offset in the data part, followed by the end of the last block
+    block_offsets: Vec<(u64, u64)>,
+    // Length of the uncompressed data
+    data_len: u64,
+    // The last decompressed block, since entries are mostly read one after another
+    cached_block: Option<(usize, Vec<u8>)>,
+}
+
+impl DataBlocks {
+    /// Returns the index of the block that holds the given uncompressed offset, None if it's past the data.
+    fn find(&self, offset: u64) -> Option<usize> {
+        if offset >= self.data_len {
+            return None;
+        }
+        let blocks = &self.block_offsets[..self.block_offsets.len() - 1];
+
+        blocks
+            .partition_point(|(block_offset, _)| *block_offset <= offset)
+            .checked_sub(1)
+    }
+}
+
+/// The summary of an SSTable, parsed once so point lookups don't read it again.
+struct Summary {
+    // Encoded min and max key of the table, None if the table has no entries
+    key_range: Option<KeyRange>,
+    // Encoded key of every summary entry and the offset of its entry in the index
+    entries: Vec<(Box<[u8]>, u64)>,
+}
+
+impl Summary {
+    /// Parses the summary of a table, which holds the min and max key followed by its entries.
+    fn deserialize(bytes: &[u8]) -> io::Result<Summary> {
+        let mut reader = Cursor::new(bytes);
+
+        // A table without entries has an empty summary
+        if bytes.is_empty() {
+            return Ok(Summary {
+                key_range: None,
+                entries: Vec::new(),
+            });
+        }
+        let key_range = Some((read_key(&mut reader)?, read_key(&mut reader)?));
+
+        let mut entries = Vec::new();
+        while reader.position() < bytes.len() as u64 {
+            let key = read_key(&mut reader)?;
+            let mut index_offset_bytes = [0u8; 8];
+            reader.read_exact(&mut index_offset_bytes)?;
+            entries.push((key, u64::from_le_bytes(index_offset_bytes)));
+        }
+
+        Ok(Summary { key_range, entries })
+    }
+}
+
+/// Reads a key prefixed with its length.
+fn read_key(reader: &mut Cursor<&[u8]>) -> io::Result<Box<[u8]>> {
+    let mut key_len_bytes = [0u8; 8];
+    reader.read_exact(&mut key_len_bytes)?;
+    let key_len = u64::from_le_bytes(key_len_bytes);
+    if key_len > reader.get_ref().len() as u64 - reader.position() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Key is longer than the rest of the summary",
+        ));
+    }
+
+    let mut key = vec![0u8; key_len as usize];
+    reader.read_exact(&mut key)?;
+
+    Ok(key.into_boxed_slice())
+}
 
 /// Struct representing an SSTable (Sorted String Table) for storing key-value pairs on disk.
 pub struct SSTable {
@@ -21,22 +194,24 @@ pub struct SSTable {
     base_path: PathBuf,
     // Flag indicating whether to store data in a single file or multiple files.
     in_single_file: bool,
-    // Offset for data file when storing in single file.
-    data_offset: usize,
-    // Offset for index file when storing in single file.
-    index_offset: usize,
-    // Offset for summary file when storing in single file.
-    summary_offset: usize,
-    // Offset for Bloom filter file when storing in single file.
-    bloom_filter_offset: usize,
-    // Offset for Merkle tree file when storing in single file.
-    merkle_offset: usize,
-    // Holds references to files for reading & writing
-    file_handles: HashMap<String, File>,
+    // Flag indicating whether the files are mapped into memory instead of being read through their handles
+    use_mmap: bool,
+    // Holds references to files for reading
+    file_handles: HashMap<String, TableFile>,
+    // Read from the properties when the data is first read
+    data_layout: Option<DataLayout>,
+    // Length of the header at the start of each file, read when the table is first read
+    header_len: Option<u64>,
+    // Read when the first key is looked up, so later lookups only read the index and the data
+    summary: Option<Summary>,
+    bloom_filter: Option<BloomFilter>,
+    // Read when a prefix is first checked, holds None if the table doesn't have a prefix filter
+    prefix_filter: Option<Option<(PrefixExtractorType, BloomFilter)>>,
 }
 
 impl SSTable {
-    /// Opens an SSTable in the given base path.
+    /// Opens an SSTable in the given base path. The files are opened once they're first read,
+    /// and the base path is created when the table is flushed.
     ///
     /// # Arguments
     ///
@@ -46,67 +221,48 @@ impl SSTable {
     /// # Returns
     ///
     /// An `io::Result` containing the initialized SSTable instance or an `io::Error`.
-    ///
-    /// # Errors
-    ///
-    /// Returns an `io::Error` if there is an issue when creating directories.
     pub(crate) fn open(base_path: PathBuf, in_single_file: bool) -> io::Result<SSTable> {
-        // Create directory if it doesn't exist
-        create_dir_all(base_path.to_owned())?;
-
-        Ok(Self {
-            base_path,
-            in_single_file,
-            data_offset: 0,
-            index_offset: 0,
-            summary_offset: 0,
-            bloom_filter_offset: 0,
-            merkle_offset: 0,
-            file_handles: HashMap::new(),
-        })
+        SSTable::open_with_mmap(base_path, in_single_file, false)
     }
 
-    /// Flushes the memory table to the SSTable files on disk.
+    /// Opens an SSTable in the given base path, whose files are mapped into memory when they're first read
+    /// if `use_mmap` is set. Lookups and scans of a mapped table read the blocks, the index and the summary
+    /// straight from the mapped files instead of seeking and copying through file handles.
     ///
     /// # Arguments
     ///
-    /// * `mem_table` - The memory table to be flushed.
-    /// * `summary_density` - The number of entries that will be skipped in the summary.
-    /// * `index_density` - The number of entries that will be skipped in the index.
+    /// * `base_path` - The base directory path where SSTable files are be stored.
+    /// * `in_single_file` - Flag indicating whether data is stored in a single file or multiple files.
+    /// * `use_mmap` - Flag indicating whether the files are mapped into memory.
     ///
     /// # Returns
     ///
-    /// An `io::Result` indicating success or an `io::Error`.
-    ///
-    /// # Errors
-    ///
-    /// Returns an `io::Error` if there is an issue flushing the data or serializing components.
-    pub(crate) fn flush(
-        &mut self,
-        mem_table: MemoryTable,
-        summary_density: usize,
-        index_density: usize,
-        lru_cache: Option<&mut LRUCache>,
-        compression_dictionary: &mut Option<CompressionDictionary>,
-        use_variable_encoding: bool,
-    ) -> io::Result<()> {
-        self.flush_to_disk(
-            mem_table.iterator().collect(),
-            summary_density,
-            index_density,
-            lru_cache,
-            compression_dictionary,
-            use_variable_encoding,
-        )
+    /// An `io::Result` containing the initialized SSTable instance or an `io::Error`.
+    pub(crate) fn open_with_mmap(
+        base_path: PathBuf,
+        in_single_file: bool,
+        use_mmap: bool,
+    ) -> io::Result<SSTable> {
+        Ok(Self {
+            base_path,
+            in_single_file,
+            use_mmap,
+            file_handles: HashMap::new(),
+            data_layout: None,
+            header_len: None,
+            summary: None,
+            bloom_filter: None,
+            prefix_filter: None,
+        })
     }
 
     /// Flushes the memory table to the SSTable files on disk.
     ///
     /// # Arguments
     ///
-    /// * `sstable_data` - The data Vec<(key, MemoryEntry)> to be flushed to the disk.
-    /// * `summary_density` - The number of entries that will be skipped in the summary.
-    /// * `index_density` - The number of entries that will be skipped in the index.
+    /// * `mem_table` - The memory table to be flushed.
+    /// * `options` - Options of the written table.
+    /// * `rate_limiter` - Limits the bytes per second written into the table, None if they aren't limited.
     ///
     /// # Returns
     ///
@@ -115,271 +271,129 @@ impl SSTable {
     /// # Errors
     ///
     /// Returns an `io::Error` if there is an issue flushing the data or serializing components.
-    fn flush_to_disk(
+    pub(crate) fn flush(
         &mut self,
-        sstable_data: Vec<(Box<[u8]>, MemoryEntry)>,
-        summary_density: usize,
-        index_density: usize,
-        lru_cache: Option<&mut LRUCache>,
-        compression_dictionary: &mut Option<CompressionDictionary>,
-        use_variable_encoding: bool,
+        mem_table: &MemoryTable,
+        options: TableOptions,
+        compression_dictionary: &Mutex<Option<CompressionDictionary>>,
+        rate_limiter: Option<&RateLimiter>,
     ) -> io::Result<()> {
-        // Build serialized data, index_builder, and bloom_filter
-        let (serialized_data, index_builder, bloom_filter) = self.build_data_and_index_and_filter(
-            sstable_data,
-            lru_cache,
-            compression_dictionary,
-            use_variable_encoding,
-        );
-
-        // Serialize the index, summary, bloom filter and merkle tree
-        let serialized_index = self.get_serialized_index(&index_builder, index_density);
-        let serialized_index_summary =
-            self.get_serialized_summary(&index_builder, index_density, summary_density);
-        let serialized_bloom_filter = bloom_filter.serialize();
-        let serialized_merkle_tree = MerkleTree::new(&serialized_data).serialize();
-
-        if self.in_single_file {
-            self.write_to_single_file(
-                &serialized_data,
-                &serialized_index,
-                &serialized_index_summary,
-                &serialized_bloom_filter,
-                &serialized_merkle_tree,
-            )?;
-        } else {
-            self.write_to_file(&serialized_data, "SSTable-Data.db")?;
-            self.write_to_file(&serialized_index, "SSTable-Index.db")?;
-            self.write_to_file(&serialized_index_summary, "SSTable-Summary.db")?;
-            self.write_to_file(&serialized_bloom_filter, "SSTable-BloomFilter.db")?;
-            self.write_to_file(&serialized_merkle_tree, "SSTable-MerkleTree.db")?;
-        }
-
-        Ok(())
-    }
-
-    /// Builds the SSTable data, index builder, and Bloom Filter.
-    ///
-    /// # Returns
-    ///
-    /// A tuple consisting of a data Vec<u8>, an index builder key pair Vec<(Vec<u8>, u64)>, and a Bloom filter.
-    ///
-    /// # Errors
-    ///
-    /// None.
-    fn build_data_and_index_and_filter(
-        &self,
-        sstable_data: Vec<(Box<[u8]>, MemoryEntry)>,
-        lru_cache: Option<&mut LRUCache>,
-        compression_dictionary: &mut Option<CompressionDictionary>,
-        use_variable_encoding: bool,
-    ) -> (Vec<u8>, Vec<(Vec<u8>, usize)>, BloomFilter) {
-        let mut index_builder = Vec::new();
-        let mut bloom_filter = BloomFilter::new(0.01, sstable_data.len());
-        let mut data = Vec::new();
-
-        if let Some(compression_dict) = compression_dictionary {
-            let keys: Vec<Box<[u8]>> = sstable_data
-                .clone()
-                .into_iter()
-                .map(|(boxed_slice, _)| boxed_slice)
-                .collect();
-            compression_dict
-                .add(&keys)
-                .expect("Failed to add keys to the dictionary!");
-        }
-
-        let mut offset = 0;
-        for (key, entry) in sstable_data {
-            let encoded_key = match compression_dictionary {
-                Some(compression_dictionary) => {
-                    compression_dictionary.encode(&key.clone()).unwrap()
-                }
-                None => key.clone(),
-            };
-            let entry_data = entry.serialize(&encoded_key, use_variable_encoding);
-            if let Some(&mut ref mut lru) = lru_cache {
-                lru.update(&key, Some(entry));
+        create_dir_all(&self.base_path)?;
+        let sstable_data: Vec<(Box<[u8]>, MemoryEntry)> = mem_table.iterator().collect();
+
+        // Keys are encoded up front, so the dictionary isn't locked while the table is written
+        let encoded_keys: Vec<Box<[u8]>> = match &mut *compression_dictionary.lock().unwrap() {
+            Some(compression_dict) => {
+                let keys: Vec<Box<[u8]>> =
+                    sstable_data.iter().map(|(key, _)| key.clone()).collect();
+                compression_dict
+                    .add(&keys)
+                    .expect("Failed to add keys to the dictionary!");
+                keys.iter()
+                    .map(|key| compression_dict.encode(key))
+                    .collect::<io::Result<_>>()?
             }
+            None => sstable_data.iter().map(|(key, _)| key.clone()).collect(),
+        };
 
-            data.extend_from_slice(&entry_data);
-            index_builder.push((encoded_key.to_vec(), offset));
-            bloom_filter.add(&encoded_key);
-
-            offset += entry_data.len();
+        let mut writer = SSTableWriter::new(
+            &self.base_path,
+            self.in_single_file,
+            options,
+            sstable_data.len(),
+        )?;
+        for ((key, entry), encoded_key) in sstable_data.into_iter().zip(encoded_keys) {
+            let written = writer.add(&encoded_key, &key, &entry)?;
+            if let Some(rate_limiter) = rate_limiter {
+                rate_limiter.request(written as u64);
+            }
         }
 
-        (data, index_builder, bloom_filter)
-    }
+        writer.finish()?;
+        // The layout, the headers, the summary and the Bloom filters are read again from the written table
+        self.data_layout = None;
+        self.header_len = None;
+        self.summary = None;
+        self.bloom_filter = None;
+        self.prefix_filter = None;
 
-    /// Serializes the index from the given key, offset pair array.
-    ///
-    /// # Arguments
-    /// * `index_builder` - An array of key, offset pairs.
-    /// * `index_density` - The number of entries that will be skipped in the index.
-    ///
-    /// # Returns
-    ///
-    /// Returns the serialized index.
-    ///
-    /// # Errors
-    ///
-    /// None.
-    fn get_serialized_index(
-        &self,
-        index_builder: &[(Vec<u8>, usize)],
-        index_density: usize,
-    ) -> Vec<u8> {
-        let mut index = Vec::new();
-
-        // Add every step-th key and its offset to the summary
-        for (key, offset) in index_builder.iter().step_by(index_density) {
-            index.extend(&key.len().to_ne_bytes());
-            index.extend(key);
-            index.extend(&offset.to_ne_bytes());
-        }
-
-        index
+        Ok(())
     }
 
-    /// Serializes the index summary from the given key, offset pair array.
+    /// Retrieves a MemoryEntry corresponding to the given key if it exists in the SSTable.
     ///
     /// # Arguments
-    /// * `index_builder` - An array of key, offset pairs.
+    ///
+    /// * `key` - The key to search for in the SSTable.
     /// * `index_density` - The number of entries that will be skipped in the index.
-    /// * `summary_density` - The number of entries that will be skipped in the summary.
     ///
     /// # Returns
     ///
-    /// Returns the serialized index summary.
+    /// Returns an Option containing the MemoryEntry if the key is found, otherwise None.
     ///
     /// # Errors
     ///
-    /// None.
-    fn get_serialized_summary(
-        &self,
-        index_builder: &[(Vec<u8>, usize)],
+    /// Returns a corruption error if the checksum of the found entry doesn't match.
+    pub(crate) fn get(
+        &mut self,
+        key: &[u8],
         index_density: usize,
-        summary_density: usize,
-    ) -> Vec<u8> {
-        if index_builder.is_empty() || index_density < 1 || summary_density < 1 {
-            return Vec::new();
-        }
-
-        let mut summary = Vec::new();
-        // Find the min and max keys
-        let (min_key, _) = index_builder.first().unwrap();
-        let (max_key, _) = index_builder.last().unwrap();
-
-        // Add the min and max keys to the summary
-        summary.extend_from_slice(&min_key.len().to_ne_bytes());
-        summary.extend_from_slice(min_key);
-        summary.extend_from_slice(&max_key.len().to_ne_bytes());
-        summary.extend_from_slice(max_key);
-
-        let mut offset_accumulator: usize = 0;
-        // Add every step-th key and its offset to the summary
-        for i in (0..index_builder.len()).step_by(index_density) {
-            let (key, _) = &index_builder[i];
-
-            if i % (summary_density * index_density) == 0 {
-                summary.extend_from_slice(&key.len().to_ne_bytes());
-                summary.extend_from_slice(key);
-                let offset_in_index = offset_accumulator;
-                summary.extend_from_slice(&offset_in_index.to_ne_bytes());
+        compression_dictionary: &mut Option<CompressionDictionary>,
+        use_variable_encoding: bool,
+    ) -> db_error::Result<Option<MemoryEntry>> {
+        let encoded_key = match compression_dictionary {
+            Some(compression_dictionary) => compression_dictionary
+                .encode(&key.to_vec().into_boxed_slice())
+                .unwrap()
+                .clone(),
+            None => key.to_vec().into_boxed_slice(),
+        };
+        if self
+            .bloom_filter_contains_key(&encoded_key)
+            .unwrap_or(false)
+        {
+            if let Some(offset) =
+                self.get_data_offset_from_summary(&encoded_key, compression_dictionary)?
+            {
+                return match self.get_entry_from_data_file(
+                    offset,
+                    Some(index_density),
+                    Some(&encoded_key),
+                    use_variable_encoding,
+                )? {
+                    Some(entry) => Ok(Some(entry.0 .1)),
+                    None => Ok(None),
+                };
             }
-
-            offset_accumulator += key.len() + 2 * std::mem::size_of::<usize>();
         }
-        summary
-    }
-
-    /// Writes the SSTable data, index, summary, Bloom Filter, and Merkle tree to a single file.
-    ///
-    /// # Arguments
-    ///
-    /// * `serialized_data` - The serialized data.
-    /// * `serialized_index` - The serialized index.
-    /// * `serialized_index_summary` - The serialized index summary.
-    /// * `serialized_bloom_filter` - The serialized Bloom filter.
-    /// * `serialized_merkle_tree` - The serialized Merkle tree.
-    ///
-    /// # Returns
-    ///
-    /// Returns the result of the IO operation.
-    ///
-    /// # Errors
-    ///
-    /// Returns an `io::Error` if the writing process fails.
-    fn write_to_single_file(
-        &mut self,
-        serialized_data: &[u8],
-        serialized_index: &[u8],
-        serialized_index_summary: &[u8],
-        serialized_bloom_filter: &[u8],
-        serialized_merkle_tree: &[u8],
-    ) -> io::Result<()> {
-        // Calculate the offset for each part of the file and write them into the buffer
-        let mut total_offset = 5 * std::mem::size_of::<usize>();
-        self.data_offset = total_offset;
-
-        total_offset += serialized_data.len();
-        self.index_offset = total_offset;
-
-        total_offset += serialized_index.len();
-        self.summary_offset = total_offset;
-
-        total_offset += serialized_index_summary.len();
-        self.bloom_filter_offset = total_offset;
 
-        total_offset += serialized_bloom_filter.len();
-        self.merkle_offset = total_offset;
-
-        // Create a buffer to hold the offsets and serialized data
-        let mut buffer = Vec::new();
-
-        // Write the offsets to the buffer
-        buffer.extend_from_slice(&self.data_offset.to_ne_bytes());
-        buffer.extend_from_slice(&self.index_offset.to_ne_bytes());
-        buffer.extend_from_slice(&self.summary_offset.to_ne_bytes());
-        buffer.extend_from_slice(&self.bloom_filter_offset.to_ne_bytes());
-        buffer.extend_from_slice(&self.merkle_offset.to_ne_bytes());
-
-        // Write the serialized data to the buffer
-        buffer.extend_from_slice(serialized_data);
-        buffer.extend_from_slice(serialized_index);
-        buffer.extend_from_slice(serialized_index_summary);
-        buffer.extend_from_slice(serialized_bloom_filter);
-
-        buffer.extend_from_slice(serialized_merkle_tree);
-
-        // Write the entire buffer to the .db file
-        self.write_to_file(&buffer, "SSTable.db")?;
-
-        Ok(())
+        Ok(None)
     }
 
-    /// Retrieves a MemoryEntry corresponding to the given key if it exists in the SSTable.
+    /// Retrieves the newest version of the key that was written at or before the given snapshot time stamp.
+    /// Versions of the same key are stored next to each other, from the newest to the oldest one.
     ///
     /// # Arguments
     ///
     /// * `key` - The key to search for in the SSTable.
+    /// * `snapshot` - The time stamp of the snapshot.
     /// * `index_density` - The number of entries that will be skipped in the index.
     ///
     /// # Returns
     ///
-    /// Returns an Option containing the MemoryEntry if the key is found, otherwise None.
+    /// Returns an Option containing the MemoryEntry if a visible version of the key is found, otherwise None.
     ///
     /// # Errors
     ///
-    /// None.
-    pub(crate) fn get(
+    /// Returns a corruption error if the checksum of a traversed entry doesn't match.
+    pub(crate) fn get_at(
         &mut self,
         key: &[u8],
+        snapshot: u128,
         index_density: usize,
         compression_dictionary: &mut Option<CompressionDictionary>,
         use_variable_encoding: bool,
-    ) -> Option<MemoryEntry> {
+    ) -> db_error::Result<Option<MemoryEntry>> {
         let encoded_key = match compression_dictionary {
             Some(compression_dictionary) => compression_dictionary
                 .encode(&key.to_vec().into_boxed_slice())
@@ -387,26 +401,40 @@ impl SSTable {
                 .clone(),
             None => key.to_vec().into_boxed_slice(),
         };
-        if self
+        if !self
             .bloom_filter_contains_key(&encoded_key)
             .unwrap_or(false)
         {
-            if let Some(offset) =
-                self.get_data_offset_from_summary(&encoded_key, compression_dictionary)
-            {
-                return match self.get_entry_from_data_file(
-                    offset,
-                    Some(index_density),
-                    Some(&encoded_key),
-                    use_variable_encoding,
-                ) {
-                    Some(entry) => Some(entry.0 .1),
-                    None => None,
+            return Ok(None);
+        }
+
+        let mut offset =
+            match self.get_data_offset_from_summary(&encoded_key, compression_dictionary)? {
+                Some(offset) => offset,
+                None => return Ok(None),
+            };
+        let mut traversed_entries = 0;
+        let mut key_found = false;
+        loop {
+            let ((current_key, entry), length) =
+                match self.get_entry_from_data_file(offset, None, None, use_variable_encoding)? {
+                    Some(entry) => entry,
+                    None => return Ok(None),
                 };
+            offset += length;
+
+            if current_key == encoded_key {
+                key_found = true;
+                if entry.get_timestamp() <= snapshot {
+                    return Ok(Some(entry));
+                }
+            } else if key_found || traversed_entries > index_density {
+                // All versions of the key have been traversed or the key isn't in the table
+                return Ok(None);
             }
-        }
 
-        None
+            traversed_entries += 1;
+        }
     }
 
     /// Compares the written merkle tree with the given tree and returns the indices of the different data chunks.
@@ -455,7 +483,7 @@ impl SSTable {
     }
 
     /// Merges multiple SSTables into a new SSTable using merge sort on keys and timestamps.
-    /// Deletes the old SSTables and flushes the merged SSTable on completion.
+    /// The old SSTables are kept, the caller removes them once the merged SSTable is published.
     ///
     /// # Arguments
     ///
@@ -463,133 +491,578 @@ impl SSTable {
     /// * `in_single_file` - Vector of booleans indicating whether corresponding SSTables are stored in a single file
     /// * `merged_base_path` - The base path where the merged SSTable files will be stored.
     /// * `merged_in_single_file` - A boolean indicating whether the merged SSTable is stored in a single file.
-    /// * `summary_density` - The number of entries that will be skipped in the summary.
-    /// * `index_density` - The number of entries that will be skipped in the index.
+    /// * `options` - Options of the merged table.
+    /// * `compression_dictionary` - The dictionary the keys are encoded with.
+    /// * `context` - The live snapshots, the key ranges of the older tables and the rate limiter of the merge.
     ///
     /// # Returns
     ///
-    /// Returns An `io::Result` indicating success or an `io::Error`.
+    /// Returns a `db_error::Result` containing the number of entries in the merged SSTable,
+    /// which is zero if all entries were dropped.
     ///
     /// # Errors
     ///
-    /// Returns an `io::Error` if the merging process fails.
+    /// Returns an error if the merging process fails or if a merged entry is corrupted.
     pub(crate) fn merge(
         sstable_paths: Vec<PathBuf>,
         in_single_file: Vec<bool>,
         merged_base_path: &PathBuf,
         merged_in_single_file: bool,
-        summary_density: usize,
-        index_density: usize,
-        use_variable_encoding: bool,
-        compression_dictionary: &mut Option<CompressionDictionary>,
-    ) -> io::Result<()> {
+        options: TableOptions,
+        compression_dictionary: &Mutex<Option<CompressionDictionary>>,
+        context: MergeContext,
+    ) -> db_error::Result<usize> {
         create_dir_all(merged_base_path)?;
+        let use_variable_encoding = options.use_variable_encoding;
+
+        // The merged table can't have more entries than all tables together, which sizes its Bloom filter
+        let mut max_entries = 0;
+        for (path, in_single_file) in sstable_paths.iter().zip(in_single_file.iter()) {
+            max_entries += SSTable::open(path.to_owned(), *in_single_file)?
+                .read_properties(use_variable_encoding)?
+                .entry_count as usize;
+        }
 
-        let merged_data = SSTable::merge_entries(
-            sstable_paths.clone(),
-            in_single_file,
-            None,
-            use_variable_encoding,
-            compression_dictionary,
+        let mut writer = SSTableWriter::new(
+            merged_base_path,
+            merged_in_single_file,
+            options,
+            max_entries,
         )?;
 
-        let mut merged_sstable = SSTable::open(merged_base_path.to_owned(), merged_in_single_file)?;
-
-        // Flush the new SSTable to disk
-        merged_sstable.flush_to_disk(
-            merged_data,
-            summary_density,
-            index_density,
-            None,
-            &mut None,
+        // Keys are written as they are stored, so the dictionary is only used to compare them
+        let mut merged_entries = SSTable::merge_entries(
+            sstable_paths,
+            in_single_file,
             use_variable_encoding,
+            compression_dictionary,
+            context.snapshots,
+            context.older_key_ranges,
         )?;
+        let mut limited_bytes_read = 0;
+        while let Some(merged_entry) = merged_entries.next() {
+            let (key, entry) = merged_entry?;
+            let decoded_key = match &*compression_dictionary.lock().unwrap() {
+                Some(compression_dictionary) => compression_dictionary.decode(&key)?,
+                None => key.clone(),
+            };
+            let written = writer.add(&key, &decoded_key, &entry)?;
 
-        let _ = sstable_paths.iter().map(|path| remove_dir_all(path));
+            // The entries read for the merged one are limited together with it, dropped versions included
+            if let Some(rate_limiter) = context.rate_limiter {
+                let bytes_read = merged_entries.bytes_read();
+                rate_limiter.request(bytes_read - limited_bytes_read + written as u64);
+                limited_bytes_read = bytes_read;
+            }
+        }
 
-        Ok(())
+        Ok(writer.finish()?)
     }
 
-    /// Merges multiple SSTables into a new Vec of key-value pairs using merge sort based on keys and timestamps.
+    /// Merges multiple SSTables using a k-way merge sort based on keys and timestamps.
     ///
     /// The function reads entries from multiple SSTables identified by their base paths.
-    /// It performs a merge sort based on the keys and timestamps of the entries. The resulting Vec contains tuples, where each tuple
-    /// represents a key-value pair from the merged SSTables.
+    /// The returned iterator yields tuples in the order of keys, where each tuple represents a key-value pair
+    /// from the merged SSTables. Besides the newest version of each key, the versions that are visible to
+    /// live snapshots are kept as well, ordered from the newest to the oldest one. Tombstones are dropped once no
+    /// older table can hold the key. The tables are read as the iterator advances, so only the next entry of each
+    /// table is held in memory.
     ///
     /// # Arguments
     ///
     /// * `sstable_paths` - Base paths to all SSTables.
     /// * `in_single_file` - Vector of booleans indicating whether corresponding SSTables are stored in a single file
+    /// * `snapshots` - Time stamps of all live snapshots.
+    /// * `older_key_ranges` - Decoded key ranges of the older tables that aren't merged.
     ///
     /// # Returns
     ///
-    /// An `io::Result` containing a Vec of key-value pairs `(Box<[u8]>, MemoryEntry)` representing the merged entries.
+    /// A `db_error::Result` containing an iterator over the merged key-value pairs `(Box<[u8]>, MemoryEntry)`.
     ///
     /// # Errors
     ///
-    /// Returns an `io::Error` if there is an issue when reading from the SSTables or if deserialization fails.
-    pub(crate) fn merge_entries(
+    /// Returns an error if there is an issue when reading from the SSTables or if an entry is corrupted.
+    /// The iterator yields such an error as its last item.
+    pub(crate) fn merge_entries<'a>(
         sstable_paths: Vec<PathBuf>,
         in_single_file: Vec<bool>,
-        total_entry_offsets: Option<Vec<u64>>,
         use_variable_encoding: bool,
-        compression_dictionary: &mut Option<CompressionDictionary>,
-    ) -> io::Result<Vec<(Box<[u8]>, MemoryEntry)>> {
-        let number_of_tables = sstable_paths.len();
-
-        // offsets for each sstable
-        let mut total_entry_offsets = total_entry_offsets.unwrap_or(vec![0; number_of_tables]);
-        let mut file_ref_sstables = Vec::with_capacity(number_of_tables);
-        for i in 0..number_of_tables {
-            file_ref_sstables.push(Self {
-                base_path: sstable_paths[i].to_owned(),
-                in_single_file: in_single_file[i],
-                data_offset: 0,
-                index_offset: 0,
-                summary_offset: 0,
-                bloom_filter_offset: 0,
-                merkle_offset: 0,
-                file_handles: HashMap::new(),
+        compression_dictionary: &'a Mutex<Option<CompressionDictionary>>,
+        snapshots: &'a [u128],
+        older_key_ranges: &'a [KeyRange],
+    ) -> db_error::Result<MergeIterator<'a>> {
+        let sstables = sstable_paths
+            .into_iter()
+            .zip(in_single_file)
+            .map(|(base_path, in_single_file)| SSTable::open(base_path, in_single_file))
+            .collect::<io::Result<_>>()?;
+
+        MergeIterator::new(
+            sstables,
+            use_variable_encoding,
+            compression_dictionary,
+            snapshots,
+            older_key_ranges,
+        )
+    }
+
+    /// Reads the properties of the table. Tables written before the properties were added don't have them,
+    /// so their properties are gathered from the summary and the data instead and have format version 0.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the properties of the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if there is an issue when reading the table.
+    pub(crate) fn read_properties(
+        &mut self,
+        use_variable_encoding: bool,
+    ) -> io::Result<TableProperties> {
+        if let Some(properties) = self.read_properties_block()? {
+            return Ok(properties);
+        }
+
+        let statistics = self.read_statistics(use_variable_encoding)?;
+        let (min_key, max_key) = if statistics.entry_count > 0 {
+            SSTable::get_key_range(self.base_path.clone(), self.in_single_file)?
+        } else {
+            (Box::default(), Box::default())
+        };
+        let header_len = self.header_len()?;
+        let data_size = if self.in_single_file {
+            // The data ends where the index starts
+            let file = self.open_file("SSTable.db")?;
+            file.seek(SeekFrom::Start(header_len))?;
+            let mut offset_bytes = [0u8; 16];
+            file.read_exact(&mut offset_bytes)?;
+            let (data_offset, index_offset) = offset_bytes.split_at(8);
+            u64::from_le_bytes(index_offset.try_into().unwrap())
+                - u64::from_le_bytes(data_offset.try_into().unwrap())
+        } else {
+            self.open_file("SSTable-Data.db")?.len()? - header_len
+        };
+
+        Ok(TableProperties {
+            format_version: 0,
+            entry_count: statistics.entry_count as u64,
+            tombstone_count: statistics.tombstone_count as u64,
+            min_time_stamp: statistics.min_time_stamp,
+            max_time_stamp: statistics.max_time_stamp,
+            data_size,
+            min_key,
+            max_key,
+            prefix_extractor: PrefixExtractorType::None,
+            prefix_filter_offset: 0,
+            prefix_filter_len: 0,
+        })
+    }
+
+    /// Reads the properties that were written at the end of the table, None if the table doesn't have them.
+    fn read_properties_block(&mut self) -> io::Result<Option<TableProperties>> {
+        if !self.in_single_file {
+            let path = self.base_path.join("SSTable-Properties.db");
+            return if path.exists() {
+                let bytes = read(path)?;
+                let header_len =
+                    match read_file_header(FileKind::SSTableProperties, &bytes, FORMAT_VERSION)? {
+                        Some(_) => FILE_HEADER_LEN,
+                        None => 0,
+                    };
+                Ok(Some(TableProperties::deserialize(&bytes[header_len..])?))
+            } else {
+                Ok(None)
+            };
+        }
+
+        let file = self.open_file("SSTable.db")?;
+        let file_len = file.len()?;
+        if file_len < PROPERTIES_FOOTER_LEN as u64 {
+            return Ok(None);
+        }
+
+        // The properties are followed by their offset and the magic number
+        file.seek(SeekFrom::End(-(PROPERTIES_FOOTER_LEN as i64)))?;
+        let mut footer = [0u8; PROPERTIES_FOOTER_LEN];
+        file.read_exact(&mut footer)?;
+        let (offset_bytes, magic_bytes) = footer.split_at(8);
+        if u64::from_le_bytes(magic_bytes.try_into().unwrap()) != PROPERTIES_MAGIC {
+            return Ok(None);
+        }
+        let properties_offset = u64::from_le_bytes(offset_bytes.try_into().unwrap());
+
+        file.seek(SeekFrom::Start(properties_offset))?;
+        let mut properties_bytes = Vec::new();
+        file.take((file_len - PROPERTIES_FOOTER_LEN as u64).saturating_sub(properties_offset))
+            .read_to_end(&mut properties_bytes)?;
+
+        Ok(Some(TableProperties::deserialize(&properties_bytes)?))
+    }
+
+    /// Reads how the entries are stored in the data part, once for each opened table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if there is an issue when reading the properties or the block index.
+    fn load_data_layout(&mut self) -> io::Result<()> {
+        if self.data_layout.is_some() {
+            return Ok(());
+        }
+
+        // Tables written before the properties were added store their entries back to back
+        if self.read_properties_block()?.is_none() {
+            self.data_layout = Some(DataLayout::Entries);
+            return Ok(());
+        }
+
+        let header_len = self.header_len()?;
+        let in_single_file = self.in_single_file;
+        let file = self.data_file()?;
+        let (data_offset, data_end) = if in_single_file {
+            // The data part ends where the index starts
+            file.seek(SeekFrom::Start(header_len))?;
+            let mut offset_bytes = [0u8; 16];
+            file.read_exact(&mut offset_bytes)?;
+            let (data_offset, index_offset) = offset_bytes.split_at(8);
+            (
+                u64::from_le_bytes(data_offset.try_into().unwrap()),
+                u64::from_le_bytes(index_offset.try_into().unwrap()),
+            )
+        } else {
+            (header_len, file.len()?)
+        };
+
+        // The data part ends with the block index, the length of the uncompressed data and the number of blocks
+        let corrupted_block_index = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "SSTable block index is corrupted",
+            )
+        };
+        let trailer_offset = data_end
+            .checked_sub(16)
+            .filter(|offset| *offset >= data_offset)
+            .ok_or_else(corrupted_block_index)?;
+        file.seek(SeekFrom::Start(trailer_offset))?;
+        let mut trailer = [0u8; 16];
+        file.read_exact(&mut trailer)?;
+        let (data_len, block_count) = trailer.split_at(8);
+        let data_len = u64::from_le_bytes(data_len.try_into().unwrap());
+        let block_count = u64::from_le_bytes(block_count.try_into().unwrap());
+
+        let block_index_offset = block_count
+            .checked_mul(16)
+            .and_then(|block_index_len| trailer_offset.checked_sub(block_index_len))
+            .filter(|offset| *offset >= data_offset)
+            .ok_or_else(corrupted_block_index)?;
+        file.seek(SeekFrom::Start(block_index_offset))?;
+        let mut block_index = vec![0u8; (trailer_offset - block_index_offset) as usize];
+        file.read_exact(&mut block_index)?;
+
+        let mut block_offsets: Vec<(u64, u64)> = block_index
+            .chunks_exact(16)
+            .map(|block| {
+                let (offset, physical_offset) = block.split_at(8);
+                (
+                    u64::from_le_bytes(offset.try_into().unwrap()),
+                    u64::from_le_bytes(physical_offset.try_into().unwrap()),
+                )
             })
+            .collect();
+        // The last block ends where the block index starts
+        block_offsets.push((data_len, block_index_offset - data_offset));
+
+        self.data_layout = Some(DataLayout::Blocks(DataBlocks {
+            data_offset,
+            block_offsets,
+            data_len,
+            cached_block: None,
+        }));
+
+        Ok(())
+    }
+
+    /// Returns the length of the header at the start of each file of the table, read from the file with the data.
+    /// All files of a table are written at once, so either all of them start with a header or none do.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the header can't be read or the table was written with a newer format.
+    fn header_len(&mut self) -> io::Result<u64> {
+        if let Some(header_len) = self.header_len {
+            return Ok(header_len);
+        }
+
+        let kind = if self.in_single_file {
+            FileKind::SSTable
+        } else {
+            FileKind::SSTableData
+        };
+        let header_len = SSTable::skip_file_header(self.data_file()?, kind)?;
+        self.header_len = Some(header_len);
+
+        Ok(header_len)
+    }
+
+    /// Reads the header at the start of a file of a table and positions the file right after it.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the length of the header, 0 if the file was written before the headers were added.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the header can't be read or the file was written with a newer format.
+    fn skip_file_header<R: Read + Seek>(file: &mut R, kind: FileKind) -> io::Result<u64> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut header = Vec::with_capacity(FILE_HEADER_LEN);
+        Read::by_ref(file)
+            .take(FILE_HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+
+        let header_len = match read_file_header(kind, &header, FORMAT_VERSION)? {
+            Some(_) => FILE_HEADER_LEN as u64,
+            None => 0,
+        };
+        file.seek(SeekFrom::Start(header_len))?;
+
+        Ok(header_len)
+    }
+
+    /// Returns the file that holds the data part of the table.
+    fn data_file(&mut self) -> io::Result<&mut TableFile> {
+        if self.in_single_file {
+            self.open_file("SSTable.db")
+        } else {
+            self.open_file("SSTable-Data.db")
         }
-        let mut merged_entries = Vec::new();
+    }
+
+    /// Returns the block index of the table, None if its entries aren't stored in blocks.
+    fn data_blocks(&mut self) -> io::Result<Option<&mut DataBlocks>> {
+        self.load_data_layout()?;
+
+        Ok(match &mut self.data_layout {
+            Some(DataLayout::Blocks(blocks)) => Some(blocks),
+            _ => None,
+        })
+    }
+
+    /// Reads and decompresses the block that holds the given uncompressed offset, unless it's already cached.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the block and the offset in it, None if the offset is past the data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the block can't be read or decompressed.
+    fn read_block(&mut self, offset: u64) -> io::Result<Option<(&[u8], u64)>> {
+        let Some(blocks) = self.data_blocks()? else {
+            return Ok(None);
+        };
+        let Some(index) = blocks.find(offset) else {
+            return Ok(None);
+        };
+        let (block_offset, physical_offset) = blocks.block_offsets[index];
+        let (next_block_offset, next_physical_offset) = blocks.block_offsets[index + 1];
+        let data_offset = blocks.data_offset;
+
+        if !matches!(blocks.cached_block, Some((cached_index, _)) if cached_index == index) {
+            let block_len = next_block_offset.saturating_sub(block_offset) as usize;
+            let block = match self
+                .mapped_element("SSTable-Data.db", &SSTableElementType::DataEntryValue)?
+            {
+                // The block is decompressed straight from the mapped data part
+                Some(data) => {
+                    let block_bytes = data
+                        .get(physical_offset as usize..next_physical_offset as usize)
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "SSTable block is past the end of the data",
+                            )
+                        })?;
+                    SSTable::decompress_block(block_bytes, block_len)?
+                }
+                None => {
+                    let file = self.data_file()?;
+                    file.seek(SeekFrom::Start(data_offset + physical_offset))?;
+                    let mut block_bytes =
+                        vec![0u8; next_physical_offset.saturating_sub(physical_offset) as usize];
+                    file.read_exact(&mut block_bytes)?;
+                    SSTable::decompress_block(&block_bytes, block_len)?
+                }
+            };
+
+            if let Some(blocks) = self.data_blocks()? {
+                blocks.cached_block = Some((index, block));
+            }
+        }
+
+        Ok(self.data_blocks()?.and_then(|blocks| {
+            let (_, block) = blocks.cached_block.as_ref()?;
+            Some((block.as_slice(), offset - block_offset))
+        }))
+    }
+
+    /// Decompresses a block of the data part into a block of the given length.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the codec of the block is unknown or the block can't be decompressed.
+    fn decompress_block(block_bytes: &[u8], block_len: usize) -> io::Result<Vec<u8>> {
+        // Each block starts with the identifier of the codec that compressed it
+        let codec = block_bytes
+            .first()
+            .and_then(|id| codec_by_id(*id))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown codec of an SSTable block",
+                )
+            })?;
+
+        codec.decompress(&block_bytes[1..], block_len)
+    }
+
+    /// Gathers the statistics of the entries in the data file, reading only their headers and keys.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the statistics of the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if there is an issue when reading the data file.
+    fn read_statistics(&mut self, use_variable_encoding: bool) -> io::Result<TableStatistics> {
+        let mut statistics = TableStatistics::default();
+        let mut offset = 0;
         loop {
-            // contains a tuple ((index, entry), offset) for each sstable
-            let option_entries: Vec<Option<_>> = file_ref_sstables
-                .iter_mut()
-                .zip(total_entry_offsets.iter())
-                .map(|(sstable, offset)| {
-                    sstable.get_entry_from_data_file(*offset, None, None, use_variable_encoding)
-                })
-                .collect();
+            let buffer = self
+                .get_cursor_data(
+                    self.in_single_file,
+                    "SSTable-Data.db",
+                    SSTableElementType::DataEntryWithoutValue,
+                    Some(offset),
+                    use_variable_encoding,
+                )?
+                .into_inner();
+            if buffer.is_empty() {
+                return Ok(statistics);
+            }
+
+            let (entry_length, mut buffer_offset) = deserialize_usize_value(&buffer, false);
+            let (_, length) = deserialize_usize_value(&buffer[buffer_offset..], false);
+            buffer_offset += length;
+            let (_, time_stamp, tombstone, ..) =
+                deserialize_header(&buffer[buffer_offset..], false);
 
-            // if all entries are none, there is no more data
-            if option_entries.iter().all(Option::is_none) {
-                break;
+            offset += entry_length as u64;
+            if statistics.entry_count == 0 || time_stamp < statistics.min_time_stamp {
+                statistics.min_time_stamp = time_stamp;
             }
+            statistics.max_time_stamp = statistics.max_time_stamp.max(time_stamp);
+            statistics.entry_count += 1;
+            statistics.tombstone_count += tombstone as usize;
+        }
+    }
 
-            // add indexes
-            let entries: Vec<_> = option_entries.iter().enumerate().collect();
+    /// Keeps the newest version of a key and the newest version visible to each snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `versions` - All versions of a single key
+    /// * `snapshots` - Time stamps of all live snapshots
+    ///
+    /// # Returns
+    ///
+    /// Kept versions sorted from the newest to the oldest one
+    pub(crate) fn retain_snapshot_versions(
+        mut versions: Vec<MemoryEntry>,
+        snapshots: &[u128],
+    ) -> Vec<MemoryEntry> {
+        versions.sort_by_key(|entry| Reverse(entry.get_timestamp()));
+
+        // A version is needed if some snapshot was taken after it, but before the next newer version
+        let mut newer_timestamp = None;
+        versions
+            .into_iter()
+            .filter(|entry| {
+                let timestamp = entry.get_timestamp();
+                let keep = match newer_timestamp {
+                    None => true,
+                    Some(newer_timestamp) => snapshots
+                        .iter()
+                        .any(|snapshot| timestamp <= *snapshot && *snapshot < newer_timestamp),
+                };
+                newer_timestamp = Some(timestamp);
+                keep
+            })
+            .collect()
+    }
 
-            // find the indexes of min keys
-            let min_key_indexes = SSTable::find_min_keys(&entries, true, compression_dictionary);
+    /// Replaces the newest version of a key with a tombstone if it has expired and no snapshot can see it while it
+    /// was still alive, so that the older data doesn't come back. The tombstone is dropped with the rest of them
+    /// if no older table can hold the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `versions` - Kept versions of a single key, sorted from the newest to the oldest one
+    /// * `snapshots` - Time stamps of all live snapshots
+    /// * `now` - Time stamp at which the expiry is checked
+    ///
+    /// # Returns
+    ///
+    /// Versions without the expired data, sorted from the newest to the oldest one
+    pub(crate) fn remove_expired_version(
+        mut versions: Vec<MemoryEntry>,
+        snapshots: &[u128],
+        now: u128,
+    ) -> Vec<MemoryEntry> {
+        let newest = match versions.first() {
+            Some(newest) if newest.is_expired(now) => newest,
+            _ => return versions,
+        };
 
-            // filter only the entries containing min key
-            let min_entries: Vec<_> = min_key_indexes
-                .iter()
-                .map(|index| entries[*index].clone())
-                .collect();
+        // A snapshot taken between the insert and the expiry still sees the value
+        let timestamp = newest.get_timestamp();
+        let expiry = newest.get_expiry().unwrap_or(timestamp);
+        if snapshots
+            .iter()
+            .any(|snapshot| timestamp <= *snapshot && *snapshot < expiry)
+        {
+            return versions;
+        }
 
-            // update the offset only for entries with minimal keys
-            let _ = min_entries.iter().for_each(|(index, element)| {
-                total_entry_offsets[*index] += element.as_ref().unwrap().1.clone();
-            });
+        versions[0] = MemoryEntry::from(&[], true, timestamp);
 
-            // insert entry with the biggest timestamp
-            let max_index = SSTable::find_max_timestamp(&min_entries);
-            merged_entries.push(entries[max_index].1.as_ref().unwrap().0.clone());
+        versions
+    }
+
+    /// Removes the oldest kept versions of a key while they are tombstones, if no older table can hold the key.
+    /// A snapshot that would see such a tombstone finds nothing without it as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `versions` - Kept versions of a single key, sorted from the newest to the oldest one
+    /// * `is_bottommost` - Indicates whether no older table can hold the key
+    ///
+    /// # Returns
+    ///
+    /// Versions without the obsolete tombstones, sorted from the newest to the oldest one
+    pub(crate) fn remove_obsolete_tombstones(
+        mut versions: Vec<MemoryEntry>,
+        is_bottommost: bool,
+    ) -> Vec<MemoryEntry> {
+        if is_bottommost {
+            while versions.last().is_some_and(MemoryEntry::get_tombstone) {
+                versions.pop();
+            }
         }
-        Ok(merged_entries)
+
+        versions
     }
 
     /// Finds the index of entry with the biggest timestamp
@@ -630,7 +1103,6 @@ impl SSTable {
     /// A Vector of indexes of entries with minimal keys
     pub(crate) fn find_min_keys(
         entries: &Vec<(usize, &Option<((Box<[u8]>, MemoryEntry), u64)>)>,
-        merging: bool,
         compression_dictionary: &mut Option<CompressionDictionary>,
     ) -> Vec<usize> {
         let mut min_key: Box<[u8]> = Box::new([255u8; 255]);
@@ -640,11 +1112,6 @@ impl SSTable {
                 continue;
             }
             let element = element.as_ref().unwrap();
-            if !merging {
-                if element.0 .1.get_tombstone() {
-                    continue;
-                }
-            }
             let key = &element.0 .0;
             let decoded_key = match compression_dictionary {
                 Some(compression_dictionary) => compression_dictionary
@@ -668,6 +1135,7 @@ impl SSTable {
     }
 
     /// Checks if the given key is likely present in the Bloom filter.
+    /// The Bloom filter is read and deserialized on the first check and kept for the later ones.
     ///
     /// # Arguments
     ///
@@ -681,35 +1149,143 @@ impl SSTable {
     ///
     /// Returns an `io::Error` if there's an issue when reading or deserializing the bloom filter data.
     fn bloom_filter_contains_key(&mut self, key: &[u8]) -> io::Result<bool> {
-        // Use the get_cursor_data function to get the Bloom filter data cursor
-        let mut filter_data_cursor = self.get_cursor_data(
-            self.in_single_file,
-            "SSTable-BloomFilter.db",
-            SSTableElementType::BloomFilter,
-            None,
-            false,
-        )?;
+        if self.bloom_filter.is_none() {
+            // Use the get_cursor_data function to get the Bloom filter data cursor
+            let mut filter_data_cursor = self.get_cursor_data(
+                self.in_single_file,
+                "SSTable-BloomFilter.db",
+                SSTableElementType::BloomFilter,
+                None,
+                false,
+            )?;
+
+            let mut filter_data = Vec::new();
+            filter_data_cursor.read_to_end(&mut filter_data)?;
+
+            // Attempt to deserialize BloomFilter
+            match BloomFilter::deserialize(&filter_data) {
+                Ok(bloom_filter) => self.bloom_filter = Some(bloom_filter),
+                Err(err) => {
+                    eprintln!("Error deserializing BloomFilter: {:?}", err);
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "Bloom filter deserialization failed",
+                    ));
+                }
+            }
+        }
+
+        // Check if key is in the Bloom filter
+        Ok(self.bloom_filter.as_ref().unwrap().contains(key))
+    }
+
+    /// Checks whether the table can hold keys that start with the given prefix, using its prefix Bloom filter.
+    /// The prefix filter is read on the first check and kept for the later ones. Tables without a prefix filter
+    /// or whose filter was built by another extractor can't rule out any prefix, and neither can prefixes
+    /// that the extractor doesn't extract anything from.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The decoded prefix of the scanned keys.
+    /// * `prefix_extractor` - The extractor of the key prefixes.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing false if no key of the table starts with the prefix, otherwise true.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if there's an issue when reading or deserializing the prefix filter.
+    pub(crate) fn may_contain_prefix(
+        &mut self,
+        prefix: &[u8],
+        prefix_extractor: PrefixExtractorType,
+    ) -> io::Result<bool> {
+        // Every key that starts with the prefix has the same extracted prefix as the prefix itself
+        let Some(extracted_prefix) = extract_key_prefix(prefix_extractor, prefix) else {
+            return Ok(true);
+        };
 
-        let mut filter_data = Vec::new();
-        filter_data_cursor.read_to_end(&mut filter_data)?;
+        if self.prefix_filter.is_none() {
+            self.prefix_filter = Some(self.read_prefix_filter()?);
+        }
 
-        // Attempt to deserialize BloomFilter
-        match BloomFilter::deserialize(&filter_data) {
-            Ok(bloom_filter) => {
-                // Check if key is in the Bloom filter
-                Ok(bloom_filter.contains(key))
+        Ok(match self.prefix_filter.as_ref().unwrap() {
+            Some((table_extractor, prefix_filter)) if *table_extractor == prefix_extractor => {
+                prefix_filter.contains(extracted_prefix)
             }
-            Err(err) => {
-                eprintln!("Error deserializing BloomFilter: {:?}", err);
-                Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "Bloom filter deserialization failed",
-                ))
+            _ => true,
+        })
+    }
+
+    /// Reads the prefix Bloom filter of the table with the extractor that built it, None if the table doesn't have one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the prefix filter can't be read or deserialized.
+    fn read_prefix_filter(&mut self) -> io::Result<Option<(PrefixExtractorType, BloomFilter)>> {
+        let properties = match self.read_properties_block()? {
+            Some(properties) if properties.prefix_extractor != PrefixExtractorType::None => {
+                properties
             }
+            _ => return Ok(None),
+        };
+
+        let file = if self.in_single_file {
+            self.open_file("SSTable.db")?
+        } else {
+            self.open_file("SSTable-PrefixFilter.db")?
+        };
+        file.seek(SeekFrom::Start(properties.prefix_filter_offset))?;
+        let mut filter_data = vec![0u8; properties.prefix_filter_len as usize];
+        file.read_exact(&mut filter_data)?;
+
+        Ok(Some((
+            properties.prefix_extractor,
+            BloomFilter::deserialize(&filter_data)?,
+        )))
+    }
+
+    /// Reads the whole summary of the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the summary can't be read or is truncated.
+    fn read_summary(&mut self) -> io::Result<Summary> {
+        if let Some(bytes) =
+            self.mapped_element("SSTable-Summary.db", &SSTableElementType::Summary)?
+        {
+            return Summary::deserialize(bytes);
         }
+
+        let header_len = self.header_len()?;
+        let mut bytes = Vec::new();
+        if self.in_single_file {
+            // The summary ends where the Bloom filter starts
+            let file = self.open_file("SSTable.db")?;
+            file.seek(SeekFrom::Start(
+                header_len + (SSTableElementType::Summary.get_id() * 8) as u64,
+            ))?;
+            let mut offset_bytes = [0u8; 16];
+            file.read_exact(&mut offset_bytes)?;
+            let (summary_offset, bloom_filter_offset) = offset_bytes.split_at(8);
+            let summary_offset = u64::from_le_bytes(summary_offset.try_into().unwrap());
+            let bloom_filter_offset = u64::from_le_bytes(bloom_filter_offset.try_into().unwrap());
+
+            file.seek(SeekFrom::Start(summary_offset))?;
+            file.take(bloom_filter_offset.saturating_sub(summary_offset))
+                .read_to_end(&mut bytes)?;
+        } else {
+            let file = self.open_file("SSTable-Summary.db")?;
+            file.seek(SeekFrom::Start(header_len))?;
+            file.read_to_end(&mut bytes)?;
+        }
+
+        Summary::deserialize(&bytes)
     }
 
     /// Finds the offset of the given key in the SSTable based on the index summary.
+    /// The summary is read and parsed on the first lookup and kept for the later ones.
     ///
     /// # Arguments
     ///
@@ -717,135 +1293,84 @@ impl SSTable {
     ///
     /// # Returns
     ///
-    /// An Option containing the offset if the key is found, otherwise None.
+    /// A `db_error::Result` containing an Option with the offset if the key is found, otherwise None.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the summary or the index can't be read.
     fn get_data_offset_from_summary(
         &mut self,
         key: &[u8],
         compression_dictionary: &mut Option<CompressionDictionary>,
-    ) -> Option<u64> {
-        let mut total_entry_offset = 0;
-        let mut summary_reader = self
-            .get_cursor_data(
-                self.in_single_file,
-                "SSTable-Summary.db",
-                SSTableElementType::Summary,
-                Some(total_entry_offset),
-                false,
-            )
-            .ok()?;
-
-        // Read the min key length and min key from the summary file
-        let mut min_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
-        summary_reader.read_exact(&mut min_key_len_bytes).unwrap();
-        total_entry_offset += std::mem::size_of::<usize>() as u64;
-
-        let min_key_len = usize::from_ne_bytes(min_key_len_bytes);
-        let mut min_key = vec![0u8; min_key_len];
-        summary_reader.read_exact(&mut min_key).unwrap();
-        total_entry_offset += min_key_len as u64;
-
-        // Read the max key length and max key from the summary file
-        let mut max_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
-        summary_reader.read_exact(&mut max_key_len_bytes).unwrap();
-        total_entry_offset += std::mem::size_of::<usize>() as u64;
-
-        let max_key_len = usize::from_ne_bytes(max_key_len_bytes);
-        let mut max_key = vec![0u8; max_key_len];
-        summary_reader.read_exact(&mut max_key).unwrap();
-        total_entry_offset += max_key_len as u64;
-
-        let decoded_key = match compression_dictionary {
-            Some(compression_dictionary) => compression_dictionary
-                .decode(&key.to_vec().into_boxed_slice())
-                .unwrap()
-                .clone(),
-            None => key.to_vec().into_boxed_slice(),
-        };
-
-        let decoded_min_key = match compression_dictionary {
-            Some(compression_dictionary) => compression_dictionary
-                .decode(&min_key.to_vec().into_boxed_slice())
-                .unwrap()
-                .clone(),
-            None => min_key.to_vec().into_boxed_slice(),
-        };
+    ) -> db_error::Result<Option<u64>> {
+        if self.summary.is_none() {
+            self.summary = Some(self.read_summary()?);
+        }
+        let summary = self.summary.as_ref().unwrap();
 
-        let decoded_max_key = match compression_dictionary {
-            Some(compression_dictionary) => compression_dictionary
-                .decode(&max_key.to_vec().into_boxed_slice())
-                .unwrap()
-                .clone(),
-            None => max_key.to_vec().into_boxed_slice(),
+        let dictionary = compression_dictionary.as_ref();
+        let decode = |key: &[u8]| -> Box<[u8]> {
+            match dictionary {
+                Some(dictionary) => dictionary.decode(key).unwrap(),
+                None => Box::from(key),
+            }
         };
+        let decoded_key = decode(key);
 
         // Check if the key is within the range of the lowest and highest keys in the summary
-        if decoded_key.as_ref().cmp(decoded_min_key.as_ref()) == Ordering::Less
-            || decoded_key.as_ref().cmp(decoded_max_key.as_ref()) == Ordering::Greater
-        {
-            return None;
+        let Some((min_key, max_key)) = summary.key_range.as_ref() else {
+            return Ok(None);
+        };
+        if decoded_key < decode(min_key) || decoded_key > decode(max_key) {
+            return Ok(None);
         }
 
-        let mut current_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
-        let mut previous_offset_bytes = [0u8; std::mem::size_of::<usize>()];
-
-        summary_reader = self
-            .get_cursor_data(
-                self.in_single_file,
-                "SSTable-Summary.db",
-                SSTableElementType::Summary,
-                Some(total_entry_offset),
-                false,
-            )
-            .ok()?;
-        while summary_reader
-            .read_exact(&mut current_key_len_bytes)
-            .is_ok()
-        {
-            total_entry_offset += std::mem::size_of::<usize>() as u64;
-
-            let current_key_len = usize::from_ne_bytes(current_key_len_bytes);
-            let mut current_key_bytes = vec![0u8; current_key_len];
-            summary_reader.read_exact(&mut current_key_bytes).unwrap();
-            total_entry_offset += current_key_len as u64;
-
-            let mut offset_bytes = [0u8; std::mem::size_of::<usize>()];
-            summary_reader.read_exact(&mut offset_bytes).unwrap();
-            total_entry_offset += std::mem::size_of::<usize>() as u64;
+        self.find_data_offset(&decoded_key, compression_dictionary)
+    }
 
-            let decoded_current_key = match compression_dictionary {
-                Some(compression_dictionary) => compression_dictionary
-                    .decode(&current_key_bytes.to_vec().into_boxed_slice())
-                    .unwrap()
-                    .clone(),
-                None => current_key_bytes.to_vec().into_boxed_slice(),
-            };
+    /// Finds the offset in the data file from which reading reaches the first entry whose key is greater than
+    /// or equal to the given one, without reading the entries before it. Scans use it to start
+    /// in the middle of the SSTable.
+    ///
+    /// # Arguments
+    ///
+    /// * `decoded_key` - The key to find, decoded if the keys are compressed.
+    ///
+    /// # Returns
+    ///
+    /// A `db_error::Result` containing an Option with the offset, or None if the index can't be read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the summary or the index can't be read.
+    fn find_data_offset(
+        &mut self,
+        decoded_key: &[u8],
+        compression_dictionary: &mut Option<CompressionDictionary>,
+    ) -> db_error::Result<Option<u64>> {
+        if self.summary.is_none() {
+            self.summary = Some(self.read_summary()?);
+        }
+        let summary = self.summary.as_ref().unwrap();
 
-            // Key < current key, read starting from previous offset previous_
-            if decoded_key.as_ref().cmp(decoded_current_key.as_ref()) == Ordering::Less {
-                return self.get_data_offset_from_index(
-                    u64::from_ne_bytes(previous_offset_bytes),
-                    key,
-                    compression_dictionary,
-                );
+        let dictionary = compression_dictionary.as_ref();
+        let decode = |key: &[u8]| -> Box<[u8]> {
+            match dictionary {
+                Some(dictionary) => dictionary.decode(key).unwrap(),
+                None => Box::from(key),
             }
+        };
 
-            previous_offset_bytes = offset_bytes;
-            summary_reader = self
-                .get_cursor_data(
-                    self.in_single_file,
-                    "SSTable-Summary.db",
-                    SSTableElementType::Summary,
-                    Some(total_entry_offset),
-                    false,
-                )
-                .ok()?;
-        }
+        // Key <= entry key, read starting from the previous offset so that the first version of the key isn't skipped
+        let entry = summary
+            .entries
+            .partition_point(|(entry_key, _)| decoded_key > decode(entry_key).as_ref());
+        let index_offset = match entry.checked_sub(1) {
+            Some(previous_entry) => summary.entries[previous_entry].1,
+            None => 0,
+        };
 
-        return self.get_data_offset_from_index(
-            u64::from_ne_bytes(previous_offset_bytes),
-            key,
-            compression_dictionary,
-        );
+        self.get_data_offset_from_index(index_offset, decoded_key, compression_dictionary)
     }
 
     /// Reads the data offset from the index file based on the seek offset and key.
@@ -853,49 +1378,52 @@ impl SSTable {
     /// # Arguments
     ///
     /// * `seek_offset` - The offset to seek in the index file.
-    /// * `key` - The key to find in the index file.
+    /// * `decoded_key` - The key to find in the index file, decoded if the keys are compressed.
     ///
     /// # Returns
     ///
-    /// An Option containing the data offset if the key is found, otherwise None.
+    /// A `db_error::Result` containing an Option with the data offset if the key is found, otherwise None.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be read.
     fn get_data_offset_from_index(
         &mut self,
         seek_offset: u64,
-        key: &[u8],
+        decoded_key: &[u8],
         compression_dictionary: &mut Option<CompressionDictionary>,
-    ) -> Option<u64> {
-        let mut total_entry_offset = seek_offset;
-        let mut index_reader = self
-            .get_cursor_data(
-                self.in_single_file,
-                "SSTable-Index.db",
-                SSTableElementType::Index,
-                Some(total_entry_offset),
-                false,
-            )
-            .ok()?;
+    ) -> db_error::Result<Option<u64>> {
+        if let Some(index) = self.mapped_element("SSTable-Index.db", &SSTableElementType::Index)? {
+            return Ok(SSTable::get_data_offset_from_mapped_index(
+                index,
+                seek_offset,
+                decoded_key,
+                compression_dictionary.as_ref(),
+            ));
+        }
 
-        let decoded_key = match compression_dictionary {
-            Some(compression_dictionary) => compression_dictionary
-                .decode(&key.to_vec().into_boxed_slice())
-                .unwrap()
-                .clone(),
-            None => key.to_vec().into_boxed_slice(),
-        };
+        let mut total_entry_offset = seek_offset;
+        let mut index_reader = self.get_cursor_data(
+            self.in_single_file,
+            "SSTable-Index.db",
+            SSTableElementType::Index,
+            Some(total_entry_offset),
+            false,
+        )?;
 
-        let mut current_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
-        let mut previous_offset_bytes = [0u8; std::mem::size_of::<usize>()];
+        let mut current_key_len_bytes = [0u8; 8];
+        let mut previous_offset_bytes = [0u8; 8];
         while index_reader.read_exact(&mut current_key_len_bytes).is_ok() {
-            total_entry_offset += std::mem::size_of::<usize>() as u64;
+            total_entry_offset += 8;
 
-            let current_key_len = usize::from_ne_bytes(current_key_len_bytes);
+            let current_key_len = u64::from_le_bytes(current_key_len_bytes) as usize;
             let mut current_key_bytes = vec![0u8; current_key_len];
-            index_reader.read_exact(&mut current_key_bytes).unwrap();
+            index_reader.read_exact(&mut current_key_bytes)?;
             total_entry_offset += current_key_len as u64;
 
-            let mut offset_bytes = [0u8; std::mem::size_of::<usize>()];
-            index_reader.read_exact(&mut offset_bytes).unwrap();
-            total_entry_offset += std::mem::size_of::<usize>() as u64;
+            let mut offset_bytes = [0u8; 8];
+            index_reader.read_exact(&mut offset_bytes)?;
+            total_entry_offset += 8;
 
             let decoded_current_key = match compression_dictionary {
                 Some(compression_dictionary) => compression_dictionary
@@ -905,25 +1433,59 @@ impl SSTable {
                 None => current_key_bytes.to_vec().into_boxed_slice(),
             };
 
-            // Key < current key, return previous offset
-            if decoded_key.as_ref().cmp(decoded_current_key.as_ref()) == Ordering::Less {
-                return Some(u64::from_ne_bytes(previous_offset_bytes));
+            // Key <= current key, return previous offset so that the first version of the key isn't skipped
+            if decoded_key.cmp(decoded_current_key.as_ref()) != Ordering::Greater {
+                return Ok(Some(u64::from_le_bytes(previous_offset_bytes)));
             }
 
             previous_offset_bytes = offset_bytes;
-            index_reader = self
-                .get_cursor_data(
-                    self.in_single_file,
-                    "SSTable-Index.db",
-                    SSTableElementType::Index,
-                    Some(total_entry_offset),
-                    false,
-                )
-                .ok()?;
+            index_reader = self.get_cursor_data(
+                self.in_single_file,
+                "SSTable-Index.db",
+                SSTableElementType::Index,
+                Some(total_entry_offset),
+                false,
+            )?;
         }
 
         // Return previous offset for the last entry in the index file
-        return Some(u64::from_ne_bytes(previous_offset_bytes));
+        Ok(Some(u64::from_le_bytes(previous_offset_bytes)))
+    }
+
+    /// Reads the data offset from the bytes of the mapped index, starting from the seek offset.
+    ///
+    /// # Returns
+    ///
+    /// An Option containing the data offset if the key is found, otherwise None.
+    fn get_data_offset_from_mapped_index(
+        index: &[u8],
+        seek_offset: u64,
+        decoded_key: &[u8],
+        compression_dictionary: Option<&CompressionDictionary>,
+    ) -> Option<u64> {
+        let decode = |key: &[u8]| -> Box<[u8]> {
+            match compression_dictionary {
+                Some(dictionary) => dictionary.decode(key).unwrap(),
+                None => Box::from(key),
+            }
+        };
+
+        let mut index_reader = Cursor::new(index);
+        index_reader.set_position(seek_offset);
+        let mut previous_offset = 0;
+        while let Ok(current_key) = read_key(&mut index_reader) {
+            let mut offset_bytes = [0u8; 8];
+            index_reader.read_exact(&mut offset_bytes).ok()?;
+
+            // Key <= current key, return previous offset so that the first version of the key isn't skipped
+            if decoded_key <= decode(&current_key).as_ref() {
+                return Some(previous_offset);
+            }
+            previous_offset = u64::from_le_bytes(offset_bytes);
+        }
+
+        // Return previous offset for the last entry in the index
+        Some(previous_offset)
     }
 
     /// Reads the MemoryEntry from the data file based on the given offset.
@@ -936,16 +1498,56 @@ impl SSTable {
     ///
     /// # Returns
     ///
-    /// An Option containing a pair of the key & MemoryEntry pair and the memory entry bytes length if successful, otherwise None.
+    /// A `db_error::Result` containing an Option with a pair of the key & MemoryEntry pair and the memory entry bytes length
+    /// if the entry is found, otherwise None.
+    ///
+    /// # Errors
+    ///
+    /// Returns a corruption error with the file and the offset of the entry if its checksum doesn't match,
+    /// or if the block that holds it can't be decompressed.
     pub(crate) fn get_entry_from_data_file(
         &mut self,
         offset: u64,
         index_density: Option<usize>,
         expected_key: Option<&[u8]>,
         use_variable_encoding: bool,
-    ) -> Option<((Box<[u8]>, MemoryEntry), u64)> {
-        let (mut crc, mut timestamp, mut tombstone, mut offset_to_key_len) =
-            (0u32, 0u128, false, 0usize);
+    ) -> db_error::Result<Option<((Box<[u8]>, MemoryEntry), u64)>> {
+        let (data_entry_bytes, entry_offset) = match self.read_data_entry_bytes(
+            offset,
+            index_density,
+            expected_key,
+            use_variable_encoding,
+        )? {
+            Some(read_entry) => read_entry,
+            None => return Ok(None),
+        };
+
+        match MemoryEntry::deserialize(&data_entry_bytes, use_variable_encoding) {
+            Ok(entry) => Ok(Some((entry, data_entry_bytes.len() as u64))),
+            Err(_) => Err(self.corruption(entry_offset)),
+        }
+    }
+
+    /// Reads the serialized MemoryEntry from the data file based on the given offset.
+    ///
+    /// # Returns
+    ///
+    /// A `db_error::Result` containing an Option with the memory entry bytes and the offset of the entry
+    /// in the data file if the entry is found, otherwise None.
+    ///
+    /// # Errors
+    ///
+    /// Returns a corruption error with the file and the offset of the entry if the entry or the block that holds it
+    /// can't be decoded, such as a block that fails to decompress, has an unknown codec or is cut short.
+    fn read_data_entry_bytes(
+        &mut self,
+        offset: u64,
+        index_density: Option<usize>,
+        expected_key: Option<&[u8]>,
+        use_variable_encoding: bool,
+    ) -> db_error::Result<Option<(Vec<u8>, u64)>> {
+        let (mut crc, mut timestamp, mut tombstone, mut expiry, mut offset_to_key_len) =
+            (0u32, 0u128, false, None, 0usize);
         let mut traversed_offset = 0;
         let mut length: usize;
 
@@ -955,29 +1557,30 @@ impl SSTable {
         // Traverse through index_density entries to find the given key only if both are not None
         if let (Some(index_density), Some(key)) = (index_density, expected_key) {
             if index_density < 1 {
-                return None;
+                return Ok(None);
             }
 
             let mut traversed_entries: usize = 0;
             while traversed_entries <= index_density {
+                let entry_offset = offset + traversed_offset;
                 let buffer = self
                     .get_cursor_data(
                         self.in_single_file,
                         "SSTable-Data.db",
                         SSTableElementType::DataEntryWithoutValue,
-                        Some(offset + traversed_offset),
+                        Some(entry_offset),
                         use_variable_encoding,
                     )
-                    .ok()?
+                    .map_err(|error| self.read_error(error, entry_offset))?
                     .into_inner();
                 if buffer.len() == 0 {
-                    return None;
+                    return Ok(None);
                 }
                 let (entry_length, mut buffer_offset) = deserialize_usize_value(&buffer, false);
                 (offset_to_key_len, length) =
                     deserialize_usize_value(&buffer[buffer_offset..], false);
                 buffer_offset += length;
-                (crc, timestamp, tombstone, _, _, length, _) =
+                (crc, timestamp, tombstone, expiry, _, _, length, _) =
                     deserialize_header(&buffer[buffer_offset..], false);
                 buffer_offset += length;
 
@@ -992,7 +1595,7 @@ impl SSTable {
 
             // If all index_density entries have been traversed and the key hasn't been found, return None
             if traversed_entries == 1 + index_density {
-                return None;
+                return Ok(None);
             }
         } else {
             let buffer = self
@@ -1003,15 +1606,15 @@ impl SSTable {
                     Some(offset),
                     use_variable_encoding,
                 )
-                .ok()?
+                .map_err(|error| self.read_error(error, offset))?
                 .into_inner();
             if buffer.len() == 0 {
-                return None;
+                return Ok(None);
             }
             let (_, mut buffer_offset) = deserialize_usize_value(&buffer, false);
             (offset_to_key_len, length) = deserialize_usize_value(&buffer[buffer_offset..], false);
             buffer_offset += length;
-            (crc, timestamp, tombstone, _, _, length, _) =
+            (crc, timestamp, tombstone, expiry, _, _, length, _) =
                 deserialize_header(&buffer[buffer_offset..], false);
             buffer_offset += length;
 
@@ -1029,7 +1632,7 @@ impl SSTable {
                 Some(offset + traversed_offset + offset_to_key_len as u64),
                 use_variable_encoding,
             )
-            .ok()?
+            .map_err(|error| self.read_error(error, offset + traversed_offset))?
             .into_inner()
         };
 
@@ -1038,7 +1641,7 @@ impl SSTable {
         let crc_bytes = if use_variable_encoding {
             variable_encode(crc as u128)
         } else {
-            Box::new(crc.to_ne_bytes())
+            Box::new(crc.to_le_bytes())
         };
 
         data_entry_bytes.extend(crc_bytes.to_vec().as_slice());
@@ -1046,16 +1649,16 @@ impl SSTable {
         let timestamp_bytes = if use_variable_encoding {
             variable_encode(timestamp)
         } else {
-            Box::new(timestamp.to_ne_bytes())
+            Box::new(timestamp.to_le_bytes())
         };
         data_entry_bytes.extend_from_slice(&timestamp_bytes);
 
-        data_entry_bytes.extend((tombstone as u8).to_ne_bytes());
+        data_entry_bytes.extend(serialize_flags(tombstone, expiry, use_variable_encoding).iter());
 
         let key_len_bytes = if use_variable_encoding {
             variable_encode(unwrapped_key.len() as u128)
         } else {
-            Box::new(unwrapped_key.len().to_ne_bytes())
+            Box::new((unwrapped_key.len() as u64).to_le_bytes())
         };
         data_entry_bytes.extend_from_slice(&key_len_bytes);
 
@@ -1063,7 +1666,7 @@ impl SSTable {
             let value_len_bytes = if use_variable_encoding {
                 variable_encode(data_entry_value.len() as u128)
             } else {
-                Box::new(data_entry_value.len().to_ne_bytes())
+                Box::new((data_entry_value.len() as u64).to_le_bytes())
             };
             data_entry_bytes.extend_from_slice(&value_len_bytes);
         }
@@ -1074,9 +1677,60 @@ impl SSTable {
             data_entry_bytes.extend(data_entry_value);
         }
 
-        match MemoryEntry::deserialize(&data_entry_bytes, use_variable_encoding) {
-            Ok(entry) => Some((entry, data_entry_bytes.len() as u64)),
-            Err(_) => None,
+        Ok(Some((data_entry_bytes, offset + traversed_offset)))
+    }
+
+    /// Converts the error of reading the data entry at the given offset. Undecodable data, such as a block that
+    /// fails to decompress or is cut short, is reported as corruption, while other errors are passed on as they are.
+    fn read_error(&mut self, error: io::Error, entry_offset: u64) -> Error {
+        match error.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                self.corruption(entry_offset)
+            }
+            _ => error.into(),
+        }
+    }
+
+    /// Returns the corruption error of the data entry at the given offset, located by the file that holds it.
+    /// Entries stored in blocks are located by the compressed block that holds them.
+    fn corruption(&mut self, entry_offset: u64) -> Error {
+        if let Ok(Some(blocks)) = self.data_blocks() {
+            let offset = blocks.data_offset
+                + blocks
+                    .find(entry_offset)
+                    .map(|index| blocks.block_offsets[index].1)
+                    .unwrap_or_default();
+            let path = if self.in_single_file {
+                self.base_path.join("SSTable.db")
+            } else {
+                self.base_path.join("SSTable-Data.db")
+            };
+
+            return Error::Corruption { path, offset };
+        }
+
+        if self.in_single_file {
+            // The data entries start at the offset written after the file header
+            let header_len = self.header_len().unwrap_or(0);
+            let data_offset = self
+                .open_file("SSTable.db")
+                .and_then(|file| {
+                    file.seek(SeekFrom::Start(header_len))?;
+                    let mut data_offset_bytes = [0u8; 8];
+                    file.read_exact(&mut data_offset_bytes)?;
+                    Ok(u64::from_le_bytes(data_offset_bytes))
+                })
+                .unwrap_or(0);
+
+            Error::Corruption {
+                path: self.base_path.join("SSTable.db"),
+                offset: data_offset + entry_offset,
+            }
+        } else {
+            Error::Corruption {
+                path: self.base_path.join("SSTable-Data.db"),
+                offset: self.header_len().unwrap_or(0) + entry_offset,
+            }
         }
     }
 
@@ -1109,36 +1763,65 @@ impl SSTable {
         let mut buffer = Vec::new();
         let total_entry_offset = total_entry_offset.unwrap_or(0);
 
+        let reads_data_entry = matches!(
+            sstable_element_type,
+            SSTableElementType::DataEntryWithoutValue | SSTableElementType::DataEntryValue
+        );
+        if reads_data_entry && self.data_blocks()?.is_some() {
+            // Entries don't cross blocks, so the whole entry is read from the block that holds it
+            let Some((block, offset_in_block)) = self.read_block(total_entry_offset)? else {
+                return Ok(Cursor::new(Vec::new()));
+            };
+            let mut block = Cursor::new(block);
+            block.set_position(offset_in_block);
+
+            return if sstable_element_type == SSTableElementType::DataEntryValue {
+                SSTable::read_entry_value(&mut block, use_variable_encoding)
+            } else {
+                SSTable::read_entry_without_value(&mut block, use_variable_encoding)
+            };
+        }
+
+        if let Some(element) = self.mapped_element(path_postfix, &sstable_element_type)? {
+            return SSTable::read_mapped_entry(
+                element,
+                sstable_element_type,
+                total_entry_offset,
+                use_variable_encoding,
+            );
+        }
+
+        let header_len = self.header_len()?;
         let file = if in_single_file {
-            self.write_to_file(&[], "SSTable.db")?
+            self.open_file("SSTable.db")?
         } else {
-            self.write_to_file(&[], path_postfix)?
+            self.open_file(path_postfix)?
         };
 
         let file_element_offset = if in_single_file {
-            // Seek to the file element offset index
+            // Seek to the file element offset index, which follows the file header
             file.seek(SeekFrom::Start(
-                (sstable_element_type.get_id() * std::mem::size_of::<usize>()) as u64,
+                header_len + (sstable_element_type.get_id() * 8) as u64,
             ))?;
 
             // Read the first offset value
-            let mut file_element_offset_bytes = [0u8; std::mem::size_of::<usize>()];
+            let mut file_element_offset_bytes = [0u8; 8];
             file.read_exact(&mut file_element_offset_bytes)?;
-            usize::from_ne_bytes(file_element_offset_bytes) as u64
+            u64::from_le_bytes(file_element_offset_bytes)
         } else {
-            0
+            header_len
         };
 
         let next_file_element_offset =
             if in_single_file && sstable_element_type != SSTableElementType::MerkleTree {
                 file.seek(SeekFrom::Start(
-                    ((sstable_element_type.get_id() + 1) * std::mem::size_of::<usize>()) as u64,
+                    header_len + ((sstable_element_type.get_id() + 1) * 8) as u64,
                 ))?;
 
                 // Read the first offset value
-                let mut file_element_offset_bytes = [0u8; std::mem::size_of::<usize>()];
+                let mut file_element_offset_bytes = [0u8; 8];
                 file.read_exact(&mut file_element_offset_bytes)?;
-                usize::from_ne_bytes(file_element_offset_bytes) as u64
+                u64::from_le_bytes(file_element_offset_bytes)
             } else {
                 0
             };
@@ -1153,49 +1836,12 @@ impl SSTable {
                 }
 
                 if in_single_file
-                    && file_element_offset
-                        + total_entry_offset
-                        + std::mem::size_of::<usize>() as u64
-                        >= next_file_element_offset
+                    && file_element_offset + total_entry_offset + 8 >= next_file_element_offset
                 {
                     return Ok(Cursor::new(Vec::new()));
                 }
 
-                // CRC + timestamp + tombstone + key_length + value_length
-                let mut header_max_length = 4 + 16 + 1 + 2 * std::mem::size_of::<usize>();
-                if use_variable_encoding {
-                    header_max_length = ((header_max_length * 8) as f64 / 7.0).ceil() as usize;
-                }
-                let mut header_bytes = vec![0u8; header_max_length];
-                // If EOF, return empty vec
-                let result = file.read(&mut header_bytes);
-                let result_len = match result {
-                    Ok(len) => {
-                        if len == 0 {
-                            return Ok(Cursor::new(Vec::new()));
-                        }
-                        len
-                    }
-                    Err(_) => return Ok(Cursor::new(Vec::new())), // Handle error or zero bytes read
-                };
-
-                let (crc, timestamp, tombstone, key_len, value_len, header_len, offset_to_key_len) =
-                    deserialize_header(&header_bytes, use_variable_encoding);
-                let entry_length = header_len + key_len + value_len;
-                file.seek(SeekFrom::Current(header_len as i64 - result_len as i64))
-                    .ok();
-                let mut key = vec![0u8; key_len];
-                file.read_exact(&mut key).ok();
-                buffer.extend_from_slice(&entry_length.to_ne_bytes());
-                buffer.extend_from_slice(&offset_to_key_len.to_ne_bytes());
-                buffer.extend_from_slice(&crc.to_ne_bytes());
-                buffer.extend_from_slice(&timestamp.to_ne_bytes());
-                buffer.extend_from_slice(&(tombstone as u8).to_ne_bytes());
-                buffer.extend_from_slice(&key_len.to_ne_bytes());
-                if !tombstone {
-                    buffer.extend_from_slice(&0usize.to_ne_bytes());
-                }
-                buffer.extend_from_slice(&key);
+                return SSTable::read_entry_without_value(file, use_variable_encoding);
             }
             SSTableElementType::DataEntryValue => {
                 let result = file.seek(SeekFrom::Start(file_element_offset + total_entry_offset));
@@ -1205,65 +1851,23 @@ impl SSTable {
                     return Err(err.into());
                 }
 
+                // The lengths of the last entry can be shorter than a usize when variable encoding is used
                 if in_single_file
-                    && file_element_offset
-                        + total_entry_offset
-                        + std::mem::size_of::<usize>() as u64
-                        >= next_file_element_offset
+                    && file_element_offset + total_entry_offset >= next_file_element_offset
                 {
                     return Ok(Cursor::new(Vec::new()));
                 }
 
-                // key_length + value_length
-                let mut key_val_len_max_len = 2 * std::mem::size_of::<usize>();
-                if use_variable_encoding {
-                    key_val_len_max_len = ((key_val_len_max_len * 8) as f64 / 7.0).ceil() as usize;
-                }
-                let mut key_val_len_bytes = vec![0u8; key_val_len_max_len];
-
-                // If EOF, return empty vec
-                let result = file.read(&mut key_val_len_bytes);
-                let result_len = match result {
-                    Ok(len) => {
-                        if len == 0 {
-                            return Ok(Cursor::new(Vec::new()));
-                        }
-                        len
-                    }
-                    Err(_) => return Ok(Cursor::new(Vec::new())), // Handle error or zero bytes read
-                };
-
-                let mut offset = 0;
-
-                let (key_len, length) =
-                    deserialize_usize_value(&key_val_len_bytes[offset..], use_variable_encoding);
-                offset += length;
-
-                let (value_len, length) =
-                    deserialize_usize_value(&key_val_len_bytes[offset..], use_variable_encoding);
-                offset += length;
-                let seek_backwards_offset = offset as i64 - result_len as i64;
-
-                file.seek(SeekFrom::Current(seek_backwards_offset)).ok();
-                let mut key_bytes = vec![0u8; key_len];
-                file.read_exact(&mut key_bytes)?;
-                let mut value = vec![0u8; value_len];
-                file.read_exact(&mut value).ok();
-
-                buffer.extend(value);
+                return SSTable::read_entry_value(file, use_variable_encoding);
             }
             SSTableElementType::Index => {
                 file.seek(SeekFrom::Start(file_element_offset + total_entry_offset))?;
 
                 // Read key len bytes, key len and offset bytes
-                let mut key_len_bytes = [0u8; std::mem::size_of::<usize>()];
+                let mut key_len_bytes = [0u8; 8];
                 let result = file.read_exact(&mut key_len_bytes);
                 if in_single_file {
-                    if file_element_offset
-                        + total_entry_offset
-                        + std::mem::size_of::<usize>() as u64
-                        >= next_file_element_offset
-                    {
+                    if file_element_offset + total_entry_offset + 8 >= next_file_element_offset {
                         return Ok(Cursor::new(Vec::new()));
                     }
                 } else {
@@ -1273,7 +1877,7 @@ impl SSTable {
                 }
                 buffer.extend_from_slice(&key_len_bytes);
 
-                let key_len = usize::from_ne_bytes(key_len_bytes);
+                let key_len = u64::from_le_bytes(key_len_bytes) as usize;
 
                 let mut key_bytes = vec![0u8; key_len];
                 file.read_exact(&mut key_bytes)?;
@@ -1288,34 +1892,31 @@ impl SSTable {
 
                 if total_entry_offset == 0 {
                     // When reading the start of the summary, read min key len, max key len
-                    let mut min_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
+                    let mut min_key_len_bytes = [0u8; 8];
                     file.read_exact(&mut min_key_len_bytes)?;
                     buffer.extend_from_slice(&min_key_len_bytes);
 
-                    let min_key_len = usize::from_ne_bytes(min_key_len_bytes);
+                    let min_key_len = u64::from_le_bytes(min_key_len_bytes) as usize;
 
                     let mut min_key_bytes = vec![0u8; min_key_len];
                     file.read_exact(&mut min_key_bytes)?;
                     buffer.extend_from_slice(&min_key_bytes);
 
-                    let mut max_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
+                    let mut max_key_len_bytes = [0u8; 8];
                     file.read_exact(&mut max_key_len_bytes)?;
                     buffer.extend_from_slice(&max_key_len_bytes);
 
-                    let max_key_len = usize::from_ne_bytes(max_key_len_bytes);
+                    let max_key_len = u64::from_le_bytes(max_key_len_bytes) as usize;
 
                     let mut max_key_bytes = vec![0u8; max_key_len];
                     file.read_exact(&mut max_key_bytes)?;
                     buffer.extend_from_slice(&max_key_bytes);
                 } else {
                     // Read key len, key and offset
-                    let mut key_len_bytes = [0u8; std::mem::size_of::<usize>()];
+                    let mut key_len_bytes = [0u8; 8];
                     let result = file.read_exact(&mut key_len_bytes);
                     if in_single_file {
-                        if file_element_offset
-                            + total_entry_offset
-                            + std::mem::size_of::<usize>() as u64
-                            >= next_file_element_offset
+                        if file_element_offset + total_entry_offset + 8 >= next_file_element_offset
                         {
                             return Ok(Cursor::new(Vec::new()));
                         }
@@ -1326,13 +1927,13 @@ impl SSTable {
                     }
                     buffer.extend_from_slice(&key_len_bytes);
 
-                    let key_len = usize::from_ne_bytes(key_len_bytes);
+                    let key_len = u64::from_le_bytes(key_len_bytes) as usize;
 
                     let mut key_bytes = vec![0u8; key_len];
                     file.read_exact(&mut key_bytes)?;
                     buffer.extend_from_slice(&key_bytes);
 
-                    let mut offset_bytes = [0u8; std::mem::size_of::<usize>()];
+                    let mut offset_bytes = [0u8; 8];
                     file.read_exact(&mut offset_bytes)?;
                     buffer.extend_from_slice(&offset_bytes);
                 }
@@ -1343,15 +1944,12 @@ impl SSTable {
                     file.take(next_file_element_offset - file_element_offset)
                         .read_to_end(&mut buffer)?;
                 } else {
-                    file.seek(SeekFrom::Start(0))?;
+                    file.seek(SeekFrom::Start(file_element_offset))?;
                     file.read_to_end(&mut buffer)?;
                 }
             }
             SSTableElementType::MerkleTree => {
-                if in_single_file {
-                    file.seek(SeekFrom::Start(file_element_offset))?;
-                }
-                file.seek(SeekFrom::Start(0))?;
+                file.seek(SeekFrom::Start(file_element_offset))?;
                 file.read(&mut buffer)?;
             }
         };
@@ -1359,6 +1957,192 @@ impl SSTable {
         Ok(Cursor::new(buffer))
     }
 
+    /// Returns the bytes of the given element of the table if its files are mapped into memory,
+    /// None if they're read through their handles.
+    ///
+    /// # Arguments
+    ///
+    /// * `path_postfix` - The postfix of the path of the file that holds the element (in multiple files mode).
+    /// * `sstable_element_type` - The element whose bytes are returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file can't be opened or the offsets of the elements can't be read.
+    fn mapped_element(
+        &mut self,
+        path_postfix: &str,
+        sstable_element_type: &SSTableElementType,
+    ) -> io::Result<Option<&[u8]>> {
+        // The Merkle tree isn't followed by an element whose offset ends it, so it's read like an unmapped file
+        if !self.use_mmap || *sstable_element_type == SSTableElementType::MerkleTree {
+            return Ok(None);
+        }
+
+        let header_len = self.header_len()?;
+        if !self.in_single_file {
+            return Ok(self
+                .open_file(path_postfix)?
+                .mapped_slice(header_len, u64::MAX));
+        }
+
+        // Each element ends where the next one starts, their offsets follow the file header
+        let file = self.open_file("SSTable.db")?;
+        let id = sstable_element_type.get_id() as u64;
+        let offsets = file
+            .mapped_slice(header_len + id * 8, header_len + (id + 2) * 8)
+            .filter(|offsets| offsets.len() == 16)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "SSTable offsets are past the end of the file",
+                )
+            })?;
+        let (start, end) = offsets.split_at(8);
+        let start = u64::from_le_bytes(start.try_into().unwrap());
+        let end = u64::from_le_bytes(end.try_into().unwrap());
+
+        Ok(file.mapped_slice(start, end))
+    }
+
+    /// Reads the entry of the given element at the offset from the mapped bytes of the element, in the same form
+    /// as it's read from a file handle. Returns an empty cursor if there is no entry at the offset.
+    fn read_mapped_entry(
+        element: &[u8],
+        sstable_element_type: SSTableElementType,
+        offset: u64,
+        use_variable_encoding: bool,
+    ) -> io::Result<Cursor<Vec<u8>>> {
+        let mut reader = Cursor::new(element);
+        reader.set_position(offset);
+
+        // Length of the key prefixed with its length at the given position
+        let key_len = |position: usize| -> Option<usize> {
+            let key_len_bytes = element.get(position..position.checked_add(8)?)?;
+            Some(8 + u64::from_le_bytes(key_len_bytes.try_into().unwrap()) as usize)
+        };
+        let start = offset as usize;
+
+        let end = match sstable_element_type {
+            SSTableElementType::DataEntryWithoutValue => {
+                return SSTable::read_entry_without_value(&mut reader, use_variable_encoding)
+            }
+            SSTableElementType::DataEntryValue => {
+                return SSTable::read_entry_value(&mut reader, use_variable_encoding)
+            }
+            // The summary starts with the min and max key
+            SSTableElementType::Summary if offset == 0 => key_len(start)
+                .and_then(|min_key_len| Some(start + min_key_len + key_len(start + min_key_len)?)),
+            // The index and the rest of the summary hold keys followed by offsets
+            SSTableElementType::Index | SSTableElementType::Summary => {
+                key_len(start).map(|key_len| start + key_len + 8)
+            }
+            SSTableElementType::BloomFilter | SSTableElementType::MerkleTree => Some(element.len()),
+        };
+
+        let bytes = end
+            .and_then(|end| element.get(start..end))
+            .unwrap_or_default();
+
+        Ok(Cursor::new(bytes.to_vec()))
+    }
+
+    /// Reads the header and the key of the data entry at the position of the reader.
+    /// Returns an empty cursor if there is no entry at the position.
+    fn read_entry_without_value<R: Read + Seek>(
+        reader: &mut R,
+        use_variable_encoding: bool,
+    ) -> io::Result<Cursor<Vec<u8>>> {
+        let mut buffer = Vec::new();
+
+        // CRC + timestamp + tombstone + expiry + key_length + value_length
+        let mut header_max_length = 4 + 16 + 1 + 16 + 2 * 8;
+        if use_variable_encoding {
+            header_max_length = ((header_max_length * 8) as f64 / 7.0).ceil() as usize;
+        }
+        let mut header_bytes = vec![0u8; header_max_length];
+        // If EOF, return empty vec
+        let result = reader.read(&mut header_bytes);
+        let result_len = match result {
+            Ok(len) => {
+                if len == 0 {
+                    return Ok(Cursor::new(Vec::new()));
+                }
+                len
+            }
+            Err(_) => return Ok(Cursor::new(Vec::new())), // Handle error or zero bytes read
+        };
+
+        let (crc, timestamp, tombstone, expiry, key_len, value_len, header_len, offset_to_key_len) =
+            deserialize_header(&header_bytes, use_variable_encoding);
+        let entry_length = header_len + key_len + value_len;
+        reader
+            .seek(SeekFrom::Current(header_len as i64 - result_len as i64))
+            .ok();
+        let mut key = vec![0u8; key_len];
+        reader.read_exact(&mut key).ok();
+        buffer.extend_from_slice(&(entry_length as u64).to_le_bytes());
+        buffer.extend_from_slice(&(offset_to_key_len as u64).to_le_bytes());
+        buffer.extend_from_slice(&crc.to_le_bytes());
+        buffer.extend_from_slice(&timestamp.to_le_bytes());
+        buffer.extend_from_slice(&serialize_flags(tombstone, expiry, false));
+        buffer.extend_from_slice(&(key_len as u64).to_le_bytes());
+        if !tombstone {
+            buffer.extend_from_slice(&0u64.to_le_bytes());
+        }
+        buffer.extend_from_slice(&key);
+
+        Ok(Cursor::new(buffer))
+    }
+
+    /// Reads the value of the data entry whose key length is at the position of the reader.
+    /// Returns an empty cursor if there is no entry at the position.
+    fn read_entry_value<R: Read + Seek>(
+        reader: &mut R,
+        use_variable_encoding: bool,
+    ) -> io::Result<Cursor<Vec<u8>>> {
+        let mut buffer = Vec::new();
+
+        // key_length + value_length
+        let mut key_val_len_max_len = 2 * 8;
+        if use_variable_encoding {
+            key_val_len_max_len = ((key_val_len_max_len * 8) as f64 / 7.0).ceil() as usize;
+        }
+        let mut key_val_len_bytes = vec![0u8; key_val_len_max_len];
+
+        // If EOF, return empty vec
+        let result = reader.read(&mut key_val_len_bytes);
+        let result_len = match result {
+            Ok(len) => {
+                if len == 0 {
+                    return Ok(Cursor::new(Vec::new()));
+                }
+                len
+            }
+            Err(_) => return Ok(Cursor::new(Vec::new())), // Handle error or zero bytes read
+        };
+
+        let mut offset = 0;
+
+        let (key_len, length) =
+            deserialize_usize_value(&key_val_len_bytes[offset..], use_variable_encoding);
+        offset += length;
+
+        let (value_len, length) =
+            deserialize_usize_value(&key_val_len_bytes[offset..], use_variable_encoding);
+        offset += length;
+        let seek_backwards_offset = offset as i64 - result_len as i64;
+
+        reader.seek(SeekFrom::Current(seek_backwards_offset)).ok();
+        let mut key_bytes = vec![0u8; key_len];
+        reader.read_exact(&mut key_bytes)?;
+        let mut value = vec![0u8; value_len];
+        reader.read_exact(&mut value).ok();
+
+        buffer.extend(value);
+
+        Ok(Cursor::new(buffer))
+    }
+
     /// Reads min and max key from SSTable at a given path.
     ///
     ///
@@ -1374,7 +2158,7 @@ impl SSTable {
     /// # Errors
     ///
     /// Returns an `io::Error` if there's an issue when reading contents of SStable file.
-    pub(crate) fn get_key_range(
+    fn get_key_range(
         sstable_base_path: PathBuf,
         in_single_file: bool,
     ) -> io::Result<(Box<[u8]>, Box<[u8]>)> {
@@ -1391,29 +2175,32 @@ impl SSTable {
 
         // Position the file cursor on beginning of summary data
         if in_single_file {
+            let header_len = SSTable::skip_file_header(&mut file_handle, FileKind::SSTable)?;
             file_handle.seek(SeekFrom::Start(
-                (SSTableElementType::Summary.get_id() * std::mem::size_of::<usize>()) as u64,
+                header_len + (SSTableElementType::Summary.get_id() * 8) as u64,
             ))?;
 
-            let mut summary_offset_bytes = [0u8; std::mem::size_of::<usize>()];
+            let mut summary_offset_bytes = [0u8; 8];
             file_handle.read_exact(&mut summary_offset_bytes)?;
-            let summary_offset_bytes = usize::from_ne_bytes(summary_offset_bytes) as u64;
+            let summary_offset_bytes = u64::from_le_bytes(summary_offset_bytes);
 
             file_handle.seek(SeekFrom::Start(summary_offset_bytes))?;
+        } else {
+            SSTable::skip_file_header(&mut file_handle, FileKind::SSTableSummary)?;
         }
 
         // Read min and max key from summary
-        let mut min_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
+        let mut min_key_len_bytes = [0u8; 8];
         file_handle.read_exact(&mut min_key_len_bytes)?;
-        let min_key_len = usize::from_ne_bytes(min_key_len_bytes);
+        let min_key_len = u64::from_le_bytes(min_key_len_bytes) as usize;
 
         let mut min_key_bytes = vec![0u8; min_key_len];
         file_handle.read_exact(&mut min_key_bytes)?;
         let min_key = min_key_bytes.into_boxed_slice();
 
-        let mut max_key_len_bytes = [0u8; std::mem::size_of::<usize>()];
+        let mut max_key_len_bytes = [0u8; 8];
         file_handle.read_exact(&mut max_key_len_bytes)?;
-        let max_key_len = usize::from_ne_bytes(max_key_len_bytes);
+        let max_key_len = u64::from_le_bytes(max_key_len_bytes) as usize;
 
         let mut max_key_bytes = vec![0u8; max_key_len];
         file_handle.read_exact(&mut max_key_bytes)?;
@@ -1463,26 +2250,29 @@ impl SSTable {
 
         // Position the file cursor on beginning of summary data
         if in_single_file {
-            file_handle.seek(SeekFrom::Start(std::mem::size_of::<usize>() as u64))?;
+            let header_len = SSTable::skip_file_header(&mut file_handle, FileKind::SSTable)?;
+            file_handle.seek(SeekFrom::Start(header_len + 8))?;
 
-            let mut index_offset_bytes = [0u8; std::mem::size_of::<usize>()];
+            let mut index_offset_bytes = [0u8; 8];
             let result = file_handle.read_exact(&mut index_offset_bytes);
             if result.is_err() {
                 return Ok(non_existent_thresh.unwrap());
             }
-            let index_offset = usize::from_ne_bytes(index_offset_bytes) as u64;
+            let index_offset = u64::from_le_bytes(index_offset_bytes);
 
             file_handle.seek(SeekFrom::Start(index_offset))?;
+        } else {
+            SSTable::skip_file_header(&mut file_handle, FileKind::SSTableIndex)?;
         }
 
         loop {
-            let mut key_len_bytes = [0u8; std::mem::size_of::<usize>()];
+            let mut key_len_bytes = [0u8; 8];
             let result = file_handle.read_exact(&mut key_len_bytes);
             if result.is_err() {
                 return Ok(non_existent_thresh.unwrap());
             }
 
-            let key_len = usize::from_ne_bytes(key_len_bytes);
+            let key_len = u64::from_le_bytes(key_len_bytes) as usize;
 
             let mut key_bytes = vec![0u8; key_len];
             let result = file_handle.read_exact(&mut key_bytes);
@@ -1498,13 +2288,13 @@ impl SSTable {
                 None => key_bytes.to_vec().into_boxed_slice(),
             };
 
-            let mut offset_bytes = [0u8; std::mem::size_of::<usize>()];
+            let mut offset_bytes = [0u8; 8];
             let result = file_handle.read_exact(&mut offset_bytes);
             if result.is_err() {
                 return Ok(non_existent_thresh.unwrap());
             }
 
-            let current_offset = usize::from_ne_bytes(offset_bytes);
+            let current_offset = u64::from_le_bytes(offset_bytes);
 
             match scan_type {
                 ScanType::RangeScan => {
@@ -1525,16 +2315,15 @@ impl SSTable {
             }
         }
 
-        Ok(offset as u64)
+        Ok(offset)
     }
 
-    /// Function that returns the offset from data file of a first key that meets the search criteria for either range or prefix scan
-    ///
+    /// Function that returns the offset from data file of a first key that meets the search criteria for either range or prefix scan.
+    /// Each sstable is read from the offset the summary and the index point to, so the keys before it aren't traversed.
     ///
     /// # Arguments
     ///
     /// * `sstables` - Vector containing sstables
-    /// * `current_offsets` - offsets form index file of a first key that meets the search criteria
     /// * `searched_key` - Can either be min key or prefix based on the scan type.
     /// * `scan_type` - The type of scan operation, range or prefix scan.
     /// * `use_variable_encoding` - set to true if the user decided to use variable encoding
@@ -1545,23 +2334,28 @@ impl SSTable {
     ///
     /// # Errors
     ///
-    /// Returns an `io::Error` if there's an issue when reading contents of SStable file.
+    /// Returns an error if a traversed entry of an SSTable is corrupted.
     pub(crate) fn update_sstable_offsets(
         sstables: &mut Vec<SSTable>,
-        mut current_offsets: Vec<u64>,
         searched_key: &[u8],
         scan_type: ScanType,
         use_variable_encoding: bool,
         compression_dictionary: &mut Option<CompressionDictionary>,
-    ) -> io::Result<Vec<u64>> {
+    ) -> db_error::Result<Vec<u64>> {
+        let mut current_offsets = vec![0; sstables.len()];
         for (index, sstable) in sstables.iter_mut().enumerate() {
+            // keys with the prefix are never smaller than the prefix itself, so both scans can seek to the searched key
+            if let Some(offset) = sstable.find_data_offset(searched_key, compression_dictionary)? {
+                current_offsets[index] = offset;
+            }
+
             loop {
                 let data = sstable.get_entry_from_data_file(
                     current_offsets[index],
                     None,
                     None,
                     use_variable_encoding,
-                );
+                )?;
                 if let Some(((key, _), offset)) = data {
                     let decoded_key = match compression_dictionary {
                         Some(compression_dictionary) => compression_dictionary
@@ -1593,59 +2387,26 @@ impl SSTable {
         Ok(current_offsets)
     }
 
-    /// Writes the provided data to a file with the given path postfix and returns a mutable reference to the file.
+    /// Opens the file with the given postfix for reading, mapping it into memory if the table is mapped.
+    /// The file is kept open for the later reads.
     ///
     /// # Arguments
     ///
-    /// * `data` - The data to be written to the file.
     /// * `path_postfix` - The postfix of the path of the file.
     ///
     /// # Returns
     ///
-    /// A `Result` containing a mutable reference to the opened file.
+    /// A `Result` containing a mutable reference to the opened `TableFile`.
     ///
     /// # Errors
     ///
-    /// Returns an `io::Error` if there's an issue when writing to the file or flushing the buffer.
-    fn write_to_file(&mut self, data: &[u8], path_postfix: &str) -> io::Result<&mut File> {
-        // Open the file directly
-        let file = self.open_file(path_postfix)?;
-
-        if data.len() > 0 {
-            file.write_all(data)?;
+    /// Returns an `io::Error` if there's an issue when opening or mapping the file.
+    fn open_file(&mut self, path_postfix: &str) -> io::Result<&mut TableFile> {
+        if !self.file_handles.contains_key(path_postfix) {
+            let file = TableFile::open(&self.base_path.join(path_postfix), self.use_mmap)?;
+            self.file_handles.insert(path_postfix.to_string(), file);
         }
 
-        file.flush()?;
-
-        Ok(file)
-    }
-
-    /// Opens a file with the given postfix in read, write & create mode.
-    ///
-    /// # Arguments
-    ///
-    /// * `path_postfix` - The postfix of the path of the file.
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing a mutable reference to the opened `File`.
-    ///
-    /// # Errors
-    ///
-    /// Returns an `io::Error` if there's an issue when opening the file.
-    fn open_file(&mut self, path_postfix: &str) -> io::Result<&mut File> {
-        let file_path = self.base_path.join(path_postfix);
-
-        let mut open_options = OpenOptions::new();
-        open_options.read(true).write(true).create(true);
-
-        // Insert the default File into the map
-        let file_handle = self
-            .file_handles
-            .entry(path_postfix.to_string())
-            .or_insert_with(|| open_options.open(&file_path).expect("Failed to open file"));
-
-        // Return a mutable reference to the File in the map
-        Ok(file_handle)
+        Ok(self.file_handles.get_mut(path_postfix).unwrap())
     }
 }
@@ -0,0 +1,207 @@
+use crate::sstable::{KeyRange, SSTable};
+use compression::CompressionDictionary;
+use segment_elements::{MemoryEntry, TimeStamp};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::Mutex;
+
+/// The next unread entry of one of the merged tables.
+struct HeapEntry {
+    // Key decoded with the compression dictionary, entries are merged in the order of decoded keys
+    decoded_key: Box<[u8]>,
+    // Index of the table that holds the entry
+    table_index: usize,
+    key: Box<[u8]>,
+    entry: MemoryEntry,
+    // Length of the serialized entry in the data file
+    length: u64,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.decoded_key
+            .cmp(&other.decoded_key)
+            .then(self.table_index.cmp(&other.table_index))
+    }
+}
+
+/// Iterator that merges the entries of multiple SSTables in the order of their keys.
+/// Only the next unread entry of each table is kept in memory, the tables are read as the merge goes on.
+/// Besides the newest version of each key, the versions that are visible to live snapshots are kept as well,
+/// ordered from the newest to the oldest one. Tombstones are dropped once no older table can hold their key.
+pub(crate) struct MergeIterator<'a> {
+    sstables: Vec<SSTable>,
+    // Offset of the next unread entry in the data file of each table
+    offsets: Vec<u64>,
+    // Length of all entries read from the tables so far
+    bytes_read: u64,
+    heap: BinaryHeap<Reverse<HeapEntry>>,
+    // Merged versions of the last key that haven't been returned yet
+    merged_versions: VecDeque<(Box<[u8]>, MemoryEntry)>,
+    use_variable_encoding: bool,
+    // Locked only while a key is decoded, so readers aren't held up for the whole merge
+    compression_dictionary: &'a Mutex<Option<CompressionDictionary>>,
+    snapshots: &'a [u128],
+    // Decoded key ranges of the older tables that aren't merged, which can still hold older versions of the keys
+    older_key_ranges: &'a [KeyRange],
+    // Time stamp at which the expiry is checked
+    now: u128,
+}
+
+impl<'a> MergeIterator<'a> {
+    /// Creates the iterator and reads the first entry of each table.
+    ///
+    /// # Errors
+    ///
+    /// Returns a corruption error if the first entry of a table is corrupted.
+    pub(crate) fn new(
+        sstables: Vec<SSTable>,
+        use_variable_encoding: bool,
+        compression_dictionary: &'a Mutex<Option<CompressionDictionary>>,
+        snapshots: &'a [u128],
+        older_key_ranges: &'a [KeyRange],
+    ) -> db_error::Result<Self> {
+        let number_of_tables = sstables.len();
+        let mut merge_iterator = MergeIterator {
+            sstables,
+            offsets: vec![0; number_of_tables],
+            bytes_read: 0,
+            heap: BinaryHeap::with_capacity(number_of_tables),
+            merged_versions: VecDeque::new(),
+            use_variable_encoding,
+            compression_dictionary,
+            snapshots,
+            older_key_ranges,
+            now: TimeStamp::Now.get_time(),
+        };
+
+        for table_index in 0..number_of_tables {
+            if let Some(heap_entry) = merge_iterator.read_entry(table_index)? {
+                merge_iterator.heap.push(Reverse(heap_entry));
+            }
+        }
+
+        Ok(merge_iterator)
+    }
+
+    /// Returns the length of all entries read from the tables so far.
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Reads the next unread entry of the table without consuming it.
+    fn read_entry(&mut self, table_index: usize) -> db_error::Result<Option<HeapEntry>> {
+        let ((key, entry), length) = match self.sstables[table_index].get_entry_from_data_file(
+            self.offsets[table_index],
+            None,
+            None,
+            self.use_variable_encoding,
+        )? {
+            Some(read_entry) => read_entry,
+            None => return Ok(None),
+        };
+        self.bytes_read += length;
+
+        let decoded_key = match &*self.compression_dictionary.lock().unwrap() {
+            Some(compression_dictionary) => compression_dictionary.decode(&key)?,
+            None => key.clone(),
+        };
+
+        Ok(Some(HeapEntry {
+            decoded_key,
+            table_index,
+            key,
+            entry,
+            length,
+        }))
+    }
+
+    /// Merges all versions of the smallest remaining key into the merged versions.
+    ///
+    /// # Returns
+    ///
+    /// False if all tables have been read, otherwise true.
+    fn merge_next_key(&mut self) -> db_error::Result<bool> {
+        let first = match self.heap.pop() {
+            Some(Reverse(first)) => first,
+            None => return Ok(false),
+        };
+
+        // Every table whose next entry holds the smallest key takes part
+        let mut min_key_entries = vec![first];
+        while let Some(Reverse(next)) = self.heap.peek() {
+            if next.decoded_key != min_key_entries[0].decoded_key {
+                break;
+            }
+            min_key_entries.push(self.heap.pop().unwrap().0);
+        }
+
+        // Collect all versions of the min key, tables can hold older versions right after the first one
+        let min_key = min_key_entries[0].key.clone();
+        let decoded_key = min_key_entries[0].decoded_key.clone();
+        let mut versions = Vec::new();
+        for heap_entry in min_key_entries {
+            let table_index = heap_entry.table_index;
+            self.offsets[table_index] += heap_entry.length;
+            versions.push(heap_entry.entry);
+
+            while let Some(next_entry) = self.read_entry(table_index)? {
+                if next_entry.key != heap_entry.key {
+                    self.heap.push(Reverse(next_entry));
+                    break;
+                }
+                self.offsets[table_index] += next_entry.length;
+                versions.push(next_entry.entry);
+            }
+        }
+
+        // Keep the newest version and the versions needed by snapshots, without the expired data and the tombstones
+        // that don't hide anything
+        let is_bottommost = !self
+            .older_key_ranges
+            .iter()
+            .any(|(min_key, max_key)| **min_key <= *decoded_key && *decoded_key <= **max_key);
+        let versions = SSTable::retain_snapshot_versions(versions, self.snapshots);
+        let versions = SSTable::remove_expired_version(versions, self.snapshots, self.now);
+        for entry in SSTable::remove_obsolete_tombstones(versions, is_bottommost) {
+            self.merged_versions.push_back((min_key.clone(), entry));
+        }
+
+        Ok(true)
+    }
+}
+
+impl<'a> Iterator for MergeIterator<'a> {
+    type Item = db_error::Result<(Box<[u8]>, MemoryEntry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Deleted and expired keys can be dropped entirely, so merging a key doesn't always give a version
+        while self.merged_versions.is_empty() {
+            match self.merge_next_key() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => {
+                    // The merge can't go on past a corrupted entry
+                    self.heap.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        self.merged_versions.pop_front().map(Ok)
+    }
+}
@@ -0,0 +1,425 @@
+use crate::sstable::table_properties::{TableProperties, FORMAT_VERSION, PROPERTIES_MAGIC};
+use crate::sstable::{extract_key_prefix, TableOptions};
+use bloom_filter::BloomFilter;
+use db_config::PrefixExtractorType;
+use merkle_tree::merkle_tree::{MerkleTree, CHUNK_SIZE};
+use segment_elements::{file_header, FileKind, MemoryEntry, FILE_HEADER_LEN};
+use std::fs::{remove_file, File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of offsets after the file header of an SSTable stored in a single file.
+const SINGLE_FILE_OFFSET_COUNT: usize = 5;
+
+/// Writes an SSTable entry by entry, so the memory it takes doesn't depend on the size of the table.
+/// Entries are gathered into blocks, which are compressed and written straight into the data file,
+/// together with the index, the summary and the Merkle tree. When the table is stored in a single file,
+/// those parts come after the data, so they are written into temporary files and appended once the table
+/// is finished. Only the Bloom filters, the current block and the block index are kept in memory.
+/// When the options set a prefix extractor, the extracted prefixes of the decoded keys go into a second Bloom
+/// filter, which follows the Merkle tree in a single file and has its own file otherwise.
+/// The properties of the table come last, followed by their offset and a magic number in a single file.
+/// Every file of the table starts with a file header, and all numbers are little-endian and 8 bytes long.
+///
+/// The index and the summary point to the offsets of the entries in the uncompressed data. Each block starts
+/// with the identifier of its codec and holds whole entries, so reading an entry decompresses a single block.
+/// The data part ends with the block index, which maps the uncompressed offset of each block to its offset
+/// in the data part, followed by the length of the uncompressed data and the number of blocks.
+pub(crate) struct SSTableWriter {
+    base_path: PathBuf,
+    in_single_file: bool,
+    options: TableOptions,
+    data: BufWriter<File>,
+    index: BufWriter<File>,
+    // Summary entries without the min and max key, which are only known once the table is finished
+    summary_entries: BufWriter<File>,
+    merkle_tree: BufWriter<File>,
+    bloom_filter: BloomFilter,
+    // Bloom filter of the extracted key prefixes, None if the options don't set a prefix extractor
+    prefix_filter: Option<BloomFilter>,
+    // Prefix of the last added key, consecutive keys often share it
+    last_prefix: Option<Box<[u8]>>,
+    // Written data that doesn't fill a whole Merkle tree chunk yet
+    merkle_chunk: Vec<u8>,
+    // Uncompressed entries of the block that isn't written yet
+    block: Vec<u8>,
+    block_index: Vec<u8>,
+    // Length of the written blocks in the data file
+    blocks_len: usize,
+    // Length of the uncompressed data
+    data_len: usize,
+    index_len: usize,
+    entry_count: usize,
+    tombstone_count: usize,
+    min_time_stamp: u128,
+    max_time_stamp: u128,
+    min_key: Option<Box<[u8]>>,
+    max_key: Option<Box<[u8]>>,
+}
+
+impl SSTableWriter {
+    /// Creates the files of a new SSTable in the given base path.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_path` - The base directory path where the SSTable files will be stored.
+    /// * `in_single_file` - Flag indicating whether to store data in a single file or multiple files.
+    /// * `options` - Options of the written table.
+    /// * `max_entries` - The most entries the table can have, used to size the Bloom filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the files can't be created.
+    pub(crate) fn new(
+        base_path: &Path,
+        in_single_file: bool,
+        options: TableOptions,
+        max_entries: usize,
+    ) -> io::Result<Self> {
+        let mut data = if in_single_file {
+            BufWriter::new(create_table_file(
+                base_path,
+                "SSTable.db",
+                FileKind::SSTable,
+            )?)
+        } else {
+            BufWriter::new(create_table_file(
+                base_path,
+                "SSTable-Data.db",
+                FileKind::SSTableData,
+            )?)
+        };
+        if in_single_file {
+            // The offsets are filled in once the lengths of all parts are known
+            data.write_all(&[0u8; SINGLE_FILE_OFFSET_COUNT * 8])?;
+        }
+
+        let (index, merkle_tree) = if in_single_file {
+            (
+                create_file(base_path, "SSTable-Index.db.tmp")?,
+                create_file(base_path, "SSTable-MerkleTree.db.tmp")?,
+            )
+        } else {
+            (
+                create_table_file(base_path, "SSTable-Index.db", FileKind::SSTableIndex)?,
+                create_table_file(
+                    base_path,
+                    "SSTable-MerkleTree.db",
+                    FileKind::SSTableMerkleTree,
+                )?,
+            )
+        };
+        let summary_entries = create_file(base_path, "SSTable-Summary.db.tmp")?;
+
+        Ok(SSTableWriter {
+            base_path: base_path.to_path_buf(),
+            in_single_file,
+            options,
+            data,
+            index: BufWriter::new(index),
+            summary_entries: BufWriter::new(summary_entries),
+            merkle_tree: BufWriter::new(merkle_tree),
+            bloom_filter: BloomFilter::new(0.01, max_entries),
+            prefix_filter: match options.prefix_extractor {
+                PrefixExtractorType::None => None,
+                _ => Some(BloomFilter::new(0.01, max_entries)),
+            },
+            last_prefix: None,
+            merkle_chunk: Vec::with_capacity(CHUNK_SIZE),
+            block: Vec::with_capacity(options.block_size),
+            block_index: Vec::new(),
+            blocks_len: 0,
+            data_len: 0,
+            index_len: 0,
+            entry_count: 0,
+            tombstone_count: 0,
+            min_time_stamp: 0,
+            max_time_stamp: 0,
+            min_key: None,
+            max_key: None,
+        })
+    }
+
+    /// Appends an entry to the table. Entries have to be added in the order of their keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the entry, already encoded with the compression dictionary.
+    /// * `decoded_key` - The key of the entry before encoding, which its prefix is extracted from.
+    /// * `entry` - The entry.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the length of the uncompressed entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing the entry fails.
+    pub(crate) fn add(
+        &mut self,
+        key: &[u8],
+        decoded_key: &[u8],
+        entry: &MemoryEntry,
+    ) -> io::Result<usize> {
+        let entry_data = entry.serialize(key, self.options.use_variable_encoding);
+
+        // Every index_density-th entry goes into the index and every summary_density-th index entry into the summary
+        let (summary_density, index_density) =
+            (self.options.summary_density, self.options.index_density);
+        if self.entry_count.is_multiple_of(index_density) {
+            if summary_density >= 1
+                && self
+                    .entry_count
+                    .is_multiple_of(summary_density * index_density)
+            {
+                self.summary_entries
+                    .write_all(&(key.len() as u64).to_le_bytes())?;
+                self.summary_entries.write_all(key)?;
+                self.summary_entries
+                    .write_all(&(self.index_len as u64).to_le_bytes())?;
+            }
+
+            self.index.write_all(&(key.len() as u64).to_le_bytes())?;
+            self.index.write_all(key)?;
+            self.index
+                .write_all(&(self.data_len as u64).to_le_bytes())?;
+            self.index_len += key.len() + 16;
+        }
+
+        self.block.extend_from_slice(&entry_data);
+        self.add_to_merkle_tree(&entry_data)?;
+        self.bloom_filter.add(key);
+        if let Some(prefix_filter) = &mut self.prefix_filter {
+            if let Some(prefix) = extract_key_prefix(self.options.prefix_extractor, decoded_key) {
+                if self.last_prefix.as_deref() != Some(prefix) {
+                    prefix_filter.add(prefix);
+                    self.last_prefix = Some(Box::from(prefix));
+                }
+            }
+        }
+
+        if self.min_key.is_none() {
+            self.min_key = Some(Box::from(key));
+            self.min_time_stamp = entry.get_timestamp();
+        }
+        self.max_key = Some(Box::from(key));
+        self.min_time_stamp = self.min_time_stamp.min(entry.get_timestamp());
+        self.max_time_stamp = self.max_time_stamp.max(entry.get_timestamp());
+        self.data_len += entry_data.len();
+        self.entry_count += 1;
+        self.tombstone_count += entry.get_tombstone() as usize;
+
+        if self.block.len() >= self.options.block_size {
+            self.write_block()?;
+        }
+
+        Ok(entry_data.len())
+    }
+
+    /// Compresses the current block, writes it into the data file and adds it to the block index.
+    fn write_block(&mut self) -> io::Result<()> {
+        let compressed_block = self.options.codec.compress(&self.block);
+
+        self.block_index
+            .extend_from_slice(&((self.data_len - self.block.len()) as u64).to_le_bytes());
+        self.block_index
+            .extend_from_slice(&(self.blocks_len as u64).to_le_bytes());
+
+        self.data.write_all(&[self.options.codec.id()])?;
+        self.data.write_all(&compressed_block)?;
+        self.blocks_len += 1 + compressed_block.len();
+        self.block.clear();
+
+        Ok(())
+    }
+
+    /// Writes the leaves of all whole Merkle tree chunks of the data.
+    fn add_to_merkle_tree(&mut self, data: &[u8]) -> io::Result<()> {
+        self.merkle_chunk.extend_from_slice(data);
+
+        let whole_chunks_len = self.merkle_chunk.len() - self.merkle_chunk.len() % CHUNK_SIZE;
+        for chunk in self.merkle_chunk[..whole_chunks_len].chunks(CHUNK_SIZE) {
+            self.merkle_tree
+                .write_all(&MerkleTree::serialize_chunk(chunk))?;
+        }
+        self.merkle_chunk.drain(..whole_chunks_len);
+
+        Ok(())
+    }
+
+    /// Writes the parts of the table that are only known once all entries are added.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the number of entries in the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing the table fails.
+    pub(crate) fn finish(mut self) -> io::Result<usize> {
+        if !self.merkle_chunk.is_empty() {
+            self.merkle_tree
+                .write_all(&MerkleTree::serialize_chunk(&self.merkle_chunk))?;
+        }
+
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
+        let block_count = self.block_index.len() / 16;
+        self.data.write_all(&self.block_index)?;
+        self.data.write_all(&(self.data_len as u64).to_le_bytes())?;
+        self.data.write_all(&(block_count as u64).to_le_bytes())?;
+        let data_part_len = self.blocks_len + self.block_index.len() + 16;
+
+        // The summary starts with the min and max key, a table without entries has an empty summary
+        let mut summary_header = Vec::new();
+        if let (Some(min_key), Some(max_key), true) = (
+            &self.min_key,
+            &self.max_key,
+            self.options.summary_density >= 1,
+        ) {
+            summary_header.extend_from_slice(&(min_key.len() as u64).to_le_bytes());
+            summary_header.extend_from_slice(min_key);
+            summary_header.extend_from_slice(&(max_key.len() as u64).to_le_bytes());
+            summary_header.extend_from_slice(max_key);
+        }
+        let summary_entries = into_file(self.summary_entries)?;
+        let summary_len = summary_header.len() + summary_entries.metadata()?.len() as usize;
+        let serialized_bloom_filter = self.bloom_filter.serialize();
+        let serialized_prefix_filter = self
+            .prefix_filter
+            .as_ref()
+            .map(|prefix_filter| prefix_filter.serialize())
+            .unwrap_or_default();
+        let merkle_tree = into_file(self.merkle_tree)?;
+
+        // The offsets of the parts in a single file, the prefix filter has its own file otherwise
+        let data_offset = FILE_HEADER_LEN + SINGLE_FILE_OFFSET_COUNT * 8;
+        let index_offset = data_offset + data_part_len;
+        let summary_offset = index_offset + self.index_len;
+        let bloom_filter_offset = summary_offset + summary_len;
+        let merkle_offset = bloom_filter_offset + serialized_bloom_filter.len();
+        let prefix_filter_offset = if self.in_single_file {
+            merkle_offset + merkle_tree.metadata()?.len() as usize
+        } else {
+            FILE_HEADER_LEN
+        };
+        let properties_offset = prefix_filter_offset + serialized_prefix_filter.len();
+
+        let serialized_properties = TableProperties {
+            format_version: FORMAT_VERSION,
+            entry_count: self.entry_count as u64,
+            tombstone_count: self.tombstone_count as u64,
+            min_time_stamp: self.min_time_stamp,
+            max_time_stamp: self.max_time_stamp,
+            data_size: self.data_len as u64,
+            min_key: self.min_key.unwrap_or_default(),
+            max_key: self.max_key.unwrap_or_default(),
+            prefix_extractor: match self.prefix_filter {
+                Some(_) => self.options.prefix_extractor,
+                None => PrefixExtractorType::None,
+            },
+            prefix_filter_offset: match self.prefix_filter {
+                Some(_) => prefix_filter_offset as u64,
+                None => 0,
+            },
+            prefix_filter_len: serialized_prefix_filter.len() as u64,
+        }
+        .serialize();
+
+        let mut data = into_file(self.data)?;
+        if self.in_single_file {
+            let index = into_file(self.index)?;
+
+            append_file(&mut data, &index)?;
+            data.write_all(&summary_header)?;
+            append_file(&mut data, &summary_entries)?;
+            data.write_all(&serialized_bloom_filter)?;
+            append_file(&mut data, &merkle_tree)?;
+            data.write_all(&serialized_prefix_filter)?;
+            data.write_all(&serialized_properties)?;
+            data.write_all(&(properties_offset as u64).to_le_bytes())?;
+            data.write_all(&PROPERTIES_MAGIC.to_le_bytes())?;
+
+            data.seek(SeekFrom::Start(FILE_HEADER_LEN as u64))?;
+            for offset in [
+                data_offset,
+                index_offset,
+                summary_offset,
+                bloom_filter_offset,
+                merkle_offset,
+            ] {
+                data.write_all(&(offset as u64).to_le_bytes())?;
+            }
+
+            remove_file(self.base_path.join("SSTable-Index.db.tmp"))?;
+            remove_file(self.base_path.join("SSTable-MerkleTree.db.tmp"))?;
+        } else {
+            let mut summary = create_table_file(
+                &self.base_path,
+                "SSTable-Summary.db",
+                FileKind::SSTableSummary,
+            )?;
+            summary.write_all(&summary_header)?;
+            append_file(&mut summary, &summary_entries)?;
+
+            create_table_file(
+                &self.base_path,
+                "SSTable-BloomFilter.db",
+                FileKind::SSTableBloomFilter,
+            )?
+            .write_all(&serialized_bloom_filter)?;
+            if self.prefix_filter.is_some() {
+                create_table_file(
+                    &self.base_path,
+                    "SSTable-PrefixFilter.db",
+                    FileKind::SSTablePrefixFilter,
+                )?
+                .write_all(&serialized_prefix_filter)?;
+            }
+            create_table_file(
+                &self.base_path,
+                "SSTable-Properties.db",
+                FileKind::SSTableProperties,
+            )?
+            .write_all(&serialized_properties)?;
+
+            into_file(self.index)?;
+        }
+        remove_file(self.base_path.join("SSTable-Summary.db.tmp"))?;
+
+        Ok(self.entry_count)
+    }
+}
+
+/// Creates a file in the base path, replacing the file that is already there.
+fn create_file(base_path: &Path, path_postfix: &str) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(base_path.join(path_postfix))
+}
+
+/// Creates a file of the table in the base path and writes its file header.
+fn create_table_file(base_path: &Path, path_postfix: &str, kind: FileKind) -> io::Result<File> {
+    let mut file = create_file(base_path, path_postfix)?;
+    file.write_all(&file_header(kind, FORMAT_VERSION))?;
+
+    Ok(file)
+}
+
+/// Flushes the buffered writes and returns the file.
+fn into_file(writer: BufWriter<File>) -> io::Result<File> {
+    writer.into_inner().map_err(|err| err.into_error())
+}
+
+/// Appends the whole content of the source file to the destination file.
+fn append_file(destination: &mut File, mut source: &File) -> io::Result<()> {
+    source.seek(SeekFrom::Start(0))?;
+    io::copy(&mut source, destination)?;
+
+    Ok(())
+}
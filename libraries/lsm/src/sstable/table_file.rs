@@ -0,0 +1,78 @@
+use memmap2::Mmap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// A file of an SSTable opened for reading. It's either read through its handle, which seeks and copies the
+/// read bytes, or mapped into memory, so that its bytes are borrowed as slices of the mapping.
+pub(crate) enum TableFile {
+    Handle(File),
+    // The mapped file and the position of the next read
+    Mapped(Cursor<Mmap>),
+}
+
+impl TableFile {
+    /// Opens the file in the given path for reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    /// * `mapped` - Flag indicating whether the file is mapped into memory or read through its handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file can't be opened or mapped.
+    pub(crate) fn open(path: &Path, mapped: bool) -> io::Result<TableFile> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        if !mapped {
+            return Ok(TableFile::Handle(file));
+        }
+
+        // Safety: published tables are never written again and are only removed once compaction replaces them,
+        // which unlinks the files but leaves the mapped pages readable until the mapping is dropped
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Ok(TableFile::Mapped(Cursor::new(mmap)))
+    }
+
+    /// Returns the length of the file in bytes.
+    pub(crate) fn len(&self) -> io::Result<u64> {
+        match self {
+            TableFile::Handle(file) => Ok(file.metadata()?.len()),
+            TableFile::Mapped(mmap) => Ok(mmap.get_ref().len() as u64),
+        }
+    }
+
+    /// Returns the bytes of the file between the given offsets, clamped to the end of the file.
+    /// Returns None if the file is read through its handle.
+    pub(crate) fn mapped_slice(&self, start: u64, end: u64) -> Option<&[u8]> {
+        match self {
+            TableFile::Handle(_) => None,
+            TableFile::Mapped(mmap) => {
+                let bytes: &[u8] = mmap.get_ref();
+                let end = (end.min(bytes.len() as u64)) as usize;
+                let start = (start as usize).min(end);
+                Some(&bytes[start..end])
+            }
+        }
+    }
+}
+
+impl Read for TableFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            TableFile::Handle(file) => file.read(buf),
+            TableFile::Mapped(mmap) => mmap.read(buf),
+        }
+    }
+}
+
+impl Seek for TableFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            TableFile::Handle(file) => file.seek(pos),
+            TableFile::Mapped(mmap) => mmap.seek(pos),
+        }
+    }
+}
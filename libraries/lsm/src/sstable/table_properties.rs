@@ -0,0 +1,120 @@
+use db_config::PrefixExtractorType;
+use std::io;
+use std::io::{Error, ErrorKind};
+
+/// Version of the SSTable format, written into the properties and the file headers of every new table.
+/// Tables written before the properties were added have version 0.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+/// Ends an SSTable stored in a single file if the offset of its properties is written before it.
+pub(crate) const PROPERTIES_MAGIC: u64 = 0x5353_5461_626c_6550;
+/// Length of the offset of the properties and the magic number at the end of an SSTable stored in a single file.
+pub(crate) const PROPERTIES_FOOTER_LEN: usize = 16;
+
+/// Properties of an SSTable, written once the table is finished, so they can be read without scanning its data.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct TableProperties {
+    pub(crate) format_version: u32,
+    // Number of entries in the table and how many of them are tombstones
+    pub(crate) entry_count: u64,
+    pub(crate) tombstone_count: u64,
+    // Smallest and largest time stamp of the entries
+    pub(crate) min_time_stamp: u128,
+    pub(crate) max_time_stamp: u128,
+    // Length of the uncompressed data of the table in bytes
+    pub(crate) data_size: u64,
+    // Min and max key of the table, encoded with the compression dictionary. Both are empty if the table is empty
+    pub(crate) min_key: Box<[u8]>,
+    pub(crate) max_key: Box<[u8]>,
+    // Extractor of the prefixes in the prefix Bloom filter, None if the table doesn't have one
+    pub(crate) prefix_extractor: PrefixExtractorType,
+    // Offset and length of the prefix Bloom filter in its file
+    pub(crate) prefix_filter_offset: u64,
+    pub(crate) prefix_filter_len: u64,
+}
+
+impl TableProperties {
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.extend(self.format_version.to_le_bytes());
+        buffer.extend(self.entry_count.to_le_bytes());
+        buffer.extend(self.tombstone_count.to_le_bytes());
+        buffer.extend(self.min_time_stamp.to_le_bytes());
+        buffer.extend(self.max_time_stamp.to_le_bytes());
+        buffer.extend(self.data_size.to_le_bytes());
+        buffer.extend((self.min_key.len() as u64).to_le_bytes());
+        buffer.extend_from_slice(&self.min_key);
+        buffer.extend((self.max_key.len() as u64).to_le_bytes());
+        buffer.extend_from_slice(&self.max_key);
+        // The extractor is stored as its identifier followed by its parameter
+        let (extractor_id, extractor_parameter) = match self.prefix_extractor {
+            PrefixExtractorType::None => (0u8, 0u64),
+            PrefixExtractorType::FixedLength(length) => (1, length as u64),
+            PrefixExtractorType::Delimiter(delimiter) => (2, delimiter as u64),
+        };
+        buffer.push(extractor_id);
+        buffer.extend(extractor_parameter.to_le_bytes());
+        buffer.extend(self.prefix_filter_offset.to_le_bytes());
+        buffer.extend(self.prefix_filter_len.to_le_bytes());
+
+        buffer
+    }
+
+    /// Deserializes the properties.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the bytes end before the properties do or the prefix extractor is unknown.
+    pub(crate) fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        let mut offset: usize = 0;
+        let mut read = |len: usize| -> io::Result<&[u8]> {
+            let end = offset.saturating_add(len);
+            let field = bytes.get(offset..end).ok_or_else(|| {
+                Error::new(ErrorKind::UnexpectedEof, "SSTable properties are cut off")
+            })?;
+            offset = end;
+            Ok(field)
+        };
+
+        let format_version = u32::from_le_bytes(read(4)?.try_into().unwrap());
+        let entry_count = u64::from_le_bytes(read(8)?.try_into().unwrap());
+        let tombstone_count = u64::from_le_bytes(read(8)?.try_into().unwrap());
+        let min_time_stamp = u128::from_le_bytes(read(16)?.try_into().unwrap());
+        let max_time_stamp = u128::from_le_bytes(read(16)?.try_into().unwrap());
+        let data_size = u64::from_le_bytes(read(8)?.try_into().unwrap());
+        let min_key_len = u64::from_le_bytes(read(8)?.try_into().unwrap()) as usize;
+        let min_key = Box::from(read(min_key_len)?);
+        let max_key_len = u64::from_le_bytes(read(8)?.try_into().unwrap()) as usize;
+        let max_key = Box::from(read(max_key_len)?);
+
+        // The extractor is stored as its identifier followed by its parameter
+        let extractor_id = read(1)?[0];
+        let extractor_parameter = u64::from_le_bytes(read(8)?.try_into().unwrap());
+        let prefix_extractor = match extractor_id {
+            0 => PrefixExtractorType::None,
+            1 => PrefixExtractorType::FixedLength(extractor_parameter as usize),
+            2 => PrefixExtractorType::Delimiter(extractor_parameter as u8),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Unknown prefix extractor of an SSTable",
+                ))
+            }
+        };
+        let prefix_filter_offset = u64::from_le_bytes(read(8)?.try_into().unwrap());
+        let prefix_filter_len = u64::from_le_bytes(read(8)?.try_into().unwrap());
+
+        Ok(TableProperties {
+            format_version,
+            entry_count,
+            tombstone_count,
+            min_time_stamp,
+            max_time_stamp,
+            data_size,
+            min_key,
+            max_key,
+            prefix_extractor,
+            prefix_filter_offset,
+            prefix_filter_len,
+        })
+    }
+}
@@ -0,0 +1,121 @@
+//! Helpers shared by the tests of the crate.
+
+use db_config::{CompactionAlgorithmType, DBConfig, PrefixExtractorType};
+use std::fs::{copy, create_dir_all, read_dir};
+use std::path::Path;
+use tempfile::TempDir;
+
+/// The options that differ between the LSMs of the tests.
+#[derive(Clone, Copy)]
+pub(crate) struct TestOptions {
+    pub(crate) compaction_algorithm_type: CompactionAlgorithmType,
+    // Bytes per second flushes and compactions can read and write, 0 for no limit
+    pub(crate) compaction_io_rate_limit: usize,
+    // Whether the SSTables are read through memory maps instead of file reads
+    pub(crate) use_mmap: bool,
+    pub(crate) prefix_extractor: PrefixExtractorType,
+}
+
+impl Default for TestOptions {
+    fn default() -> Self {
+        TestOptions {
+            compaction_algorithm_type: CompactionAlgorithmType::SizeTiered,
+            compaction_io_rate_limit: 0,
+            use_mmap: false,
+            prefix_extractor: PrefixExtractorType::None,
+        }
+    }
+}
+
+/// Returns the configuration of a small LSM, which flushes a memory table every 9 writes and compacts
+/// once a level holds 2 tables, so the tests reach the sstables and their compactions with few keys.
+///
+/// # Arguments
+///
+/// * `sstable_dir` - Directory of the sstables, which also holds the compression dictionary.
+/// * `write_ahead_log_dir` - Directory of the write-ahead log.
+/// * `options` - The options that differ between the tests.
+pub(crate) fn get_config(
+    sstable_dir: &Path,
+    write_ahead_log_dir: &Path,
+    options: TestOptions,
+) -> DBConfig {
+    let mut db_config = DBConfig::default();
+    db_config.memory_table_pool_num = 2;
+    db_config.memory_table_capacity = 10;
+    db_config.lsm_max_per_level = 2;
+    db_config.use_compression = false;
+    db_config.compaction_algorithm_type = options.compaction_algorithm_type;
+    db_config.lsm_compaction_io_rate_limit = options.compaction_io_rate_limit;
+    db_config.sstable_use_mmap = options.use_mmap;
+    db_config.sstable_prefix_extractor = options.prefix_extractor;
+    db_config.sstable_dir = sstable_dir.to_str().unwrap().to_string() + "/";
+    db_config.write_ahead_log_dir = write_ahead_log_dir.to_str().unwrap().to_string() + "/";
+    db_config.compression_dictionary_path = sstable_dir
+        .join("dictionary.bin")
+        .to_str()
+        .unwrap()
+        .to_string();
+    db_config
+}
+
+/// Returns the i-th key of a test, the keys sort in the order of their indexes.
+pub(crate) fn get_key(i: usize) -> String {
+    format!("key_{:03}", i)
+}
+
+/// Copies a fixture written in the format from before the files had headers into a temporary directory,
+/// so the test can rewrite it. The fixtures are described in `test_fixtures/legacy_format/README.md`.
+///
+/// # Arguments
+///
+/// * `layout` - `separate_files` or `single_file`, the way the tables of the fixture are stored.
+pub(crate) fn legacy_fixture(layout: &str) -> TempDir {
+    let fixture = TempDir::new().unwrap();
+    copy_dir(
+        &Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test_fixtures/legacy_format")
+            .join(layout),
+        fixture.path(),
+    );
+
+    fixture
+}
+
+/// Returns the configuration a legacy fixture was written with, reading from the copy of the fixture.
+pub(crate) fn legacy_config(fixture: &TempDir, in_single_file: bool) -> DBConfig {
+    let mut db_config = DBConfig::default();
+    db_config.memory_table_capacity = 10;
+    db_config.memory_table_pool_num = 2;
+    db_config.compaction_enabled = false;
+    db_config.use_compression = false;
+    db_config.sstable_single_file = in_single_file;
+    db_config.summary_density = 3;
+    db_config.index_density = 2;
+    db_config.use_variable_encoding = true;
+    db_config.sstable_dir = fixture.path().join("sstables").to_str().unwrap().to_string() + "/";
+    db_config.write_ahead_log_dir =
+        fixture.path().join("wal").to_str().unwrap().to_string() + "/";
+    db_config
+}
+
+/// Returns the value of the i-th key of a legacy fixture, None for the deleted keys.
+pub(crate) fn legacy_value(i: usize) -> Option<String> {
+    match i {
+        5 | 25 => None,
+        _ => Some(format!("value{:03}", i)),
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    create_dir_all(to).unwrap();
+    for dir_entry in read_dir(from).unwrap() {
+        let path = dir_entry.unwrap().path();
+        let destination = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &destination);
+        } else {
+            copy(&path, &destination).unwrap();
+        }
+    }
+}
@@ -4,6 +4,7 @@ pub mod merkle_tree;
 mod tests {
     use crate::merkle_tree::MerkleTree;
     use crate::merkle_tree::Node;
+    use crate::merkle_tree::CHUNK_SIZE;
     use sha256::digest;
 
     /// Tests the hashing mechanism of the Merkle Tree.
@@ -88,4 +89,21 @@ mod tests {
             Vec::<usize>::new()
         );
     }
+
+    /// Tests that serializing the data chunk by chunk gives the serialized tree.
+    #[test]
+    fn test_serialize_chunks() {
+        let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let merkle_tree = MerkleTree::new(&data);
+
+        let serialized_chunks: Vec<u8> = data
+            .chunks(CHUNK_SIZE)
+            .flat_map(|chunk| MerkleTree::serialize_chunk(chunk).into_vec())
+            .collect();
+
+        assert_eq!(
+            merkle_tree.serialize().as_ref(),
+            serialized_chunks.as_slice()
+        );
+    }
 }
@@ -1,5 +1,8 @@
 use sha256::digest;
 
+/// Size of the data chunks that the leaves of the tree are built from.
+pub const CHUNK_SIZE: usize = 1024;
+
 /// Represents a node in a Merkle tree.
 #[derive(Clone, Debug)]
 pub struct Node {
@@ -71,7 +74,7 @@ impl MerkleTree {
     pub fn new(data: &[u8]) -> MerkleTree {
         let mut tree = MerkleTree { root: None };
 
-        let nodes: Vec<Node> = data.chunks(1024).map(Node::new).collect();
+        let nodes: Vec<Node> = data.chunks(CHUNK_SIZE).map(Node::new).collect();
 
         tree.build_tree(nodes);
 
@@ -156,6 +159,12 @@ impl MerkleTree {
         data.into_boxed_slice()
     }
 
+    /// Serializes the leaf of a single data chunk. A tree is serialized as the leaves of its chunks in order,
+    /// so the serialized tree of large data can be written chunk by chunk without building the tree.
+    pub fn serialize_chunk(chunk: &[u8]) -> Box<[u8]> {
+        Node::new(chunk).hash.into_bytes().into_boxed_slice()
+    }
+
     /// Recursively collects the data blocks from the Merkle tree nodes.
     fn get_data_blocks(root: Option<&Node>, data: &mut Vec<u8>) {
         if let Some(node) = root {
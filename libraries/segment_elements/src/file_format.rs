@@ -0,0 +1,108 @@
+use std::io;
+use std::io::{Error, ErrorKind};
+
+/// Length of the header at the start of every file written by the database.
+///
+/// The header holds a magic number that tells what kind of file it is, followed by the version of its format
+/// as a little-endian `u32`. Everything after the header is little-endian as well, and all lengths and offsets
+/// take 8 bytes, so the files can be read on any machine.
+///
+/// Files written before the headers were added start right with their content, which used the byte order
+/// and the width of `usize` of the machine that wrote them. On 64-bit little-endian machines that's the same
+/// as the current layout, so such files are read there as they are, until they're rewritten. Other machines
+/// can't read them at all, since the layout they were written with isn't recorded anywhere.
+pub const FILE_HEADER_LEN: usize = 8;
+
+/// Kinds of files written by the database, each with its own magic number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    // An SSTable stored in a single file
+    SSTable,
+    // Parts of an SSTable stored in multiple files
+    SSTableData,
+    SSTableIndex,
+    SSTableSummary,
+    SSTableBloomFilter,
+    SSTableMerkleTree,
+    SSTableProperties,
+    SSTablePrefixFilter,
+    WriteAheadLog,
+    // Position in the write-ahead log from which it's replayed
+    WriteAheadLogIndex,
+    Manifest,
+}
+
+impl FileKind {
+    fn magic(self) -> [u8; 4] {
+        match self {
+            FileKind::SSTable => *b"SSTB",
+            FileKind::SSTableData => *b"SSDT",
+            FileKind::SSTableIndex => *b"SSIX",
+            FileKind::SSTableSummary => *b"SSSM",
+            FileKind::SSTableBloomFilter => *b"SSBF",
+            FileKind::SSTableMerkleTree => *b"SSMT",
+            FileKind::SSTableProperties => *b"SSPR",
+            FileKind::SSTablePrefixFilter => *b"SSPF",
+            FileKind::WriteAheadLog => *b"WALG",
+            FileKind::WriteAheadLogIndex => *b"WALI",
+            FileKind::Manifest => *b"MNFT",
+        }
+    }
+}
+
+/// Returns the header of a file of the given kind, written with the given version of its format.
+pub fn file_header(kind: FileKind, version: u32) -> [u8; FILE_HEADER_LEN] {
+    let mut header = [0u8; FILE_HEADER_LEN];
+    header[..4].copy_from_slice(&kind.magic());
+    header[4..].copy_from_slice(&version.to_le_bytes());
+
+    header
+}
+
+/// Reads the header at the start of a file of the given kind.
+///
+/// # Arguments
+///
+/// * `kind` - The kind of the file.
+/// * `bytes` - The start of the file, the whole header if the file has one.
+/// * `max_version` - The newest version of the format that can be read.
+///
+/// # Returns
+///
+/// An `io::Result` containing the version of the format, None if the file was written before the headers were added.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file was written with a newer version of the format,
+/// or if it has no header and this machine can't read the layout such files were written with.
+pub fn read_file_header(kind: FileKind, bytes: &[u8], max_version: u32) -> io::Result<Option<u32>> {
+    if bytes.len() < FILE_HEADER_LEN || bytes[..4] != kind.magic() {
+        // Empty files don't hold anything that could be read in the wrong layout
+        if !bytes.is_empty() && (cfg!(target_endian = "big") || std::mem::size_of::<usize>() != 8) {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "{:?} file has no header, so it was written by an older version in the layout of the \
+                     machine that wrote it, which is only read on 64-bit little-endian machines. Upgrade the \
+                     database with DB::upgrade_format on such a machine before moving it here",
+                    kind
+                ),
+            ));
+        }
+
+        return Ok(None);
+    }
+
+    let version = u32::from_le_bytes(bytes[4..FILE_HEADER_LEN].try_into().unwrap());
+    if version > max_version {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{:?} file has the format version {}, but only versions up to {} can be read",
+                kind, version, max_version
+            ),
+        ));
+    }
+
+    Ok(Some(version))
+}
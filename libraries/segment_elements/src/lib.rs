@@ -1,15 +1,19 @@
 pub mod crc_error;
+mod file_format;
 mod hashmap_impl;
 mod hashmap_iterator;
 mod memory_entry;
 mod segment_trait;
 mod timestamp;
+mod write_batch;
 
+pub use file_format::{file_header, read_file_header, FileKind, FILE_HEADER_LEN};
 pub use hashmap_impl::MemEntryHashMap;
 pub use memory_entry::MemoryEntry;
-pub use memory_entry::{deserialize_header, deserialize_usize_value};
+pub use memory_entry::{deserialize_header, deserialize_usize_value, serialize_flags};
 pub use segment_trait::SegmentTrait;
 pub use timestamp::TimeStamp;
+pub use write_batch::{BatchOperation, WriteBatch};
 
 #[cfg(test)]
 mod memory_entry_tests {
@@ -80,6 +84,29 @@ mod memory_entry_tests {
         assert_eq!(entry.get_timestamp(), new_entry.get_timestamp());
         assert_eq!(entry.get_tombstone(), new_entry.get_tombstone());
     }
+
+    #[test]
+    fn test_serialization_expiry() {
+        test_serialization_expiry_variable_encoding(true);
+        test_serialization_expiry_variable_encoding(false);
+    }
+
+    fn test_serialization_expiry_variable_encoding(use_variable_encoding: bool) {
+        let timestamp = TimeStamp::Now.get_time();
+        let mut entry = MemoryEntry::from(&[1, 2], false, timestamp);
+        entry.set_expiry(Some(timestamp + 1000));
+
+        let (key, new_entry) = MemoryEntry::deserialize(
+            &entry.serialize(&[1], use_variable_encoding),
+            use_variable_encoding,
+        )
+        .expect("Failed to deserialize entry");
+
+        assert_eq!(&[1], key.as_ref());
+        assert_eq!(entry, new_entry);
+        assert!(!new_entry.is_expired(timestamp + 999));
+        assert!(new_entry.is_expired(timestamp + 1000));
+    }
 }
 
 #[cfg(test)]
@@ -131,3 +158,43 @@ mod tests_hashmap_impl {
         assert_eq!(iter.next(), None);
     }
 }
+
+#[cfg(test)]
+mod file_format_tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_file_header() {
+        let header = file_header(FileKind::WriteAheadLog, 3);
+        assert_eq!(
+            read_file_header(FileKind::WriteAheadLog, &header, 3).unwrap(),
+            Some(3)
+        );
+        // The magic number of another kind of file isn't taken for a header
+        assert_eq!(
+            read_file_header(FileKind::Manifest, &header, 3).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_file_without_header() {
+        assert_eq!(read_file_header(FileKind::SSTable, &[], 1).unwrap(), None);
+        assert_eq!(
+            read_file_header(FileKind::SSTable, &[1, 2, 3], 1).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_newer_file_format() {
+        let header = file_header(FileKind::SSTableIndex, 2);
+        assert_eq!(
+            read_file_header(FileKind::SSTableIndex, &header, 1)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
@@ -10,14 +10,20 @@ pub struct MemoryEntry {
     value: Box<[u8]>,
     tombstone: bool,
     timestamp: u128,
+    // Time stamp after which the entry is considered deleted
+    expiry: Option<u128>,
 }
 
+/// Bit of the tombstone byte which marks that the expiry follows it.
+const EXPIRY_FLAG: u8 = 2;
+
 impl MemoryEntry {
     pub fn from(value: &[u8], tombstone: bool, timestamp: u128) -> Self {
         MemoryEntry {
             value: Box::from(value),
             timestamp,
             tombstone,
+            expiry: None,
         }
     }
 
@@ -29,16 +35,20 @@ impl MemoryEntry {
         let timestamp_bytes = if use_variable_encoding {
             variable_encode(self.timestamp)
         } else {
-            Box::new(self.timestamp.to_ne_bytes())
+            Box::new(self.timestamp.to_le_bytes())
         };
         entry_bytes.extend(timestamp_bytes.as_ref());
 
-        entry_bytes.extend((self.tombstone as u8).to_ne_bytes());
+        entry_bytes.extend(serialize_flags(
+            self.tombstone,
+            self.expiry,
+            use_variable_encoding,
+        ));
 
         let key_len_bytes = if use_variable_encoding {
             variable_encode(key.len() as u128)
         } else {
-            Box::new(key.len().to_ne_bytes())
+            Box::new((key.len() as u64).to_le_bytes())
         };
         entry_bytes.extend(key_len_bytes.as_ref());
 
@@ -46,7 +56,7 @@ impl MemoryEntry {
             let value_len_bytes = Vec::from(if use_variable_encoding {
                 variable_encode(self.value.len() as u128)
             } else {
-                Box::new(self.value.len().to_ne_bytes())
+                Box::new((self.value.len() as u64).to_le_bytes())
             });
             entry_bytes.extend(&value_len_bytes);
         }
@@ -61,7 +71,7 @@ impl MemoryEntry {
         let crc_bytes = if use_variable_encoding {
             variable_encode(crc as u128)
         } else {
-            Box::new(crc.to_ne_bytes())
+            Box::new(crc.to_le_bytes())
         };
         with_hasher.extend(crc_bytes.as_ref());
         with_hasher.extend(entry_bytes);
@@ -74,7 +84,7 @@ impl MemoryEntry {
     ) -> Result<(Box<[u8]>, Self), Box<dyn Error>> {
         let crc_hasher = Crc::<u32>::new(&CRC_32_ISCSI);
 
-        let (crc, timestamp, tombstone, key_len, value_len, mut offset, _) =
+        let (crc, timestamp, tombstone, expiry, key_len, value_len, mut offset, _) =
             deserialize_header(bytes, use_variable_encoding);
 
         let mut key = vec![0u8; key_len].into_boxed_slice();
@@ -94,21 +104,21 @@ impl MemoryEntry {
         let timestamp_bytes = if use_variable_encoding {
             variable_encode(timestamp)
         } else {
-            Box::new(timestamp.to_ne_bytes())
+            Box::new(timestamp.to_le_bytes())
         };
         crc_bytes.extend(timestamp_bytes.as_ref());
-        crc_bytes.extend((tombstone as u8).to_ne_bytes());
+        crc_bytes.extend(serialize_flags(tombstone, expiry, use_variable_encoding));
         let key_len_bytes = if use_variable_encoding {
             variable_encode(key.len() as u128)
         } else {
-            Box::new(key.len().to_ne_bytes())
+            Box::new((key.len() as u64).to_le_bytes())
         };
         crc_bytes.extend(key_len_bytes.as_ref());
         if !tombstone {
             let value_len_bytes = if use_variable_encoding {
                 variable_encode(value_len as u128)
             } else {
-                Box::new(value_len.to_ne_bytes())
+                Box::new((value_len as u64).to_le_bytes())
             };
             crc_bytes.extend(value_len_bytes.as_ref());
         }
@@ -123,6 +133,7 @@ impl MemoryEntry {
                 value,
                 tombstone,
                 timestamp,
+                expiry,
             };
             Ok((key, entry))
         }
@@ -155,18 +166,58 @@ impl MemoryEntry {
     pub fn get_val_size(&self) -> usize {
         self.value.len()
     }
+
+    pub fn get_expiry(&self) -> Option<u128> {
+        self.expiry
+    }
+
+    pub fn set_expiry(&mut self, expiry: Option<u128>) {
+        self.expiry = expiry;
+    }
+
+    /// Returns true if the entry has an expiry that has passed at the given time.
+    pub fn is_expired(&self, time: u128) -> bool {
+        match self.expiry {
+            Some(expiry) => expiry <= time,
+            None => false,
+        }
+    }
+}
+
+/// Serializes the tombstone byte and the expiry that follows it if it's present.
+pub fn serialize_flags(
+    tombstone: bool,
+    expiry: Option<u128>,
+    use_variable_encoding: bool,
+) -> Box<[u8]> {
+    let mut flags_bytes = Vec::new();
+
+    match expiry {
+        Some(expiry) => {
+            flags_bytes.push(tombstone as u8 | EXPIRY_FLAG);
+            let expiry_bytes = if use_variable_encoding {
+                variable_encode(expiry)
+            } else {
+                Box::new(expiry.to_le_bytes())
+            };
+            flags_bytes.extend(expiry_bytes.as_ref());
+        }
+        None => flags_bytes.push(tombstone as u8),
+    }
+
+    flags_bytes.into_boxed_slice()
 }
 
 pub fn deserialize_header(
     bytes: &[u8],
     use_variable_encoding: bool,
-) -> (u32, u128, bool, usize, usize, usize, usize) {
+) -> (u32, u128, bool, Option<u128>, usize, usize, usize, usize) {
     let mut offset = 0;
 
     let (crc_opt, length) = match use_variable_encoding {
         true => variable_decode(bytes),
         false => (
-            Some(u32::from_ne_bytes(bytes[..4].try_into().unwrap()) as u128),
+            Some(u32::from_le_bytes(bytes[..4].try_into().unwrap()) as u128),
             4,
         ),
     };
@@ -176,7 +227,7 @@ pub fn deserialize_header(
     let (timestamp_opt, length) = match use_variable_encoding {
         true => variable_decode(&bytes[offset..]),
         false => (
-            Some(u128::from_ne_bytes(
+            Some(u128::from_le_bytes(
                 bytes[offset..offset + 16].try_into().unwrap(),
             )),
             16,
@@ -185,9 +236,26 @@ pub fn deserialize_header(
     let timestamp = timestamp_opt.unwrap();
     offset += length;
 
-    let tombstone = bytes[offset] != 0;
+    let flags = bytes[offset];
+    let tombstone = flags & 1 != 0;
     offset += 1;
 
+    let expiry = if flags & EXPIRY_FLAG != 0 {
+        let (expiry_opt, length) = match use_variable_encoding {
+            true => variable_decode(&bytes[offset..]),
+            false => (
+                Some(u128::from_le_bytes(
+                    bytes[offset..offset + 16].try_into().unwrap(),
+                )),
+                16,
+            ),
+        };
+        offset += length;
+        expiry_opt
+    } else {
+        None
+    };
+
     let offset_to_key_len = offset;
 
     let (key_len, length) = deserialize_usize_value(&bytes[offset..], use_variable_encoding);
@@ -205,6 +273,7 @@ pub fn deserialize_header(
         crc,
         timestamp,
         tombstone,
+        expiry,
         key_len,
         value_len,
         offset,
@@ -216,11 +285,8 @@ pub fn deserialize_usize_value(bytes: &[u8], use_variable_encoding: bool) -> (us
     let (value_len_opt, length) = match use_variable_encoding {
         true => variable_decode(bytes),
         false => (
-            Some(
-                usize::from_ne_bytes(bytes[..std::mem::size_of::<usize>()].try_into().unwrap())
-                    as u128,
-            ),
-            std::mem::size_of::<usize>(),
+            Some(u64::from_le_bytes(bytes[..8].try_into().unwrap()) as u128),
+            8,
         ),
     };
     (value_len_opt.unwrap() as usize, length)
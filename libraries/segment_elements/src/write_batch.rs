@@ -0,0 +1,57 @@
+/// A single operation that is a part of a write batch.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchOperation {
+    Insert { key: Box<[u8]>, value: Box<[u8]> },
+    Delete { key: Box<[u8]> },
+}
+
+impl BatchOperation {
+    pub fn get_key(&self) -> &[u8] {
+        match self {
+            BatchOperation::Insert { key, .. } => key,
+            BatchOperation::Delete { key } => key,
+        }
+    }
+}
+
+/// Collection of inserts and deletes that are applied all-or-nothing.
+/// Operations are applied in the order they were added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WriteBatch {
+    operations: Vec<BatchOperation>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        WriteBatch {
+            operations: Vec::new(),
+        }
+    }
+
+    /// Adds an insert of the key value pair to the batch.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.operations.push(BatchOperation::Insert {
+            key: Box::from(key),
+            value: Box::from(value),
+        });
+    }
+
+    /// Adds a delete of the key to the batch.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.operations.push(BatchOperation::Delete {
+            key: Box::from(key),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BatchOperation> {
+        self.operations.iter()
+    }
+}
@@ -2,4 +2,5 @@ mod wal_byte_index;
 mod wal_file;
 mod write_ahead_log;
 
-pub use write_ahead_log::WriteAheadLog;
+pub use wal_byte_index::read_byte_index;
+pub use write_ahead_log::{WriteAheadLog, FORMAT_VERSION};
@@ -1,3 +1,5 @@
+use crate::write_ahead_log::FORMAT_VERSION;
+use segment_elements::{file_header, read_file_header, FileKind, FILE_HEADER_LEN};
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{Read, Seek, SeekFrom, Write};
@@ -22,21 +24,13 @@ impl WALByteIndex {
             current_value: 0,
         };
 
-        let mut byte_buffer = [0u8; 8];
-        val.file.as_mut().unwrap().read_exact(&mut byte_buffer).ok();
-        val.current_value = usize::from_ne_bytes(byte_buffer);
+        let mut bytes = Vec::new();
+        val.file.as_mut().unwrap().read_to_end(&mut bytes)?;
+        val.current_value = read_byte_index(&bytes)?;
 
         Ok(val)
     }
 
-    pub(crate) fn add(&mut self, value: usize) -> io::Result<()> {
-        self.current_value += value;
-
-        self.set(self.current_value)?;
-
-        Ok(())
-    }
-
     pub(crate) fn set(&mut self, byte: usize) -> io::Result<()> {
         self.current_value = byte;
 
@@ -49,21 +43,30 @@ impl WALByteIndex {
             .unwrap()
             .seek(SeekFrom::Start(0))
             .map(|_| ())?;
-        self.file.as_mut().unwrap().write_all(&byte.to_ne_bytes())?;
+        let mut bytes = Vec::from(file_header(FileKind::WriteAheadLogIndex, FORMAT_VERSION));
+        bytes.extend((byte as u64).to_le_bytes());
+        self.file.as_mut().unwrap().write_all(&bytes)?;
         self.file.as_mut().unwrap().flush()
     }
 
-    pub(crate) fn get(&mut self) -> usize {
-        if self.file.is_none() {
-            0
-        } else {
-            self.current_value
-        }
-    }
-
     pub(crate) fn close(&mut self) {
         if self.file.is_some() {
             self.file = None;
         }
     }
 }
+
+/// Reads the position from which the log is loaded out of the content of the byte index file.
+/// Files written before the headers were added only hold the position, 0 if the file is empty.
+pub fn read_byte_index(bytes: &[u8]) -> io::Result<usize> {
+    let position = match read_file_header(FileKind::WriteAheadLogIndex, bytes, FORMAT_VERSION)? {
+        Some(_) => &bytes[FILE_HEADER_LEN..],
+        None => bytes,
+    };
+
+    let mut position_bytes = [0u8; 8];
+    let len = position.len().min(8);
+    position_bytes[..len].copy_from_slice(&position[..len]);
+
+    Ok(u64::from_le_bytes(position_bytes) as usize)
+}
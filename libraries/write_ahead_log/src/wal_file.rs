@@ -1,12 +1,17 @@
+use crate::write_ahead_log::FORMAT_VERSION;
+use segment_elements::{file_header, read_file_header, FileKind, FILE_HEADER_LEN};
 use std::fs::{remove_file, File, OpenOptions};
 use std::io;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub(crate) struct WALFile {
     file: Option<File>,
     file_path: PathBuf,
+    // Length of the file header, 0 for files written before the headers were added
+    header_len: usize,
+    // Length of the records in the file, without the header
     pub(crate) current_size: usize,
     pub(crate) num_entries: usize,
 }
@@ -20,26 +25,43 @@ impl WALFile {
 
         let file_path = Path::new(dir).join(time.to_string() + ".log");
 
-        let file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .append(true)
             .create(true)
             .open(&file_path)?;
+        file.write_all(&file_header(FileKind::WriteAheadLog, FORMAT_VERSION))?;
+        file.flush()?;
 
         Ok(Self {
             file: Some(file),
             file_path,
+            header_len: FILE_HEADER_LEN,
             current_size: 0,
             num_entries: 0,
         })
     }
 
     pub(crate) fn open(file_path: PathBuf) -> io::Result<Self> {
-        let file = OpenOptions::new().append(true).open(&file_path)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&file_path)?;
+
+        let mut header = Vec::with_capacity(FILE_HEADER_LEN);
+        Read::by_ref(&mut file)
+            .take(FILE_HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+        let header_len = match read_file_header(FileKind::WriteAheadLog, &header, FORMAT_VERSION)? {
+            Some(_) => FILE_HEADER_LEN,
+            None => 0,
+        };
+        let current_size = file.metadata()?.len() as usize - header_len;
 
         Ok(Self {
             file: Some(file),
             file_path,
-            current_size: 0,
+            header_len,
+            current_size,
             num_entries: 0,
         })
     }
@@ -69,13 +91,16 @@ impl WALFile {
         remove_file(self.file_path)
     }
 
+    /// Returns the length of the records in the file, without the header.
     pub(crate) fn get_len(&mut self) -> io::Result<u64> {
-        match &self.file {
-            Some(file) => Ok(file.metadata()?.len()),
+        let len = match &self.file {
+            Some(file) => file.metadata()?.len(),
             None => {
                 let temp_file = OpenOptions::new().read(true).open(&self.file_path).unwrap();
-                Ok(temp_file.metadata()?.len())
+                temp_file.metadata()?.len()
             }
-        }
+        };
+
+        Ok(len - self.header_len as u64)
     }
 }
@@ -2,11 +2,18 @@ use crate::wal_byte_index::WALByteIndex;
 use crate::wal_file::WALFile;
 use crc::{Crc, CRC_32_ISCSI};
 use db_config::DBConfig;
-use segment_elements::TimeStamp;
-use std::collections::VecDeque;
+use segment_elements::{BatchOperation, TimeStamp, WriteBatch};
+use std::collections::{HashMap, VecDeque};
 use std::fs::read_dir;
 use std::path::PathBuf;
-use std::{fs, io};
+use std::fs;
+
+/// Version of the format of the log files, written into their headers.
+/// Records are stored in little-endian order, with lengths of 8 bytes.
+pub const FORMAT_VERSION: u32 = 1;
+/// Bit of the tombstone byte that marks a record of a named column family.
+/// The name of the column family follows the value length, records without it belong to the default one.
+const COLUMN_FAMILY_FLAG: u8 = 0x80;
 
 struct WALConfig {
     wal_dir: PathBuf,
@@ -29,12 +36,19 @@ pub struct WriteAheadLog {
     config: WALConfig,
     last_byte_file: WALByteIndex,
     files: VecDeque<WALFile>,
+    // Position right after the last written byte. Positions are counted from the start of the first log file
+    // when the log was opened, so they don't change when log files are removed
+    end_position: usize,
+    // Position at which the first file that is still kept starts
+    removed_bytes: usize,
+    // Position of the oldest record that isn't flushed yet, for every column family that has one
+    oldest_unflushed: HashMap<Option<String>, usize>,
 }
 
 impl WriteAheadLog {
     /// Creates a new file with the current time and a WAL that points to it.
     /// Can be used when continuing a journal from a new file or when starting a new journal.
-    pub fn new(dbconfig: &DBConfig) -> io::Result<WriteAheadLog> {
+    pub fn new(dbconfig: &DBConfig) -> db_error::Result<WriteAheadLog> {
         let wal_config = WALConfig::from(dbconfig);
 
         // Create a directory if it doesn't exist
@@ -45,12 +59,18 @@ impl WriteAheadLog {
             files: VecDeque::from(vec![WALFile::build(&wal_config.wal_dir)?]),
             last_byte_file: WALByteIndex::open(&wal_config.wal_dir)?,
             config: WALConfig::from(dbconfig),
+            end_position: 0,
+            removed_bytes: 0,
+            oldest_unflushed: HashMap::new(),
         })
     }
 
-    pub fn from_dir(dbconfig: &DBConfig) -> io::Result<WriteAheadLog> {
+    pub fn from_dir(dbconfig: &DBConfig) -> db_error::Result<WriteAheadLog> {
         let wal_config = WALConfig::from(dbconfig);
 
+        // Create a directory if it doesn't exist
+        fs::create_dir_all(&wal_config.wal_dir)?;
+
         let mut files = VecDeque::new();
 
         match read_dir(&dbconfig.write_ahead_log_dir) {
@@ -70,60 +90,191 @@ impl WriteAheadLog {
             Err(_) => (),
         };
 
+        if files.is_empty() {
+            files.push_back(WALFile::build(&wal_config.wal_dir)?);
+        }
+
+        let end_position = files.iter().map(|file| file.current_size).sum();
+
         Ok(Self {
             crc_hasher: Crc::<u32>::new(&CRC_32_ISCSI),
             files,
             last_byte_file: WALByteIndex::open(&wal_config.wal_dir)?,
             config: WALConfig::from(dbconfig),
+            end_position,
+            removed_bytes: 0,
+            oldest_unflushed: HashMap::new(),
         })
     }
 
-    pub fn insert(&mut self, key: &[u8], value: &[u8], timestamp: TimeStamp) -> io::Result<()> {
+    pub fn insert(
+        &mut self,
+        column_family: Option<&str>,
+        key: &[u8],
+        value: &[u8],
+        timestamp: TimeStamp,
+    ) -> db_error::Result<()> {
+        let mut record_bytes: Vec<u8> = Vec::new();
+        record_bytes.extend(timestamp.get_time().to_le_bytes().as_ref());
+        record_bytes.extend(Self::flag(false as u8, column_family).to_le_bytes());
+        record_bytes.extend((key.len() as u64).to_le_bytes());
+        record_bytes.extend((value.len() as u64).to_le_bytes());
+        Self::extend_column_family(&mut record_bytes, column_family);
+        record_bytes.extend(key);
+        record_bytes.extend(value);
+
+        let checksum_bytes = Vec::from(self.crc_hasher.checksum(&record_bytes).to_le_bytes());
+
+        let complete_bytes = checksum_bytes
+            .into_iter()
+            .chain(record_bytes)
+            .collect::<Vec<u8>>();
+
+        self.push_all_bytes(column_family, complete_bytes)?;
+
+        Ok(())
+    }
+
+    /// Appends an insert of a key that expires at the given time stamp. The tombstone byte is set to 3
+    /// and the expiry is written right after the value length.
+    pub fn insert_with_expiry(
+        &mut self,
+        column_family: Option<&str>,
+        key: &[u8],
+        value: &[u8],
+        expiry: u128,
+        timestamp: TimeStamp,
+    ) -> db_error::Result<()> {
         let mut record_bytes: Vec<u8> = Vec::new();
-        record_bytes.extend(timestamp.get_time().to_ne_bytes().as_ref());
-        record_bytes.extend((false as u8).to_ne_bytes());
-        record_bytes.extend(key.len().to_ne_bytes());
-        record_bytes.extend(value.len().to_ne_bytes());
+        record_bytes.extend(timestamp.get_time().to_le_bytes().as_ref());
+        record_bytes.extend(Self::flag(3u8, column_family).to_le_bytes());
+        record_bytes.extend((key.len() as u64).to_le_bytes());
+        record_bytes.extend((value.len() as u64).to_le_bytes());
+        Self::extend_column_family(&mut record_bytes, column_family);
+        record_bytes.extend(expiry.to_le_bytes());
         record_bytes.extend(key);
         record_bytes.extend(value);
 
-        let checksum_bytes = Vec::from(self.crc_hasher.checksum(&record_bytes).to_ne_bytes());
+        let checksum_bytes = Vec::from(self.crc_hasher.checksum(&record_bytes).to_le_bytes());
 
         let complete_bytes = checksum_bytes
             .into_iter()
             .chain(record_bytes)
             .collect::<Vec<u8>>();
 
-        self.push_all_bytes(complete_bytes)?;
+        self.push_all_bytes(column_family, complete_bytes)?;
 
         Ok(())
     }
 
-    pub fn delete(&mut self, key: &[u8], timestamp: TimeStamp) -> io::Result<()> {
+    pub fn delete(
+        &mut self,
+        column_family: Option<&str>,
+        key: &[u8],
+        timestamp: TimeStamp,
+    ) -> db_error::Result<()> {
         let mut record_bytes: Vec<u8> = Vec::new();
 
-        record_bytes.extend(timestamp.get_time().to_ne_bytes().as_ref());
-        record_bytes.extend((true as u8).to_ne_bytes());
-        record_bytes.extend(key.len().to_ne_bytes());
-        record_bytes.extend(0usize.to_ne_bytes().as_ref()); // value len
+        record_bytes.extend(timestamp.get_time().to_le_bytes().as_ref());
+        record_bytes.extend(Self::flag(true as u8, column_family).to_le_bytes());
+        record_bytes.extend((key.len() as u64).to_le_bytes());
+        record_bytes.extend(0u64.to_le_bytes().as_ref()); // value len
+        Self::extend_column_family(&mut record_bytes, column_family);
         record_bytes.extend(key);
 
-        let checksum_bytes = Vec::from(self.crc_hasher.checksum(&record_bytes).to_ne_bytes());
+        let checksum_bytes = Vec::from(self.crc_hasher.checksum(&record_bytes).to_le_bytes());
+
+        let complete_bytes = checksum_bytes
+            .into_iter()
+            .chain(record_bytes)
+            .collect::<Vec<u8>>();
+
+        self.push_all_bytes(column_family, complete_bytes)?;
+
+        Ok(())
+    }
+
+    /// Appends all operations of the batch as a single record that is protected by one checksum.
+    /// The header has the same layout as a regular record, but the tombstone byte is set to 2,
+    /// the key length holds the number of operations and the value length holds the payload length.
+    /// Each operation in the payload is written as: tombstone | key length | value length | key | value.
+    pub fn write_batch(
+        &mut self,
+        column_family: Option<&str>,
+        batch: &WriteBatch,
+        timestamp: TimeStamp,
+    ) -> db_error::Result<()> {
+        let mut payload: Vec<u8> = Vec::new();
+        for operation in batch.iter() {
+            match operation {
+                BatchOperation::Insert { key, value } => {
+                    payload.extend((false as u8).to_le_bytes());
+                    payload.extend((key.len() as u64).to_le_bytes());
+                    payload.extend((value.len() as u64).to_le_bytes());
+                    payload.extend(key.iter());
+                    payload.extend(value.iter());
+                }
+                BatchOperation::Delete { key } => {
+                    payload.extend((true as u8).to_le_bytes());
+                    payload.extend((key.len() as u64).to_le_bytes());
+                    payload.extend(0u64.to_le_bytes().as_ref()); // value len
+                    payload.extend(key.iter());
+                }
+            }
+        }
+
+        let mut record_bytes: Vec<u8> = Vec::new();
+        record_bytes.extend(timestamp.get_time().to_le_bytes().as_ref());
+        record_bytes.extend(Self::flag(2u8, column_family).to_le_bytes());
+        record_bytes.extend((batch.len() as u64).to_le_bytes());
+        record_bytes.extend((payload.len() as u64).to_le_bytes());
+        Self::extend_column_family(&mut record_bytes, column_family);
+        record_bytes.extend(payload);
+
+        let checksum_bytes = Vec::from(self.crc_hasher.checksum(&record_bytes).to_le_bytes());
 
         let complete_bytes = checksum_bytes
             .into_iter()
             .chain(record_bytes)
             .collect::<Vec<u8>>();
 
-        self.push_all_bytes(complete_bytes)?;
+        self.push_all_bytes(column_family, complete_bytes)?;
 
         Ok(())
     }
 
-    fn push_all_bytes(&mut self, mut bytes: Vec<u8>) -> io::Result<()> {
+    /// Marks the tombstone byte of a record that belongs to a named column family.
+    fn flag(flag: u8, column_family: Option<&str>) -> u8 {
+        match column_family {
+            Some(_) => flag | COLUMN_FAMILY_FLAG,
+            None => flag,
+        }
+    }
+
+    /// Appends the length and the name of the column family if the record doesn't belong to the default one.
+    fn extend_column_family(record_bytes: &mut Vec<u8>, column_family: Option<&str>) {
+        if let Some(column_family) = column_family {
+            record_bytes.extend((column_family.len() as u64).to_le_bytes());
+            record_bytes.extend(column_family.as_bytes());
+        }
+    }
+
+    fn push_all_bytes(
+        &mut self,
+        column_family: Option<&str>,
+        mut bytes: Vec<u8>,
+    ) -> db_error::Result<()> {
+        // The first record that isn't flushed keeps the log from being removed
+        self.oldest_unflushed
+            .entry(column_family.map(String::from))
+            .or_insert(self.end_position);
+        self.end_position += bytes.len();
+
         let mut last_file = self.files.back_mut().unwrap();
 
-        if last_file.num_entries == self.config.wal_max_entries {
+        if last_file.num_entries == self.config.wal_max_entries
+            || last_file.current_size >= self.config.wal_max_size
+        {
             last_file.close_file();
             self.files.push_back(WALFile::build(&self.config.wal_dir)?);
             last_file = self.files.back_mut().unwrap();
@@ -142,22 +293,56 @@ impl WriteAheadLog {
         Ok(())
     }
 
-    pub fn remove_logs_until(&mut self, byte: usize) -> io::Result<()> {
-        self.last_byte_file.add(byte)?;
+    /// Returns the position right after the last written record.
+    pub fn end_position(&self) -> usize {
+        self.end_position
+    }
 
-        let mut file_num = 1;
-        let mut subtract_bytes = 0;
-        let byte_index = self.last_byte_file.get();
+    /// Records that the column family has records that aren't flushed yet, starting from the given position.
+    /// Used for the records that were loaded from the log, because their position isn't known to the log anymore.
+    pub fn mark_unflushed(&mut self, column_family: Option<&str>, position: usize) {
+        let oldest_unflushed = self
+            .oldest_unflushed
+            .entry(column_family.map(String::from))
+            .or_insert(position);
+        *oldest_unflushed = (*oldest_unflushed).min(position);
+    }
+
+    /// Records that all records of the column family before the given position are flushed, or all of them
+    /// if there is no position. The log files that only hold flushed records of every column family are removed,
+    /// and the log is loaded from the oldest record that isn't flushed.
+    pub fn mark_flushed(
+        &mut self,
+        column_family: Option<&str>,
+        oldest_unflushed: Option<usize>,
+    ) -> db_error::Result<()> {
+        let column_family = column_family.map(String::from);
+        match oldest_unflushed {
+            Some(position) => self.oldest_unflushed.insert(column_family, position),
+            None => self.oldest_unflushed.remove(&column_family),
+        };
 
-        while file_num * self.config.wal_max_size < byte_index {
-            let mut file = self.files.pop_front().unwrap();
-            subtract_bytes += file.get_len()?;
-            file.remove_file()?;
-            file_num += 1;
+        let start_position = self
+            .oldest_unflushed
+            .values()
+            .copied()
+            .min()
+            .unwrap_or(self.end_position);
+        let mut byte_index = start_position - self.removed_bytes;
+
+        while self.files.len() > 1 {
+            let file_len = self.files.front_mut().unwrap().get_len()? as usize;
+            if file_len > byte_index {
+                break;
+            }
+
+            self.files.pop_front().unwrap().remove_file()?;
+            self.removed_bytes += file_len;
+            byte_index -= file_len;
         }
 
-        self.last_byte_file
-            .set(byte_index - subtract_bytes as usize)?;
+        self.last_byte_file.set(byte_index)?;
+
         Ok(())
     }
 
@@ -0,0 +1,61 @@
+use db_config::DBConfig;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::env;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::exit;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use NoSQLDB::{serve, DB};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:6379";
+const CONFIG_FILE_PATH: &str = "config.json";
+
+/// Serves the database over RESP, so that Redis clients can connect to it. Stops on SIGINT, SIGTERM
+/// or the SHUTDOWN command, after which the database is shut down cleanly.
+///
+/// Usage: `server [address] [config file]`
+fn main() {
+    let mut args = env::args().skip(1);
+    let address = args.next().unwrap_or(DEFAULT_ADDRESS.to_string());
+    let config_path = args.next().unwrap_or(CONFIG_FILE_PATH.to_string());
+
+    let dbconfig = if Path::new(&config_path).exists() {
+        DBConfig::load(&config_path).unwrap_or_else(|e| {
+            eprintln!("Failed to load configuration: {}", e);
+            exit(1);
+        })
+    } else {
+        DBConfig::default()
+    };
+
+    let db = DB::build(dbconfig).unwrap_or_else(|e| {
+        eprintln!("Failed to build the database: {}", e);
+        exit(1);
+    });
+
+    let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+        eprintln!("Failed to bind to {}: {}", address, e);
+        exit(1);
+    });
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signal, Arc::clone(&shutdown)) {
+            eprintln!("Failed to register the signal handler: {}", e);
+            exit(1);
+        }
+    }
+
+    println!("Listening on {}", address);
+    let db = Arc::new(db);
+    if let Err(e) = serve(Arc::clone(&db), listener, shutdown) {
+        eprintln!("Failed to serve: {}", e);
+    }
+
+    println!("Shutting down");
+    match Arc::try_unwrap(db) {
+        Ok(db) => db.shut_down(),
+        Err(_) => eprintln!("Failed to shut down the database, because it's still in use"),
+    }
+}
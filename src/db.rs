@@ -1,160 +1,487 @@
 use bloom_filter::BloomFilter;
 use count_min_sketch::CMSketch;
-use db_config::DBConfig;
+use db_config::{ColumnFamilyConfig, DBConfig};
+use db_error::Error;
 use hyperloglog::HLL;
-use lsm::{Paginator, LSM};
-use segment_elements::TimeStamp;
+use lsm::{LSMSnapshot, Paginator, LSM};
+use segment_elements::{TimeStamp, WriteBatch};
 use simhash::hamming_distance;
-use std::error::Error;
+use std::collections::HashMap;
 use std::fs::create_dir_all;
-use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use token_bucket::token_bucket::TokenBucket;
-
-use crate::token_bucket_error::TokenBucketError;
-use crate::ProbabilisticTypeError;
-use crate::ReservedKeyError;
-
+use write_ahead_log::WriteAheadLog;
+
+use crate::Snapshot;
+
+/// Name of the column family that holds the probabilistic data structures and the token bucket state.
+const SYSTEM_COLUMN_FAMILY: &str = "system";
+const TOKEN_BUCKET_KEY: &[u8] = b"t0k3n_buck3t/state";
+/// Key prefixes that separate the probabilistic data structures in the system column family.
+const SYSTEM_KEY_PREFIXES: [&[u8]; 4] = [
+    b"bl00m_f1lt3r/",
+    b"c0unt_m1n_$k3tch/",
+    b"hyp3r_l0g_l0g/",
+    b"$1m_ha$h/",
+];
+/// Marks that the system keys of a database written before the system column family existed were moved into it.
+const SYSTEM_KEYS_MIGRATED_KEY: &[u8] = b"m1gr4t10n/system_keys";
+
+/// The database handle. All methods take `&self`, so the handle can be shared between threads,
+/// for example through an `Arc<DB>`. Reads run in parallel, while writes are serialized by the LSM.
+/// The user data is kept in the default column family and in the named column families from the configuration,
+/// which all share one WAL.
 pub struct DB {
     config: DBConfig,
     lsm: LSM,
-    // System reserved key prefixes for probabilistic data structures and the token bucket
-    reserved_key_prefixes: [&'static [u8]; 5],
+    // Holds the probabilistic data structures and the token bucket state, apart from the user data
+    system_lsm: LSM,
+    column_families: HashMap<String, LSM>,
+    // The rate limiter is kept in memory and only checkpointed to the system column family
+    token_bucket: Mutex<CheckpointedTokenBucket>,
+    // Serializes updates of probabilistic data structures, which are read, changed and written back
+    probabilistic_lock: Mutex<()>,
 }
 
-impl DB {
-    pub fn build(config: DBConfig) -> Result<Self, Box<dyn Error>> {
-        let lsm = if Path::new(&config.write_ahead_log_dir).exists() {
-            match LSM::load_from_dir(&config) {
-                Ok(lsm) => lsm,
-                Err(e) => {
-                    eprintln!("Error occurred: {}", e);
-                    eprintln!("Memory pool wasn't reconstructed.");
-                    LSM::new(&config)?
-                }
-            }
-        } else {
-            LSM::new(&config)?
-        };
+/// The token bucket together with the time it was last written to the system column family.
+struct CheckpointedTokenBucket {
+    token_bucket: TokenBucket,
+    last_checkpoint: Instant,
+}
 
+impl DB {
+    pub fn build(config: DBConfig) -> Result<Self, Error> {
         create_dir_all(&config.sstable_dir)?;
         create_dir_all(&config.write_ahead_log_dir)?;
 
+        let wal = Arc::new(Mutex::new(WriteAheadLog::from_dir(&config)?));
+
+        let lsm = DB::open_column_family(&config, None, &wal)?;
+        let system_config =
+            config.for_column_family(&ColumnFamilyConfig::new(SYSTEM_COLUMN_FAMILY));
+        let system_lsm = DB::open_column_family(&system_config, Some(SYSTEM_COLUMN_FAMILY), &wal)?;
+
+        let mut column_families = HashMap::new();
+        for column_family in &config.column_families {
+            if column_family.name == SYSTEM_COLUMN_FAMILY {
+                return Err(Error::ReservedKey(SYSTEM_COLUMN_FAMILY.to_string()));
+            }
+            if column_families.contains_key(&column_family.name) {
+                return Err(Error::Config(format!(
+                    "Column family {} is configured more than once.",
+                    column_family.name
+                )));
+            }
+
+            let column_family_config = config.for_column_family(column_family);
+            let column_family_lsm =
+                DB::open_column_family(&column_family_config, Some(&column_family.name), &wal)?;
+            column_families.insert(column_family.name.clone(), column_family_lsm);
+        }
+
+        DB::migrate_system_keys(&lsm, &system_lsm)?;
+
+        // The state from the last checkpoint, so a restart doesn't refill the bucket
+        let token_bucket = match system_lsm.get(TOKEN_BUCKET_KEY)? {
+            Some(bytes) => TokenBucket::deserialize(&bytes),
+            None => TokenBucket::new(
+                config.token_bucket_capacity,
+                config.token_bucket_refill_rate,
+            ),
+        };
+
         Ok(DB {
             lsm,
+            system_lsm,
+            column_families,
             config,
-            reserved_key_prefixes: [
-                "bl00m_f1lt3r/".as_bytes(),
-                "c0unt_m1n_$k3tch/".as_bytes(),
-                "hyp3r_l0g_l0g/".as_bytes(),
-                "$1m_ha$h/".as_bytes(),
-                "t0k3n_buck3t/state".as_bytes(),
-            ],
+            token_bucket: Mutex::new(CheckpointedTokenBucket {
+                token_bucket,
+                last_checkpoint: Instant::now(),
+            }),
+            probabilistic_lock: Mutex::new(()),
         })
     }
 
-    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>> {
-        self.system_insert(key, value, true)
+    /// Loads the column family from the shared WAL, or creates an empty one if its records can't be loaded.
+    fn open_column_family(
+        config: &DBConfig,
+        column_family: Option<&str>,
+        wal: &Arc<Mutex<WriteAheadLog>>,
+    ) -> Result<LSM, Error> {
+        match LSM::load_column_family(config, column_family, Arc::clone(wal)) {
+            Ok(lsm) => Ok(lsm),
+            Err(e) => {
+                eprintln!("Error occurred: {}", e);
+                eprintln!("Memory pool wasn't reconstructed.");
+                LSM::new_column_family(config, column_family, Arc::clone(wal))
+            }
+        }
     }
 
-    /// Inserts a new key value pair into the system.
-    fn system_insert(
-        &mut self,
-        key: &[u8],
-        value: &[u8],
-        check_reserved_prefixes: bool,
-    ) -> Result<(), Box<dyn Error>> {
-        if key != "t0k3n_buck3t/state".as_bytes() {
-            if self.token_bucket_take()? {
-                if check_reserved_prefixes {
-                    for forbidden_key_prefix in self.reserved_key_prefixes {
-                        if key.starts_with(forbidden_key_prefix) {
-                            return Err(Box::new(ReservedKeyError {
-                                message: format!(
-                                    "Cannot insert key with system reserved prefix {}.",
-                                    String::from_utf8_lossy(forbidden_key_prefix)
-                                ),
-                            }));
-                        }
-                    }
-                }
-            } else {
-                return Err(From::from(TokenBucketError));
+    /// Moves the probabilistic data structures and the token bucket state of a database written before the system
+    /// column family existed out of the default column family and into the system one. Runs only once per database,
+    /// because afterwards keys with the reserved prefixes in the default column family belong to the user.
+    /// The keys are deleted from the default column family after they are written to the system one, and the
+    /// migration is marked as done last, so a crash in between only repeats it on the next start.
+    fn migrate_system_keys(lsm: &LSM, system_lsm: &LSM) -> Result<(), Error> {
+        if system_lsm.get(SYSTEM_KEYS_MIGRATED_KEY)?.is_some() {
+            return Ok(());
+        }
+
+        let mut system_entries = Vec::new();
+        for prefix in SYSTEM_KEY_PREFIXES {
+            let mut paginator = Paginator::new(lsm);
+            while let Some((key, entry)) = paginator.prefix_iterate_next(prefix)? {
+                system_entries.push((key, entry.get_value()));
             }
         }
+        if let Some(value) = lsm.get(TOKEN_BUCKET_KEY)? {
+            system_entries.push((Box::from(TOKEN_BUCKET_KEY), value));
+        }
+
+        for (key, value) in &system_entries {
+            system_lsm.insert(key, value, TimeStamp::Now)?;
+        }
+        for (key, _) in &system_entries {
+            lsm.delete(key, TimeStamp::Now)?;
+        }
+        system_lsm.insert(SYSTEM_KEYS_MIGRATED_KEY, &[1], TimeStamp::Now)?;
 
-        self.lsm.insert(key, value, TimeStamp::Now)?;
         Ok(())
     }
 
-    pub fn delete(&mut self, key: &[u8]) -> Result<(), Box<dyn Error>> {
-        self.system_delete(key, true)
+    /// Inserts a new key value pair into the system.
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if self.token_bucket_take()? {
+            self.lsm.insert(key, value, TimeStamp::Now)?;
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Inserts a key value pair into the system column family.
+    fn system_insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if self.token_bucket_take()? {
+            self.system_lsm.insert(key, value, TimeStamp::Now)?;
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Inserts a key value pair that expires after the given time to live. Once it expires, the key
+    /// is no longer returned by gets, scans and iterators, and its data is removed during compaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to insert.
+    /// * `value` - The value associated with the key.
+    /// * `ttl` - How long the key lives, counting from the insertion.
+    pub fn insert_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<(), Error> {
+        if self.token_bucket_take()? {
+            self.lsm.insert_with_ttl(key, value, ttl, TimeStamp::Now)?;
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
     }
 
     /// Removes the value that's associated to the given key.
-    fn system_delete(
-        &mut self,
-        key: &[u8],
-        check_reserved_prefixes: bool,
-    ) -> Result<(), Box<dyn Error>> {
+    pub fn delete(&self, key: &[u8]) -> Result<(), Error> {
         if self.token_bucket_take()? {
-            if check_reserved_prefixes {
-                for forbidden_key_prefix in &self.reserved_key_prefixes {
-                    if key.starts_with(forbidden_key_prefix) {
-                        return Err(Box::new(ReservedKeyError {
-                            message: format!(
-                                "Cannot insert key with system reserved prefix {}.",
-                                String::from_utf8_lossy(forbidden_key_prefix)
-                            ),
-                        }));
-                    }
-                }
-            }
             self.lsm.delete(key, TimeStamp::Now)?;
             Ok(())
         } else {
-            Err(From::from(TokenBucketError))
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Removes the value of the key if it has one. The check and the delete happen while writes are
+    /// locked out in the LSM, so of concurrent deletes of the same key exactly one reports the deletion.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the key had a value and was deleted, otherwise false.
+    pub fn delete_if_present(&self, key: &[u8]) -> Result<bool, Error> {
+        if self.token_bucket_take()? {
+            Ok(self.lsm.delete_if_present(key, TimeStamp::Now)?)
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Applies all inserts and deletes of the batch atomically. Either every operation of the batch
+    /// is persisted or none of them are, even if the program crashes in the middle of the write.
+    /// The whole batch takes a single token from the token bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The operations to apply, in the order they were added to the batch.
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or an `Error`.
+    pub fn write_batch(&self, batch: WriteBatch) -> Result<(), Error> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        if !self.token_bucket_take()? {
+            return Err(Error::RateLimited);
         }
+
+        self.lsm.write_batch(&batch, TimeStamp::Now)?;
+        Ok(())
+    }
+
+    /// Atomically replaces the value of the key if its current value matches the expected one.
+    /// The comparison and the write happen while writes are locked out in the LSM,
+    /// so no other write through this handle can happen in between. Takes a single token from the token bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key whose value is compared and swapped.
+    /// * `expected` - The expected current value, `None` if the key is expected to be absent.
+    /// * `new` - The new value, `None` if the key should be deleted.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the current value matched and the new one was written, otherwise false.
+    pub fn compare_and_swap(
+        &self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        if !self.token_bucket_take()? {
+            return Err(Error::RateLimited);
+        }
+
+        Ok(self
+            .lsm
+            .compare_and_swap(key, expected, new, TimeStamp::Now)?)
+    }
+
+    /// Inserts the key value pair only if the key isn't already present.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the pair was inserted, or false if the key already had a value.
+    pub fn insert_if_absent(&self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        self.compare_and_swap(key, None, Some(value))
+    }
+
+    /// Inserts a key value pair that expires after the given time to live, but only if the key isn't already present.
+    /// The check and the insert happen while writes are locked out in the LSM, the same as in `insert_if_absent`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to insert.
+    /// * `value` - The value associated with the key.
+    /// * `ttl` - How long the key lives, counting from the insertion.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the pair was inserted, or false if the key already had a value.
+    pub fn insert_with_ttl_if_absent(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<bool, Error> {
+        if !self.token_bucket_take()? {
+            return Err(Error::RateLimited);
+        }
+
+        Ok(self
+            .lsm
+            .insert_with_ttl_if_absent(key, value, ttl, TimeStamp::Now)?)
     }
 
     /// Retrieves the data that is associated to a given key.
-    pub fn get(&mut self, key: &[u8]) -> Result<Option<Box<[u8]>>, Box<dyn Error>> {
-        self.system_get(key, true)
+    pub fn get(&self, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        if self.token_bucket_take()? {
+            self.lsm.get(key)
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Retrieves the data that is associated to a given key in the system column family.
+    fn system_get(&self, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        if self.token_bucket_take()? {
+            self.system_lsm.get(key)
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Inserts a new key value pair into the given column family.
+    ///
+    /// # Arguments
+    ///
+    /// * `column_family` - The name of the column family from the configuration.
+    /// * `key` - The key to insert.
+    /// * `value` - The value associated with the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnknownColumnFamily` if there is no column family with the given name.
+    pub fn column_family_insert(
+        &self,
+        column_family: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        let column_family_lsm = self.get_column_family(column_family)?;
+        if self.token_bucket_take()? {
+            column_family_lsm.insert(key, value, TimeStamp::Now)?;
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
     }
 
-    fn system_get(
-        &mut self,
+    /// Retrieves the data that is associated to a given key in the given column family.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnknownColumnFamily` if there is no column family with the given name.
+    pub fn column_family_get(
+        &self,
+        column_family: &str,
         key: &[u8],
-        check_reserved_prefixes: bool,
-    ) -> Result<Option<Box<[u8]>>, Box<dyn Error>> {
-        if key == "t0k3n_buck3t/state".as_bytes() {
-            self.lsm.get(key).map_err(|err| From::from(err))
+    ) -> Result<Option<Box<[u8]>>, Error> {
+        let column_family_lsm = self.get_column_family(column_family)?;
+        if self.token_bucket_take()? {
+            column_family_lsm.get(key)
         } else {
-            match self.token_bucket_take() {
-                Ok(true) => {
-                    if check_reserved_prefixes {
-                        for forbidden_key_prefix in &self.reserved_key_prefixes {
-                            if key.starts_with(forbidden_key_prefix) {
-                                return Err(ReservedKeyError {
-                                    message: format!(
-                                        "Cannot insert key with system reserved prefix {}.",
-                                        String::from_utf8_lossy(forbidden_key_prefix)
-                                    ),
-                                }
-                                .into());
-                            }
-                        }
-                    }
-                    self.lsm.get(key).map_err(|err| From::from(err))
-                }
-                _ => Err(From::from(TokenBucketError)),
-            }
+            Err(Error::RateLimited)
         }
     }
 
+    /// Removes the value that's associated to the given key in the given column family.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnknownColumnFamily` if there is no column family with the given name.
+    pub fn column_family_delete(&self, column_family: &str, key: &[u8]) -> Result<(), Error> {
+        let column_family_lsm = self.get_column_family(column_family)?;
+        if self.token_bucket_take()? {
+            column_family_lsm.delete(key, TimeStamp::Now)?;
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Returns the LSM of the column family with the given name.
+    fn get_column_family(&self, column_family: &str) -> Result<&LSM, Error> {
+        self.column_families
+            .get(column_family)
+            .ok_or_else(|| Error::UnknownColumnFamily(column_family.to_string()))
+    }
+
+    /// Takes a point-in-time snapshot of the database. Reads through the snapshot see all writes made
+    /// before this call and none of the writes made after it.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self.lsm.snapshot())
+    }
+
+    /// Retrieves the data that was associated to a given key at the time of the snapshot.
+    pub(crate) fn snapshot_get(
+        &self,
+        snapshot: &LSMSnapshot,
+        key: &[u8],
+    ) -> Result<Option<Box<[u8]>>, Error> {
+        if !self.token_bucket_take()? {
+            return Err(Error::RateLimited);
+        }
+
+        self.lsm.get_at(key, snapshot)
+    }
+
+    /// Merges every SSTable that overlaps the key range down to the last level, so the space taken by deleted
+    /// and expired entries in it is reclaimed right away, instead of waiting for the levels to fill up.
+    /// Blocks until the compaction is done. Entries that are still in memory aren't compacted.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The smallest key of the range, or None if the range is unbounded from below.
+    /// * `end` - The largest key of the range, or None if the range is unbounded from above.
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or an `Error`.
+    pub fn compact_range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<(), Error> {
+        self.lsm.compact_range(start, end)
+    }
+
+    /// Rewrites the SSTables of every column family that were written with an older version of the on-disk format,
+    /// such as the tables written before the files had headers and a fixed byte order. Tables without headers use
+    /// the byte order and the width of `usize` of the machine that wrote them, and are only read in the layout of
+    /// 64-bit little-endian machines, so they can only be opened and upgraded there. On any other machine opening
+    /// them fails with an `Unsupported` error, so this has to run on a 64-bit little-endian machine before the files
+    /// are moved elsewhere. Blocks until all tables are rewritten.
+    ///
+    /// # Returns
+    ///
+    /// Result containing the number of rewritten tables or an `Error`.
+    pub fn upgrade_format(&self) -> Result<usize, Error> {
+        let mut upgraded = 0;
+        for lsm in self.all_lsms() {
+            upgraded += lsm.upgrade_tables()?;
+        }
+
+        Ok(upgraded)
+    }
+
+    /// Changes the number of bytes per second that flushes and compactions of every column family can read
+    /// and write, so they leave disk bandwidth to reads and writes. 0 removes the limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes_per_second` - The new limit.
+    pub fn set_compaction_io_rate_limit(&self, bytes_per_second: usize) {
+        for lsm in self.all_lsms() {
+            lsm.set_compaction_io_rate_limit(bytes_per_second);
+        }
+    }
+
+    /// Turns the auto-tuning of the compaction I/O rate limit on or off. While it's on, flushes and compactions
+    /// slow down below the limit while reads and writes are frequent.
+    ///
+    /// # Arguments
+    ///
+    /// * `auto_tune` - Whether the rate limit is auto-tuned.
+    pub fn set_compaction_io_auto_tune(&self, auto_tune: bool) {
+        for lsm in self.all_lsms() {
+            lsm.set_compaction_io_auto_tune(auto_tune);
+        }
+    }
+
+    /// Returns the LSMs of all column families, including the system one.
+    fn all_lsms(&self) -> impl Iterator<Item = &LSM> {
+        [&self.lsm, &self.system_lsm]
+            .into_iter()
+            .chain(self.column_families.values())
+    }
+
     /// Should be called before the program exit to gracefully finish all memory tables writes,
     /// SStable merges and compactions.
     pub fn shut_down(self) {
+        if let Err(e) = self.checkpoint_token_bucket(&mut self.token_bucket.lock().unwrap()) {
+            eprintln!("Failed to checkpoint the token bucket: {}", e);
+        }
+
         self.lsm.finalize();
+        self.system_lsm.finalize();
+        for (_, column_family_lsm) in self.column_families {
+            column_family_lsm.finalize();
+        }
     }
 
     /// Creates a Bloom filter for the specified key, then returns its serialized representation.
@@ -173,19 +500,19 @@ impl DB {
     ///
     /// # Errors
     ///
-    /// Returns a Boxed Error if there is an issue creating or serializing the Bloom filter.
+    /// Returns an `Error` if there is an issue creating or serializing the Bloom filter.
     pub fn bloom_filter_create(
-        &mut self,
+        &self,
         key: &[u8],
         probability: Option<f64>,
         cap: Option<usize>,
-    ) -> Result<Box<[u8]>, Box<dyn Error>> {
+    ) -> Result<Box<[u8]>, Error> {
         let probability = probability.unwrap_or(self.config.bloom_filter_probability);
         let cap = cap.unwrap_or(self.config.bloom_filter_cap);
         let bloom_filter = BloomFilter::new(probability, cap);
 
         let combined_key = self.get_combined_key(key, 0);
-        self.system_insert(&combined_key, bloom_filter.serialize().as_ref(), false)?;
+        self.system_insert(&combined_key, bloom_filter.serialize().as_ref())?;
 
         self.get_probabilistic_ds_bytes(&combined_key)
     }
@@ -199,7 +526,7 @@ impl DB {
     /// # Returns
     ///
     /// An `Option` containing the value associated with the key, or `None` if the key is not present.
-    pub fn bloom_filter_get(&mut self, key: &[u8]) -> Result<Option<Box<[u8]>>, Box<dyn Error>> {
+    pub fn bloom_filter_get(&self, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
         self.reserved_get(key, 0)
     }
 
@@ -212,21 +539,55 @@ impl DB {
     ///
     /// # Returns
     ///
-    /// Result indicating success or an error wrapped in a `Box<dyn Error>`.
-    pub fn bloom_filter_insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>> {
+    /// Result indicating success or an `Error`.
+    pub fn bloom_filter_insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let _guard = self.probabilistic_lock.lock().unwrap();
         let combined_key = self.get_combined_key(key, 0);
         let bf_bytes = self.get_probabilistic_ds_bytes(combined_key.as_slice())?;
 
         let mut bloom_filter = match BloomFilter::deserialize(&bf_bytes) {
             Ok(filter) => filter,
             Err(err) => {
-                return Err(Box::new(err));
+                return Err(err.into());
             }
         };
 
         bloom_filter.add(value);
 
-        self.system_insert(&combined_key, bloom_filter.serialize().as_ref(), false)
+        self.system_insert(&combined_key, bloom_filter.serialize().as_ref())
+    }
+
+    /// Inserts a new value into the Bloom Filter associated with the given key, first creating the filter
+    /// from the database configuration if it doesn't exist. The creation and the insertion happen under one lock,
+    /// so concurrent calls can't overwrite each other's filters.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key for which to insert the value.
+    /// * `value` - The value to insert into the Bloom Filter.
+    ///
+    /// # Returns
+    ///
+    /// Result containing true if the value wasn't already likely present, or an `Error`.
+    pub fn bloom_filter_add(&self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        let _guard = self.probabilistic_lock.lock().unwrap();
+        let combined_key = self.get_combined_key(key, 0);
+
+        let mut bloom_filter = match self.system_get(&combined_key)? {
+            Some(bf_bytes) => BloomFilter::deserialize(&bf_bytes)?,
+            None => BloomFilter::new(
+                self.config.bloom_filter_probability,
+                self.config.bloom_filter_cap,
+            ),
+        };
+        if bloom_filter.contains(value) {
+            return Ok(false);
+        }
+
+        bloom_filter.add(value);
+        self.system_insert(&combined_key, bloom_filter.serialize().as_ref())?;
+
+        Ok(true)
     }
 
     /// Checks if the given value is likely present in the Bloom Filter associated with the key.
@@ -239,24 +600,20 @@ impl DB {
     /// # Returns
     ///
     /// Result indicating whether the value is likely present (`Ok(true)`) or not present (`Ok(false)`),
-    /// or an error wrapped in a `Box<dyn Error>`.
-    pub fn bloom_filter_contains(
-        &mut self,
-        key: &[u8],
-        value: &[u8],
-    ) -> Result<bool, Box<dyn Error>> {
+    /// or an `Error`.
+    pub fn bloom_filter_contains(&self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
         return if self.token_bucket_take()? {
             let combined_key = self.get_combined_key(key, 0);
             let bf_bytes = self.get_probabilistic_ds_bytes(combined_key.as_slice())?;
 
             let bloom_filter = match BloomFilter::deserialize(&bf_bytes) {
                 Ok(filter) => filter,
-                Err(err) => return Err(Box::new(err)),
+                Err(err) => return Err(err.into()),
             };
 
             Ok(bloom_filter.contains(value))
         } else {
-            Err(From::from(TokenBucketError))
+            Err(Error::RateLimited)
         };
     }
 
@@ -276,19 +633,19 @@ impl DB {
     ///
     /// # Errors
     ///
-    /// Returns a Boxed Error if there is an issue creating or serializing the count-min sketch.
+    /// Returns an `Error` if there is an issue creating or serializing the count-min sketch.
     pub fn count_min_sketch_create(
-        &mut self,
+        &self,
         key: &[u8],
         probability: Option<f64>,
         tolerance: Option<f64>,
-    ) -> Result<Box<[u8]>, Box<dyn Error>> {
+    ) -> Result<Box<[u8]>, Error> {
         let probability = probability.unwrap_or(self.config.count_min_sketch_probability);
         let tolerance = tolerance.unwrap_or(self.config.count_min_sketch_tolerance);
         let count_min_sketch = CMSketch::new(probability, tolerance);
 
         let combined_key = self.get_combined_key(key, 1);
-        self.system_insert(&combined_key, count_min_sketch.serialize().as_ref(), false)?;
+        self.system_insert(&combined_key, count_min_sketch.serialize().as_ref())?;
 
         self.get_probabilistic_ds_bytes(&combined_key)
     }
@@ -302,10 +659,7 @@ impl DB {
     /// # Returns
     ///
     /// An `Option` containing the value associated with the key, or `None` if the key is not present.
-    pub fn count_min_sketch_get(
-        &mut self,
-        key: &[u8],
-    ) -> Result<Option<Box<[u8]>>, Box<dyn Error>> {
+    pub fn count_min_sketch_get(&self, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
         self.reserved_get(key, 1)
     }
 
@@ -318,20 +672,77 @@ impl DB {
     ///
     /// # Returns
     ///
-    /// Result indicating success or an error wrapped in a `Box<dyn Error>`.
-    pub fn count_min_sketch_increase_count(
-        &mut self,
+    /// Result indicating success or an `Error`.
+    pub fn count_min_sketch_increase_count(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.count_min_sketch_increase_count_by(key, value, 1)
+    }
+
+    /// Increases the count associated with the given value in the Count-Min Sketch by the given amount.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key for which to increase the count of the value.
+    /// * `value` - The value for which to increment the count.
+    /// * `count` - The amount to add to the count of the value.
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or an `Error`.
+    pub fn count_min_sketch_increase_count_by(
+        &self,
         key: &[u8],
         value: &[u8],
-    ) -> Result<(), Box<dyn Error>> {
+        count: u64,
+    ) -> Result<(), Error> {
+        let _guard = self.probabilistic_lock.lock().unwrap();
         let combined_key = self.get_combined_key(key, 1);
         let cms_bytes = self.get_probabilistic_ds_bytes(combined_key.as_slice())?;
 
         let mut count_min_sketch = CMSketch::deserialize(&cms_bytes);
 
-        count_min_sketch.increase_count(value);
+        count_min_sketch.increase_count_by(value, count);
 
-        self.system_insert(&combined_key, count_min_sketch.serialize().as_ref(), false)
+        self.system_insert(&combined_key, count_min_sketch.serialize().as_ref())
+    }
+
+    /// Increases the counts of the given values in the Count-Min Sketch associated with the given key, first creating
+    /// the sketch from the database configuration if it doesn't exist. The creation and all increases happen under one lock,
+    /// so concurrent calls can't overwrite each other's sketches.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key for which to increase the counts.
+    /// * `increments` - The values together with the amounts to add to their counts.
+    ///
+    /// # Returns
+    ///
+    /// Result containing the count of each value after its increase, or an `Error`.
+    pub fn count_min_sketch_add(
+        &self,
+        key: &[u8],
+        increments: &[(&[u8], u64)],
+    ) -> Result<Vec<u64>, Error> {
+        let _guard = self.probabilistic_lock.lock().unwrap();
+        let combined_key = self.get_combined_key(key, 1);
+
+        let mut count_min_sketch = match self.system_get(&combined_key)? {
+            Some(cms_bytes) => CMSketch::deserialize(&cms_bytes),
+            None => CMSketch::new(
+                self.config.count_min_sketch_probability,
+                self.config.count_min_sketch_tolerance,
+            ),
+        };
+
+        let counts = increments
+            .iter()
+            .map(|(value, count)| {
+                count_min_sketch.increase_count_by(value, *count);
+                count_min_sketch.get_count(value)
+            })
+            .collect();
+        self.system_insert(&combined_key, count_min_sketch.serialize().as_ref())?;
+
+        Ok(counts)
     }
 
     /// Gets the count associated with the given value in the Count-Min Sketch.
@@ -343,12 +754,8 @@ impl DB {
     ///
     /// # Returns
     ///
-    /// Result containing the count associated with the value or an error wrapped in a `Box<dyn Error>`.
-    pub fn count_min_sketch_get_count(
-        &mut self,
-        key: &[u8],
-        value: &[u8],
-    ) -> Result<u64, Box<dyn Error>> {
+    /// Result containing the count associated with the value or an `Error`.
+    pub fn count_min_sketch_get_count(&self, key: &[u8], value: &[u8]) -> Result<u64, Error> {
         if self.token_bucket_take()? {
             let combined_key = self.get_combined_key(key, 1);
             let cms_bytes = self.get_probabilistic_ds_bytes(combined_key.as_slice())?;
@@ -357,7 +764,7 @@ impl DB {
 
             Ok(count_min_sketch.get_count(&value))
         } else {
-            Err(From::from(TokenBucketError))
+            Err(Error::RateLimited)
         }
     }
 
@@ -375,17 +782,17 @@ impl DB {
     ///
     /// # Errors
     ///
-    /// Returns a Boxed Error if there is an issue creating or serializing the hyperloglog.
+    /// Returns an `Error` if there is an issue creating or serializing the hyperloglog.
     pub fn hyperloglog_create(
-        &mut self,
+        &self,
         key: &[u8],
         precision: Option<u32>,
-    ) -> Result<Box<[u8]>, Box<dyn Error>> {
+    ) -> Result<Box<[u8]>, Error> {
         let precision = precision.unwrap_or(self.config.hyperloglog_precision);
         let hyperloglog = HLL::new(precision);
 
         let combined_key = self.get_combined_key(key, 2);
-        self.system_insert(&combined_key, hyperloglog.serialize().as_ref(), false)?;
+        self.system_insert(&combined_key, hyperloglog.serialize().as_ref())?;
 
         self.get_probabilistic_ds_bytes(&combined_key)
     }
@@ -399,7 +806,7 @@ impl DB {
     /// # Returns
     ///
     /// An `Option` containing the value associated with the key, or `None` if the key is not present.
-    pub fn hyperloglog_get(&mut self, key: &[u8]) -> Result<Option<Box<[u8]>>, Box<dyn Error>> {
+    pub fn hyperloglog_get(&self, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
         self.reserved_get(key, 2)
     }
 
@@ -412,12 +819,9 @@ impl DB {
     ///
     /// # Returns
     ///
-    /// Result indicating success or an error wrapped in a `Box<dyn Error>`.
-    pub fn hyperloglog_increase_count(
-        &mut self,
-        key: &[u8],
-        value: &[u8],
-    ) -> Result<(), Box<dyn Error>> {
+    /// Result indicating success or an `Error`.
+    pub fn hyperloglog_increase_count(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let _guard = self.probabilistic_lock.lock().unwrap();
         let combined_key = self.get_combined_key(key, 2);
         let hll_bytes = self.get_probabilistic_ds_bytes(combined_key.as_slice())?;
 
@@ -425,7 +829,42 @@ impl DB {
 
         hyperloglog.add_to_count(&value);
 
-        self.system_insert(&combined_key, hyperloglog.serialize().as_ref(), false)
+        self.system_insert(&combined_key, hyperloglog.serialize().as_ref())
+    }
+
+    /// Adds the given values to the HyperLogLog associated with the given key, first creating it from the database
+    /// configuration if it doesn't exist. The creation and all additions happen under one lock,
+    /// so concurrent calls can't overwrite each other's HyperLogLogs.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key for which to add the values.
+    /// * `values` - The values to add to the HyperLogLog.
+    ///
+    /// # Returns
+    ///
+    /// Result containing true if the HyperLogLog was created or changed, or an `Error`.
+    pub fn hyperloglog_add(&self, key: &[u8], values: &[&[u8]]) -> Result<bool, Error> {
+        let _guard = self.probabilistic_lock.lock().unwrap();
+        let combined_key = self.get_combined_key(key, 2);
+
+        let before = self.system_get(&combined_key)?;
+        let mut hyperloglog = match &before {
+            Some(hll_bytes) => HLL::deserialize(hll_bytes),
+            None => HLL::new(self.config.hyperloglog_precision),
+        };
+
+        for value in values {
+            hyperloglog.add_to_count(value);
+        }
+        let after = hyperloglog.serialize();
+        if before.as_deref() == Some(after.as_ref()) {
+            return Ok(false);
+        }
+
+        self.system_insert(&combined_key, after.as_ref())?;
+
+        Ok(true)
     }
 
     /// Gets the count estimated by the HyperLogLog.
@@ -436,8 +875,8 @@ impl DB {
     ///
     /// # Returns
     ///
-    /// Result containing the estimated count or an error wrapped in a `Box<dyn Error>`.
-    pub fn hyperloglog_get_count(&mut self, key: &[u8]) -> Result<u64, Box<dyn Error>> {
+    /// Result containing the estimated count or an `Error`.
+    pub fn hyperloglog_get_count(&self, key: &[u8]) -> Result<u64, Error> {
         if self.token_bucket_take()? {
             let combined_key = self.get_combined_key(key, 2);
             let hll_bytes = self.get_probabilistic_ds_bytes(combined_key.as_slice())?;
@@ -446,7 +885,7 @@ impl DB {
 
             Ok(hyperloglog.get_count())
         } else {
-            Err(From::from(TokenBucketError))
+            Err(Error::RateLimited)
         }
     }
 
@@ -474,21 +913,17 @@ impl DB {
     /// # Returns
     ///
     /// An `Option` containing the value associated with the key, or `None` if the key is not present.
-    fn reserved_get(
-        &mut self,
-        key: &[u8],
-        index: usize,
-    ) -> Result<Option<Box<[u8]>>, Box<dyn Error>> {
+    fn reserved_get(&self, key: &[u8], index: usize) -> Result<Option<Box<[u8]>>, Error> {
         let combined_key = self.get_combined_key(key, index);
 
-        if let Some(value) = self.system_get(&combined_key, false)? {
+        if let Some(value) = self.system_get(&combined_key)? {
             return Ok(Some(value));
         }
 
         Ok(None)
     }
 
-    /// Combines the given key with a reserved key prefix based on the specified index.
+    /// Combines the given key with a system key prefix based on the specified index.
     ///
     /// # Arguments
     ///
@@ -497,9 +932,9 @@ impl DB {
     ///
     /// # Returns
     ///
-    /// The combined key, which includes a reserved key prefix and the provided key.
+    /// The combined key, which includes a system key prefix and the provided key.
     fn get_combined_key(&self, key: &[u8], index: usize) -> Vec<u8> {
-        let mut combined_key = self.reserved_key_prefixes[index].to_vec();
+        let mut combined_key = SYSTEM_KEY_PREFIXES[index].to_vec();
         combined_key.extend_from_slice(key);
 
         combined_key
@@ -514,52 +949,65 @@ impl DB {
     /// # Returns
     ///
     /// Result containing the serialized bytes of the probabilistic data structure or an error
-    /// wrapped in a `Box<dyn Error>`.
-    fn get_probabilistic_ds_bytes(
-        &mut self,
-        combined_key: &[u8],
-    ) -> Result<Box<[u8]>, Box<dyn Error>> {
-        match self.system_get(combined_key, false)? {
+    /// as an `Error`.
+    fn get_probabilistic_ds_bytes(&self, combined_key: &[u8]) -> Result<Box<[u8]>, Error> {
+        match self.system_get(combined_key)? {
             Some(bytes) => Ok(bytes),
-            None => Err(Box::new(ProbabilisticTypeError {
-                message: format!(
-                    "Failed to get probabilistic structure with combined key {}.",
-                    String::from_utf8_lossy(combined_key)
-                ),
-            })),
+            None => Err(Error::MissingProbabilisticStructure(
+                String::from_utf8_lossy(combined_key).to_string(),
+            )),
         }
     }
 
     /// Takes tokens from the token bucket, updating its state.
     ///
     /// This function controls the rate of operations by allowing or
-    /// disallowing based on token availability.
+    /// disallowing based on token availability. The state is kept in memory
+    /// and written to the system column family once the checkpoint interval passes.
     ///
     /// # Returns
     ///
     /// A result indicating whether tokens were successfully taken (`Ok(true)`)
     /// or if an error occurred (`Err`).
-    pub fn token_bucket_take(&mut self) -> Result<bool, Box<dyn Error>> {
-        let mut token_bucket = match self.system_get("t0k3n_buck3t/state".as_bytes(), false)? {
-            Some(bytes) => TokenBucket::deserialize(&bytes),
-            None => TokenBucket::new(
-                self.config.token_bucket_capacity,
-                self.config.token_bucket_refill_rate,
-            ),
-        };
+    pub fn token_bucket_take(&self) -> Result<bool, Error> {
+        let mut token_bucket = self.token_bucket.lock().unwrap();
+        let token_taken = token_bucket.token_bucket.take(1);
+
+        let checkpoint_interval =
+            Duration::from_millis(self.config.token_bucket_checkpoint_interval_ms as u64);
+        if token_bucket.last_checkpoint.elapsed() >= checkpoint_interval {
+            self.checkpoint_token_bucket(&mut token_bucket)?;
+        }
 
-        let token_taken = token_bucket.take(1);
+        Ok(token_taken)
+    }
 
-        self.system_insert(
-            "t0k3n_buck3t/state".as_bytes(),
-            token_bucket.serialize().as_ref(),
-            false,
+    /// Writes the token bucket state to the system column family, so it survives a restart.
+    fn checkpoint_token_bucket(
+        &self,
+        token_bucket: &mut CheckpointedTokenBucket,
+    ) -> Result<(), Error> {
+        // Written directly, because taking a token for the write would lock the token bucket again
+        self.system_lsm.insert(
+            TOKEN_BUCKET_KEY,
+            token_bucket.token_bucket.serialize().as_ref(),
+            TimeStamp::Now,
         )?;
+        token_bucket.last_checkpoint = Instant::now();
 
-        Ok(token_taken)
+        Ok(())
+    }
+
+    pub fn get_paginator(&self) -> Paginator<'_> {
+        Paginator::new(&self.lsm)
+    }
+
+    /// Returns a paginator over the given column family.
+    pub fn get_column_family_paginator(&self, column_family: &str) -> Result<Paginator<'_>, Error> {
+        Ok(Paginator::new(self.get_column_family(column_family)?))
     }
 
-    pub fn get_paginator(&mut self) -> Paginator {
-        Paginator::new(&mut self.lsm)
+    pub(crate) fn get_snapshot_paginator<'a>(&'a self, snapshot: &'a LSMSnapshot) -> Paginator<'a> {
+        Paginator::with_snapshot(&self.lsm, snapshot)
     }
 }
@@ -1,11 +1,12 @@
 mod db;
-mod probabilistic_type_error;
-mod reserved_key_error;
-mod token_bucket_error;
+mod server;
+mod snapshot;
 
 pub use db::DB;
-pub use probabilistic_type_error::ProbabilisticTypeError;
-pub use reserved_key_error::ReservedKeyError;
+pub use db_error::Error;
+pub use segment_elements::{BatchOperation, WriteBatch};
+pub use server::serve;
+pub use snapshot::Snapshot;
 
 pub fn dummy_fn() {
     println!("Hello from lib.rs")
@@ -28,7 +28,7 @@ impl_menu!(
     "Back".yellow().italic()
 );
 
-pub fn bloom_filter_menu(db: &mut DB) {
+pub fn bloom_filter_menu(db: &DB) {
     loop {
         match BloomFilterMenu::get_menu() {
             BloomFilterMenu::CreateRetrieve => {
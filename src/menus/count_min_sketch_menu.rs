@@ -28,7 +28,7 @@ impl_menu!(
     "Back".yellow().italic()
 );
 
-pub fn count_min_sketch_menu(db: &mut DB) {
+pub fn count_min_sketch_menu(db: &DB) {
     loop {
         match CountMinSketchMenu::get_menu() {
             CountMinSketchMenu::Create => {
@@ -2,7 +2,9 @@ use crate::impl_menu;
 use crate::menus::{get_input_with_range, UserMenu};
 use clearscreen;
 use colored::Colorize;
-use db_config::{CompactionAlgorithmType, DBConfig, MemoryTableType};
+use db_config::{
+    BlockCodecType, CompactionAlgorithmType, DBConfig, MemoryTableType, PrefixExtractorType,
+};
 use enum_iterator::Sequence;
 use inquire::{Confirm, Select};
 use std::io;
@@ -29,6 +31,11 @@ enum CustomizeMenu {
     SummaryDensity,
     SSTableSingleFile,
     SSTableDir,
+    SSTableBlockSize,
+    SSTableBlockCodec,
+    SSTableCacheCapacity,
+    SSTableUseMmap,
+    SSTablePrefixExtractor,
     LsmMaxLevel,
     LsmMaxPerLevel,
     CompactionEnabled,
@@ -36,9 +43,16 @@ enum CustomizeMenu {
     CacheMaxSize,
     TokenBucketCap,
     TokenBucketRefillRate,
+    TokenBucketCheckpointInterval,
     UseCompression,
     CompressionDictionaryPath,
     LsmLeveledAmplification,
+    LsmLeveledBaseSize,
+    LsmTombstoneCompactionRatio,
+    LsmTimeWindow,
+    LsmFifoMaxSize,
+    LsmCompactionIoRateLimit,
+    LsmCompactionIoAutoTune,
     UseVariableEncoding,
 }
 
@@ -81,12 +95,34 @@ impl_menu!(
     "SSTable Single File".blink(),
     CustomizeMenu::SSTableDir,
     "SSTable Directory".blink(),
+    CustomizeMenu::SSTableBlockSize,
+    "SSTable Block Size".blink(),
+    CustomizeMenu::SSTableBlockCodec,
+    "SSTable Block Codec".blink(),
+    CustomizeMenu::SSTableCacheCapacity,
+    "SSTable Cache Capacity".blink(),
+    CustomizeMenu::SSTableUseMmap,
+    "SSTable Memory-Mapped Reads".blink(),
+    CustomizeMenu::SSTablePrefixExtractor,
+    "SSTable Prefix Extractor".blink(),
     CustomizeMenu::LsmMaxLevel,
     "LSM Max Level".blink(),
     CustomizeMenu::LsmMaxPerLevel,
     "LSM Max Per Level".blink(),
     CustomizeMenu::LsmLeveledAmplification,
     "LSM Leveled Amplification".blink(),
+    CustomizeMenu::LsmLeveledBaseSize,
+    "LSM Leveled Base Size".blink(),
+    CustomizeMenu::LsmTombstoneCompactionRatio,
+    "LSM Tombstone Compaction Ratio".blink(),
+    CustomizeMenu::LsmTimeWindow,
+    "LSM Time Window".blink(),
+    CustomizeMenu::LsmFifoMaxSize,
+    "LSM FIFO Max Size".blink(),
+    CustomizeMenu::LsmCompactionIoRateLimit,
+    "LSM Compaction I/O Rate Limit".blink(),
+    CustomizeMenu::LsmCompactionIoAutoTune,
+    "LSM Compaction I/O Auto Tune".blink(),
     CustomizeMenu::CompactionEnabled,
     "Compaction Enabled".blink(),
     CustomizeMenu::CompactionAlgorithmType,
@@ -97,6 +133,8 @@ impl_menu!(
     "Token Bucket Number".blink(),
     CustomizeMenu::TokenBucketRefillRate,
     "Token Bucket Interval".blink(),
+    CustomizeMenu::TokenBucketCheckpointInterval,
+    "Token Bucket Checkpoint Interval".blink(),
     CustomizeMenu::UseCompression,
     "Use Compression".blink(),
     CustomizeMenu::UseVariableEncoding,
@@ -326,6 +364,102 @@ pub fn customize_menu(dbconfig: &mut DBConfig) {
                     println!("Error: Path does not exist.");
                 }
             }
+            CustomizeMenu::SSTableBlockSize => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let new_value =
+                    get_input_with_range("Enter new SSTable block size (bytes): ", 256, 16_777_216);
+                dbconfig.sstable_block_size = new_value;
+                println!("SSTable block size changed to {}", new_value);
+            }
+            CustomizeMenu::SSTableBlockCodec => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let codec_choices = vec!["None".to_string(), "Lz4".to_string()];
+
+                let choice = Select::new("Select SSTable block codec:", codec_choices).prompt();
+                let choice_str = choice
+                    .as_ref()
+                    .map(|s| s.as_str())
+                    .unwrap_or("Invalid Selection");
+                let block_codec = match choice_str {
+                    "None" => BlockCodecType::None,
+                    "Lz4" => BlockCodecType::Lz4,
+                    _ => {
+                        println!("Invalid selection");
+                        continue;
+                    }
+                };
+
+                dbconfig.sstable_block_codec = block_codec;
+                println!("Set SSTable block codec to {}", block_codec);
+            }
+            CustomizeMenu::SSTableCacheCapacity => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let new_value =
+                    get_input_with_range("Enter new SSTable cache capacity (tables): ", 0, 10_000);
+                dbconfig.sstable_cache_capacity = new_value;
+                println!("SSTable cache capacity changed to {}", new_value);
+            }
+            CustomizeMenu::SSTableUseMmap => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let is_enabled = Confirm::new("Enable memory-mapped sstable reads?")
+                    .with_default(false)
+                    .prompt();
+
+                match is_enabled {
+                    Ok(true) => {
+                        dbconfig.sstable_use_mmap = true;
+                        println!("SSTable memory-mapped reads enabled.")
+                    }
+                    Ok(false) => {
+                        dbconfig.sstable_use_mmap = false;
+                        println!("SSTable memory-mapped reads disabled.")
+                    }
+                    Err(_) => println!("Error, try again."),
+                }
+            }
+            CustomizeMenu::SSTablePrefixExtractor => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let extractor_choices = vec![
+                    "None".to_string(),
+                    "FixedLength".to_string(),
+                    "Delimiter".to_string(),
+                ];
+
+                let choice =
+                    Select::new("Select SSTable prefix extractor:", extractor_choices).prompt();
+                let choice_str = choice
+                    .as_ref()
+                    .map(|s| s.as_str())
+                    .unwrap_or("Invalid Selection");
+                let prefix_extractor = match choice_str {
+                    "None" => PrefixExtractorType::None,
+                    "FixedLength" => PrefixExtractorType::FixedLength(get_input_with_range(
+                        "Enter prefix length: ",
+                        1,
+                        1024,
+                    )),
+                    "Delimiter" => {
+                        println!("Enter prefix delimiter:");
+
+                        let mut input = String::new();
+                        io::stdin().read_line(&mut input).unwrap();
+                        match input.trim_end_matches(['\r', '\n']).as_bytes() {
+                            [delimiter] => PrefixExtractorType::Delimiter(*delimiter),
+                            _ => {
+                                println!("Error: The delimiter has to be a single byte.");
+                                continue;
+                            }
+                        }
+                    }
+                    _ => {
+                        println!("Invalid selection");
+                        continue;
+                    }
+                };
+
+                dbconfig.sstable_prefix_extractor = prefix_extractor;
+                println!("Set SSTable prefix extractor to {}", prefix_extractor);
+            }
             CustomizeMenu::LsmMaxLevel => {
                 clearscreen::clear().expect("Failed to clear screen.");
                 let new_value = get_input_with_range("Enter new LSM max level: ", 0, 10);
@@ -345,6 +479,85 @@ pub fn customize_menu(dbconfig: &mut DBConfig) {
                 dbconfig.lsm_leveled_amplification = new_value;
                 println!("LSM leveled amplification changed to {}", new_value);
             }
+            CustomizeMenu::LsmLeveledBaseSize => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let new_value = get_input_with_range(
+                    "Enter new LSM leveled base size (bytes): ",
+                    65_536,
+                    1_099_511_627_776,
+                );
+                dbconfig.lsm_leveled_base_size = new_value;
+                println!("LSM leveled base size changed to {}", new_value);
+            }
+            CustomizeMenu::LsmTombstoneCompactionRatio => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                loop {
+                    print!("Enter new LSM tombstone compaction ratio (0-1, above 1 disables it): ");
+                    io::stdout().flush().unwrap();
+
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input).unwrap();
+
+                    match input.trim().parse::<f64>() {
+                        Ok(value) if value >= 0.0 => {
+                            dbconfig.lsm_tombstone_compaction_ratio = value;
+                            println!("LSM tombstone compaction ratio changed to {}", value);
+                            break;
+                        }
+                        _ => {
+                            println!("Invalid input. Please enter a valid non-negative number.")
+                        }
+                    }
+                }
+            }
+            CustomizeMenu::LsmTimeWindow => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let new_value = get_input_with_range(
+                    "Enter new LSM time window (milliseconds): ",
+                    1_000,
+                    2_592_000_000,
+                );
+                dbconfig.lsm_time_window_ms = new_value;
+                println!("LSM time window changed to {}", new_value);
+            }
+            CustomizeMenu::LsmFifoMaxSize => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let new_value = get_input_with_range(
+                    "Enter new LSM FIFO max size (bytes): ",
+                    1_048_576,
+                    1_099_511_627_776,
+                );
+                dbconfig.lsm_fifo_max_size = new_value;
+                println!("LSM FIFO max size changed to {}", new_value);
+            }
+            CustomizeMenu::LsmCompactionIoRateLimit => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let new_value = get_input_with_range(
+                    "Enter new LSM compaction I/O rate limit (bytes per second, 0 for no limit): ",
+                    0,
+                    1_099_511_627_776,
+                );
+                dbconfig.lsm_compaction_io_rate_limit = new_value;
+                println!("LSM compaction I/O rate limit changed to {}", new_value);
+            }
+            CustomizeMenu::LsmCompactionIoAutoTune => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let is_enabled = Confirm::new("Enable compaction I/O auto tuning?")
+                    .with_default(false)
+                    .prompt();
+
+                match is_enabled {
+                    Ok(true) => {
+                        println!("Compaction I/O auto tuning enabled.");
+                        dbconfig.lsm_compaction_io_auto_tune = true
+                    }
+                    Ok(false) => {
+                        println!("Compaction I/O auto tuning disabled.");
+                        dbconfig.lsm_compaction_io_auto_tune = false
+                    }
+                    Err(_) => println!("Error, try again."),
+                }
+            }
             CustomizeMenu::CompactionEnabled => {
                 clearscreen::clear().expect("Failed to clear screen.");
                 let is_enabled = Confirm::new("Enable compaction?")
@@ -359,8 +572,12 @@ pub fn customize_menu(dbconfig: &mut DBConfig) {
             }
             CustomizeMenu::CompactionAlgorithmType => {
                 clearscreen::clear().expect("Failed to clear screen.");
-                let compaction_algorithm_choices =
-                    vec!["SizeTiered".to_string(), "Leveled".to_string()];
+                let compaction_algorithm_choices = vec![
+                    "SizeTiered".to_string(),
+                    "Leveled".to_string(),
+                    "TimeWindow".to_string(),
+                    "Fifo".to_string(),
+                ];
 
                 let choice = Select::new(
                     "Select compaction algorithm type:",
@@ -375,6 +592,8 @@ pub fn customize_menu(dbconfig: &mut DBConfig) {
                 let compaction_algorithm_type = match choice_str {
                     "SizeTiered" => CompactionAlgorithmType::SizeTiered,
                     "Leveled" => CompactionAlgorithmType::Leveled,
+                    "TimeWindow" => CompactionAlgorithmType::TimeWindow,
+                    "Fifo" => CompactionAlgorithmType::Fifo,
                     _ => {
                         println!("Invalid selection");
                         continue;
@@ -409,6 +628,16 @@ pub fn customize_menu(dbconfig: &mut DBConfig) {
                 dbconfig.token_bucket_refill_rate = new_value;
                 println!("Token bucket refill rate changed to {}", new_value);
             }
+            CustomizeMenu::TokenBucketCheckpointInterval => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let new_value = get_input_with_range(
+                    "Enter new Token bucket checkpoint interval (milliseconds): ",
+                    0,
+                    60_000,
+                );
+                dbconfig.token_bucket_checkpoint_interval_ms = new_value;
+                println!("Token bucket checkpoint interval changed to {}", new_value);
+            }
             CustomizeMenu::UseCompression => {
                 clearscreen::clear().expect("Failed to clear screen.");
                 let is_enabled = Confirm::new("Enable compression?")
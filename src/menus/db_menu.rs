@@ -6,16 +6,18 @@ use crate::menus::prefix_iter_menu::prefix_iter_menu;
 use crate::menus::prefix_scan_menu::prefix_scan_menu;
 use crate::menus::range_iter_menu::range_iter_menu;
 use crate::menus::range_scan_menu::range_scan_menu;
-use crate::menus::{get_input_u8, UserMenu};
+use crate::menus::{get_input_u32, get_input_u8, UserMenu};
 use colored::Colorize;
 use db_config::DBConfig;
 use enum_iterator::Sequence;
 use inquire::Text;
+use std::time::Duration;
 use NoSQLDB::DB;
 
 #[derive(Sequence)]
 enum DBMenu {
     Insert,
+    InsertWithTTL,
     Get,
     Delete,
     BloomFilter,
@@ -34,6 +36,8 @@ impl_menu!(
     "MAIN MENU",
     DBMenu::Insert,
     "Insert".blink(),
+    DBMenu::InsertWithTTL,
+    "Insert with TTL".blink(),
     DBMenu::Get,
     "Get".blink(),
     DBMenu::Delete,
@@ -59,7 +63,7 @@ impl_menu!(
 );
 
 pub fn db_menu(dbconfig: &mut DBConfig) {
-    let mut db = match DB::build(dbconfig.clone()) {
+    let db = match DB::build(dbconfig.clone()) {
         Ok(db) => db,
         Err(err) => {
             eprintln!("Error building DB: {}", err);
@@ -89,6 +93,32 @@ pub fn db_menu(dbconfig: &mut DBConfig) {
                     Err(err) => eprintln!("Error during insertion: {}", err),
                 }
             }
+            DBMenu::InsertWithTTL => {
+                clearscreen::clear().expect("Failed to clear screen.");
+                let key = get_input_u8("Enter key: ");
+                let value = get_input_u8("Enter value: ");
+                let ttl = get_input_u32("Enter time to live in seconds: ");
+                if key.is_none() {
+                    println!("Failed to serialize key into bytes.");
+                    continue;
+                }
+                if value.is_none() {
+                    println!("Failed to serialize value into bytes.");
+                    continue;
+                }
+                if ttl.is_none() {
+                    println!("Failed to parse time to live.");
+                    continue;
+                }
+
+                let key = &key.unwrap();
+                let value = &value.unwrap();
+                let ttl = Duration::from_secs(ttl.unwrap() as u64);
+                match db.insert_with_ttl(key, value, ttl) {
+                    Ok(()) => println!("Insertion successful"),
+                    Err(err) => eprintln!("Error during insertion: {}", err),
+                }
+            }
             DBMenu::Get => {
                 clearscreen::clear().expect("Failed to clear screen.");
                 let key = get_input_u8("Enter key: ");
@@ -123,9 +153,9 @@ pub fn db_menu(dbconfig: &mut DBConfig) {
                     Err(err) => eprintln!("Error during deletion: {}", err),
                 }
             }
-            DBMenu::BloomFilter => bloom_filter_menu(&mut db),
-            DBMenu::CountMinSketch => count_min_sketch_menu(&mut db),
-            DBMenu::HyperLogLog => hyperloglog_menu(&mut db),
+            DBMenu::BloomFilter => bloom_filter_menu(&db),
+            DBMenu::CountMinSketch => count_min_sketch_menu(&db),
+            DBMenu::HyperLogLog => hyperloglog_menu(&db),
             DBMenu::SimHash => {
                 clearscreen::clear().expect("Failed to clear screen.");
 
@@ -139,16 +169,16 @@ pub fn db_menu(dbconfig: &mut DBConfig) {
                 );
             }
             DBMenu::PrefixScan => {
-                prefix_scan_menu(&mut db);
+                prefix_scan_menu(&db);
             }
             DBMenu::RangeScan => {
-                range_scan_menu(&mut db);
+                range_scan_menu(&db);
             }
             DBMenu::PrefixIter => {
-                prefix_iter_menu(&mut db);
+                prefix_iter_menu(&db);
             }
             DBMenu::RangeIter => {
-                range_iter_menu(&mut db);
+                range_iter_menu(&db);
             }
             DBMenu::Exit => {
                 println!("Exiting...");
@@ -28,7 +28,7 @@ impl_menu!(
     "Back".yellow().italic()
 );
 
-pub fn hyperloglog_menu(db: &mut DB) {
+pub fn hyperloglog_menu(db: &DB) {
     loop {
         match HyperLogLogMenu::get_menu() {
             HyperLogLogMenu::CreateRetrieve => {
@@ -37,7 +37,7 @@ impl_menu!(
     "Back".yellow()
 );
 
-pub fn prefix_iter_menu(db: &mut DB) {
+pub fn prefix_iter_menu(db: &DB) {
     loop {
         match PrefixIterMenu::get_menu() {
             PrefixIterMenu::StartIteration => {
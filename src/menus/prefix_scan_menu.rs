@@ -22,7 +22,7 @@ impl_menu!(
     "Back".yellow()
 );
 
-pub fn prefix_scan_menu(db: &mut DB) {
+pub fn prefix_scan_menu(db: &DB) {
     let (mut page_count, mut page_len) = (Some(0), Some(20));
     loop {
         println!(
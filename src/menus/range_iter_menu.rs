@@ -37,7 +37,7 @@ impl_menu!(
     "Back".yellow()
 );
 
-pub fn range_iter_menu(db: &mut DB) {
+pub fn range_iter_menu(db: &DB) {
     loop {
         match RangeIterMenu::get_menu() {
             RangeIterMenu::StartIteration => {
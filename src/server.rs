@@ -0,0 +1,116 @@
+mod command;
+mod resp;
+
+use crate::DB;
+use resp::RespValue;
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How often the accept loop checks whether a shutdown was requested while no client is connecting.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Serves the database to clients that speak the Redis serialization protocol (RESP), handling
+/// each connection on its own thread. Runs until the shutdown flag is set, either by the caller
+/// or by a client sending SHUTDOWN. Then it closes all connections, waits for their threads
+/// and returns, so that the caller can shut the database down.
+///
+/// # Arguments
+///
+/// * `db` - The database shared between all connections.
+/// * `listener` - The bound listener to accept connections from.
+/// * `shutdown` - The flag that stops the server once it's set.
+pub fn serve(db: Arc<DB>, listener: TcpListener, shutdown: Arc<AtomicBool>) -> io::Result<()> {
+    // Accepting without blocking lets the loop notice the shutdown flag
+    listener.set_nonblocking(true)?;
+    let mut connections: Vec<(TcpStream, JoinHandle<()>)> = Vec::new();
+
+    while !shutdown.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => match spawn_connection(&db, &shutdown, stream) {
+                Ok(connection) => connections.push(connection),
+                Err(e) => eprintln!("Failed to set up a connection: {}", e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(SHUTDOWN_POLL_INTERVAL)
+            }
+            Err(e) => eprintln!("Failed to accept a connection: {}", e),
+        }
+        connections.retain(|(_, handle)| !handle.is_finished());
+    }
+
+    // Closing the streams ends the connections that wait for a command, while the ones
+    // executing a command finish it first
+    for (stream, handle) in connections {
+        let _ = stream.shutdown(Shutdown::Both);
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
+
+/// Starts the thread that handles the connection, returning it together with a handle to its stream.
+fn spawn_connection(
+    db: &Arc<DB>,
+    shutdown: &Arc<AtomicBool>,
+    stream: TcpStream,
+) -> io::Result<(TcpStream, JoinHandle<()>)> {
+    stream.set_nonblocking(false)?;
+    let handle = stream.try_clone()?;
+    let (db, shutdown) = (Arc::clone(db), Arc::clone(shutdown));
+
+    let thread = thread::spawn(move || {
+        if let Err(e) = handle_connection(&db, &shutdown, stream) {
+            eprintln!("Connection closed with an error: {}", e);
+        }
+    });
+
+    Ok((handle, thread))
+}
+
+/// Executes the commands of a single client until it disconnects or sends QUIT or SHUTDOWN.
+fn handle_connection(db: &DB, shutdown: &AtomicBool, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let mut session = command::Session::default();
+
+    loop {
+        let args = match resp::read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => return writer.flush(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                RespValue::Error(format!("ERR Protocol error: {}", e)).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Inline commands can be empty lines, which are ignored
+        if args.is_empty() {
+            continue;
+        }
+
+        if args[0].eq_ignore_ascii_case(b"QUIT") {
+            RespValue::SimpleString("OK".to_string()).write_to(&mut writer)?;
+            return writer.flush();
+        }
+
+        // As in Redis, the connection is closed without a reply once the shutdown starts
+        if args[0].eq_ignore_ascii_case(b"SHUTDOWN") {
+            shutdown.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
+
+        command::execute(db, &mut session, &args).write_to(&mut writer)?;
+
+        // Replies to pipelined commands are sent together once all of them are executed
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use crate::server::resp::RespValue;
+use crate::DB;
+use db_error::Error;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Number of keys returned by SCAN when COUNT isn't given, the same as in Redis.
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// The state a connection keeps between its commands.
+#[derive(Default)]
+pub(crate) struct Session {
+    // The last key of the page each open SCAN cursor was returned with, the next page starts after it
+    scan_cursors: HashMap<u64, Box<[u8]>>,
+    last_scan_cursor: u64,
+}
+
+enum CommandError {
+    Arity,
+    Syntax,
+    NotInteger,
+    InvalidCursor,
+    Db(Error),
+}
+
+impl From<Error> for CommandError {
+    fn from(error: Error) -> Self {
+        CommandError::Db(error)
+    }
+}
+
+type CommandResult = Result<RespValue, CommandError>;
+
+/// Executes a single command on the database and returns the reply for the client.
+///
+/// # Arguments
+///
+/// * `db` - The database the command is executed on.
+/// * `session` - The state of the connection that sent the command.
+/// * `args` - The command name followed by its arguments.
+pub(crate) fn execute(db: &DB, session: &mut Session, args: &[Vec<u8>]) -> RespValue {
+    let name = match args.first() {
+        Some(name) => String::from_utf8_lossy(name).to_uppercase(),
+        None => return RespValue::Error("ERR empty command".to_string()),
+    };
+    let args = &args[1..];
+
+    let result = match name.as_str() {
+        "PING" => ping(args),
+        "GET" => get(db, args),
+        "SET" => set(db, args),
+        "DEL" => del(db, args),
+        "SCAN" => scan(db, session, args),
+        "BF.ADD" => bf_add(db, args),
+        "BF.EXISTS" => bf_exists(db, args),
+        "CMS.INCRBY" => cms_incrby(db, args),
+        "CMS.QUERY" => cms_query(db, args),
+        "PFADD" => pfadd(db, args),
+        "PFCOUNT" => pfcount(db, args),
+        _ => return RespValue::Error(format!("ERR unknown command '{}'", name.to_lowercase())),
+    };
+
+    match result {
+        Ok(reply) => reply,
+        Err(CommandError::Arity) => RespValue::Error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            name.to_lowercase()
+        )),
+        Err(CommandError::Syntax) => RespValue::Error("ERR syntax error".to_string()),
+        Err(CommandError::NotInteger) => {
+            RespValue::Error("ERR value is not an integer or out of range".to_string())
+        }
+        Err(CommandError::InvalidCursor) => RespValue::Error("ERR invalid cursor".to_string()),
+        Err(CommandError::Db(error)) => {
+            let code = match error {
+                Error::RateLimited => "RATELIMITED",
+                Error::Corruption { .. } => "CORRUPTED",
+                _ => "ERR",
+            };
+            RespValue::Error(format!("{} {}", code, error))
+        }
+    }
+}
+
+fn ping(args: &[Vec<u8>]) -> CommandResult {
+    match args {
+        [] => Ok(RespValue::SimpleString("PONG".to_string())),
+        [message] => Ok(RespValue::BulkString(message.clone())),
+        _ => Err(CommandError::Arity),
+    }
+}
+
+fn get(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    let [key] = args else {
+        return Err(CommandError::Arity);
+    };
+
+    Ok(match db.get(key)? {
+        Some(value) => RespValue::BulkString(value.to_vec()),
+        None => RespValue::Null,
+    })
+}
+
+/// SET key value [EX seconds | PX milliseconds] [NX]
+fn set(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    if args.len() < 2 {
+        return Err(CommandError::Arity);
+    }
+    let (key, value) = (&args[0], &args[1]);
+
+    let mut ttl = None;
+    let mut only_if_absent = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"EX" if ttl.is_none() => {
+                let seconds = parse_positive(options.next().ok_or(CommandError::Syntax)?)?;
+                ttl = Some(Duration::from_secs(seconds));
+            }
+            b"PX" if ttl.is_none() => {
+                let millis = parse_positive(options.next().ok_or(CommandError::Syntax)?)?;
+                ttl = Some(Duration::from_millis(millis));
+            }
+            b"NX" => only_if_absent = true,
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    match (ttl, only_if_absent) {
+        (Some(ttl), true) => Ok(if db.insert_with_ttl_if_absent(key, value, ttl)? {
+            ok()
+        } else {
+            RespValue::Null
+        }),
+        (Some(ttl), false) => {
+            db.insert_with_ttl(key, value, ttl)?;
+            Ok(ok())
+        }
+        (None, true) => Ok(if db.insert_if_absent(key, value)? {
+            ok()
+        } else {
+            RespValue::Null
+        }),
+        (None, false) => {
+            db.insert(key, value)?;
+            Ok(ok())
+        }
+    }
+}
+
+fn del(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    if args.is_empty() {
+        return Err(CommandError::Arity);
+    }
+
+    let mut deleted = 0;
+    for key in args {
+        if db.delete_if_present(key)? {
+            deleted += 1;
+        }
+    }
+
+    Ok(RespValue::Integer(deleted))
+}
+
+/// SCAN cursor [MATCH prefix*] [RANGE min max] [COUNT count]
+///
+/// The connection remembers the last key of the page each cursor is returned with, and the next page
+/// starts right after it, so a full scan reads every key once. Cursors are only valid on the connection
+/// that got them and can be used once. Only patterns matching a prefix are supported.
+fn scan(db: &DB, session: &mut Session, args: &[Vec<u8>]) -> CommandResult {
+    let Some((cursor, options)) = args.split_first() else {
+        return Err(CommandError::Arity);
+    };
+    let start_after = match parse_integer(cursor)? {
+        0 => None,
+        cursor => Some(
+            session
+                .scan_cursors
+                .remove(&cursor)
+                .ok_or(CommandError::InvalidCursor)?,
+        ),
+    };
+
+    let mut prefix: Option<&[u8]> = None;
+    let mut range: Option<(&[u8], &[u8])> = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"MATCH" => {
+                let pattern = options.next().ok_or(CommandError::Syntax)?;
+                prefix = Some(match pattern.split_last() {
+                    Some((b'*', prefix)) if !prefix.contains(&b'*') => prefix,
+                    _ => return Err(CommandError::Syntax),
+                });
+            }
+            b"RANGE" => {
+                let min = options.next().ok_or(CommandError::Syntax)?;
+                let max = options.next().ok_or(CommandError::Syntax)?;
+                range = Some((min, max));
+            }
+            b"COUNT" => {
+                count = parse_positive(options.next().ok_or(CommandError::Syntax)?)? as usize;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    let mut paginator = db.get_paginator();
+    let start_after = start_after.as_deref();
+    let entries = match (prefix, range) {
+        (Some(_), Some(_)) => return Err(CommandError::Syntax),
+        (_, Some((min, max))) => paginator.range_scan_after(min, max, start_after, count)?,
+        (prefix, None) => {
+            paginator.prefix_scan_after(prefix.unwrap_or_default(), start_after, count)?
+        }
+    };
+
+    // A full page might be followed by another one
+    let next_cursor = match entries.last() {
+        Some((last_key, _)) if entries.len() == count => {
+            session.last_scan_cursor += 1;
+            session
+                .scan_cursors
+                .insert(session.last_scan_cursor, last_key.clone());
+            session.last_scan_cursor
+        }
+        _ => 0,
+    };
+
+    Ok(RespValue::Array(vec![
+        RespValue::BulkString(next_cursor.to_string().into_bytes()),
+        RespValue::Array(
+            entries
+                .into_iter()
+                .map(|(key, _)| RespValue::BulkString(key.to_vec()))
+                .collect(),
+        ),
+    ]))
+}
+
+/// BF.ADD key item, which creates the Bloom filter if it doesn't exist.
+fn bf_add(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    let [key, item] = args else {
+        return Err(CommandError::Arity);
+    };
+
+    Ok(RespValue::Integer(db.bloom_filter_add(key, item)? as i64))
+}
+
+fn bf_exists(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    let [key, item] = args else {
+        return Err(CommandError::Arity);
+    };
+
+    if db.bloom_filter_get(key)?.is_none() {
+        return Ok(RespValue::Integer(0));
+    }
+
+    Ok(RespValue::Integer(db.bloom_filter_contains(key, item)? as i64))
+}
+
+/// CMS.INCRBY key item increment [item increment ...], which creates the count-min sketch
+/// if it doesn't exist.
+fn cms_incrby(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    let Some((key, pairs)) = args.split_first() else {
+        return Err(CommandError::Arity);
+    };
+    if pairs.is_empty() || pairs.len() % 2 != 0 {
+        return Err(CommandError::Arity);
+    }
+
+    let increments = pairs
+        .chunks(2)
+        .map(|pair| Ok((pair[0].as_slice(), parse_integer(&pair[1])?)))
+        .collect::<Result<Vec<_>, CommandError>>()?;
+
+    Ok(RespValue::Array(
+        db.count_min_sketch_add(key, &increments)?
+            .into_iter()
+            .map(|count| RespValue::Integer(count as i64))
+            .collect(),
+    ))
+}
+
+fn cms_query(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    let Some((key, items)) = args.split_first() else {
+        return Err(CommandError::Arity);
+    };
+    if items.is_empty() {
+        return Err(CommandError::Arity);
+    }
+
+    let counts = items
+        .iter()
+        .map(|item| Ok(RespValue::Integer(db.count_min_sketch_get_count(key, item)? as i64)))
+        .collect::<Result<Vec<_>, CommandError>>()?;
+
+    Ok(RespValue::Array(counts))
+}
+
+/// PFADD key element [element ...], which creates the HyperLogLog if it doesn't exist.
+/// Replies with 1 if the HyperLogLog was created or changed.
+fn pfadd(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    let Some((key, elements)) = args.split_first() else {
+        return Err(CommandError::Arity);
+    };
+
+    let elements: Vec<&[u8]> = elements.iter().map(Vec::as_slice).collect();
+
+    Ok(RespValue::Integer(db.hyperloglog_add(key, &elements)? as i64))
+}
+
+fn pfcount(db: &DB, args: &[Vec<u8>]) -> CommandResult {
+    let [key] = args else {
+        return Err(CommandError::Arity);
+    };
+
+    if db.hyperloglog_get(key)?.is_none() {
+        return Ok(RespValue::Integer(0));
+    }
+
+    Ok(RespValue::Integer(db.hyperloglog_get_count(key)? as i64))
+}
+
+fn ok() -> RespValue {
+    RespValue::SimpleString("OK".to_string())
+}
+
+fn parse_integer(bytes: &[u8]) -> Result<u64, CommandError> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|integer| integer.parse().ok())
+        .ok_or(CommandError::NotInteger)
+}
+
+fn parse_positive(bytes: &[u8]) -> Result<u64, CommandError> {
+    match parse_integer(bytes)? {
+        0 => Err(CommandError::Syntax),
+        integer => Ok(integer),
+    }
+}
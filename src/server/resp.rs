@@ -0,0 +1,133 @@
+use std::io;
+use std::io::{BufRead, Read, Write};
+
+/// Maximum length of a line, which holds an inline command or the length of an array or a bulk string.
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+/// Maximum number of arguments of a single command.
+const MAX_ARGUMENTS: usize = 1024 * 1024;
+/// Maximum length of a single argument, the same as in Redis.
+const MAX_BULK_LENGTH: usize = 512 * 1024 * 1024;
+
+/// A reply in the Redis serialization protocol (RESP2).
+#[derive(Debug, PartialEq)]
+pub(crate) enum RespValue {
+    SimpleString(String),
+    // The first word of the message is the error code, for example ERR
+    Error(String),
+    Integer(i64),
+    BulkString(Vec<u8>),
+    // The null bulk string, returned for missing values
+    Null,
+    Array(Vec<RespValue>),
+}
+
+impl RespValue {
+    /// Writes the serialized value to the writer.
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            RespValue::SimpleString(string) => write!(writer, "+{}\r\n", string),
+            RespValue::Error(message) => write!(writer, "-{}\r\n", message),
+            RespValue::Integer(integer) => write!(writer, ":{}\r\n", integer),
+            RespValue::BulkString(bytes) => {
+                write!(writer, "${}\r\n", bytes.len())?;
+                writer.write_all(bytes)?;
+                writer.write_all(b"\r\n")
+            }
+            RespValue::Null => writer.write_all(b"$-1\r\n"),
+            RespValue::Array(values) => {
+                write!(writer, "*{}\r\n", values.len())?;
+                for value in values {
+                    value.write_to(writer)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads the next command, sent either as an array of bulk strings or as an inline command
+/// whose arguments are separated by spaces.
+///
+/// # Returns
+///
+/// The arguments of the command, or None if the client closed the connection.
+///
+/// # Errors
+///
+/// Returns an `io::Error` of the `InvalidData` kind if the command doesn't follow the protocol.
+pub(crate) fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+
+    if line.first() != Some(&b'*') {
+        return Ok(Some(
+            line.split(|byte| byte.is_ascii_whitespace())
+                .filter(|argument| !argument.is_empty())
+                .map(|argument| argument.to_vec())
+                .collect(),
+        ));
+    }
+
+    let num_arguments = parse_length(&line[1..], MAX_ARGUMENTS)?;
+    let mut arguments = Vec::with_capacity(num_arguments);
+    for _ in 0..num_arguments {
+        let line = read_line(reader)?.ok_or_else(unexpected_eof)?;
+        if line.first() != Some(&b'$') {
+            return Err(protocol_error("expected '$'"));
+        }
+
+        let length = parse_length(&line[1..], MAX_BULK_LENGTH)?;
+        let mut argument = vec![0u8; length + 2];
+        reader.read_exact(&mut argument)?;
+        if !argument.ends_with(b"\r\n") {
+            return Err(protocol_error("expected CRLF after the bulk string"));
+        }
+        argument.truncate(length);
+
+        arguments.push(argument);
+    }
+
+    Ok(Some(arguments))
+}
+
+/// Reads a line without its line ending. Returns None if the reader is at its end.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE_LENGTH).read_until(b'\n', &mut line)?;
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(protocol_error("line is too long or not terminated"));
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+
+    Ok(Some(line))
+}
+
+/// Parses the length of an array or a bulk string, which can't be bigger than the given maximum.
+fn parse_length(bytes: &[u8], max_length: usize) -> io::Result<usize> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|length| length.parse::<usize>().ok())
+        .filter(|length| *length <= max_length)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "connection closed in the middle of a command",
+    )
+}
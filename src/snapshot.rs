@@ -0,0 +1,43 @@
+use crate::DB;
+use db_error::Error;
+use lsm::{LSMSnapshot, Paginator};
+
+/// A point-in-time view of the database. Reads through the snapshot only see the data that was written before
+/// it was taken, while writes to the database can continue. Older versions of the data are kept until it's dropped.
+pub struct Snapshot {
+    inner: LSMSnapshot,
+}
+
+impl Snapshot {
+    pub(crate) fn new(inner: LSMSnapshot) -> Self {
+        Snapshot { inner }
+    }
+
+    /// Returns the time stamp at which the snapshot was taken.
+    pub fn get_timestamp(&self) -> u128 {
+        self.inner.get_timestamp()
+    }
+
+    /// Retrieves the data that was associated to the given key at the time of the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database the snapshot was taken from.
+    /// * `key` - The key to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the value at the time of the snapshot, or `None` if the key wasn't present.
+    pub fn get(&self, db: &DB, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        db.snapshot_get(&self.inner, key)
+    }
+
+    /// Returns a paginator whose range and prefix scans only see the data at the time of the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database the snapshot was taken from.
+    pub fn get_paginator<'a>(&'a self, db: &'a DB) -> Paginator<'a> {
+        db.get_snapshot_paginator(&self.inner)
+    }
+}
@@ -14,7 +14,7 @@ fn prepare_dirs(dbconfig: &DBConfig) {
     match read_dir(&dbconfig.sstable_dir) {
         Ok(dir) => dir
             .map(|dir_entry| dir_entry.unwrap().path())
-            .for_each(|dir| remove_dir_all(dir).unwrap_or(())),
+            .for_each(|path| remove_dir_all(&path).or_else(|_| remove_file(&path)).unwrap_or(())),
         Err(_) => (),
     }
 
@@ -42,7 +42,7 @@ fn test_compressed_size_vs_uncompressed_100_diff_keys() {
 
     prepare_dirs(&db_config);
 
-    let mut db_uncompressed = DB::build(db_config.clone()).unwrap();
+    let db_uncompressed = DB::build(db_config.clone()).unwrap();
 
     for i in 0..100_000 {
         let key_num = i % 100;
@@ -65,7 +65,7 @@ fn test_compressed_size_vs_uncompressed_100_diff_keys() {
 
     prepare_dirs(&db_config);
 
-    let mut db_compressed = DB::build(db_config.clone()).unwrap();
+    let db_compressed = DB::build(db_config.clone()).unwrap();
 
     for i in 0..100_000 {
         let key_num = i % 100;
@@ -111,7 +111,7 @@ fn test_compressed_size_vs_uncompressed_50000_diff_keys() {
 
     prepare_dirs(&db_config);
 
-    let mut db_uncompressed = DB::build(db_config.clone()).unwrap();
+    let db_uncompressed = DB::build(db_config.clone()).unwrap();
 
     for i in 0..100_000 {
         let key_num = i % 50_000;
@@ -134,7 +134,7 @@ fn test_compressed_size_vs_uncompressed_50000_diff_keys() {
 
     prepare_dirs(&db_config);
 
-    let mut db_compressed = DB::build(db_config.clone()).unwrap();
+    let db_compressed = DB::build(db_config.clone()).unwrap();
 
     for i in 0..100_000 {
         let key_num = i % 50_000;
@@ -1,5 +1,7 @@
 use db_config::DBConfig;
 use std::fs::{read_dir, remove_dir_all, remove_file};
+use std::sync::Arc;
+use std::thread;
 use NoSQLDB::DB;
 
 fn prepare_dirs(dbconfig: &DBConfig) {
@@ -13,7 +15,7 @@ fn prepare_dirs(dbconfig: &DBConfig) {
     match read_dir(&dbconfig.sstable_dir) {
         Ok(dir) => dir
             .map(|dir_entry| dir_entry.unwrap().path())
-            .for_each(|dir| remove_dir_all(dir).unwrap_or(())),
+            .for_each(|path| remove_dir_all(&path).or_else(|_| remove_file(&path)).unwrap_or(())),
         Err(_) => (),
     }
 }
@@ -28,7 +30,7 @@ fn test_hyperloglog_insert_and_get_count() {
 
     prepare_dirs(&db_config);
 
-    let mut db = DB::build(db_config).unwrap();
+    let db = DB::build(db_config).unwrap();
 
     let key = "test_hyperloglog".as_bytes();
     let value = "test_value".as_bytes();
@@ -52,7 +54,7 @@ fn test_bloom_filter_operations() {
 
     prepare_dirs(&db_config);
 
-    let mut db = DB::build(db_config).unwrap();
+    let db = DB::build(db_config).unwrap();
 
     let key = "test_bloom_filter".as_bytes();
     let value1 = "value1".as_bytes();
@@ -89,7 +91,7 @@ fn test_count_min_sketch_operations() {
 
     prepare_dirs(&db_config);
 
-    let mut db = DB::build(db_config).unwrap();
+    let db = DB::build(db_config).unwrap();
 
     let key = "test_count_min_sketch".as_bytes();
     let value1 = "value1".as_bytes();
@@ -108,3 +110,52 @@ fn test_count_min_sketch_operations() {
 
     assert_eq!(db.count_min_sketch_get_count(key, value2).expect(""), 0);
 }
+
+#[test]
+fn test_add_creates_structures_once() {
+    let mut db_config = db_config::DBConfig::default();
+    db_config.sstable_dir += "probabilistic_add_integration/";
+    db_config.write_ahead_log_dir += "probabilistic_add_integration/";
+    db_config.token_bucket_capacity = 999999;
+    db_config.token_bucket_refill_rate = 999999;
+
+    prepare_dirs(&db_config);
+
+    let db = Arc::new(DB::build(db_config).unwrap());
+
+    // Every thread's first addition creates the structures if they don't exist, so none may be lost
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                let value = format!("value{}", i);
+                assert!(db.bloom_filter_add(b"filter", value.as_bytes()).unwrap());
+                db.count_min_sketch_add(b"sketch", &[(b"value", 1)])
+                    .unwrap();
+                assert!(db.hyperloglog_add(b"hll", &[value.as_bytes()]).unwrap());
+            })
+        })
+        .collect();
+    threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+
+    for i in 0..8 {
+        let value = format!("value{}", i);
+        assert!(db
+            .bloom_filter_contains(b"filter", value.as_bytes())
+            .unwrap());
+        assert!(!db.bloom_filter_add(b"filter", value.as_bytes()).unwrap());
+    }
+    assert_eq!(
+        db.count_min_sketch_get_count(b"sketch", b"value").unwrap(),
+        8
+    );
+    assert_eq!(
+        db.count_min_sketch_add(b"sketch", &[(b"value", 2), (b"other", 1)])
+            .unwrap(),
+        vec![10, 1]
+    );
+    assert!(!db.hyperloglog_add(b"hll", &[b"value0"]).unwrap());
+    assert!(db.hyperloglog_get_count(b"hll").unwrap() > 0);
+}
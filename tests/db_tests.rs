@@ -1,7 +1,13 @@
-use db_config::DBConfig;
-use db_config::MemoryTableType::BTree;
-use std::fs::{read_dir, remove_dir_all, remove_file};
-use NoSQLDB::DB;
+use db_config::MemoryTableType::{BTree, SkipList};
+use db_config::{ColumnFamilyConfig, DBConfig};
+use segment_elements::{read_file_header, FileKind};
+use std::fs::{copy, create_dir_all, read, read_dir, remove_dir_all, remove_file};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+use NoSQLDB::{Error, WriteBatch, DB};
 
 fn prepare_dirs(dbconfig: &DBConfig) {
     match read_dir(&dbconfig.write_ahead_log_dir) {
@@ -14,11 +20,24 @@ fn prepare_dirs(dbconfig: &DBConfig) {
     match read_dir(&dbconfig.sstable_dir) {
         Ok(dir) => dir
             .map(|dir_entry| dir_entry.unwrap().path())
-            .for_each(|dir| remove_dir_all(dir).unwrap_or(())),
+            .for_each(|path| remove_dir_all(&path).or_else(|_| remove_file(&path)).unwrap_or(())),
         Err(_) => (),
     }
 }
 
+fn copy_dir(from: &Path, to: &Path) {
+    create_dir_all(to).unwrap();
+    for dir_entry in read_dir(from).unwrap() {
+        let path = dir_entry.unwrap().path();
+        let destination = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &destination);
+        } else {
+            copy(&path, &destination).unwrap();
+        }
+    }
+}
+
 #[test]
 fn test_read_write_path_one() {
     let mut db_config = DBConfig::default();
@@ -27,7 +46,7 @@ fn test_read_write_path_one() {
 
     prepare_dirs(&db_config);
 
-    let mut db = DB::build(db_config).unwrap();
+    let db = DB::build(db_config).unwrap();
 
     db.insert("test_key".as_bytes(), "test_value".as_bytes())
         .unwrap();
@@ -54,7 +73,7 @@ fn test_read_write_path_multiple() {
 
     prepare_dirs(&db_config);
 
-    let mut db = DB::build(db_config).unwrap();
+    let db = DB::build(db_config).unwrap();
 
     let base_key = "test_key";
     let base_value = "test_value";
@@ -124,7 +143,7 @@ fn test_read_write_path_multiple_single_file() {
 
     prepare_dirs(&db_config);
 
-    let mut db = DB::build(db_config).unwrap();
+    let db = DB::build(db_config).unwrap();
 
     let base_key = "test_key";
     let base_value = "test_value";
@@ -176,3 +195,657 @@ fn test_read_write_path_multiple_single_file() {
         assert_eq!(value, Box::new(&*get_op));
     }
 }
+
+#[test]
+fn test_write_batch() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_write_batch/";
+    db_config.write_ahead_log_dir += "general_write_batch/";
+
+    prepare_dirs(&db_config);
+
+    let db = DB::build(db_config.clone()).unwrap();
+
+    db.insert("key_to_delete".as_bytes(), "value".as_bytes())
+        .unwrap();
+
+    let mut batch = WriteBatch::new();
+    batch.insert("key1".as_bytes(), "value1".as_bytes());
+    batch.insert("key2".as_bytes(), "value2".as_bytes());
+    batch.delete("key_to_delete".as_bytes());
+    db.write_batch(batch).unwrap();
+
+    // Keys that look like the internal structures are ordinary user keys
+    let mut user_batch = WriteBatch::new();
+    user_batch.insert("key3".as_bytes(), "value3".as_bytes());
+    user_batch.insert("bl00m_f1lt3r/key".as_bytes(), "value".as_bytes());
+    db.write_batch(user_batch).unwrap();
+
+    db.shut_down();
+
+    let db = DB::build(db_config).unwrap();
+
+    assert_eq!(
+        "value1".as_bytes(),
+        &*db.get("key1".as_bytes()).unwrap().unwrap()
+    );
+    assert_eq!(
+        "value2".as_bytes(),
+        &*db.get("key2".as_bytes()).unwrap().unwrap()
+    );
+    assert_eq!(None, db.get("key_to_delete".as_bytes()).unwrap());
+    assert_eq!(
+        "value3".as_bytes(),
+        &*db.get("key3".as_bytes()).unwrap().unwrap()
+    );
+    assert_eq!(
+        "value".as_bytes(),
+        &*db.get("bl00m_f1lt3r/key".as_bytes()).unwrap().unwrap()
+    );
+}
+
+#[test]
+fn test_snapshot() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_snapshot/";
+    db_config.write_ahead_log_dir += "general_snapshot/";
+    db_config.memory_table_capacity = 10;
+
+    prepare_dirs(&db_config);
+
+    let db = DB::build(db_config).unwrap();
+
+    for i in 0..50 {
+        db.insert(format!("key{:02}", i).as_bytes(), "old".as_bytes())
+            .unwrap();
+    }
+
+    let snapshot = db.snapshot();
+
+    for i in 0..50 {
+        let key = format!("key{:02}", i);
+        if i % 2 == 0 {
+            db.insert(key.as_bytes(), "new".as_bytes()).unwrap();
+        } else {
+            db.delete(key.as_bytes()).unwrap();
+        }
+    }
+
+    for i in 0..50 {
+        let key = format!("key{:02}", i);
+        assert_eq!(
+            "old".as_bytes(),
+            &*snapshot.get(&db, key.as_bytes()).unwrap().unwrap()
+        );
+        if i % 2 == 0 {
+            assert_eq!("new".as_bytes(), &*db.get(key.as_bytes()).unwrap().unwrap());
+        } else {
+            assert_eq!(None, db.get(key.as_bytes()).unwrap());
+        }
+    }
+
+    let mut paginator = snapshot.get_paginator(&db);
+    for i in 0..50 {
+        let (key, entry) = paginator
+            .prefix_iterate_next("key".as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(format!("key{:02}", i).as_bytes(), &*key);
+        assert_eq!("old".as_bytes(), &*entry.get_value());
+    }
+}
+
+#[test]
+fn test_insert_with_ttl() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_insert_with_ttl/";
+    db_config.write_ahead_log_dir += "general_insert_with_ttl/";
+
+    prepare_dirs(&db_config);
+
+    let db = DB::build(db_config.clone()).unwrap();
+
+    db.insert_with_ttl(
+        "session".as_bytes(),
+        "value".as_bytes(),
+        Duration::from_millis(1),
+    )
+    .unwrap();
+    db.insert_with_ttl(
+        "long_session".as_bytes(),
+        "value".as_bytes(),
+        Duration::from_secs(3600),
+    )
+    .unwrap();
+    sleep(Duration::from_millis(10));
+
+    assert_eq!(None, db.get("session".as_bytes()).unwrap());
+    assert_eq!(
+        "value".as_bytes(),
+        &*db.get("long_session".as_bytes()).unwrap().unwrap()
+    );
+
+    // Expired keys count as absent
+    assert!(db
+        .insert_with_ttl_if_absent(
+            "session".as_bytes(),
+            "value".as_bytes(),
+            Duration::from_millis(1)
+        )
+        .unwrap());
+    assert!(!db
+        .insert_with_ttl_if_absent(
+            "long_session".as_bytes(),
+            "other".as_bytes(),
+            Duration::from_secs(3600)
+        )
+        .unwrap());
+    sleep(Duration::from_millis(10));
+    assert_eq!(None, db.get("session".as_bytes()).unwrap());
+
+    db.shut_down();
+
+    let db = DB::build(db_config).unwrap();
+
+    assert_eq!(None, db.get("session".as_bytes()).unwrap());
+    assert_eq!(
+        "value".as_bytes(),
+        &*db.get("long_session".as_bytes()).unwrap().unwrap()
+    );
+}
+
+#[test]
+fn test_compare_and_swap() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_compare_and_swap/";
+    db_config.write_ahead_log_dir += "general_compare_and_swap/";
+
+    prepare_dirs(&db_config);
+
+    let db = DB::build(db_config).unwrap();
+
+    assert!(db
+        .insert_if_absent("lease".as_bytes(), "owner1".as_bytes())
+        .unwrap());
+    assert!(!db
+        .insert_if_absent("lease".as_bytes(), "owner2".as_bytes())
+        .unwrap());
+    assert_eq!(
+        "owner1".as_bytes(),
+        &*db.get("lease".as_bytes()).unwrap().unwrap()
+    );
+
+    // The swap only happens if the current value matches the expected one
+    assert!(!db
+        .compare_and_swap(
+            "lease".as_bytes(),
+            Some("owner2".as_bytes()),
+            Some("owner3".as_bytes())
+        )
+        .unwrap());
+    assert!(db
+        .compare_and_swap(
+            "lease".as_bytes(),
+            Some("owner1".as_bytes()),
+            Some("owner2".as_bytes())
+        )
+        .unwrap());
+    assert_eq!(
+        "owner2".as_bytes(),
+        &*db.get("lease".as_bytes()).unwrap().unwrap()
+    );
+
+    // Swapping to None deletes the key, after which it can be inserted again
+    assert!(db
+        .compare_and_swap("lease".as_bytes(), Some("owner2".as_bytes()), None)
+        .unwrap());
+    assert_eq!(None, db.get("lease".as_bytes()).unwrap());
+    assert!(!db
+        .compare_and_swap("lease".as_bytes(), Some("owner2".as_bytes()), None)
+        .unwrap());
+    assert!(db
+        .insert_if_absent("lease".as_bytes(), "owner3".as_bytes())
+        .unwrap());
+
+    assert!(db
+        .compare_and_swap(
+            "bl00m_f1lt3r/key".as_bytes(),
+            None,
+            Some("value".as_bytes())
+        )
+        .unwrap());
+}
+
+#[test]
+fn test_compare_and_swap_counter() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_compare_and_swap_counter/";
+    db_config.write_ahead_log_dir += "general_compare_and_swap_counter/";
+    db_config.memory_table_capacity = 10;
+
+    prepare_dirs(&db_config);
+
+    let db = DB::build(db_config).unwrap();
+
+    // Increments survive flushes of the memory tables
+    db.insert("counter".as_bytes(), &0u32.to_ne_bytes())
+        .unwrap();
+    for i in 0..50u32 {
+        assert!(db
+            .compare_and_swap(
+                "counter".as_bytes(),
+                Some(&i.to_ne_bytes()),
+                Some(&(i + 1).to_ne_bytes())
+            )
+            .unwrap());
+        db.insert(format!("filler{}", i).as_bytes(), "value".as_bytes())
+            .unwrap();
+    }
+
+    assert_eq!(
+        &50u32.to_ne_bytes(),
+        &*db.get("counter".as_bytes()).unwrap().unwrap()
+    );
+}
+
+#[test]
+fn test_shared_between_threads() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_shared_between_threads/";
+    db_config.write_ahead_log_dir += "general_shared_between_threads/";
+    db_config.memory_table_capacity = 10;
+    db_config.token_bucket_capacity = 9999999999;
+    db_config.token_bucket_refill_rate = 9999999999;
+
+    prepare_dirs(&db_config);
+
+    let db = Arc::new(DB::build(db_config).unwrap());
+    db.insert("counter".as_bytes(), &0u32.to_ne_bytes())
+        .unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|thread| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for i in 0..25u32 {
+                    let key = format!("thread{}_key{}", thread, i);
+                    db.insert(key.as_bytes(), &i.to_ne_bytes()).unwrap();
+                    assert_eq!(&i.to_ne_bytes(), &*db.get(key.as_bytes()).unwrap().unwrap());
+
+                    // Retry the increment until no other thread changed the counter in between
+                    loop {
+                        let current = db.get("counter".as_bytes()).unwrap().unwrap();
+                        let value = u32::from_ne_bytes(current.as_ref().try_into().unwrap());
+                        if db
+                            .compare_and_swap(
+                                "counter".as_bytes(),
+                                Some(&current),
+                                Some(&(value + 1).to_ne_bytes()),
+                            )
+                            .unwrap()
+                        {
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(
+        &100u32.to_ne_bytes(),
+        &*db.get("counter".as_bytes()).unwrap().unwrap()
+    );
+    for thread in 0..4 {
+        let mut paginator = db.get_paginator();
+        let prefix = format!("thread{}_", thread);
+        assert_eq!(
+            25,
+            paginator
+                .prefix_scan(prefix.as_bytes(), 0, 100)
+                .unwrap()
+                .len()
+        );
+    }
+}
+
+#[test]
+fn test_column_families() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_column_families/";
+    db_config.write_ahead_log_dir += "general_column_families/";
+    db_config.memory_table_capacity = 10;
+    let mut users = ColumnFamilyConfig::new("users");
+    users.memory_table_type = Some(SkipList);
+    db_config.column_families = vec![users, ColumnFamilyConfig::new("orders")];
+
+    prepare_dirs(&db_config);
+
+    let db = DB::build(db_config.clone()).unwrap();
+
+    for i in 0..30u32 {
+        db.insert(format!("key{}", i).as_bytes(), "default".as_bytes())
+            .unwrap();
+        db.column_family_insert("users", format!("key{}", i).as_bytes(), "user".as_bytes())
+            .unwrap();
+    }
+    db.column_family_insert("orders", "key0".as_bytes(), "order".as_bytes())
+        .unwrap();
+    db.column_family_delete("users", "key1".as_bytes()).unwrap();
+
+    // Column families don't see each other's keys
+    assert_eq!(
+        "default".as_bytes(),
+        &*db.get("key0".as_bytes()).unwrap().unwrap()
+    );
+    assert_eq!(
+        "user".as_bytes(),
+        &*db.column_family_get("users", "key0".as_bytes())
+            .unwrap()
+            .unwrap()
+    );
+    assert_eq!(
+        "order".as_bytes(),
+        &*db.column_family_get("orders", "key0".as_bytes())
+            .unwrap()
+            .unwrap()
+    );
+    assert_eq!(
+        None,
+        db.column_family_get("orders", "key2".as_bytes()).unwrap()
+    );
+    assert_eq!(
+        "default".as_bytes(),
+        &*db.get("key1".as_bytes()).unwrap().unwrap()
+    );
+    assert!(db.column_family_get("missing", "key0".as_bytes()).is_err());
+
+    db.shut_down();
+
+    // Both flushed and replayed records survive a restart
+    let db = DB::build(db_config).unwrap();
+
+    assert_eq!(
+        None,
+        db.column_family_get("users", "key1".as_bytes()).unwrap()
+    );
+    for i in (0..30u32).filter(|i| *i != 1) {
+        assert_eq!(
+            "user".as_bytes(),
+            &*db.column_family_get("users", format!("key{}", i).as_bytes())
+                .unwrap()
+                .unwrap()
+        );
+    }
+    assert_eq!(
+        "order".as_bytes(),
+        &*db.column_family_get("orders", "key0".as_bytes())
+            .unwrap()
+            .unwrap()
+    );
+    assert_eq!(
+        11,
+        db.get_column_family_paginator("users")
+            .unwrap()
+            .prefix_scan("key2".as_bytes(), 0, 100)
+            .unwrap()
+            .len()
+    );
+}
+
+#[test]
+fn test_typed_errors() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_typed_errors/";
+    db_config.write_ahead_log_dir += "general_typed_errors/";
+    db_config.token_bucket_capacity = 5;
+    db_config.token_bucket_refill_rate = 0;
+
+    prepare_dirs(&db_config);
+
+    let mut reserved_config = db_config.clone();
+    reserved_config.column_families = vec![ColumnFamilyConfig::new("system")];
+    assert!(matches!(
+        DB::build(reserved_config),
+        Err(Error::ReservedKey(_))
+    ));
+
+    let mut duplicate_config = db_config.clone();
+    duplicate_config.column_families = vec![
+        ColumnFamilyConfig::new("users"),
+        ColumnFamilyConfig::new("users"),
+    ];
+    assert!(matches!(DB::build(duplicate_config), Err(Error::Config(_))));
+
+    let db = DB::build(db_config).unwrap();
+
+    assert!(matches!(
+        db.column_family_get("missing", "key".as_bytes()),
+        Err(Error::UnknownColumnFamily(_))
+    ));
+    assert!(matches!(
+        db.bloom_filter_insert("missing".as_bytes(), "value".as_bytes()),
+        Err(Error::MissingProbabilisticStructure(_))
+    ));
+
+    // The remaining tokens run out, after which the requests can be retried once the bucket refills
+    for i in 0..4 {
+        db.insert(format!("key{}", i).as_bytes(), "value".as_bytes())
+            .unwrap();
+    }
+    assert!(matches!(
+        db.insert("key4".as_bytes(), "value".as_bytes()),
+        Err(Error::RateLimited)
+    ));
+}
+
+#[test]
+fn test_token_bucket_checkpoint() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_token_bucket_checkpoint/";
+    db_config.write_ahead_log_dir += "general_token_bucket_checkpoint/";
+    db_config.token_bucket_capacity = 5;
+    db_config.token_bucket_refill_rate = 0;
+    db_config.token_bucket_checkpoint_interval_ms = 60_000;
+
+    prepare_dirs(&db_config);
+
+    let wal_size = |dir: &str| -> u64 {
+        read_dir(dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().metadata().unwrap().len())
+            .sum()
+    };
+
+    let db = DB::build(db_config.clone()).unwrap();
+
+    // Reads only take tokens in memory, so nothing is appended to the WAL
+    let size_before = wal_size(&db_config.write_ahead_log_dir);
+    for _ in 0..3 {
+        assert_eq!(db.get("key".as_bytes()).unwrap(), None);
+    }
+    assert_eq!(size_before, wal_size(&db_config.write_ahead_log_dir));
+
+    // The state is checkpointed on shut down, so a restart doesn't refill the bucket
+    db.shut_down();
+    let db = DB::build(db_config).unwrap();
+
+    for _ in 0..2 {
+        assert_eq!(db.get("key".as_bytes()).unwrap(), None);
+    }
+    assert!(matches!(db.get("key".as_bytes()), Err(Error::RateLimited)));
+}
+
+#[test]
+fn test_compact_range() {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_compact_range/";
+    db_config.write_ahead_log_dir += "general_compact_range/";
+    db_config.memory_table_capacity = 20;
+    db_config.memory_table_pool_num = 2;
+    db_config.compaction_enabled = false;
+    db_config.use_compression = false;
+    db_config.token_bucket_capacity = 9999999999;
+    db_config.token_bucket_refill_rate = 9999999999;
+
+    prepare_dirs(&db_config);
+
+    let tables_size = |dir: &str| -> u64 {
+        read_dir(dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap())
+            .filter(|dir_entry| dir_entry.file_name().to_string_lossy().starts_with("sstable_"))
+            .flat_map(|dir_entry| read_dir(dir_entry.path()).unwrap())
+            .map(|file| file.unwrap().metadata().unwrap().len())
+            .sum()
+    };
+
+    let db = DB::build(db_config.clone()).unwrap();
+    for i in 0..300 {
+        db.insert(format!("key{:03}", i).as_bytes(), &[7; 100])
+            .unwrap();
+    }
+    for i in 0..200 {
+        db.delete(format!("key{:03}", i).as_bytes()).unwrap();
+    }
+
+    // Nothing is compacted on its own, so the deleted entries only go away with the manual compaction
+    let size_before = tables_size(&db_config.sstable_dir);
+    db.compact_range(None, None).unwrap();
+    assert!(tables_size(&db_config.sstable_dir) < size_before / 2);
+
+    for i in 0..300 {
+        let value = db.get(format!("key{:03}", i).as_bytes()).unwrap();
+        assert_eq!(value.is_some(), i >= 200);
+    }
+}
+
+#[test]
+fn test_upgrade_format() {
+    // The fixtures were written before the files had headers, see the README next to them
+    for (layout, in_single_file) in [("separate_files", false), ("single_file", true)] {
+        let mut db_config = DBConfig::default();
+        db_config.sstable_dir += &format!("general_upgrade_{}/", layout);
+        db_config.write_ahead_log_dir += &format!("general_upgrade_{}/", layout);
+        db_config.memory_table_capacity = 10;
+        db_config.memory_table_pool_num = 2;
+        db_config.compaction_enabled = false;
+        db_config.use_compression = false;
+        db_config.sstable_single_file = in_single_file;
+        db_config.summary_density = 3;
+        db_config.index_density = 2;
+        db_config.use_variable_encoding = true;
+        db_config.token_bucket_capacity = 9999999999;
+        db_config.token_bucket_refill_rate = 9999999999;
+
+        prepare_dirs(&db_config);
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("libraries/lsm/test_fixtures/legacy_format")
+            .join(layout);
+        copy_dir(&fixture.join("sstables"), Path::new(&db_config.sstable_dir));
+        copy_dir(&fixture.join("wal"), Path::new(&db_config.write_ahead_log_dir));
+
+        let db = DB::build(db_config.clone()).unwrap();
+        assert_eq!(db.upgrade_format().unwrap(), 2);
+        assert_eq!(db.upgrade_format().unwrap(), 0);
+
+        for i in 0..30 {
+            let value = db.get(format!("key{:03}", i).as_bytes()).unwrap();
+            let expected = format!("value{:03}", i);
+            match i {
+                5 | 25 => assert_eq!(value, None),
+                _ => assert_eq!(value.as_deref(), Some(expected.as_bytes())),
+            }
+        }
+
+        // Every rewritten table starts with a file header
+        let (kind, file_name) = if in_single_file {
+            (FileKind::SSTable, "SSTable.db")
+        } else {
+            (FileKind::SSTableData, "SSTable-Data.db")
+        };
+        let tables: Vec<_> = read_dir(&db_config.sstable_dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap())
+            .filter(|dir_entry| dir_entry.file_name().to_string_lossy().starts_with("sstable_"))
+            .collect();
+        assert_eq!(tables.len(), 2);
+        for table in tables {
+            let bytes = read(table.path().join(file_name)).unwrap();
+            assert!(read_file_header(kind, &bytes, u32::MAX).unwrap().is_some());
+        }
+    }
+}
+
+#[test]
+fn test_system_keys_migrated() {
+    // The fixture was written before the system column family existed, see the README next to it
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += "general_system_keys_migrated/";
+    db_config.write_ahead_log_dir += "general_system_keys_migrated/";
+    db_config.memory_table_capacity = 10;
+    db_config.memory_table_pool_num = 2;
+    db_config.compaction_enabled = false;
+    db_config.use_compression = false;
+    db_config.token_bucket_capacity = 1000;
+    db_config.token_bucket_refill_rate = 0;
+
+    prepare_dirs(&db_config);
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/legacy_system_keys");
+    copy_dir(&fixture.join("sstables"), Path::new(&db_config.sstable_dir));
+    copy_dir(
+        &fixture.join("wal"),
+        Path::new(&db_config.write_ahead_log_dir),
+    );
+
+    let db = DB::build(db_config.clone()).unwrap();
+
+    // The structures are read from the system column family and are gone from the default one
+    assert!(db
+        .bloom_filter_contains("filter".as_bytes(), "apple".as_bytes())
+        .unwrap());
+    assert_eq!(
+        3,
+        db.count_min_sketch_get_count("sketch".as_bytes(), "apple".as_bytes())
+            .unwrap()
+    );
+    assert!(db.hyperloglog_get("log".as_bytes()).unwrap().is_some());
+    for key in [
+        "bl00m_f1lt3r/filter",
+        "c0unt_m1n_$k3tch/sketch",
+        "hyp3r_l0g_l0g/log",
+        "t0k3n_buck3t/state",
+    ] {
+        assert_eq!(None, db.get(key.as_bytes()).unwrap());
+    }
+    assert_eq!(
+        "value".as_bytes(),
+        &*db.get("key19".as_bytes()).unwrap().unwrap()
+    );
+
+    // Keys with a reserved prefix written after the migration belong to the user, so they aren't moved again
+    db.insert("bl00m_f1lt3r/user".as_bytes(), "value".as_bytes())
+        .unwrap();
+    db.shut_down();
+
+    let db = DB::build(db_config).unwrap();
+
+    assert!(db
+        .bloom_filter_contains("filter".as_bytes(), "apple".as_bytes())
+        .unwrap());
+    assert_eq!(
+        "value".as_bytes(),
+        &*db.get("bl00m_f1lt3r/user".as_bytes()).unwrap().unwrap()
+    );
+
+    // The bucket state of the old version was moved too, so the tokens it took aren't refilled.
+    // Each of the 11 requests above took one token from the bucket
+    let remaining = (0..1000)
+        .take_while(|_| db.get("key00".as_bytes()).is_ok())
+        .count();
+    assert!(remaining < 1000 - 11);
+}
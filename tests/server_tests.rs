@@ -0,0 +1,377 @@
+use db_config::DBConfig;
+use std::fs::{read_dir, remove_dir_all, remove_file};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+use NoSQLDB::{serve, DB};
+
+fn prepare_dirs(dbconfig: &DBConfig) {
+    match read_dir(&dbconfig.write_ahead_log_dir) {
+        Ok(dir) => dir
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .for_each(|file| remove_file(file).unwrap()),
+        Err(_) => (),
+    }
+
+    match read_dir(&dbconfig.sstable_dir) {
+        Ok(dir) => dir
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .for_each(|path| remove_dir_all(&path).or_else(|_| remove_file(&path)).unwrap_or(())),
+        Err(_) => (),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Nil,
+    Array(Vec<Reply>),
+}
+
+fn bulk(value: &str) -> Reply {
+    Reply::Bulk(value.as_bytes().to_vec())
+}
+
+/// A minimal RESP client, the same as the ones Redis libraries implement.
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(address: &str) -> Self {
+        let stream = TcpStream::connect(address).unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    fn send(&mut self, args: &[&str]) {
+        let mut command = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            command.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+        }
+        self.writer.write_all(&command).unwrap();
+    }
+
+    fn command(&mut self, args: &[&str]) -> Reply {
+        self.send(args);
+        self.read_reply()
+    }
+
+    fn read_reply(&mut self) -> Reply {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        let (kind, rest) = line.split_at(1);
+
+        match kind {
+            "+" => Reply::Simple(rest.to_string()),
+            "-" => Reply::Error(rest.to_string()),
+            ":" => Reply::Integer(rest.parse().unwrap()),
+            "$" if rest == "-1" => Reply::Nil,
+            "$" => {
+                let mut value = vec![0u8; rest.parse::<usize>().unwrap() + 2];
+                self.reader.read_exact(&mut value).unwrap();
+                value.truncate(value.len() - 2);
+                Reply::Bulk(value)
+            }
+            "*" => Reply::Array(
+                (0..rest.parse::<usize>().unwrap())
+                    .map(|_| self.read_reply())
+                    .collect(),
+            ),
+            _ => panic!("Unexpected reply {}", line),
+        }
+    }
+}
+
+fn server_config(name: &str) -> DBConfig {
+    let mut db_config = DBConfig::default();
+    db_config.sstable_dir += &format!("server_{}/", name);
+    db_config.write_ahead_log_dir += &format!("server_{}/", name);
+    db_config.token_bucket_capacity = 999999;
+    db_config.token_bucket_refill_rate = 999999;
+
+    db_config
+}
+
+/// Starts a server on a free local port and returns its address.
+fn start_server(name: &str) -> String {
+    let db_config = server_config(name);
+    prepare_dirs(&db_config);
+
+    let db = Arc::new(DB::build(db_config).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve(db, listener, Arc::new(AtomicBool::new(false))));
+
+    address
+}
+
+#[test]
+fn test_server_get_set_del() {
+    let address = start_server("get_set_del");
+    let mut client = Client::connect(&address);
+
+    assert_eq!(client.command(&["PING"]), Reply::Simple("PONG".to_string()));
+    assert_eq!(client.command(&["ping", "hello"]), bulk("hello"));
+
+    assert_eq!(client.command(&["GET", "key"]), Reply::Nil);
+    assert_eq!(
+        client.command(&["SET", "key", "value"]),
+        Reply::Simple("OK".to_string())
+    );
+    assert_eq!(client.command(&["GET", "key"]), bulk("value"));
+
+    assert_eq!(client.command(&["SET", "key", "other", "NX"]), Reply::Nil);
+    assert_eq!(
+        client.command(&["SET", "key2", "value2", "NX"]),
+        Reply::Simple("OK".to_string())
+    );
+    assert_eq!(client.command(&["GET", "key"]), bulk("value"));
+
+    assert_eq!(
+        client.command(&["SET", "temporary", "value", "PX", "50"]),
+        Reply::Simple("OK".to_string())
+    );
+    thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(client.command(&["GET", "temporary"]), Reply::Nil);
+
+    // An expired key is absent, so NX can set it again
+    assert_eq!(
+        client.command(&["SET", "temporary", "value", "PX", "50", "NX"]),
+        Reply::Simple("OK".to_string())
+    );
+    assert_eq!(
+        client.command(&["SET", "temporary", "other", "EX", "10", "NX"]),
+        Reply::Nil
+    );
+    assert_eq!(client.command(&["GET", "temporary"]), bulk("value"));
+    thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(
+        client.command(&["SET", "temporary", "other", "EX", "10", "NX"]),
+        Reply::Simple("OK".to_string())
+    );
+    assert_eq!(client.command(&["GET", "temporary"]), bulk("other"));
+
+    assert_eq!(
+        client.command(&["DEL", "key", "key2", "missing"]),
+        Reply::Integer(2)
+    );
+    assert_eq!(client.command(&["GET", "key"]), Reply::Nil);
+
+    // Replies to pipelined commands come in order
+    client.send(&["SET", "a", "1"]);
+    client.send(&["SET", "b", "2"]);
+    client.send(&["GET", "a"]);
+    assert_eq!(client.read_reply(), Reply::Simple("OK".to_string()));
+    assert_eq!(client.read_reply(), Reply::Simple("OK".to_string()));
+    assert_eq!(client.read_reply(), bulk("1"));
+
+    // Inline commands are supported for tools like telnet
+    client.writer.write_all(b"GET b\r\n").unwrap();
+    assert_eq!(client.read_reply(), bulk("2"));
+
+    assert!(matches!(client.command(&["GET"]), Reply::Error(message) if message.starts_with("ERR wrong number")));
+    assert!(matches!(client.command(&["UNKNOWN"]), Reply::Error(message) if message.starts_with("ERR unknown")));
+    assert!(matches!(client.command(&["SET", "key", "value", "EX", "10", "PX", "10"]), Reply::Error(_)));
+
+    assert_eq!(client.command(&["QUIT"]), Reply::Simple("OK".to_string()));
+}
+
+#[test]
+fn test_server_concurrent_del() {
+    let address = start_server("concurrent_del");
+    let mut client = Client::connect(&address);
+
+    // Each round sets the key once and deletes it from many clients at the same time
+    for round in 0..20 {
+        let key = format!("key{}", round);
+        assert_eq!(
+            client.command(&["SET", &key, "value"]),
+            Reply::Simple("OK".to_string())
+        );
+
+        let deletes: Vec<_> = (0..8)
+            .map(|_| {
+                let (address, key) = (address.clone(), key.clone());
+                thread::spawn(move || Client::connect(&address).command(&["DEL", &key]))
+            })
+            .collect();
+        let deleted: i64 = deletes
+            .into_iter()
+            .map(|delete| match delete.join().unwrap() {
+                Reply::Integer(deleted) => deleted,
+                reply => panic!("Unexpected reply {:?}", reply),
+            })
+            .sum();
+
+        assert_eq!(deleted, 1);
+        assert_eq!(client.command(&["GET", &key]), Reply::Nil);
+    }
+}
+
+#[test]
+fn test_server_scan() {
+    let address = start_server("scan");
+    let mut client = Client::connect(&address);
+
+    for i in 0..25 {
+        client.command(&["SET", &format!("key{:02}", i), "value"]);
+    }
+    client.command(&["SET", "other", "value"]);
+
+    let mut keys = Vec::new();
+    let mut cursor = "0".to_string();
+    loop {
+        let Reply::Array(reply) = client.command(&["SCAN", &cursor, "MATCH", "key*"]) else {
+            panic!("SCAN should reply with an array");
+        };
+        let [Reply::Bulk(next_cursor), Reply::Array(page)] = &reply[..] else {
+            panic!("SCAN should reply with a cursor and keys");
+        };
+
+        keys.extend(page.iter().map(|key| match key {
+            Reply::Bulk(key) => String::from_utf8(key.clone()).unwrap(),
+            _ => panic!("Keys should be bulk strings"),
+        }));
+
+        cursor = String::from_utf8(next_cursor.clone()).unwrap();
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(keys, (0..25).map(|i| format!("key{:02}", i)).collect::<Vec<_>>());
+
+    // Cursors belong to the connection that got them and can be used once
+    let Reply::Array(reply) = client.command(&["SCAN", "0", "COUNT", "5"]) else {
+        panic!("SCAN should reply with an array");
+    };
+    let Reply::Bulk(next_cursor) = &reply[0] else {
+        panic!("SCAN should reply with a cursor");
+    };
+    let next_cursor = String::from_utf8(next_cursor.clone()).unwrap();
+    assert!(matches!(
+        Client::connect(&address).command(&["SCAN", &next_cursor]),
+        Reply::Error(message) if message == "ERR invalid cursor"
+    ));
+    let Reply::Array(reply) = client.command(&["SCAN", &next_cursor, "COUNT", "2"]) else {
+        panic!("SCAN should reply with an array");
+    };
+    assert_ne!(reply[0], bulk("0"));
+    assert_ne!(reply[0], bulk(&next_cursor));
+    assert_eq!(reply[1], Reply::Array(vec![bulk("key05"), bulk("key06")]));
+    assert!(matches!(
+        client.command(&["SCAN", &next_cursor]),
+        Reply::Error(message) if message == "ERR invalid cursor"
+    ));
+
+    assert_eq!(
+        client.command(&["SCAN", "0", "RANGE", "key05", "key07", "COUNT", "5"]),
+        Reply::Array(vec![
+            bulk("0"),
+            Reply::Array(vec![bulk("key05"), bulk("key06"), bulk("key07")])
+        ])
+    );
+
+    assert_eq!(
+        client.command(&["SCAN", "0", "COUNT", "100"]),
+        Reply::Array(vec![
+            bulk("0"),
+            Reply::Array(
+                (0..25)
+                    .map(|i| bulk(&format!("key{:02}", i)))
+                    .chain([bulk("other")])
+                    .collect()
+            )
+        ])
+    );
+}
+
+#[test]
+fn test_server_probabilistic() {
+    let address = start_server("probabilistic");
+    let mut client = Client::connect(&address);
+
+    assert_eq!(client.command(&["BF.EXISTS", "filter", "a"]), Reply::Integer(0));
+    assert_eq!(client.command(&["BF.ADD", "filter", "a"]), Reply::Integer(1));
+    assert_eq!(client.command(&["BF.ADD", "filter", "a"]), Reply::Integer(0));
+    assert_eq!(client.command(&["BF.EXISTS", "filter", "a"]), Reply::Integer(1));
+    assert_eq!(client.command(&["BF.EXISTS", "filter", "b"]), Reply::Integer(0));
+
+    assert_eq!(
+        client.command(&["CMS.INCRBY", "sketch", "a", "3", "b", "1"]),
+        Reply::Array(vec![Reply::Integer(3), Reply::Integer(1)])
+    );
+    assert_eq!(
+        client.command(&["CMS.INCRBY", "sketch", "a", "2"]),
+        Reply::Array(vec![Reply::Integer(5)])
+    );
+    assert_eq!(
+        client.command(&["CMS.QUERY", "sketch", "a", "b", "c"]),
+        Reply::Array(vec![Reply::Integer(5), Reply::Integer(1), Reply::Integer(0)])
+    );
+    assert!(matches!(
+        client.command(&["CMS.INCRBY", "sketch", "a", "many"]),
+        Reply::Error(_)
+    ));
+
+    assert_eq!(client.command(&["PFCOUNT", "hll"]), Reply::Integer(0));
+    assert_eq!(client.command(&["PFADD", "hll", "a", "b", "c"]), Reply::Integer(1));
+    assert_eq!(client.command(&["PFADD", "hll", "a"]), Reply::Integer(0));
+    // The estimate isn't exact for small cardinalities
+    let Reply::Integer(count) = client.command(&["PFCOUNT", "hll"]) else {
+        panic!("PFCOUNT should reply with an integer");
+    };
+    assert!(count > 0);
+
+    // The probabilistic structures don't show up as keys
+    assert_eq!(client.command(&["GET", "filter"]), Reply::Nil);
+}
+
+#[test]
+fn test_server_shutdown() {
+    let db_config = server_config("shutdown");
+    prepare_dirs(&db_config);
+
+    let db = Arc::new(DB::build(db_config.clone()).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = {
+        let db = Arc::clone(&db);
+        thread::spawn(move || serve(db, listener, Arc::new(AtomicBool::new(false))))
+    };
+
+    let mut client = Client::connect(&address);
+    let mut idle_client = Client::connect(&address);
+    assert_eq!(
+        client.command(&["SET", "key", "value"]),
+        Reply::Simple("OK".to_string())
+    );
+    assert_eq!(
+        idle_client.command(&["PING"]),
+        Reply::Simple("PONG".to_string())
+    );
+
+    // The server stops accepting, closes the idle connection and releases the database
+    client.send(&["SHUTDOWN"]);
+    server.join().unwrap().unwrap();
+    let mut line = String::new();
+    assert_eq!(idle_client.reader.read_line(&mut line).unwrap(), 0);
+    Arc::try_unwrap(db)
+        .unwrap_or_else(|_| panic!("The server should release the database"))
+        .shut_down();
+
+    let db = DB::build(db_config).unwrap();
+    assert_eq!(
+        "value".as_bytes(),
+        &*db.get("key".as_bytes()).unwrap().unwrap()
+    );
+}